@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use robo_news_core::{healthcheck, init_logging, load_config_file, load_secret_files, set_service_name, shutdown_requested};
+use tracing::info;
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+// Pipeline order, also the order of a `--once` run
+const STAGES: [&str; 7] = [
+    "parser-feed1",
+    "downloader-feed1",
+    "scraper",
+    "translator",
+    "rewriter",
+    "illustrator",
+    "publisher",
+];
+
+fn main() -> Result<()> {
+    load_config_file()?;
+    load_secret_files()?;
+    init_logging()?;
+    let options = parse_run_options()?;
+    let stages = read_stages_from_env()?;
+
+    if options.healthcheck {
+        for stage in &stages {
+            set_service_name(stage);
+            healthcheck()?;
+        }
+        return Ok(());
+    }
+
+    if options.once {
+        // One cycle of each stage, in order, so an item can go through the whole pipeline
+        for stage in &stages {
+            set_service_name(stage);
+            run_stage(stage, true)?;
+        }
+        return Ok(());
+    }
+
+    info!("Starting stages: {}", stages.join(", "));
+
+    let (sender, receiver) = mpsc::channel();
+    for stage in &stages {
+        let stage = *stage;
+        let sender = sender.clone();
+        thread::Builder::new().name(stage.to_string()).spawn(move || {
+            set_service_name(stage);
+            let result = match panic::catch_unwind(AssertUnwindSafe(|| run_stage(stage, false))) {
+                Ok(result) => result,
+                Err(_) => Err(anyhow!("panicked")),
+            };
+            let _ = sender.send((stage, result));
+        })?;
+    }
+    drop(sender);
+
+    // On SIGTERM or Ctrl-C every stage finishes its current item and returns; otherwise a stage
+    // only returns on a fatal error, which stops the process so it gets restarted as a whole
+    for (stage, result) in receiver {
+        match result {
+            Ok(()) if shutdown_requested() => {}
+            Ok(()) => return Err(anyhow!("Stage {} stopped", stage)),
+            Err(e) => return Err(anyhow!("Stage {} failed: {}", stage, e)),
+        }
+    }
+    Ok(())
+}
+
+fn run_stage(stage: &str, once: bool) -> Result<()> {
+    match stage {
+        "parser-feed1" => parser_feed1::run(parser_feed1::RunOptions { once, limit: None }),
+        "downloader-feed1" => downloader_feed1::run(downloader_feed1::RunOptions { once, limit: None }),
+        "scraper" => scraper_stage::run(scraper_stage::RunOptions { once, limit: None }),
+        "translator" => translator::run(translator::RunOptions { once }),
+        "rewriter" => rewriter::run(rewriter::RunOptions { once }),
+        "illustrator" => illustrator::run(illustrator::RunOptions { once }),
+        "publisher" => publisher::run(publisher::RunOptions { once }),
+        other => Err(anyhow!("Unknown stage: {}", other)),
+    }
+}
+
+/// Reads ALL_IN_ONE_STAGES, a comma-separated subset of the stages; all of them by default.
+/// The stages keep pipeline order whatever the order of the list.
+fn read_stages_from_env() -> Result<Vec<&'static str>> {
+    let value = match env::var("ALL_IN_ONE_STAGES") {
+        Ok(value) if !value.trim().is_empty() => value,
+        _ => return Ok(STAGES.to_vec()),
+    };
+    let names: Vec<&str> = value.split(',').map(str::trim).filter(|name| !name.is_empty()).collect();
+    if let Some(unknown) = names.iter().find(|name| !STAGES.contains(name)) {
+        return Err(anyhow!(
+            "ALL_IN_ONE_STAGES names an unknown stage: {} (expected some of {})",
+            unknown,
+            STAGES.join(", ")
+        ));
+    }
+    Ok(STAGES.iter().copied().filter(|stage| names.contains(stage)).collect())
+}
+
+/// `healthcheck` checks the heartbeat of every stage (for Docker's HEALTHCHECK), `--once` runs a
+/// single cycle of every stage in pipeline order and exits.
+struct RunOptions {
+    healthcheck: bool,
+    once: bool,
+}
+
+fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { healthcheck: false, once: false };
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "healthcheck" => options.healthcheck = true,
+            "--once" => options.once = true,
+            other => return Err(anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
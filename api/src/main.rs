@@ -0,0 +1,319 @@
+use anyhow::{Context, Result};
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use robo_news_core::{
+    artifact_exists, artifact_url, builtin_stage, init_logging, load_artifact, load_config_file, load_secret_files,
+    open_db, open_db_read_only, PipelineStage,
+};
+use rusqlite::{params, OptionalExtension};
+use std::env;
+use tracing::{error, info};
+
+const DEFAULT_BIND_ADDR: &str = "127.0.0.1:8080";
+const DEFAULT_PAGE_SIZE: i32 = 20;
+const MAX_PAGE_SIZE: i32 = 100;
+
+type ApiSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Shared by all resolvers.
+struct ApiConfig {
+    /// Publisher stage: its output status marks published items, its output artifact holds the body.
+    publisher: PipelineStage,
+    /// First TARGET_LANGUAGES entry, stored on the `news` rows themselves.
+    default_lang: Option<String>,
+    /// Prefix for image links, e.g. `https://news.example.com` (API_PUBLIC_URL).
+    public_url: String,
+}
+
+#[derive(SimpleObject)]
+struct PublishedItem {
+    id: String,
+    /// Language of this version; null when TARGET_LANGUAGES is not set.
+    lang: Option<String>,
+    title: String,
+    /// Source article.
+    url: String,
+    /// Source publication date.
+    date: String,
+    /// When the publisher posted the item (from the publish chain).
+    published_at: Option<String>,
+    /// `published`, or `published_plain` when the post went out without formatting.
+    status: String,
+    /// Published HTML as sent to Telegram.
+    body: Option<String>,
+    /// Link to the illustration served by this API.
+    image: Option<String>,
+    /// Words of the title and the URL path, as used for forum topic routing.
+    tags: Vec<String>,
+}
+
+struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Published items, newest first. `lang` selects one of TARGET_LANGUAGES (default: the first).
+    async fn items(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        lang: Option<String>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> async_graphql::Result<Vec<PublishedItem>> {
+        let config = ctx.data::<ApiConfig>()?;
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let offset = offset.unwrap_or(0).max(0);
+        Ok(fetch_published_items(config, lang.as_deref(), None, limit, offset)?)
+    }
+
+    /// A single published item.
+    async fn item(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        id: String,
+        lang: Option<String>,
+    ) -> async_graphql::Result<Option<PublishedItem>> {
+        let config = ctx.data::<ApiConfig>()?;
+        Ok(fetch_published_items(config, lang.as_deref(), Some(id.as_str()), 1, 0)?.pop())
+    }
+}
+
+fn main() -> Result<()> {
+    load_config_file()?;
+    load_secret_files()?;
+    init_logging()?;
+    serve()
+}
+
+#[tokio::main]
+async fn serve() -> Result<()> {
+    let publisher = builtin_stage("publisher")?;
+    // Brings the schema up to date before the first read-only query
+    open_db()?;
+
+    let default_lang = env::var("TARGET_LANGUAGES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|code| code.trim().to_ascii_lowercase())
+        .find(|code| !code.is_empty());
+
+    let public_url = env::var("API_PUBLIC_URL").unwrap_or_default().trim().trim_end_matches('/').to_string();
+    let bind_addr = match env::var("API_BIND_ADDR") {
+        Ok(addr) if !addr.trim().is_empty() => addr.trim().to_string(),
+        _ => DEFAULT_BIND_ADDR.to_string(),
+    };
+
+    let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(ApiConfig {
+            publisher,
+            default_lang,
+            public_url,
+        })
+        .finish();
+
+    let app = Router::new()
+        .route("/graphql", get(graphiql).post(graphql_handler))
+        .route("/images/:id", get(image_handler))
+        .route("/r/:code", get(short_link_handler))
+        .with_state(schema);
+
+    let listener = tokio::net::TcpListener::bind(&bind_addr)
+        .await
+        .with_context(|| format!("Failed to bind {}", bind_addr))?;
+    info!("Serving GraphQL API on http://{}/graphql", bind_addr);
+
+    axum::serve(listener, app).await.context("API server stopped")?;
+    Ok(())
+}
+
+async fn graphql_handler(State(schema): State<ApiSchema>, Json(request): Json<async_graphql::Request>) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+/// Serves the `illustrator_<id>.png` artifact, or redirects to the store's presigned URL
+/// (ARTIFACT_STORE=s3); `<id>` may carry a `.png` extension.
+async fn image_handler(UrlPath(id): UrlPath<String>) -> Response {
+    let id = id.trim_end_matches(".png");
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let name = format!("illustrator_{}.png", id);
+    match artifact_url(&name) {
+        Ok(Some(url)) => return (StatusCode::FOUND, [(header::LOCATION, url)]).into_response(),
+        Ok(None) => {}
+        Err(e) => {
+            error!("Failed to sign the URL of image {}: {:#}", id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+    match load_artifact(&name) {
+        Ok(Some(bytes)) => ([(header::CONTENT_TYPE, "image/png")], bytes).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!("Failed to read image {}: {:#}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Redirects an internal short link (publisher SHORTENER=internal) to its source URL and counts
+/// the click.
+async fn short_link_handler(UrlPath(code): UrlPath<String>) -> Response {
+    if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    match follow_short_link(&code) {
+        Ok(Some(url)) => (StatusCode::FOUND, [(header::LOCATION, url)]).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!("Failed to resolve short link {}: {:#}", code, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn follow_short_link(code: &str) -> Result<Option<String>> {
+    // The click counter is the only thing the API writes
+    let conn = open_db()?;
+    let has_table: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'short_links')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(None);
+    }
+    let url: Option<String> = conn
+        .query_row("SELECT url FROM short_links WHERE code = ?", params![code], |row| row.get(0))
+        .optional()?;
+    if url.is_some() {
+        conn.execute("UPDATE short_links SET clicks = clicks + 1 WHERE code = ?", params![code])?;
+    }
+    Ok(url)
+}
+
+/// Published items of one language, newest first; `id` narrows the result to one item.
+fn fetch_published_items(
+    config: &ApiConfig,
+    lang: Option<&str>,
+    id: Option<&str>,
+    limit: i32,
+    offset: i32,
+) -> Result<Vec<PublishedItem>> {
+    // The API only reads; the pipeline services own the database
+    let conn = open_db_read_only()?;
+    let lang = lang
+        .map(|code| code.trim().to_ascii_lowercase())
+        .filter(|code| config.default_lang.as_deref() != Some(code.as_str()));
+
+    let mut items = match &lang {
+        None => {
+            let mut stmt = conn.prepare(
+                "SELECT id, title, url, date, status FROM news \
+                 WHERE status IN (?1, 'published_plain') AND (?2 IS NULL OR id = ?2) \
+                 ORDER BY date DESC LIMIT ?3 OFFSET ?4",
+            )?;
+            let rows = stmt
+                .query_map(params![config.publisher.output_status, id, limit, offset], |row| {
+                    published_item_from_row(row, config.default_lang.clone())
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        }
+        Some(lang) => {
+            let mut stmt = conn.prepare(
+                "SELECT n.id, n.title, n.url, n.date, t.status FROM news_translations t JOIN news n ON n.id = t.news_id \
+                 WHERE t.lang = ?1 AND t.status IN (?2, 'published_plain') AND (?3 IS NULL OR n.id = ?3) \
+                 ORDER BY n.date DESC LIMIT ?4 OFFSET ?5",
+            )?;
+            let rows = stmt
+                .query_map(params![lang, config.publisher.output_status, id, limit, offset], |row| {
+                    published_item_from_row(row, Some(lang.clone()))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        }
+    };
+
+    let has_chain: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'publish_chain'",
+        [],
+        |row| row.get(0),
+    )?;
+
+    for item in &mut items {
+        // Extra languages use `<artifact>_<id>_<lang>`, the default language `<artifact>_<id>`
+        let suffix = match &lang {
+            Some(lang) => format!("{}_{}", item.id, lang),
+            None => item.id.clone(),
+        };
+        item.body = load_artifact(&format!("{}_{}.html", config.publisher.output_artifact, suffix))?
+            .map(|body| String::from_utf8_lossy(&body).into_owned());
+        let image = format!("illustrator_{}.png", item.id);
+        if artifact_exists(&image)? {
+            // A presigned URL spares the API serving the image (ARTIFACT_STORE=s3)
+            item.image = Some(match artifact_url(&image)? {
+                Some(url) => url,
+                None => format!("{}/images/{}.png", config.public_url, item.id),
+            });
+        }
+        if has_chain {
+            item.published_at = conn
+                .query_row(
+                    "SELECT published_at FROM publish_chain WHERE news_id = ? AND lang = ? ORDER BY seq DESC LIMIT 1",
+                    params![item.id, lang.as_deref().unwrap_or("")],
+                    |row| row.get(0),
+                )
+                .optional()?;
+        }
+    }
+
+    Ok(items)
+}
+
+fn published_item_from_row(row: &rusqlite::Row, lang: Option<String>) -> rusqlite::Result<PublishedItem> {
+    let title: String = row.get(1)?;
+    let url: String = row.get(2)?;
+    let tags = item_tags(&title, &url);
+    Ok(PublishedItem {
+        id: row.get(0)?,
+        lang,
+        title,
+        url,
+        date: row.get(3)?,
+        published_at: None,
+        status: row.get(4)?,
+        body: None,
+        image: None,
+        tags,
+    })
+}
+
+/// Same tags the publisher routes forum topics by: the words of the title and of the URL path.
+fn item_tags(title: &str, url: &str) -> Vec<String> {
+    let path = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest.split_once('/').map_or("", |(_, path)| path));
+
+    let mut tags: Vec<String> = Vec::new();
+    for word in format!("{} {}", title, path)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+    {
+        if !tags.contains(&word) {
+            tags.push(word);
+        }
+    }
+    tags
+}
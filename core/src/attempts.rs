@@ -0,0 +1,86 @@
+//! Failed attempts of the rewriter and illustrator: an item is retried after a growing delay
+//! until MAX_ATTEMPTS is used up, then moves to the stage's error status.
+
+use crate::{record_failure, unix_now, with_immediate_transaction, Status};
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection};
+use std::env;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 2;
+const DEFAULT_RETRY_DELAY_SECS: u64 = 60;
+
+/// How often an item is attempted before it is marked as failed (MAX_ATTEMPTS, RETRY_DELAY_SECS).
+pub struct AttemptPolicy {
+    pub max_attempts: u32,
+    /// Wait after the first failed attempt; doubles with every further one.
+    pub retry_delay_secs: u64,
+    /// Status of an item that is tried again, e.g. `rewriter_retry`.
+    pub retry_status: Status,
+    /// Status of an item that used up its attempts, e.g. `rewriter_error`.
+    pub error_status: Status,
+}
+
+impl AttemptPolicy {
+    fn delay_before(&self, attempts: u32) -> i64 {
+        let delay = self
+            .retry_delay_secs
+            .saturating_mul(1 << attempts.saturating_sub(1).min(16));
+        delay.min(i64::MAX as u64) as i64
+    }
+}
+
+/// Reads MAX_ATTEMPTS and RETRY_DELAY_SECS for a stage whose failed items move to `retry_status`
+/// and, once out of attempts, to `error_status`.
+pub fn read_attempt_policy_from_env(retry_status: Status, error_status: Status) -> Result<AttemptPolicy> {
+    let max_attempts = match env::var("MAX_ATTEMPTS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|attempts| *attempts > 0)
+            .ok_or_else(|| anyhow!("MAX_ATTEMPTS must be a positive integer, got '{}'", value.trim()))?,
+        _ => DEFAULT_MAX_ATTEMPTS,
+    };
+    let retry_delay_secs = match env::var("RETRY_DELAY_SECS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u64>()
+            .map_err(|e| anyhow!("RETRY_DELAY_SECS must be a non-negative integer: {}", e))?,
+        _ => DEFAULT_RETRY_DELAY_SECS,
+    };
+    Ok(AttemptPolicy {
+        max_attempts,
+        retry_delay_secs,
+        retry_status,
+        error_status,
+    })
+}
+
+/// Counts a failed attempt of the item and returns the status it moves to: the policy's retry
+/// status (picked up again after a growing delay) or its error status once MAX_ATTEMPTS is used up.
+pub fn record_failed_attempt(
+    conn: &Connection,
+    id: &str,
+    lang: Option<&str>,
+    policy: &AttemptPolicy,
+    error: &str,
+) -> Result<(Status, u32)> {
+    let attempts = with_immediate_transaction(conn, |conn| {
+        let attempts = record_failure(conn, id, lang, error)?;
+        let next_attempt_at = unix_now() + policy.delay_before(attempts);
+        match lang {
+            Some(lang) => conn.execute(
+                "UPDATE news_translations SET next_attempt_at = ? WHERE news_id = ? AND lang = ?",
+                params![next_attempt_at, id, lang],
+            )?,
+            None => conn.execute("UPDATE news SET next_attempt_at = ? WHERE id = ?", params![next_attempt_at, id])?,
+        };
+        Ok(attempts)
+    })?;
+
+    if attempts >= policy.max_attempts {
+        Ok((policy.error_status.clone(), attempts))
+    } else {
+        Ok((policy.retry_status.clone(), attempts))
+    }
+}
@@ -0,0 +1,492 @@
+//! OpenAI-compatible chat requests of the translator and rewriter (OpenRouter, Perplexity and
+//! Gemini's compatibility endpoint): sampling and reasoning settings, request bodies and the
+//! parsing of the HTML document the model returns.
+
+use crate::{add_usage, parse_provider_error, ApiError};
+use anyhow::{anyhow, Result};
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::Arc;
+use tracing::{debug, error, warn};
+
+/// Standard sampling parameters sent with every chat request when set.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SamplingConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReasoningConfig {
+    /// When set, explicitly enables/disables reasoning.
+    ///
+    /// OpenRouter docs: https://openrouter.ai/docs/guides/best-practices/reasoning-tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+
+    /// Reasoning effort level.
+    /// Allowed values include: xhigh, high, medium, low, minimal, none.
+    ///
+    /// OpenRouter docs: https://openrouter.ai/docs/guides/best-practices/reasoning-tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effort: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Serialize)]
+pub struct OpenRouterChatRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<ReasoningConfig>,
+    #[serde(flatten)]
+    pub sampling: SamplingConfig,
+}
+
+#[derive(Serialize)]
+pub struct PerplexityChatRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    #[serde(flatten)]
+    pub sampling: SamplingConfig,
+}
+
+#[derive(Serialize)]
+pub struct GeminiChatRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    #[serde(flatten)]
+    pub sampling: SamplingConfig,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatResponse {
+    #[allow(dead_code)]
+    id: Option<String>,
+    choices: Vec<Choice>,
+    usage: Option<ChatUsage>,
+}
+
+/// Token counts of an OpenAI-compatible response.
+#[derive(Deserialize, Debug)]
+struct ChatUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct Choice {
+    #[allow(dead_code)]
+    index: Option<u32>,
+    message: ResponseMessage,
+    #[allow(dead_code)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseMessage {
+    #[allow(dead_code)]
+    role: Option<String>,
+    content: String,
+}
+
+/// Reads `{prefix}_TEMPERATURE`, `{prefix}_TOP_P` and `{prefix}_MAX_TOKENS`, e.g. with the prefix
+/// `AI_PROVIDER_TRANSLATOR`.
+pub fn read_sampling_config_from_env(prefix: &str) -> Result<SamplingConfig> {
+    let var = format!("{}_TEMPERATURE", prefix);
+    let temperature = match env::var(&var) {
+        Ok(value) if !value.trim().is_empty() => Some(
+            value
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|temperature| (0.0..=2.0).contains(temperature))
+                .ok_or_else(|| anyhow!("{} must be a number between 0 and 2, got '{}'", var, value.trim()))?,
+        ),
+        _ => None,
+    };
+
+    let var = format!("{}_TOP_P", prefix);
+    let top_p = match env::var(&var) {
+        Ok(value) if !value.trim().is_empty() => Some(
+            value
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|top_p| (0.0..=1.0).contains(top_p))
+                .ok_or_else(|| anyhow!("{} must be a number between 0 and 1, got '{}'", var, value.trim()))?,
+        ),
+        _ => None,
+    };
+
+    let var = format!("{}_MAX_TOKENS", prefix);
+    let max_tokens = match env::var(&var) {
+        Ok(value) if !value.trim().is_empty() => Some(
+            value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|max_tokens| *max_tokens > 0)
+                .ok_or_else(|| anyhow!("{} must be a positive integer, got '{}'", var, value.trim()))?,
+        ),
+        _ => None,
+    };
+
+    Ok(SamplingConfig {
+        temperature,
+        top_p,
+        max_tokens,
+    })
+}
+
+/// Reads `{prefix}_REASONING_ENABLED` and `{prefix}_REASONING_EFFORT`; `None` when neither is set.
+pub fn read_reasoning_config_from_env(prefix: &str) -> Option<ReasoningConfig> {
+    // Env-driven, optional behavior:
+    // - if neither env is provided (or both empty), behave as before (no `reasoning` field)
+    // - if provided, attach `reasoning` object to request
+    let enabled_var = format!("{}_REASONING_ENABLED", prefix);
+    let effort_var = format!("{}_REASONING_EFFORT", prefix);
+    let enabled_raw = env::var(&enabled_var).ok();
+    let effort_raw = env::var(&effort_var).ok();
+
+    let mut enabled = enabled_raw
+        .as_deref()
+        .and_then(|value| parse_optional_bool_env(&enabled_var, value));
+
+    let effort = effort_raw
+        .as_deref()
+        .and_then(|value| parse_optional_effort_env(&effort_var, value));
+
+    // Convenience + explicitness:
+    // If effort is provided but enabled isn't, set enabled based on effort.
+    if enabled.is_none() {
+        if let Some(e) = effort.as_deref() {
+            if e == "none" {
+                enabled = Some(false);
+            } else {
+                enabled = Some(true);
+            }
+        }
+    }
+
+    if enabled.is_none() && effort.is_none() {
+        return None;
+    }
+
+    Some(ReasoningConfig { enabled, effort })
+}
+
+fn parse_optional_bool_env(var: &str, value: &str) -> Option<bool> {
+    let v = value.trim();
+    if v.is_empty() || v == "-" {
+        return None;
+    }
+
+    match v.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "y" | "on" => Some(true),
+        "0" | "false" | "no" | "n" | "off" => Some(false),
+        _ => {
+            warn!("{} has invalid value '{}'. Ignoring.", var, v);
+            None
+        }
+    }
+}
+
+fn parse_optional_effort_env(var: &str, value: &str) -> Option<String> {
+    let v = value.trim();
+    if v.is_empty() || v == "-" {
+        return None;
+    }
+
+    // Docs allow: xhigh, high, medium, low, minimal, none
+    let normalized = v.to_ascii_lowercase();
+    match normalized.as_str() {
+        "xhigh" | "high" | "medium" | "low" | "minimal" | "none" => Some(normalized),
+        _ => {
+            warn!(
+                "{} has invalid value '{}'. Allowed: xhigh|high|medium|low|minimal|none. Ignoring.",
+                var, v
+            );
+            None
+        }
+    }
+}
+
+pub fn gemini_reasoning_effort_from_reasoning(reasoning: &Option<ReasoningConfig>) -> Option<String> {
+    let reasoning = reasoning.as_ref()?;
+
+    // If explicitly disabled, do not send reasoning_effort.
+    if reasoning.enabled == Some(false) {
+        return None;
+    }
+
+    let effort = reasoning.effort.as_deref()?;
+
+    // Gemini (OpenAI compatibility) docs mention reasoning_effort like:
+    // minimal | low | medium | high
+    // We map OpenRouter-style values to Gemini values:
+    // xhigh/high -> high, medium -> medium, low -> low, minimal -> minimal, none -> omit.
+    match effort {
+        "xhigh" | "high" => Some("high".to_string()),
+        "medium" => Some("medium".to_string()),
+        "low" => Some("low".to_string()),
+        "minimal" => Some("minimal".to_string()),
+        "none" => None,
+        other => {
+            warn!("Reasoning effort '{}' is not supported for Gemini. Omitting reasoning_effort.", other);
+            None
+        }
+    }
+}
+
+pub fn perplexity_reasoning_effort_from_reasoning(reasoning: &Option<ReasoningConfig>) -> Option<String> {
+    let reasoning = reasoning.as_ref()?;
+
+    // If explicitly disabled, do not send reasoning_effort.
+    if reasoning.enabled == Some(false) {
+        return None;
+    }
+
+    let effort = reasoning.effort.as_deref()?;
+
+    // Perplexity docs allow: low | medium | high.
+    // We map OpenRouter-style values to Perplexity values:
+    // xhigh/high -> high, medium -> medium, low/minimal -> low, none -> omit.
+    match effort {
+        "xhigh" | "high" => Some("high".to_string()),
+        "medium" => Some("medium".to_string()),
+        "low" | "minimal" => Some("low".to_string()),
+        "none" => None,
+        // Note: effort is validated on input, so this branch is mainly defensive.
+        other => {
+            warn!("Reasoning effort '{}' is not supported for Perplexity. Omitting reasoning_effort.", other);
+            None
+        }
+    }
+}
+
+/// Reads a chat completion and returns its HTML content (run through `post_process`) and finish
+/// reason; see `parse_chat_body`.
+pub async fn parse_chat_response(
+    response: Response,
+    post_process: fn(&str) -> String,
+) -> Result<(String, Option<String>), ApiError> {
+    let status = response.status();
+    // Read the body text regardless of status code
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+
+    parse_chat_body(status, &response_text, post_process)
+}
+
+/// Parses a chat completion body. Error statuses, `error`/`length` finish reasons and content
+/// that doesn't look like HTML come back as `ApiReturnedError` so the stage can retry.
+/// The reported tokens are added to the item's usage either way.
+pub fn parse_chat_body(
+    status: StatusCode,
+    response_text: &str,
+    post_process: fn(&str) -> String,
+) -> Result<(String, Option<String>), ApiError> {
+    // Try to parse the JSON response
+    let response_data: ChatResponse = match serde_json::from_str(response_text) {
+        Ok(data) => data,
+        Err(e) => {
+            // Log the raw text on parsing failure
+            error!(
+                "Failed to parse AI provider response JSON. Status: {}. Body: {}",
+                status, response_text
+            );
+            // Error bodies ({"error": ...}) don't match the chat response shape; keep the HTTP status.
+            if !status.is_success() {
+                return Err(ApiError::ApiReturnedError {
+                    provider_error: parse_provider_error(response_text),
+                    status,
+                    content: String::new(),
+                    finish_reason: Some("error".to_string()),
+                });
+            }
+            return Err(ApiError::ParseError(Arc::new(e.into())));
+        }
+    };
+
+    // Log the parsed response - ignore result
+    debug!(
+        "Parsed response from AI provider: {:?}",
+        response_data
+    );
+
+    // Count tokens even for responses that end up being retried; they are billed all the same
+    if let Some(usage) = &response_data.usage {
+        add_usage(usage.prompt_tokens, usage.completion_tokens);
+    }
+
+    if response_data.choices.is_empty() {
+        error!("AI provider returned empty choices array.");
+        return Err(ApiError::EmptyChoices);
+    }
+
+    let choice = &response_data.choices[0];
+    let content = choice.message.content.clone();
+    let finish_reason = choice.finish_reason.clone();
+
+    // Check HTTP status AFTER parsing, as API might return error status but valid JSON body
+    if !status.is_success() {
+        let cleaned_content = post_process(&content);
+
+        // Defensive validation: ensure we actually got HTML back.
+        // If the model returns meta-text (reasoning, instructions, markdown), force a retry.
+        if !looks_like_html(&cleaned_content) {
+            warn!(
+                "AI provider returned non-success status ({}) AND content does not look like HTML. Forcing finish_reason='error' to trigger retry.",
+                status
+            );
+            return Err(ApiError::ApiReturnedError {
+                provider_error: parse_provider_error(response_text),
+                status,
+                content: cleaned_content,
+                finish_reason: Some("error".to_string()),
+            });
+        }
+
+        warn!(
+            "AI provider returned non-success status: {}. Finish Reason: {:?}. Content received: {} bytes.",
+            status,
+            finish_reason,
+            cleaned_content.len()
+        );
+
+        return Err(ApiError::ApiReturnedError {
+            provider_error: parse_provider_error(response_text),
+            status,
+            content: cleaned_content,
+            finish_reason,
+        });
+    }
+
+    // Check finish_reason even on success status
+    if let Some(reason) = &finish_reason {
+        if reason == "error" || reason == "length" {
+            warn!(
+                "AI provider returned success status ({}) but finish_reason is '{}'.",
+                status, reason
+            );
+
+            let cleaned_content = post_process(&content);
+
+            if !looks_like_html(&cleaned_content) {
+                warn!("finish_reason is error/length AND cleaned content does not look like HTML (keeping finish_reason as-is).");
+            }
+            return Err(ApiError::ApiReturnedError {
+                provider_error: None,
+                status,
+                content: cleaned_content,
+                finish_reason: finish_reason.clone(),
+            });
+        }
+    }
+
+    let cleaned_content = post_process(&content);
+    if !looks_like_html(&cleaned_content) {
+        warn!("AI provider returned success status but cleaned content does not look like HTML. Forcing finish_reason='error' to trigger retry.");
+        return Err(ApiError::ApiReturnedError {
+            provider_error: None,
+            status,
+            content: cleaned_content,
+            finish_reason: Some("error".to_string()),
+        });
+    }
+    Ok((cleaned_content, finish_reason))
+}
+
+/// The HTML document in a model's answer: a full document anywhere in the text, else the
+/// first ```html fenced block, else any fenced block, else the trimmed text itself.
+pub fn extract_html_response(content: &str) -> String {
+    let content = content.trim();
+
+    // 1) Prefer extracting an HTML document if present anywhere in the response.
+    if let Some(extracted) = extract_html_document_block(content) {
+        return extracted;
+    }
+
+    // 2) Then try fenced blocks with explicit html language.
+    if let Some(extracted) = extract_fenced_block(content, "```html") {
+        return extracted;
+    }
+
+    // 3) Finally, try any fenced block.
+    if let Some(extracted) = extract_any_fenced_block(content) {
+        return extracted;
+    }
+
+    content.to_string()
+}
+
+pub fn looks_like_html(content: &str) -> bool {
+    let lower = content.to_ascii_lowercase();
+    (lower.contains("<html") && lower.contains("</html>"))
+        || (lower.contains("<body") && lower.contains("</body>"))
+        || (lower.contains("<!doctype html") && lower.contains("</html>"))
+}
+
+fn extract_html_document_block(s: &str) -> Option<String> {
+    // Try to extract a full HTML document if the model wrapped it with commentary.
+    let start = s.find("<html").or_else(|| s.find("<!DOCTYPE")).or_else(|| s.find("<!doctype"))?;
+    let end_tag = "</html>";
+    let end = s.rfind(end_tag)? + end_tag.len();
+    if start >= end {
+        return None;
+    }
+    Some(s[start..end].trim().to_string())
+}
+
+fn extract_fenced_block(s: &str, fence_start: &str) -> Option<String> {
+    let start_pos = s.find(fence_start)?;
+    let after = &s[start_pos + fence_start.len()..];
+
+    // If fence is followed by a newline, skip it. Otherwise keep the following text as-is.
+    let after = if let Some(stripped) = after.strip_prefix("\r\n") {
+        stripped
+    } else if let Some(stripped) = after.strip_prefix('\n') {
+        stripped
+    } else {
+        after
+    };
+
+    let end_pos = after.find("```")?;
+    Some(after[..end_pos].trim().to_string())
+}
+
+fn extract_any_fenced_block(s: &str) -> Option<String> {
+    let start_pos = s.find("```")?;
+    let after = &s[start_pos + 3..];
+
+    // Skip language id line if present.
+    let (after, _) = if let Some(nl) = after.find('\n') {
+        (&after[nl + 1..], true)
+    } else {
+        (after, false)
+    };
+
+    let end_pos = after.find("```")?;
+    Some(after[..end_pos].trim().to_string())
+}
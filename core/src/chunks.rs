@@ -0,0 +1,187 @@
+//! Splitting long HTML articles into chunks that fit the model's context, for the translator
+//! and rewriter.
+
+use crate::ApiError;
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+use tracing::{debug, info, warn};
+
+/// Chunk size in characters for one request: `chunk_chars` (0 = no chunking) unless the prompt,
+/// the article and the expected output would overflow `context_tokens` (0 = unknown, as set in
+/// `context_var`), in which case the article is cut into chunks that fit.
+pub fn effective_chunk_chars(
+    context_var: &str,
+    chunk_chars: usize,
+    context_tokens: usize,
+    max_tokens: Option<u32>,
+    prompt: &str,
+    content: &str,
+) -> usize {
+    if context_tokens == 0 {
+        return chunk_chars;
+    }
+
+    let prompt_tokens = count_tokens(prompt);
+    let content_tokens = count_tokens(content).max(1);
+    // The output is about as long as its input unless max_tokens caps it
+    let output_tokens = max_tokens.map_or(content_tokens, |max_tokens| max_tokens as usize);
+    if prompt_tokens + content_tokens + output_tokens <= context_tokens {
+        return chunk_chars;
+    }
+
+    let available = context_tokens.saturating_sub(prompt_tokens);
+    let chunk_tokens = match max_tokens {
+        Some(max_tokens) => available.saturating_sub(max_tokens as usize),
+        None => available / 2,
+    };
+    if chunk_tokens == 0 {
+        warn!(
+            "The prompt ({} tokens) leaves no room in {}={}",
+            prompt_tokens, context_var, context_tokens
+        );
+        return chunk_chars;
+    }
+
+    // Convert back to characters with this article's own ratio, keeping a 10% margin
+    let chars_per_token = content.len() as f64 / content_tokens as f64;
+    let derived = ((chunk_tokens as f64 * chars_per_token) * 0.9).max(1.0) as usize;
+    info!(
+        "Estimated {} prompt + {} article tokens exceed the {}-token context, splitting into chunks of up to {} characters",
+        prompt_tokens, content_tokens, context_tokens, derived
+    );
+    match chunk_chars {
+        0 => derived,
+        configured => configured.min(derived),
+    }
+}
+
+/// Token estimate with the o200k_base encoding; other models' tokenizers differ slightly, which the
+/// margin in `effective_chunk_chars` absorbs. Falls back to ~4 characters per token.
+pub fn count_tokens(text: &str) -> usize {
+    static ENCODING: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    match ENCODING.get_or_init(|| tiktoken_rs::o200k_base().ok()) {
+        Some(bpe) => bpe.encode_with_special_tokens(text).len(),
+        None => text.len().div_ceil(4),
+    }
+}
+
+/// Splits the document body on block boundaries, runs `process` on each chunk (wrapped as its
+/// own HTML document) in order and puts the results back into the original document.
+/// Stops at the first chunk that fails or finishes with `length`/`error`.
+pub async fn process_in_chunks<F>(html: &str, chunk_chars: usize, process: F) -> Result<(String, Option<String>), ApiError>
+where
+    F: AsyncFn(&str) -> Result<(String, Option<String>), ApiError>,
+{
+    let (prefix, body, suffix) = split_html_body(html);
+    let chunks = split_html_chunks(body, chunk_chars);
+    let total = chunks.len();
+
+    let mut output = String::from(prefix);
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        debug!(
+            "Processing chunk {}/{} ({} characters)",
+            index + 1,
+            total,
+            chunk.len()
+        );
+        let (content, finish_reason) = process(&format!("<html><body>{}</body></html>", chunk)).await?;
+        output.push_str(split_html_body(&content).1.trim());
+        output.push('\n');
+
+        if matches!(finish_reason.as_deref(), Some("length") | Some("error")) {
+            output.push_str(suffix);
+            return Ok((output, finish_reason));
+        }
+    }
+    output.push_str(suffix);
+
+    Ok((output, None))
+}
+
+/// Returns `(everything up to and including <body>, body content, </body> and the rest)`.
+/// Documents without a body are treated as all body.
+fn split_html_body(html: &str) -> (&str, &str, &str) {
+    let lower = html.to_ascii_lowercase();
+    let start = lower
+        .find("<body")
+        .and_then(|pos| lower[pos..].find('>').map(|end| pos + end + 1));
+    let end = lower.rfind("</body>");
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end => (&html[..start], &html[start..end], &html[end..]),
+        _ => ("", html, ""),
+    }
+}
+
+/// Cuts HTML into chunks of at most `max_chars` (unless a single block is longer) right after
+/// closing paragraph, heading, list, quote, table or figure tags.
+fn split_html_chunks(html: &str, max_chars: usize) -> Vec<String> {
+    const BLOCK_ENDS: [&str; 12] = [
+        "</p>", "</h1>", "</h2>", "</h3>", "</h4>", "</h5>", "</h6>", "</ul>", "</ol>", "</blockquote>", "</table>",
+        "</figure>",
+    ];
+    let lower = html.to_ascii_lowercase();
+    let mut boundaries: Vec<usize> = BLOCK_ENDS
+        .iter()
+        .flat_map(|tag| lower.match_indices(tag).map(move |(pos, _)| pos + tag.len()))
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    boundaries.push(html.len());
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut last = 0;
+    for boundary in boundaries {
+        if boundary - start > max_chars && last > start {
+            chunks.push(html[start..last].to_string());
+            start = last;
+        }
+        last = boundary;
+    }
+    if last > start {
+        chunks.push(html[start..last].to_string());
+    }
+    chunks.retain(|chunk| !chunk.trim().is_empty());
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_html_chunks_cuts_after_block_ends() {
+        let html = "<p>one</p><p>two</p><p>three</p>";
+        assert_eq!(split_html_chunks(html, 20), vec!["<p>one</p><p>two</p>", "<p>three</p>"]);
+        assert_eq!(split_html_chunks(html, 10), vec!["<p>one</p>", "<p>two</p>", "<p>three</p>"]);
+    }
+
+    #[test]
+    fn split_html_chunks_keeps_short_documents_whole() {
+        let html = "<h1>Title</h1>\n<p>Text</p>\n";
+        assert_eq!(split_html_chunks(html, 1000), vec![html]);
+    }
+
+    #[test]
+    fn split_html_chunks_never_cuts_inside_a_block() {
+        let long = format!("<p>{}</p>", "x".repeat(50));
+        let html = format!("<p>a</p>{}<p>b</p>", long);
+        assert_eq!(split_html_chunks(&html, 10), vec!["<p>a</p>".to_string(), long, "<p>b</p>".to_string()]);
+    }
+
+    #[test]
+    fn split_html_chunks_matches_tags_case_insensitively_and_drops_blank_chunks() {
+        let html = "<UL><li>a</li></UL>\n\n<Table><tr><td>b</td></tr></TABLE>  ";
+        assert_eq!(
+            split_html_chunks(html, 5),
+            vec!["<UL><li>a</li></UL>", "\n\n<Table><tr><td>b</td></tr></TABLE>"]
+        );
+    }
+
+    #[test]
+    fn split_html_chunks_keeps_trailing_text_without_a_block_end() {
+        assert_eq!(split_html_chunks("<p>a</p>tail", 4), vec!["<p>a</p>", "tail"]);
+        assert!(split_html_chunks("", 10).is_empty());
+    }
+}
@@ -0,0 +1,483 @@
+//! The optional TOML configuration file, a single place for the settings the services otherwise
+//! read from dozens of environment variables, and secrets read from mounted files.
+
+use anyhow::{anyhow, Context, Result};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+const DEFAULT_CONFIG_FILE: &str = "robo-news.toml";
+const REDACTED: &str = "[REDACTED]";
+/// Shorter values (an empty token, `1`) would blank out ordinary text.
+const MIN_REDACTED_LEN: usize = 6;
+
+/// Variables holding credentials: they can be read from the file `<NAME>_FILE` names and their
+/// values never appear in the logs.
+const SECRET_SUFFIXES: &[&str] = &["_API_KEY", "_API_HASH", "_TOKEN", "_PASSWORD", "_SECRET_KEY"];
+const SECRET_VARS: &[&str] = &["AI_BUDGET_ALERT_URL", "S3_SECRET_ACCESS_KEY", "TG_PHONE"];
+
+static SECRET_VALUES: OnceLock<Vec<String>> = OnceLock::new();
+
+/// What a setting holds, checked when the file is loaded so that a mistyped number or flag is
+/// reported at startup with every other problem of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Integer,
+    Number,
+    Bool,
+}
+
+/// Every variable the services and tools read, so that a misspelled section or key is reported
+/// instead of silently ignored. Lists are text. Secrets may also come as `<NAME>_FILE`.
+const TEXT_SETTINGS: &[&str] = &[
+    "ADMIN_BOT_TOKEN", "ADMIN_CHAT_ID", "AI_BUDGET_ALERT_URL", "AI_PROVIDER_ILLUSTRATOR_API_KEY",
+    "AI_PROVIDER_ILLUSTRATOR_API_URL", "AI_PROVIDER_ILLUSTRATOR_ASPECT_RATIO",
+    "AI_PROVIDER_ILLUSTRATOR_FALLBACK_API_KEY", "AI_PROVIDER_ILLUSTRATOR_FALLBACK_API_URL",
+    "AI_PROVIDER_ILLUSTRATOR_FALLBACK_MODEL", "AI_PROVIDER_ILLUSTRATOR_FALLBACK_TYPE", "AI_PROVIDER_ILLUSTRATOR_MODEL",
+    "AI_PROVIDER_ILLUSTRATOR_NEGATIVE_PROMPT", "AI_PROVIDER_ILLUSTRATOR_PROMPT", "AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL",
+    "AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_API_KEY", "AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_API_URL",
+    "AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_INSTRUCTION", "AI_PROVIDER_ILLUSTRATOR_QUALITY",
+    "AI_PROVIDER_ILLUSTRATOR_REASONING_EFFORT", "AI_PROVIDER_ILLUSTRATOR_RESOLUTION", "AI_PROVIDER_ILLUSTRATOR_SIZE",
+    "AI_PROVIDER_ILLUSTRATOR_TYPE", "AI_PROVIDER_ILLUSTRATOR_WORKFLOW_FILE", "AI_PROVIDER_REWRITER_API_KEY",
+    "AI_PROVIDER_REWRITER_API_URL", "AI_PROVIDER_REWRITER_BASE_URL", "AI_PROVIDER_REWRITER_CATEGORY_MODEL",
+    "AI_PROVIDER_REWRITER_CATEGORY_PROMPT", "AI_PROVIDER_REWRITER_CATEGORY_PROMPT_FILE",
+    "AI_PROVIDER_REWRITER_FACT_CHECK_MODEL", "AI_PROVIDER_REWRITER_FACT_CHECK_PROMPT",
+    "AI_PROVIDER_REWRITER_FACT_CHECK_PROMPT_FILE", "AI_PROVIDER_REWRITER_MODEL", "AI_PROVIDER_REWRITER_PROMPT",
+    "AI_PROVIDER_REWRITER_PROMPT_FILE", "AI_PROVIDER_REWRITER_REASONING_EFFORT", "AI_PROVIDER_REWRITER_SOURCE_PROMPTS",
+    "AI_PROVIDER_REWRITER_SUMMARY_PROMPT", "AI_PROVIDER_REWRITER_SUMMARY_PROMPT_FILE", "AI_PROVIDER_REWRITER_TYPE",
+    "AI_PROVIDER_REWRITER_VARIANT_PROMPT", "AI_PROVIDER_REWRITER_VARIANT_PROMPT_FILE", "AI_PROVIDER_TRANSLATOR_API_KEY",
+    "AI_PROVIDER_TRANSLATOR_API_URL", "AI_PROVIDER_TRANSLATOR_FALLBACK_TYPE",
+    "AI_PROVIDER_TRANSLATOR_GOOGLE_ACCESS_TOKEN", "AI_PROVIDER_TRANSLATOR_GOOGLE_LOCATION",
+    "AI_PROVIDER_TRANSLATOR_GOOGLE_MODEL", "AI_PROVIDER_TRANSLATOR_GOOGLE_PROJECT_ID", "AI_PROVIDER_TRANSLATOR_MODEL",
+    "AI_PROVIDER_TRANSLATOR_PROMPT", "AI_PROVIDER_TRANSLATOR_PROMPT_CUT", "AI_PROVIDER_TRANSLATOR_REASONING_EFFORT",
+    "AI_PROVIDER_TRANSLATOR_SOURCE_LANGUAGE", "AI_PROVIDER_TRANSLATOR_TARGET_LANGUAGE", "AI_PROVIDER_TRANSLATOR_TYPE",
+    "ALL_IN_ONE_STAGES", "API_BIND_ADDR", "API_PUBLIC_URL", "APPROVAL_BOT_TOKEN", "APPROVAL_CHAT_ID", "ARTIFACT_STORE",
+    "BLUESKY_APP_PASSWORD", "BLUESKY_HANDLE", "BLUESKY_PDS_URL", "CATEGORIES", "CATEGORY_KEYWORDS_FILE", "CTL_BIN_DIR",
+    "DASHBOARD_BIND_ADDR", "DIGEST_FROM", "DIGEST_RECIPIENTS", "DIGEST_SUBJECT", "DIGEST_TEMPLATE",
+    "DIGEST_TIME", "DISCORD_WEBHOOK_URL", "FEED1_SOURCE", "FEED1_URL", "GLOSSARY_FILE",
+    "ILLUSTRATOR_DEDUP_VARIATION_HINT", "ILLUSTRATOR_SKIP_KEYWORDS", "ILLUSTRATOR_SKIP_SOURCES", "JANITOR_ARCHIVE_DIR",
+    "LOG_FORMAT", "LOG_LEVEL", "MATRIX_ACCESS_TOKEN", "MATRIX_HOMESERVER_URL", "MATRIX_ROOM_ID",
+    "MODERATION_KEYWORDS_FILE", "MODERATION_MODEL", "MODERATION_OPENAI_API_KEY", "NOSTR_RELAYS", "NOSTR_SECRET_KEY",
+    "PIN_KEYWORDS", "PIPELINE_CONFIG", "POST_CATEGORY_EMOJI", "POST_COVER", "POST_HASHTAGS", "POST_TEMPLATES",
+    "PUBLISHER_DAILY_HEADER", "PUBLISHER_DAILY_HEADER_DATE_FORMAT", "PUBLISHER_HOURS", "PUBLISHER_QUIET_HOURS",
+    "PUBLISHER_TIMEZONE", "PUBLISHER_VARIANT_STRATEGY", "S3_ACCESS_KEY_ID", "S3_BUCKET", "S3_ENDPOINT",
+    "S3_PUBLIC_ENDPOINT", "S3_REGION", "S3_SECRET_ACCESS_KEY", "SCRAPER_BOILERPLATE_PATTERNS", "SCRAPER_OCR_COMMAND",
+    "SCRAPER_OCR_LANGUAGES", "SHORTENER", "SHORTENER_API_KEY", "SHORTENER_URL", "SITE_TITLE", "SLACK_BOT_TOKEN",
+    "SLACK_CHANNEL", "SLACK_WEBHOOK_URL", "SMTP_HOST", "SMTP_PASSWORD", "SMTP_SECURITY", "SMTP_USERNAME",
+    "TARGET_LANGUAGES", "TELEGRAPH_ACCESS_TOKEN", "TELEGRAPH_AUTHOR_NAME", "TG_API_HASH", "TG_CHAT_ID",
+    "TG_MODERATION_CHAT_ID", "TG_PHONE", "TG_TOPIC_CATEGORIES", "TG_TOPIC_RULES", "WORKER_ID",
+];
+const INTEGER_SETTINGS: &[&str] = &[
+    "AI_DAILY_TOKEN_BUDGET", "AI_PROVIDER_ILLUSTRATOR_CONCURRENCY", "AI_PROVIDER_ILLUSTRATOR_MAX_WAIT_SECS",
+    "AI_PROVIDER_ILLUSTRATOR_MIN_CONCURRENCY", "AI_PROVIDER_ILLUSTRATOR_TIMEOUT_SECS",
+    "AI_PROVIDER_REWRITER_CACHE_DAYS", "AI_PROVIDER_REWRITER_CHUNK_CHARS", "AI_PROVIDER_REWRITER_CONCURRENCY",
+    "AI_PROVIDER_REWRITER_CONTEXT_TOKENS", "AI_PROVIDER_REWRITER_MAX_TOKENS", "AI_PROVIDER_REWRITER_MIN_CONCURRENCY",
+    "AI_PROVIDER_REWRITER_RATE_LIMIT_MAX_DELAY_SECS", "AI_PROVIDER_REWRITER_RATE_LIMIT_RETRIES",
+    "AI_PROVIDER_REWRITER_TIMEOUT_SECS", "AI_PROVIDER_TRANSLATOR_CHUNK_CHARS", "AI_PROVIDER_TRANSLATOR_CONCURRENCY",
+    "AI_PROVIDER_TRANSLATOR_CONTEXT_TOKENS", "AI_PROVIDER_TRANSLATOR_MAX_TOKENS",
+    "AI_PROVIDER_TRANSLATOR_MIN_CONCURRENCY", "AI_PROVIDER_TRANSLATOR_TIMEOUT_SECS", "CLAIM_TIMEOUT_SECS",
+    "DB_BUSY_TIMEOUT_SECS", "DOWNLOADER_CONCURRENCY", "DOWNLOADER_INTERVAL_SECS", "ENGAGEMENT_DAYS",
+    "ENGAGEMENT_INTERVAL_SECS", "HEALTHCHECK_MAX_AGE_SECS", "HTTP_TIMEOUT_SECS", "ILLUSTRATOR_DEDUP_MAX_DISTANCE",
+    "ILLUSTRATOR_DEDUP_WINDOW", "ILLUSTRATOR_IMAGES_PER_ITEM", "ILLUSTRATOR_INTERVAL_SECS",
+    "ILLUSTRATOR_LEAD_IMAGE_MIN_WIDTH", "ILLUSTRATOR_MAX_BYTES", "ILLUSTRATOR_MAX_DIMENSION",
+    "ILLUSTRATOR_SKIP_MIN_WORDS", "JANITOR_INTERVAL_SECS", "JANITOR_RETENTION_DAYS", "MAILER_INTERVAL_SECS",
+    "MAX_ATTEMPTS", "PARSER_INTERVAL_SECS", "PIN_DURATION_SECS", "PUBLISHER_DEDUP_WINDOW", "PUBLISHER_INTERVAL_SECS",
+    "PUBLISHER_MAX_ATTEMPTS", "PUBLISHER_MAX_POSTS_PER_HOUR", "PUBLISHER_ORDER_TIMEOUT_SECS",
+    "PUBLISHER_RETRY_DELAY_SECS", "PUBLISHER_RETRY_MAX_DELAY_SECS", "RETRY_DELAY_SECS", "REWRITER_INTERVAL_SECS",
+    "S3_URL_EXPIRES_SECS", "SCRAPER_INTERVAL_SECS", "SCRAPER_OCR_MAX_IMAGES", "SCRAPER_OCR_MIN_IMAGE_BYTES",
+    "SCRAPER_OCR_MIN_TEXT_CHARS", "SMTP_PORT", "TG_API_ID", "TG_TOPIC_DEFAULT", "TRANSLATOR_INTERVAL_SECS",
+    "WAKEUP_POLL_SECS",
+];
+const NUMBER_SETTINGS: &[&str] = &[
+    "AI_DAILY_COST_BUDGET_USD", "AI_PROVIDER_ILLUSTRATOR_FALLBACK_PRICE_IMAGE", "AI_PROVIDER_ILLUSTRATOR_PRICE_IMAGE",
+    "AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_PRICE_INPUT", "AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_PRICE_OUTPUT",
+    "AI_PROVIDER_REWRITER_CATEGORY_PRICE_INPUT",
+    "AI_PROVIDER_REWRITER_CATEGORY_PRICE_OUTPUT", "AI_PROVIDER_REWRITER_FACT_CHECK_PRICE_INPUT",
+    "AI_PROVIDER_REWRITER_FACT_CHECK_PRICE_OUTPUT", "AI_PROVIDER_REWRITER_PRICE_INPUT",
+    "AI_PROVIDER_REWRITER_PRICE_OUTPUT", "AI_PROVIDER_REWRITER_TEMPERATURE", "AI_PROVIDER_REWRITER_TOP_P",
+    "AI_PROVIDER_TRANSLATOR_PRICE_INPUT", "AI_PROVIDER_TRANSLATOR_PRICE_OUTPUT", "AI_PROVIDER_TRANSLATOR_TEMPERATURE",
+    "AI_PROVIDER_TRANSLATOR_TOP_P", "POST_FOOTER_RATIO",
+];
+const BOOL_SETTINGS: &[&str] = &[
+    "AI_PROVIDER_ILLUSTRATOR_ADAPTIVE_CONCURRENCY", "AI_PROVIDER_ILLUSTRATOR_REASONING_ENABLED",
+    "AI_PROVIDER_REWRITER_ADAPTIVE_CONCURRENCY", "AI_PROVIDER_REWRITER_LOG_REQUESTS",
+    "AI_PROVIDER_REWRITER_REASONING_ENABLED", "AI_PROVIDER_TRANSLATOR_ADAPTIVE_CONCURRENCY",
+    "AI_PROVIDER_TRANSLATOR_REASONING_ENABLED", "CAPTION_ABOVE_PHOTO", "ILLUSTRATOR_SAFETY_CHECK",
+    "ILLUSTRATOR_USE_LEAD_IMAGE", "LINK_PREVIEW", "LINK_PREVIEW_ABOVE_TEXT", "PUBLISHER_STRICT_ORDER",
+    "PUBLISH_DRY_RUN", "SCRAPER_OCR_ENABLED", "TELEGRAPH_ALWAYS",
+];
+
+/// Names built at runtime: `<LANG>` is a code from TARGET_LANGUAGES (`PT_BR` for `pt-br`),
+/// `<CATEGORY>` one of CATEGORIES and `<N>` a feed number. The first match counts.
+const SETTING_PATTERNS: &[(&str, Kind)] = &[
+    ("AI_PROVIDER_REWRITER_PROMPT_<LANG>_FILE", Kind::Text),
+    ("AI_PROVIDER_REWRITER_PROMPT_<LANG>", Kind::Text),
+    ("AI_PROVIDER_TRANSLATOR_PROMPT_<LANG>", Kind::Text),
+    ("TG_CHAT_ID_CATEGORY_<CATEGORY>", Kind::Text),
+    ("TG_CHAT_ID_<LANG>", Kind::Text),
+    ("DISCORD_WEBHOOK_URL_<LANG>", Kind::Text),
+    ("SLACK_WEBHOOK_URL_<LANG>", Kind::Text),
+    ("SLACK_CHANNEL_<LANG>", Kind::Text),
+    ("MATRIX_ROOM_ID_<LANG>", Kind::Text),
+    ("NOSTR_RELAYS_<LANG>", Kind::Text),
+    ("POST_COVER_<LANG>", Kind::Text),
+    ("LINK_PREVIEW_ABOVE_TEXT_<LANG>", Kind::Bool),
+    ("LINK_PREVIEW_<LANG>", Kind::Bool),
+    ("CAPTION_ABOVE_PHOTO_<LANG>", Kind::Bool),
+    ("FEED<N>_SOURCE", Kind::Text),
+    ("FEED<N>_BOILERPLATE_PATTERNS", Kind::Text),
+];
+
+/// Loads the file CONFIG_FILE names (`robo-news.toml` in the working directory if it exists) into
+/// the environment. Every key stands for the variable its path spells: `interval_secs` under
+/// `[publisher]` is PUBLISHER_INTERVAL_SECS, `model` under `[ai_provider.rewriter]` is
+/// AI_PROVIDER_REWRITER_MODEL. Arrays become comma-separated lists. A variable already set in the
+/// environment wins over the file.
+///
+/// The whole file is checked before anything is applied, and every problem is listed in the error.
+/// Call it first thing in `main`, before any other thread starts.
+pub fn load_config_file() -> Result<()> {
+    let path = match env::var("CONFIG_FILE") {
+        Ok(path) if !path.trim().is_empty() => path.trim().to_string(),
+        _ if Path::new(DEFAULT_CONFIG_FILE).exists() => DEFAULT_CONFIG_FILE.to_string(),
+        _ => return Ok(()),
+    };
+
+    let raw = fs::read_to_string(&path).with_context(|| format!("Failed to read config file: {}", path))?;
+    let table: Table = raw.parse().map_err(|e| anyhow!("Config file {} is not valid TOML: {}", path, e))?;
+
+    let mut settings = HashMap::new();
+    let mut problems = Vec::new();
+    collect_settings(&table, &mut Vec::new(), &mut settings, &mut problems);
+    if !problems.is_empty() {
+        problems.sort();
+        return Err(anyhow!("Config file {} has {} problem(s):\n  {}", path, problems.len(), problems.join("\n  ")));
+    }
+
+    for (var, (_, value)) in settings {
+        if env::var_os(&var).is_none() {
+            env::set_var(var, value);
+        }
+    }
+    Ok(())
+}
+
+/// Walks `table` and records every value under its variable name, with the key path it came from.
+fn collect_settings(
+    table: &Table,
+    path: &mut Vec<String>,
+    settings: &mut HashMap<String, (String, String)>,
+    problems: &mut Vec<String>,
+) {
+    for (key, value) in table {
+        path.push(key.clone());
+        let key_path = path.join(".");
+        if let Value::Table(inner) = value {
+            collect_settings(inner, path, settings, problems);
+            path.pop();
+            continue;
+        }
+
+        let var = path.join("_").to_ascii_uppercase().replace(['-', ' '], "_");
+        match setting_kind(&var) {
+            None => problems.push(format!("{}: unknown setting ({})", key_path, var)),
+            Some(kind) => match setting_value(value).and_then(|value| check_value(kind, value)) {
+                Ok(value) => {
+                    if let Some((other, _)) = settings.get(&var) {
+                        problems.push(format!("{}: {} is already set by {}", key_path, var, other));
+                    } else {
+                        settings.insert(var, (key_path, value));
+                    }
+                }
+                Err(e) => problems.push(format!("{}: {}", key_path, e)),
+            },
+        }
+        path.pop();
+    }
+}
+
+/// What `var` holds, or None when no service reads it.
+fn setting_kind(var: &str) -> Option<Kind> {
+    let lists = [
+        (TEXT_SETTINGS, Kind::Text),
+        (INTEGER_SETTINGS, Kind::Integer),
+        (NUMBER_SETTINGS, Kind::Number),
+        (BOOL_SETTINGS, Kind::Bool),
+    ];
+    if let Some((_, kind)) = lists.iter().find(|(names, _)| names.contains(&var)) {
+        return Some(*kind);
+    }
+    if let Some((_, kind)) = SETTING_PATTERNS.iter().find(|(pattern, _)| matches_pattern(var, pattern)) {
+        return Some(*kind);
+    }
+    // A secret read from a file, e.g. TG_API_HASH_FILE
+    var.strip_suffix("_FILE")
+        .filter(|secret| is_secret_var(secret) && setting_kind(secret).is_some())
+        .map(|_| Kind::Text)
+}
+
+/// Whether `var` is `pattern` with its placeholder filled in (see SETTING_PATTERNS).
+fn matches_pattern(var: &str, pattern: &str) -> bool {
+    let Some((start, rest)) = pattern.split_once('<') else {
+        return var == pattern;
+    };
+    let Some((placeholder, end)) = rest.split_once('>') else {
+        return false;
+    };
+    let Some(value) = var.strip_prefix(start).and_then(|var| var.strip_suffix(end)) else {
+        return false;
+    };
+    !value.is_empty()
+        && match placeholder {
+            "N" => value.bytes().all(|byte| byte.is_ascii_digit()),
+            _ => value.bytes().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit() || byte == b'_'),
+        }
+}
+
+/// `value` if it parses as `kind` the way the services read it.
+fn check_value(kind: Kind, value: String) -> Result<String, String> {
+    let text = value.trim();
+    let valid = match kind {
+        Kind::Text => true,
+        Kind::Integer => text.is_empty() || text.parse::<u64>().is_ok(),
+        Kind::Number => text.is_empty() || text.parse::<f64>().is_ok_and(f64::is_finite),
+        Kind::Bool => matches!(
+            text.to_ascii_lowercase().as_str(),
+            "" | "1" | "true" | "yes" | "on" | "0" | "false" | "no" | "off"
+        ),
+    };
+    if valid {
+        return Ok(value);
+    }
+    let expected = match kind {
+        Kind::Integer => "a non-negative integer",
+        Kind::Number => "a number",
+        _ => "true or false",
+    };
+    Err(format!("must be {}, got '{}'", expected, text))
+}
+
+/// The value as the environment variable would hold it.
+fn setting_value(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Array(_) | Value::Table(_) => Err("lists may only hold strings, numbers and booleans".to_string()),
+                value => setting_value(value),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|values| values.join(",")),
+        Value::Datetime(_) => Err("dates are not supported, write them as strings".to_string()),
+        Value::Table(_) => Err("unexpected table".to_string()),
+    }
+}
+
+fn is_secret_var(name: &str) -> bool {
+    SECRET_VARS.contains(&name)
+        || SECRET_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+        // Mirror webhooks come per language too, e.g. DISCORD_WEBHOOK_URL_DE
+        || (name.contains("_WEBHOOK_URL") && !name.ends_with("_FILE"))
+}
+
+/// Reads every secret from the file its `<NAME>_FILE` variable names (Docker and Kubernetes
+/// secrets), e.g. AI_PROVIDER_REWRITER_API_KEY from AI_PROVIDER_REWRITER_API_KEY_FILE. Trailing
+/// newlines are dropped. Setting both a secret and its file is an error, as is a file that can't
+/// be read; all of them are listed at once.
+///
+/// Call it after `load_config_file` (the file can name `*_FILE` variables too) and before any
+/// other thread starts.
+pub fn load_secret_files() -> Result<()> {
+    let mut secrets = Vec::new();
+    let mut problems = Vec::new();
+    for (name, path) in env::vars() {
+        let Some(var) = name.strip_suffix("_FILE").filter(|var| is_secret_var(var)) else {
+            continue;
+        };
+        if env::var_os(var).is_some() {
+            problems.push(format!("both {} and {} are set", var, name));
+            continue;
+        }
+        match fs::read_to_string(path.trim()) {
+            Ok(value) => secrets.push((var.to_string(), value.trim_end_matches(['\r', '\n']).to_string())),
+            Err(e) => problems.push(format!("{}: failed to read {}: {}", name, path.trim(), e)),
+        }
+    }
+    if !problems.is_empty() {
+        problems.sort();
+        return Err(anyhow!("Failed to load secrets:\n  {}", problems.join("\n  ")));
+    }
+
+    for (var, value) in secrets {
+        env::set_var(var, value);
+    }
+    Ok(())
+}
+
+/// `text` with the value of every secret variable replaced by `[REDACTED]`. The values are taken
+/// from the environment on the first call.
+pub fn redact_secrets(text: &str) -> Cow<'_, str> {
+    let secrets = SECRET_VALUES.get_or_init(|| {
+        let mut values: Vec<String> = env::vars()
+            .filter(|(name, value)| is_secret_var(name) && value.trim().len() >= MIN_REDACTED_LEN)
+            .map(|(_, value)| value.trim().to_string())
+            .collect();
+        // Longest first, so a secret containing another one is replaced whole
+        values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+        values.dedup();
+        values
+    });
+
+    let mut text = Cow::Borrowed(text);
+    for secret in secrets {
+        if text.contains(secret.as_str()) {
+            text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+        }
+    }
+    text
+}
+
+/// Log output with the secrets taken out, see `redact_secrets`. The log formatter hands over one
+/// whole line per write, so a secret is never split between two writes.
+pub(crate) struct Redacted<W: Write>(pub W);
+
+impl<W: Write> Write for Redacted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(text) => self.0.write_all(redact_secrets(text).as_bytes())?,
+            Err(_) => self.0.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(raw: &str) -> (HashMap<String, String>, Vec<String>) {
+        let table: Table = raw.parse().unwrap();
+        let mut settings = HashMap::new();
+        let mut problems = Vec::new();
+        collect_settings(&table, &mut Vec::new(), &mut settings, &mut problems);
+        problems.sort();
+        let settings = settings.into_iter().map(|(var, (_, value))| (var, value)).collect();
+        (settings, problems)
+    }
+
+    #[test]
+    fn keys_map_to_the_variables_their_path_spells() {
+        let (settings, problems) = collect(
+            r#"
+            log_level = "debug"
+            target_languages = ["en", "de"]
+
+            [publisher]
+            interval_secs = 30
+
+            [publish]
+            dry-run = true
+
+            [ai_provider.rewriter]
+            model = "gpt"
+            temperature = 0.5
+
+            [tg]
+            chat_id_de = "-100"
+            api_hash_file = "/run/secrets/hash"
+
+            [feed2]
+            source = "other"
+            "#,
+        );
+        assert!(problems.is_empty(), "{:?}", problems);
+        let expected = [
+            ("LOG_LEVEL", "debug"),
+            ("TARGET_LANGUAGES", "en,de"),
+            ("PUBLISHER_INTERVAL_SECS", "30"),
+            ("PUBLISH_DRY_RUN", "true"),
+            ("AI_PROVIDER_REWRITER_MODEL", "gpt"),
+            ("AI_PROVIDER_REWRITER_TEMPERATURE", "0.5"),
+            ("TG_CHAT_ID_DE", "-100"),
+            ("TG_API_HASH_FILE", "/run/secrets/hash"),
+            ("FEED2_SOURCE", "other"),
+        ];
+        assert_eq!(settings.len(), expected.len());
+        for (var, value) in expected {
+            assert_eq!(settings.get(var).map(String::as_str), Some(value), "{}", var);
+        }
+    }
+
+    #[test]
+    fn unknown_keys_wrong_types_and_duplicates_are_all_reported() {
+        let (settings, problems) = collect(
+            r#"
+            publisher_interval_secs = 10
+            interval = 5
+
+            [publisher]
+            interval_secs = 30
+            max_attempts = "many"
+
+            [tg]
+            link_preview_de = "maybe"
+            "#,
+        );
+        assert_eq!(
+            problems,
+            vec![
+                "interval: unknown setting (INTERVAL)",
+                "publisher.max_attempts: must be a non-negative integer, got 'many'",
+                "publisher_interval_secs: PUBLISHER_INTERVAL_SECS is already set by publisher.interval_secs",
+                "tg.link_preview_de: unknown setting (TG_LINK_PREVIEW_DE)",
+            ]
+        );
+        assert_eq!(settings.get("PUBLISHER_INTERVAL_SECS").map(String::as_str), Some("30"));
+    }
+
+    #[test]
+    fn values_are_checked_against_the_setting_kind() {
+        let (_, problems) = collect(
+            r#"
+            max_attempts = -1
+            post_footer_ratio = "half"
+            link_preview = "maybe"
+            target_languages = [["en"]]
+            "#,
+        );
+        assert_eq!(
+            problems,
+            vec![
+                "link_preview: must be true or false, got 'maybe'",
+                "max_attempts: must be a non-negative integer, got '-1'",
+                "post_footer_ratio: must be a number, got 'half'",
+                "target_languages: lists may only hold strings, numbers and booleans",
+            ]
+        );
+    }
+
+    #[test]
+    fn patterns_need_a_filled_in_placeholder() {
+        assert_eq!(setting_kind("LINK_PREVIEW_PT_BR"), Some(Kind::Bool));
+        assert_eq!(setting_kind("FEED12_SOURCE"), Some(Kind::Text));
+        assert_eq!(setting_kind("FEEDX_SOURCE"), None);
+        assert_eq!(setting_kind("TG_CHAT_ID_"), None);
+        assert_eq!(setting_kind("TG_CHAT_ID_de"), None);
+        assert_eq!(setting_kind("SMTP_PASSWORD_FILE"), Some(Kind::Text));
+        assert_eq!(setting_kind("SMTP_HOST_FILE"), None);
+    }
+}
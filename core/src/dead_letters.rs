@@ -0,0 +1,138 @@
+//! Alerts in an admin Telegram chat about items that ended in a `*_error` status, so failures
+//! don't wait for someone to look at the dashboard.
+
+use crate::{http_timeout, unix_now, with_immediate_transaction, Status};
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection};
+use std::env;
+
+const ALERT_BATCH: usize = 20;
+const ERROR_EXCERPT_CHARS: usize = 300;
+/// An alert a worker took but never confirmed (it stopped or the send failed) goes out again after this.
+const RESEND_AFTER_SECS: i64 = 300;
+
+/// ADMIN_BOT_TOKEN and ADMIN_CHAT_ID; alerts are off unless both are set.
+struct AdminChat {
+    bot_token: String,
+    chat_id: String,
+}
+
+struct DeadLetter {
+    id: i64,
+    news_id: String,
+    lang: Option<String>,
+    status: Status,
+    title: String,
+    error: Option<String>,
+}
+
+fn read_admin_chat_from_env() -> Option<AdminChat> {
+    let read = |var: &str| env::var(var).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+    Some(AdminChat {
+        bot_token: read("ADMIN_BOT_TOKEN")?,
+        chat_id: read("ADMIN_CHAT_ID")?,
+    })
+}
+
+/// Queues an alert about an item that has just entered the error status `status`, in the
+/// transaction that moved it. Nothing is queued while alerts are off.
+pub(crate) fn record_dead_letter(conn: &Connection, id: &str, lang: Option<&str>, status: &Status) -> Result<()> {
+    if read_admin_chat_from_env().is_none() {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO dead_letters (news_id, lang, status, created_at) VALUES (?, ?, ?, ?)",
+        params![id, lang, status, unix_now()],
+    )?;
+    Ok(())
+}
+
+/// Sends the queued alerts to ADMIN_CHAT_ID through the bot ADMIN_BOT_TOKEN names: item title,
+/// status, an excerpt of the error and the `ctl requeue` command that puts the item back.
+/// A sent alert is removed; one that failed is sent again by a later cycle.
+pub async fn send_dead_letter_alerts(conn: &Connection) -> Result<()> {
+    let Some(admin) = read_admin_chat_from_env() else {
+        return Ok(());
+    };
+
+    // Taken by this worker, so replicas of a stage don't send the same alert
+    let now = unix_now();
+    let alerts = with_immediate_transaction(conn, |conn| {
+        let alerts = conn
+            .prepare(
+                "SELECT d.id, d.news_id, d.lang, d.status, n.title, \
+                        CASE WHEN d.lang IS NULL THEN n.error ELSE t.error END \
+                 FROM dead_letters d \
+                 JOIN news n ON n.id = d.news_id \
+                 LEFT JOIN news_translations t ON t.news_id = d.news_id AND t.lang = d.lang \
+                 WHERE d.taken_at IS NULL OR d.taken_at < ? \
+                 ORDER BY d.id LIMIT ?",
+            )?
+            .query_map(params![now - RESEND_AFTER_SECS, ALERT_BATCH as i64], |row| {
+                Ok(DeadLetter {
+                    id: row.get(0)?,
+                    news_id: row.get(1)?,
+                    lang: row.get(2)?,
+                    status: row.get(3)?,
+                    title: row.get(4)?,
+                    error: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for alert in &alerts {
+            conn.execute("UPDATE dead_letters SET taken_at = ? WHERE id = ?", params![now, alert.id])?;
+        }
+        Ok(alerts)
+    })?;
+    if alerts.is_empty() {
+        return Ok(());
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(http_timeout()?)
+        .build()
+        .context("Failed to build the HTTP client")?;
+    let url = format!("https://api.telegram.org/bot{}/sendMessage", admin.bot_token);
+    for alert in alerts {
+        let body = serde_json::json!({
+            "chat_id": admin.chat_id,
+            "text": alert_text(&alert),
+            "disable_web_page_preview": true,
+        });
+        let response: serde_json::Value = client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to send a dead-letter alert")?
+            .json()
+            .await
+            .context("Failed to read the Bot API response")?;
+        if response["ok"].as_bool() != Some(true) {
+            return Err(anyhow!(
+                "Bot API refused a dead-letter alert: {}",
+                response["description"].as_str().unwrap_or("no description")
+            ));
+        }
+        conn.execute("DELETE FROM dead_letters WHERE id = ?", params![alert.id])?;
+    }
+    Ok(())
+}
+
+fn alert_text(alert: &DeadLetter) -> String {
+    let item = match &alert.lang {
+        Some(lang) => format!("{} (language: {})", alert.news_id, lang),
+        None => alert.news_id.clone(),
+    };
+    let error = alert.error.as_deref().map(str::trim).filter(|error| !error.is_empty()).unwrap_or("not recorded");
+    let excerpt: String = error.chars().take(ERROR_EXCERPT_CHARS).collect();
+    let ellipsis = if excerpt.len() < error.len() { "…" } else { "" };
+    let requeue = match &alert.lang {
+        Some(lang) => format!("ctl requeue {} --lang {}", alert.news_id, lang),
+        None => format!("ctl requeue {}", alert.news_id),
+    };
+    format!(
+        "Item {} ended in {}\n{}\n\nError: {}{}\n\nRequeue: {}",
+        item, alert.status, alert.title, excerpt, ellipsis, requeue
+    )
+}
@@ -0,0 +1,109 @@
+//! Terms from GLOSSARY_FILE that the translator and rewriter must keep as given.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+#[derive(Clone)]
+pub struct GlossaryTerm {
+    pub source: String,
+    pub target: String,
+}
+
+impl GlossaryTerm {
+    /// A term kept as is rather than translated.
+    pub fn is_kept(&self) -> bool {
+        self.source == self.target
+    }
+}
+
+/// Glossary terms per target language.
+#[derive(Clone, Default)]
+pub struct Glossary {
+    /// Terms above the first `[<lang>]` header
+    shared: Vec<GlossaryTerm>,
+    /// Terms under `[<lang>]`, by lowercased language code
+    languages: HashMap<String, Vec<GlossaryTerm>>,
+    /// First TARGET_LANGUAGES code, whose items have no `lang`
+    default_language: Option<String>,
+}
+
+impl Glossary {
+    /// Terms for items in `lang` (`None` for the default language). The default language gets
+    /// every shared term; extra languages only the shared terms kept as is, since a fixed
+    /// translation is written for one language. A language's own section comes on top and
+    /// overrides shared terms with the same source.
+    pub fn for_language(&self, lang: Option<&str>) -> Vec<GlossaryTerm> {
+        let code = lang.or(self.default_language.as_deref()).map(str::to_ascii_lowercase);
+        let own = code.as_ref().and_then(|code| self.languages.get(code)).map(Vec::as_slice).unwrap_or_default();
+
+        let mut terms: Vec<GlossaryTerm> = self
+            .shared
+            .iter()
+            .filter(|term| lang.is_none() || term.is_kept())
+            .filter(|term| !own.iter().any(|other| other.source.eq_ignore_ascii_case(&term.source)))
+            .cloned()
+            .collect();
+        terms.extend(own.iter().cloned());
+        terms
+    }
+}
+
+/// Reads GLOSSARY_FILE: one `source = target` pair per line (a bare term is kept as is),
+/// `#` comments and blank lines skipped. A `[<lang>]` line starts the terms of one target
+/// language; lines above the first one are shared. Empty when the variable is unset.
+pub fn read_glossary_from_env() -> Result<Glossary> {
+    let default_language = env::var("TARGET_LANGUAGES")
+        .ok()
+        .and_then(|raw| raw.split(',').next().map(|code| code.trim().to_ascii_lowercase()))
+        .filter(|code| !code.is_empty());
+    let path = match env::var("GLOSSARY_FILE") {
+        Ok(path) if !path.trim().is_empty() => path,
+        _ => {
+            return Ok(Glossary {
+                default_language,
+                ..Glossary::default()
+            })
+        }
+    };
+    let raw = fs::read_to_string(path.trim()).context(format!("Failed to read glossary file: {}", path))?;
+
+    let mut glossary = parse_glossary(&raw).map_err(|e| anyhow!("{}: {}", path, e))?;
+    glossary.default_language = default_language;
+    Ok(glossary)
+}
+
+fn parse_glossary(raw: &str) -> Result<Glossary> {
+    let mut glossary = Glossary::default();
+    let mut section: Option<String> = None;
+    for line in raw.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        if let Some(code) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            let code = code.trim().to_ascii_lowercase();
+            if code.is_empty() {
+                return Err(anyhow!("invalid glossary section '{}'", line));
+            }
+            glossary.languages.entry(code.clone()).or_default();
+            section = Some(code);
+            continue;
+        }
+
+        let (source, target) = match line.split_once('=') {
+            Some((source, target)) => (source.trim(), target.trim()),
+            None => (line, line),
+        };
+        if source.is_empty() || target.is_empty() {
+            return Err(anyhow!("invalid glossary line '{}'", line));
+        }
+        let term = GlossaryTerm {
+            source: source.to_string(),
+            target: target.to_string(),
+        };
+        match &section {
+            Some(code) => glossary.languages.entry(code.clone()).or_default().push(term),
+            None => glossary.shared.push(term),
+        }
+    }
+
+    Ok(glossary)
+}
@@ -0,0 +1,1423 @@
+//! Code shared by the pipeline services: the database location and row type,
+//! statuses and their transitions, pipeline wiring, configuration and logging.
+
+mod attempts;
+mod chat;
+mod chunks;
+mod config;
+mod dead_letters;
+mod glossary;
+mod provider_errors;
+mod s3;
+mod usage;
+mod workers;
+
+use anyhow::{anyhow, Context, Result};
+use rusqlite::types::{FromSql, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, ToSql, Transaction, TransactionBehavior};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::{stdout, ErrorKind, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Runtime;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tracing::{info, info_span, warn, Span};
+use tracing_subscriber::EnvFilter;
+
+pub use attempts::{read_attempt_policy_from_env, record_failed_attempt, AttemptPolicy};
+pub use chat::{
+    extract_html_response, gemini_reasoning_effort_from_reasoning, looks_like_html, parse_chat_body, parse_chat_response,
+    perplexity_reasoning_effort_from_reasoning, read_reasoning_config_from_env, read_sampling_config_from_env,
+    GeminiChatRequest, Message, OpenRouterChatRequest, PerplexityChatRequest, ReasoningConfig, SamplingConfig,
+};
+pub use chunks::{count_tokens, effective_chunk_chars, process_in_chunks};
+pub use config::{load_config_file, load_secret_files, redact_secrets};
+use config::Redacted;
+pub use dead_letters::send_dead_letter_alerts;
+use dead_letters::record_dead_letter;
+pub use glossary::{read_glossary_from_env, Glossary, GlossaryTerm};
+pub use provider_errors::{
+    classify_provider_failure, classify_request_failure, clear_provider_error, parse_provider_error, record_provider_error,
+    ApiError, ProviderError, ProviderFailure,
+};
+pub use s3::S3Artifacts;
+pub use usage::{
+    add_usage, check_daily_budget, read_daily_budget_from_env, read_price_from_env, record_image_usage, record_usage,
+    take_usage, track_usage, DailyBudget, UsageModel,
+};
+pub use workers::{
+    read_concurrency_from_env, run_workers, Concurrency, ConcurrencyTuner, ConnectionPool, PooledConnection,
+    WORKER_CONNECTIONS,
+};
+
+pub const DB_PATH: &str = "data/news.db";
+const ARTIFACT_DIR: &str = "data";
+const DEFAULT_BUSY_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_CLAIM_TIMEOUT_SECS: u64 = 1800;
+const MIN_HEARTBEAT_AGE_SECS: u64 = 900;
+const DEFAULT_WAKEUP_POLL_SECS: u64 = 1;
+const DEFAULT_HTTP_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_LOG_LEVEL: &str = "info";
+/// `prev_hash` of the first record in the publisher's `publish_chain` table.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Value of `news.status` and `news_translations.status`.
+///
+/// The built-in stages and their side statuses have their own variants; statuses that only a
+/// PIPELINE_CONFIG file names are kept as `Other`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Status {
+    New,
+    Downloaded,
+    /// Scraped, the scraper's output.
+    Scraper,
+    ScraperRetry,
+    ScraperError,
+    Translated,
+    TranslatorRetry,
+    /// Translation came out too long and is retried with a shortening prompt.
+    TranslatorLength,
+    TranslatorError,
+    /// Rewritten, the rewriter's output.
+    Rewriter,
+    RewriterRetry,
+    RewriterError,
+    /// Waits for an operator after the rewriter's fact check.
+    Review,
+    /// Illustrated (or skipped by the illustrator), the illustrator's output.
+    Illustrator,
+    IllustratorRetry,
+    IllustratorError,
+    IllustratorReview,
+    ReadyToPublish,
+    PendingApproval,
+    ModerationHold,
+    Rejected,
+    PublisherRetry,
+    PublishError,
+    PublishDuplicate,
+    Published,
+    PublishedPlain,
+    PublishedDryrun,
+    Updated,
+    Other(String),
+}
+
+impl Status {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Status::New => "new",
+            Status::Downloaded => "downloaded",
+            Status::Scraper => "scraper",
+            Status::ScraperRetry => "scraper_retry",
+            Status::ScraperError => "scraper_error",
+            Status::Translated => "translated",
+            Status::TranslatorRetry => "translator_retry",
+            Status::TranslatorLength => "translator_length",
+            Status::TranslatorError => "translator_error",
+            Status::Rewriter => "rewriter",
+            Status::RewriterRetry => "rewriter_retry",
+            Status::RewriterError => "rewriter_error",
+            Status::Review => "review",
+            Status::Illustrator => "illustrator",
+            Status::IllustratorRetry => "illustrator_retry",
+            Status::IllustratorError => "illustrator_error",
+            Status::IllustratorReview => "illustrator_review",
+            Status::ReadyToPublish => "ready_to_publish",
+            Status::PendingApproval => "pending_approval",
+            Status::ModerationHold => "moderation_hold",
+            Status::Rejected => "rejected",
+            Status::PublisherRetry => "publisher_retry",
+            Status::PublishError => "publish_error",
+            Status::PublishDuplicate => "publish_duplicate",
+            Status::Published => "published",
+            Status::PublishedPlain => "published_plain",
+            Status::PublishedDryrun => "published_dryrun",
+            Status::Updated => "updated",
+            Status::Other(status) => status,
+        }
+    }
+
+    /// No worker moves an item out of these; only an operator (`ctl`) can.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            Status::ScraperError
+                | Status::TranslatorError
+                | Status::RewriterError
+                | Status::IllustratorError
+                | Status::Rejected
+                | Status::PublishError
+                | Status::PublishDuplicate
+                | Status::Published
+                | Status::PublishedPlain
+                | Status::PublishedDryrun
+                | Status::Updated
+        )
+    }
+
+    /// Final status of an item that failed a stage, also of a stage only PIPELINE_CONFIG names.
+    pub fn is_error(&self) -> bool {
+        self.as_str().ends_with("_error")
+    }
+
+    fn is_retry(&self) -> bool {
+        matches!(
+            self,
+            Status::ScraperRetry
+                | Status::TranslatorRetry
+                | Status::RewriterRetry
+                | Status::IllustratorRetry
+                | Status::PublisherRetry
+        )
+    }
+
+    /// Whether a worker may move an item from this status to `next`.
+    ///
+    /// The stage order itself comes from PIPELINE_CONFIG, so any forward move is allowed; what is
+    /// refused is leaving a final status, going back to `new` and staying put outside a retry.
+    pub fn can_transition_to(&self, next: &Status) -> bool {
+        if self.is_final() || *next == Status::New {
+            return false;
+        }
+        self != next || self.is_retry()
+    }
+}
+
+impl From<&str> for Status {
+    fn from(status: &str) -> Self {
+        match status {
+            "new" => Status::New,
+            "downloaded" => Status::Downloaded,
+            "scraper" => Status::Scraper,
+            "scraper_retry" => Status::ScraperRetry,
+            "scraper_error" => Status::ScraperError,
+            "translated" => Status::Translated,
+            "translator_retry" => Status::TranslatorRetry,
+            "translator_length" => Status::TranslatorLength,
+            "translator_error" => Status::TranslatorError,
+            "rewriter" => Status::Rewriter,
+            "rewriter_retry" => Status::RewriterRetry,
+            "rewriter_error" => Status::RewriterError,
+            "review" => Status::Review,
+            "illustrator" => Status::Illustrator,
+            "illustrator_retry" => Status::IllustratorRetry,
+            "illustrator_error" => Status::IllustratorError,
+            "illustrator_review" => Status::IllustratorReview,
+            "ready_to_publish" => Status::ReadyToPublish,
+            "pending_approval" => Status::PendingApproval,
+            "moderation_hold" => Status::ModerationHold,
+            "rejected" => Status::Rejected,
+            "publisher_retry" => Status::PublisherRetry,
+            "publish_error" => Status::PublishError,
+            "publish_duplicate" => Status::PublishDuplicate,
+            "published" => Status::Published,
+            "published_plain" => Status::PublishedPlain,
+            "published_dryrun" => Status::PublishedDryrun,
+            "updated" => Status::Updated,
+            other => Status::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ToSql for Status {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for Status {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().map(Status::from)
+    }
+}
+
+/// A row of the `news` table as the services select it.
+#[derive(Debug, Clone)]
+pub struct NewsItem {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub date: String,
+    pub status: Status,
+}
+
+/// Id of the item of an article: the hex SHA-256 of its URL, so a feed listing the article
+/// again doesn't add it twice.
+pub fn news_id(url: &str) -> String {
+    hex::encode(Sha256::digest(url.as_bytes()))
+}
+
+/// Input/output wiring of one pipeline stage.
+///
+/// Loaded from the file referenced by `PIPELINE_CONFIG`, where every non-comment line reads
+/// `<stage> <input_status> <output_status> <input_artifact> <output_artifact>` (`-` = none).
+/// Without that file the built-in stage order is used.
+#[derive(Debug, Clone)]
+pub struct PipelineStage {
+    pub input_status: Status,
+    pub output_status: Status,
+    pub input_artifact: String,
+    pub output_artifact: String,
+}
+
+pub fn read_pipeline_config() -> Result<Option<Vec<(String, PipelineStage)>>> {
+    let path = match env::var("PIPELINE_CONFIG") {
+        Ok(path) if !path.trim().is_empty() => path.trim().to_string(),
+        _ => return Ok(None),
+    };
+
+    let raw = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read pipeline config: {}", path))?;
+
+    let mut stages = Vec::new();
+    for (index, line) in raw.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow!(
+                "{}:{}: expected '<stage> <input_status> <output_status> <input_artifact> <output_artifact>', got '{}'",
+                path,
+                index + 1,
+                line
+            ));
+        }
+
+        stages.push((
+            fields[0].to_string(),
+            PipelineStage {
+                input_status: Status::from(fields[1]),
+                output_status: Status::from(fields[2]),
+                input_artifact: fields[3].to_string(),
+                output_artifact: fields[4].to_string(),
+            },
+        ));
+    }
+
+    Ok(Some(stages))
+}
+
+/// The wiring of stage `name` from PIPELINE_CONFIG, or `default` without that file.
+pub fn load_pipeline_stage(name: &str, default: PipelineStage) -> Result<PipelineStage> {
+    let Some(stages) = read_pipeline_config()? else {
+        return Ok(default);
+    };
+
+    stages
+        .into_iter()
+        .find(|(stage, _)| stage == name)
+        .map(|(_, stage)| stage)
+        .ok_or_else(|| anyhow!("Stage '{}' is not defined in PIPELINE_CONFIG", name))
+}
+
+/// The wiring of a built-in stage, for the tools that act on several stages (`ctl`, the
+/// dashboard): its PIPELINE_CONFIG line, or the built-in stage order. Any other name is the publisher.
+pub fn builtin_stage(name: &str) -> Result<PipelineStage> {
+    let (input_status, output_status, input_artifact, output_artifact) = match name {
+        "scraper" => ("downloaded", "scraper", "news", "scraper"),
+        "translator" => ("scraper", "translated", "scraper", "translator"),
+        "rewriter" => ("translated", "rewriter", "translator", "rewriter"),
+        "illustrator" => ("rewriter", "illustrator", "rewriter", "illustrator"),
+        _ => ("illustrator", "published", "rewriter", "publisher"),
+    };
+    load_pipeline_stage(
+        name,
+        PipelineStage {
+            input_status: Status::from(input_status),
+            output_status: Status::from(output_status),
+            input_artifact: input_artifact.to_string(),
+            output_artifact: output_artifact.to_string(),
+        },
+    )
+}
+
+/// The status `stage` takes items from. Translations are not illustrated: they go from the
+/// rewriter straight to the publisher.
+pub fn stage_input_status(stage: &str, translation: bool) -> Result<Status> {
+    if stage == "publisher" && translation {
+        return Ok(builtin_stage("rewriter")?.output_status);
+    }
+    Ok(builtin_stage(stage)?.input_status)
+}
+
+/// Columns of `news` a stage fills in besides the status.
+#[derive(Debug, Clone, Copy)]
+pub enum NewsField {
+    LeadImageUrl,
+    Lang,
+    /// Set by the parser; read by stages with per-feed settings.
+    Source,
+}
+
+impl NewsField {
+    pub fn column(self) -> &'static str {
+        match self {
+            NewsField::LeadImageUrl => "lead_image_url",
+            NewsField::Lang => "lang",
+            NewsField::Source => "source",
+        }
+    }
+}
+
+/// Whether the feed item `id` is already stored.
+pub fn news_exists(conn: &Connection, id: &str) -> Result<bool> {
+    Ok(conn.prepare("SELECT 1 FROM news WHERE id = ? LIMIT 1")?.exists(params![id])?)
+}
+
+/// Stores a new item from the feed `source` and wakes the stage reading its status.
+pub fn insert_news(conn: &Connection, item: &NewsItem, source: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO news (id, title, url, date, status, source) VALUES (?, ?, ?, ?, ?, ?)",
+        params![item.id, item.title, item.url, item.date, item.status, source],
+    )?;
+    signal_wakeup(conn, &item.status)
+}
+
+pub fn news_field(conn: &Connection, id: &str, field: NewsField) -> Result<Option<String>> {
+    Ok(conn
+        .query_row(&format!("SELECT {} FROM news WHERE id = ?", field.column()), params![id], |row| row.get(0))
+        .optional()?
+        .flatten())
+}
+
+pub fn set_news_field(conn: &Connection, id: &str, field: NewsField, value: Option<&str>) -> Result<()> {
+    conn.execute(&format!("UPDATE news SET {} = ? WHERE id = ?", field.column()), params![value, id])?;
+    Ok(())
+}
+
+/// Where the files the stages hand to each other live, named as in `data/`
+/// (`scraper_<id>.html`, `illustrator_<id>.png`, ...).
+///
+/// One store serves every thread of the process at once, so implementations keep whatever they
+/// can't share (a database connection) per thread.
+pub trait ArtifactStore: Send + Sync {
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>>;
+    fn write(&self, name: &str, content: &[u8]) -> Result<()>;
+    fn remove(&self, name: &str) -> Result<()>;
+    /// Names of all artifacts in the store.
+    fn list(&self) -> Result<Vec<String>>;
+
+    /// A URL readers outside the pipeline can fetch the artifact from directly, or None when the
+    /// store has none and the artifact has to be served or copied.
+    fn url(&self, _name: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Artifacts as files in `data/`, for services that share a file system.
+pub struct FileArtifacts;
+
+impl ArtifactStore for FileArtifacts {
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let path = Path::new(ARTIFACT_DIR).join(name);
+        match fs::read(&path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context(format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn write(&self, name: &str, content: &[u8]) -> Result<()> {
+        let path = Path::new(ARTIFACT_DIR).join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+        }
+        fs::write(&path, content).context(format!("Failed to write {}", path.display()))
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        let path = Path::new(ARTIFACT_DIR).join(name);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e).context(format!("Failed to delete {}", path.display())),
+            _ => Ok(()),
+        }
+    }
+
+    /// The files directly in `data/`; subdirectories such as `ai_log/` hold no artifacts.
+    fn list(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let entries = match fs::read_dir(ARTIFACT_DIR) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e).context(format!("Failed to read {}", ARTIFACT_DIR)),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                if let Some(name) = entry.file_name().to_str() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Ok(names)
+    }
+}
+
+/// Artifacts in the `artifacts` table of `data/news.db`, on a connection of the calling thread.
+pub struct DatabaseArtifacts;
+
+thread_local! {
+    /// The connection `DatabaseArtifacts` uses on this thread, opened on first use.
+    static ARTIFACT_CONNECTION: RefCell<Option<Connection>> = const { RefCell::new(None) };
+}
+
+impl DatabaseArtifacts {
+    fn with_connection<T>(&self, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        let conn = match ARTIFACT_CONNECTION.with(|conn| conn.borrow_mut().take()) {
+            Some(conn) => conn,
+            None => open_db()?,
+        };
+        let result = f(&conn);
+        ARTIFACT_CONNECTION.with(|slot| *slot.borrow_mut() = Some(conn));
+        result
+    }
+}
+
+impl ArtifactStore for DatabaseArtifacts {
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        self.with_connection(|conn| {
+            Ok(conn
+                .query_row("SELECT content FROM artifacts WHERE name = ?", params![name], |row| row.get(0))
+                .optional()?)
+        })
+    }
+
+    fn write(&self, name: &str, content: &[u8]) -> Result<()> {
+        self.with_connection(|conn| {
+            conn.execute(
+                "INSERT INTO artifacts (name, content, updated_at) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT(name) DO UPDATE SET content = ?2, updated_at = ?3",
+                params![name, content, unix_now()],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        self.with_connection(|conn| {
+            conn.execute("DELETE FROM artifacts WHERE name = ?", params![name])?;
+            Ok(())
+        })
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        self.with_connection(|conn| {
+            let mut stmt = conn.prepare("SELECT name FROM artifacts")?;
+            let names = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(names)
+        })
+    }
+}
+
+/// Opens the artifact store ARTIFACT_STORE names: `files` (the default) for `data/`,
+/// `database` for the `artifacts` table of `data/news.db`, or `s3` for a bucket
+/// (see `S3Artifacts`).
+pub fn open_artifact_store() -> Result<Box<dyn ArtifactStore>> {
+    match env::var("ARTIFACT_STORE").unwrap_or_default().trim() {
+        "" | "files" => Ok(Box::new(FileArtifacts)),
+        "database" => Ok(Box::new(DatabaseArtifacts)),
+        "s3" => Ok(Box::new(S3Artifacts::from_env()?)),
+        other => Err(anyhow!("ARTIFACT_STORE must be 'files', 'database' or 's3', got '{}'", other)),
+    }
+}
+
+/// The process-wide artifact store, opened on first use and used by all threads at once.
+fn artifacts() -> Result<&'static dyn ArtifactStore> {
+    static ARTIFACTS: OnceLock<Box<dyn ArtifactStore>> = OnceLock::new();
+    if ARTIFACTS.get().is_none() {
+        let store = open_artifact_store()?;
+        let _ = ARTIFACTS.set(store);
+    }
+    let store = ARTIFACTS.get().ok_or_else(|| anyhow!("Artifact store is not open"))?;
+    Ok(store.as_ref())
+}
+
+/// Reads an artifact, `None` if it doesn't exist.
+pub fn load_artifact(name: &str) -> Result<Option<Vec<u8>>> {
+    artifacts()?.read(name)
+}
+
+/// Reads an artifact that must exist.
+pub fn read_artifact(name: &str) -> Result<Vec<u8>> {
+    load_artifact(name)?.ok_or_else(|| anyhow!("Artifact {} does not exist", name))
+}
+
+pub fn read_artifact_string(name: &str) -> Result<String> {
+    String::from_utf8(read_artifact(name)?).context(format!("Artifact {} is not valid UTF-8", name))
+}
+
+pub fn artifact_exists(name: &str) -> Result<bool> {
+    Ok(load_artifact(name)?.is_some())
+}
+
+pub fn write_artifact(name: &str, content: impl AsRef<[u8]>) -> Result<()> {
+    artifacts()?.write(name, content.as_ref())
+}
+
+pub fn remove_artifact(name: &str) -> Result<()> {
+    artifacts()?.remove(name)
+}
+
+/// Names of all artifacts in the store ARTIFACT_STORE names.
+pub fn list_artifacts() -> Result<Vec<String>> {
+    artifacts()?.list()
+}
+
+/// A direct link to an artifact, with ARTIFACT_STORE=s3 a presigned URL (see `ArtifactStore::url`).
+pub fn artifact_url(name: &str) -> Result<Option<String>> {
+    artifacts()?.url(name)
+}
+
+/// Opens `data/news.db` and brings the shared schema up to date (see `MIGRATIONS`).
+/// The services create their own tables after this.
+pub fn open_db() -> Result<Connection> {
+    let mut conn = Connection::open(DB_PATH).context("Failed to open database connection")?;
+    // Every worker writes to the same file: WAL lets readers run next to a writer, and the busy
+    // timeout makes a writer wait for the lock instead of failing with "database is locked".
+    set_busy_timeout(&conn)?;
+    let journal_mode: String = conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+    if !journal_mode.eq_ignore_ascii_case("wal") {
+        warn!("Database journal mode is '{}', WAL could not be enabled", journal_mode);
+    }
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    migrate(&mut conn)?;
+    Ok(conn)
+}
+
+/// Opens `data/news.db` without write access, for the pages and queries of the read-side
+/// services. They call `open_db` once at startup, which brings the schema up to date.
+pub fn open_db_read_only() -> Result<Connection> {
+    let conn = Connection::open_with_flags(DB_PATH, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .context("Failed to open database connection")?;
+    set_busy_timeout(&conn)?;
+    Ok(conn)
+}
+
+/// DB_BUSY_TIMEOUT_SECS (default 30).
+fn set_busy_timeout(conn: &Connection) -> Result<()> {
+    let busy_timeout = read_interval_from_env("DB_BUSY_TIMEOUT_SECS", DEFAULT_BUSY_TIMEOUT_SECS)?;
+    conn.busy_timeout(Duration::from_secs(busy_timeout))?;
+    Ok(())
+}
+
+type MigrationStep = fn(&Connection) -> Result<()>;
+
+/// Schema changes of the `news` and `news_translations` tables, in order. `PRAGMA user_version`
+/// holds how many of them a database has; only append new ones, never edit or reorder them.
+///
+/// Databases from before the migrations already have some of these columns, so a step adds
+/// a column only if it is missing.
+const MIGRATIONS: &[(&str, MigrationStep)] = &[
+    ("create news and news_translations", |conn| {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS news (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                url TEXT NOT NULL,
+                date TEXT NOT NULL,
+                status TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS news_translations (
+                news_id TEXT NOT NULL,
+                lang TEXT NOT NULL,
+                status TEXT NOT NULL,
+                PRIMARY KEY (news_id, lang)
+            );",
+        )?;
+        Ok(())
+    }),
+    // Feed the item came from, set by the parser (per-source prompts and templates)
+    ("add news.source", |conn| ensure_column(conn, "news", "source", "TEXT").map(drop)),
+    // Lead image of the article page, set by the downloader
+    ("add news.lead_image_url", |conn| ensure_column(conn, "news", "lead_image_url", "TEXT").map(drop)),
+    // Source language detected by the scraper
+    ("add news.lang", |conn| ensure_column(conn, "news", "lang", "TEXT").map(drop)),
+    // Category assigned by the rewriter's classifier
+    ("add news.category", |conn| ensure_column(conn, "news", "category", "TEXT").map(drop)),
+    // Set by the illustrator for items it publishes without an image
+    ("add news.illustration_skipped", |conn| {
+        ensure_column(conn, "news", "illustration_skipped", "TEXT").map(drop)
+    }),
+    // Last AI provider error of the item
+    ("add news provider error columns", |conn| {
+        for (column, column_type) in [
+            ("error_stage", "TEXT"),
+            ("error_http_status", "INTEGER"),
+            ("error_code", "TEXT"),
+            ("error_message", "TEXT"),
+            ("error_retryable", "INTEGER"),
+        ] {
+            ensure_column(conn, "news", column, column_type)?;
+        }
+        Ok(())
+    }),
+    // Failed attempts and when the next one is due (rewriter, illustrator, publisher)
+    ("add attempts and next_attempt_at", |conn| {
+        for table in ["news", "news_translations"] {
+            if ensure_column(conn, table, "attempts", "INTEGER NOT NULL DEFAULT 0")? {
+                // Items already waiting for their retry have used one attempt under the old two-strike rule
+                conn.execute(
+                    &format!("UPDATE {} SET attempts = 1 WHERE status IN ('rewriter_retry', 'illustrator_retry')", table),
+                    [],
+                )?;
+            }
+            ensure_column(conn, table, "next_attempt_at", "INTEGER")?;
+        }
+        Ok(())
+    }),
+    // Why the last attempt of the item failed and when a worker last tried it (every worker)
+    ("add error and last_attempt_at", |conn| {
+        for table in ["news", "news_translations"] {
+            ensure_column(conn, table, "error", "TEXT")?;
+            ensure_column(conn, table, "last_attempt_at", "INTEGER")?;
+        }
+        Ok(())
+    }),
+    // When the row was inserted and last changed, kept up to date by triggers (stale items, throughput)
+    ("add created_at and updated_at", |conn| {
+        for table in ["news", "news_translations"] {
+            let created = ensure_column(conn, table, "created_at", "INTEGER")?;
+            ensure_column(conn, table, "updated_at", "INTEGER")?;
+            if created {
+                // The real insert time of older rows is unknown; they start from the upgrade
+                conn.execute(
+                    &format!("UPDATE {} SET created_at = ?1, updated_at = ?1", table),
+                    params![unix_now()],
+                )?;
+            }
+            // SQLite can't add a column with a non-constant default, so triggers fill both in.
+            // The WHEN clauses let a writer set the values itself and keep the triggers from firing each other.
+            conn.execute_batch(&format!(
+                "CREATE TRIGGER IF NOT EXISTS {0}_created_at AFTER INSERT ON {0}
+                 WHEN NEW.created_at IS NULL
+                 BEGIN
+                     UPDATE {0} SET created_at = CAST(strftime('%s', 'now') AS INTEGER),
+                         updated_at = COALESCE(NEW.updated_at, CAST(strftime('%s', 'now') AS INTEGER))
+                     WHERE rowid = NEW.rowid;
+                 END;
+                 CREATE TRIGGER IF NOT EXISTS {0}_updated_at AFTER UPDATE ON {0}
+                 WHEN NEW.updated_at IS OLD.updated_at
+                 BEGIN
+                     UPDATE {0} SET updated_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE rowid = NEW.rowid;
+                 END;",
+                table
+            ))?;
+        }
+        Ok(())
+    }),
+    // Which worker is processing the item and since when, so replicas of a service don't take the same item
+    ("add claimed_by and claimed_at", |conn| {
+        for table in ["news", "news_translations"] {
+            ensure_column(conn, table, "claimed_by", "TEXT")?;
+            ensure_column(conn, table, "claimed_at", "INTEGER")?;
+        }
+        Ok(())
+    }),
+    // Stage artifacts, when ARTIFACT_STORE=database keeps them here instead of in data/
+    ("create artifacts", |conn| {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS artifacts (
+                name TEXT PRIMARY KEY,
+                content BLOB NOT NULL,
+                updated_at INTEGER NOT NULL
+            );",
+        )?;
+        Ok(())
+    }),
+    // Last cycle of every worker, for `<service> healthcheck` and the dashboard
+    ("create worker_health", |conn| {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS worker_health (
+                worker_id TEXT PRIMARY KEY,
+                service TEXT NOT NULL,
+                host TEXT NOT NULL,
+                interval_secs INTEGER NOT NULL,
+                beat_at INTEGER NOT NULL
+            );",
+        )?;
+        Ok(())
+    }),
+    // How many items entered each status, so the stage reading it can wake up (`wait_for_wakeup`)
+    ("create wakeups", |conn| {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS wakeups (
+                status TEXT PRIMARY KEY,
+                seq INTEGER NOT NULL
+            );",
+        )?;
+        Ok(())
+    }),
+    // Alerts about items that ended in a `*_error` status, until they reach the admin chat
+    ("create dead_letters", |conn| {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS dead_letters (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                news_id TEXT NOT NULL,
+                lang TEXT,
+                status TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                taken_at INTEGER
+            );",
+        )?;
+        Ok(())
+    }),
+    // Tokens and estimated cost of every AI call (translator, rewriter, illustrator; `ctl usage`)
+    ("create ai_usage", |conn| {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS ai_usage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                news_id TEXT NOT NULL,
+                lang TEXT,
+                stage TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                model TEXT NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                cost_usd REAL NOT NULL,
+                created_at INTEGER NOT NULL
+            );",
+        )?;
+        Ok(())
+    }),
+    // Set when an operator sends the item back (`set_item_status`), so its next rewrite skips `rewriter_cache`
+    ("add skip_cache", |conn| {
+        for table in ["news", "news_translations"] {
+            ensure_column(conn, table, "skip_cache", "INTEGER NOT NULL DEFAULT 0")?;
+        }
+        Ok(())
+    }),
+];
+
+/// Applies the migrations the database doesn't have yet, each in its own transaction.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    for (version, (name, step)) in MIGRATIONS.iter().enumerate() {
+        let version = version as i64 + 1;
+        // Immediate, so two services starting together don't both apply the same step
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let current: i64 = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if current >= version {
+            continue;
+        }
+        step(&tx).with_context(|| format!("Migration {} ({}) failed", version, name))?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+        info!("Applied database migration {}: {}", version, name);
+    }
+    Ok(())
+}
+
+/// Adds `column` to `table` unless it is already there; returns whether it was added.
+pub fn ensure_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if !columns.iter().any(|name| name == column) {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])
+            .with_context(|| format!("Failed to add {} column to {} table", column, table))?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Items in `status`, oldest first.
+pub fn fetch_items_in(conn: &Connection, status: &Status) -> Result<Vec<NewsItem>> {
+    let mut stmt = conn.prepare("SELECT id, title, url, date, status FROM news WHERE status = ? ORDER BY date ASC")?;
+    let items = stmt
+        .query_map(params![status], news_item_from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(items)
+}
+
+/// Items in `status`, and items in `retry_status` whose `next_attempt_at` has passed, oldest first.
+pub fn fetch_items_due(conn: &Connection, status: &Status, retry_status: &Status) -> Result<Vec<NewsItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, url, date, status FROM news \
+         WHERE status = ? OR (status = ? AND COALESCE(next_attempt_at, 0) <= ?) ORDER BY date ASC",
+    )?;
+    let items = stmt
+        .query_map(params![status, retry_status, unix_now()], news_item_from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(items)
+}
+
+fn news_item_from_row(row: &rusqlite::Row) -> rusqlite::Result<NewsItem> {
+    Ok(NewsItem {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        url: row.get(2)?,
+        date: row.get(3)?,
+        status: row.get(4)?,
+    })
+}
+
+/// Moves a `news` row, or its `news_translations` row when `lang` is given, from `from` to `to`.
+///
+/// Fails on a transition `Status::can_transition_to` refuses. Returns `false` without changing
+/// anything when the row is no longer in `from`, e.g. because an operator moved it meanwhile.
+pub fn transition(conn: &Connection, id: &str, lang: Option<&str>, from: &Status, to: &Status) -> Result<bool> {
+    if !from.can_transition_to(to) {
+        return Err(anyhow!("Item {} cannot go from status '{}' to '{}'", id, from, to));
+    }
+    let updated = with_immediate_transaction(conn, |conn| {
+        let updated = match lang {
+            Some(lang) => conn.execute(
+                "UPDATE news_translations SET status = ?, claimed_by = NULL, claimed_at = NULL \
+                 WHERE news_id = ? AND lang = ? AND status = ?",
+                params![to, id, lang, from],
+            )?,
+            None => conn.execute(
+                "UPDATE news SET status = ?, claimed_by = NULL, claimed_at = NULL WHERE id = ? AND status = ?",
+                params![to, id, from],
+            )?,
+        };
+        if updated > 0 {
+            signal_wakeup(conn, to)?;
+            if to.is_error() {
+                record_dead_letter(conn, id, lang, to)?;
+            }
+        }
+        Ok(updated)
+    })?;
+    if updated == 0 {
+        log_status_moved(id, lang, from);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// `transition` for the stage that processed the item: logs the new status, and leaves the item
+/// alone when it was moved meanwhile.
+pub fn update_status(conn: &Connection, id: &str, lang: Option<&str>, from: &Status, status: &Status) -> Result<()> {
+    if transition(conn, id, lang, from, status)? {
+        info!("Updated status to '{}' for id '{}' (language: {})", status, id, lang.unwrap_or("default"));
+    }
+    Ok(())
+}
+
+/// Current status of an item, or of its translation into `lang`.
+pub fn item_status(conn: &Connection, id: &str, lang: Option<&str>) -> Result<Status> {
+    let status = match lang {
+        Some(lang) => conn
+            .query_row(
+                "SELECT status FROM news_translations WHERE news_id = ?1 AND lang = ?2",
+                params![id, lang],
+                |row| row.get(0),
+            )
+            .optional()?,
+        None => conn
+            .query_row("SELECT status FROM news WHERE id = ?1", params![id], |row| row.get(0))
+            .optional()?,
+    };
+    status.ok_or_else(|| match lang {
+        Some(lang) => anyhow!("Item {} has no {} translation", id, lang),
+        None => anyhow!("No item with id {}", id),
+    })
+}
+
+/// Puts an item into `status` on an operator's behalf (`ctl`, the dashboard), whatever status it
+/// is in: its retry state and claim are cleared, its next rewrite skips the rewriter's cache, and
+/// the stage reading `status` wakes up instead of leaving the item to its next cycle.
+pub fn set_item_status(conn: &Connection, id: &str, lang: Option<&str>, status: &Status) -> Result<()> {
+    match lang {
+        Some(lang) => conn.execute(
+            "UPDATE news_translations SET status = ?1, attempts = 0, next_attempt_at = NULL, error = NULL,
+                 claimed_by = NULL, claimed_at = NULL, skip_cache = 1
+             WHERE news_id = ?2 AND lang = ?3",
+            params![status, id, lang],
+        )?,
+        None => conn.execute(
+            "UPDATE news SET status = ?1, attempts = 0, next_attempt_at = NULL, error = NULL,
+                 claimed_by = NULL, claimed_at = NULL, skip_cache = 1
+             WHERE id = ?2",
+            params![status, id],
+        )?,
+    };
+    signal_wakeup(conn, status)
+}
+
+/// Counts an item entering `status`, which wakes the stages waiting for it in `wait_for_wakeup`.
+fn signal_wakeup(conn: &Connection, status: &Status) -> Result<()> {
+    conn.execute(
+        "INSERT INTO wakeups (status, seq) VALUES (?1, 1) ON CONFLICT(status) DO UPDATE SET seq = seq + 1",
+        params![status],
+    )?;
+    Ok(())
+}
+
+/// Sum of the wakeup counters of `statuses`; it changes whenever an item enters one of them.
+pub fn wakeup_seq(conn: &Connection, statuses: &[&Status]) -> Result<i64> {
+    let mut seq = 0;
+    for status in statuses {
+        seq += conn
+            .query_row("SELECT seq FROM wakeups WHERE status = ?", params![status], |row| row.get::<_, i64>(0))
+            .optional()?
+            .unwrap_or(0);
+    }
+    Ok(seq)
+}
+
+/// How often a sleeping stage checks for new work: WAKEUP_POLL_SECS (default 1).
+pub fn wakeup_poll_interval() -> Result<Duration> {
+    Ok(Duration::from_secs(read_interval_from_env("WAKEUP_POLL_SECS", DEFAULT_WAKEUP_POLL_SECS)?))
+}
+
+/// Sleeps between two cycles of a stage: `interval_secs` at most, but only until `seq` (see
+/// `wakeup_seq`) differs from `seen`, read before the cycle, because an upstream stage handed
+/// over an item meanwhile, or until the process is asked to stop.
+pub fn wait_for_wakeup(interval_secs: u64, seen: i64, seq: impl FnMut() -> Result<i64>) -> Result<()> {
+    if run_async(wait_for_change(interval_secs, seen, seq))? {
+        info!("New items arrived, waking up");
+    }
+    Ok(())
+}
+
+/// The wait of `wait_for_wakeup` for any counter: returns true once `seq` differs from `seen`,
+/// false when `interval_secs` passed or the process is asked to stop first.
+pub(crate) async fn wait_for_change(interval_secs: u64, seen: i64, mut seq: impl FnMut() -> Result<i64>) -> Result<bool> {
+    let poll = wakeup_poll_interval()?;
+    let deadline = Instant::now() + Duration::from_secs(interval_secs);
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(false);
+        }
+        if pause(poll.min(deadline - now)).await.is_err() {
+            return Ok(false);
+        }
+        match seq() {
+            Ok(current) if current != seen => return Ok(true),
+            Ok(_) => {}
+            // Sleeping on is the old behaviour, so a failed check only costs latency
+            Err(e) => warn!("Failed to check for new items: {}", e),
+        }
+    }
+}
+
+/// Error of work cut short because the process is asked to stop (see `listen_for_shutdown`).
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled by shutdown")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+fn shutdown_sender() -> &'static watch::Sender<bool> {
+    static SHUTDOWN: OnceLock<watch::Sender<bool>> = OnceLock::new();
+    SHUTDOWN.get_or_init(|| watch::channel(false).0)
+}
+
+/// Handles SIGTERM (`docker stop`) and Ctrl-C from now on: `shutdown_requested` turns true,
+/// sleeping stages wake up and requests in flight (`block_on`) end with `Cancelled`, so the
+/// stages can stop after the current item. A second signal exits at once. Calling it again
+/// does nothing.
+pub fn listen_for_shutdown() -> Result<()> {
+    static LISTENING: AtomicBool = AtomicBool::new(false);
+    if LISTENING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    // Registered before returning, so that a signal right after start isn't lost
+    let (mut terminate, mut interrupt) = {
+        let _context = runtime.enter();
+        (signal(SignalKind::terminate())?, signal(SignalKind::interrupt())?)
+    };
+    thread::Builder::new().name("shutdown".to_string()).spawn(move || {
+        runtime.block_on(async {
+            tokio::select! {
+                _ = terminate.recv() => {}
+                _ = interrupt.recv() => {}
+            }
+            info!("Shutting down after the current item, signal again to exit now");
+            shutdown_sender().send_replace(true);
+            tokio::select! {
+                _ = terminate.recv() => {}
+                _ = interrupt.recv() => {}
+            }
+            std::process::exit(1);
+        })
+    })?;
+    Ok(())
+}
+
+/// Whether the process was asked to stop; a stage checks it before every item.
+pub fn shutdown_requested() -> bool {
+    *shutdown_sender().borrow()
+}
+
+/// Completes once the process is asked to stop, for stages that run on their own async runtime.
+pub async fn shutdown_signal() {
+    let mut receiver = shutdown_sender().subscribe();
+    let _ = receiver.wait_for(|stop| *stop).await;
+}
+
+thread_local! {
+    static RUNTIME: Runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to start the async runtime");
+}
+
+/// Runs `future` to completion on this thread's async runtime: a stage's cycle of items, whose
+/// requests end with `Cancelled` on their own (see `cancellable`) so that it can give up its
+/// claims before it returns. Not for code already running on a runtime, like the publisher's.
+pub fn run_async<T>(future: impl Future<Output = T>) -> T {
+    RUNTIME.with(|runtime| runtime.block_on(future))
+}
+
+/// Runs `future`, typically a few HTTP requests, on this thread's async runtime and returns its
+/// result, or `Cancelled` as soon as the process is asked to stop. For the synchronous parts of
+/// the stages; async code awaits `cancellable` instead.
+pub fn block_on<T, E: From<Cancelled>>(future: impl Future<Output = std::result::Result<T, E>>) -> std::result::Result<T, E> {
+    run_async(cancellable(future))
+}
+
+/// The result of `future`, or `Cancelled` as soon as the process is asked to stop.
+pub async fn cancellable<T, E: From<Cancelled>>(
+    future: impl Future<Output = std::result::Result<T, E>>,
+) -> std::result::Result<T, E> {
+    tokio::select! {
+        result = future => result,
+        _ = shutdown_signal() => Err(Cancelled.into()),
+    }
+}
+
+/// `thread::sleep` that ends early with `Cancelled` when the process is asked to stop.
+pub fn sleep(duration: Duration) -> std::result::Result<(), Cancelled> {
+    run_async(pause(duration))
+}
+
+/// The async `sleep`: waits `duration`, or ends early with `Cancelled` when the process is asked to stop.
+pub async fn pause(duration: Duration) -> std::result::Result<(), Cancelled> {
+    cancellable(async {
+        tokio::time::sleep(duration).await;
+        Ok(())
+    })
+    .await
+}
+
+/// Limit of a single request to a feed, article page, image host, the Bot API or a mirror:
+/// HTTP_TIMEOUT_SECS (default 30). AI providers have their own timeouts.
+pub fn http_timeout() -> Result<Duration> {
+    Ok(Duration::from_secs(read_interval_from_env("HTTP_TIMEOUT_SECS", DEFAULT_HTTP_TIMEOUT_SECS)?))
+}
+
+fn log_status_moved(id: &str, lang: Option<&str>, from: &Status) {
+    warn!(
+        "Item {} (language: {}) is no longer in status '{}', leaving it as it is",
+        id,
+        lang.unwrap_or("default"),
+        from
+    );
+}
+
+/// Claims an item still in `status` for this worker before processing it, so that replicas of
+/// a service never work on the same item. Returns `false` if another worker holds the claim.
+///
+/// The claim ends with the item's next transition. A claim older than CLAIM_TIMEOUT_SECS
+/// (default 1800) is taken to belong to a worker that died and can be taken over.
+pub fn claim_item(conn: &Connection, id: &str, lang: Option<&str>, status: &Status) -> Result<bool> {
+    let now = unix_now();
+    let stale_before = claim_stale_before(now)?;
+    let claimed = match lang {
+        Some(lang) => conn.execute(
+            "UPDATE news_translations SET claimed_by = ?1, claimed_at = ?2 \
+             WHERE news_id = ?3 AND lang = ?4 AND status = ?5 \
+             AND (claimed_by IS NULL OR claimed_by = ?1 OR claimed_at < ?6)",
+            params![worker_id(), now, id, lang, status, stale_before],
+        )?,
+        None => conn.execute(
+            "UPDATE news SET claimed_by = ?1, claimed_at = ?2 \
+             WHERE id = ?3 AND status = ?4 AND (claimed_by IS NULL OR claimed_by = ?1 OR claimed_at < ?5)",
+            params![worker_id(), now, id, status, stale_before],
+        )?,
+    };
+    if claimed == 0 {
+        log_claim_skipped(id, lang, status);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Gives up this worker's claim on an item it stopped working on (on shutdown), so that the
+/// next start or another replica can take it without waiting for CLAIM_TIMEOUT_SECS.
+pub fn release_claim(conn: &Connection, id: &str, lang: Option<&str>) -> Result<()> {
+    match lang {
+        Some(lang) => conn.execute(
+            "UPDATE news_translations SET claimed_by = NULL, claimed_at = NULL \
+             WHERE news_id = ? AND lang = ? AND claimed_by = ?",
+            params![id, lang, worker_id()],
+        )?,
+        None => conn.execute(
+            "UPDATE news SET claimed_by = NULL, claimed_at = NULL WHERE id = ? AND claimed_by = ?",
+            params![id, worker_id()],
+        )?,
+    };
+    Ok(())
+}
+
+/// Claims taken before this time belong to a worker that presumably died.
+fn claim_stale_before(now: i64) -> Result<i64> {
+    Ok(now - read_interval_from_env("CLAIM_TIMEOUT_SECS", DEFAULT_CLAIM_TIMEOUT_SECS)? as i64)
+}
+
+fn log_claim_skipped(id: &str, lang: Option<&str>, status: &Status) {
+    info!(
+        "Item {} (language: {}) is taken by another worker or no longer in status '{}', skipping",
+        id,
+        lang.unwrap_or("default"),
+        status
+    );
+}
+
+/// Names this worker in `claimed_by`: WORKER_ID if set, otherwise `<service>@<host>:<pid>`.
+pub fn worker_id() -> String {
+    explicit_worker_id().unwrap_or_else(|| format!("{}@{}:{}", service_name(), host_name(), std::process::id()))
+}
+
+fn explicit_worker_id() -> Option<String> {
+    let id = env::var("WORKER_ID").ok().map(|id| id.trim().to_string()).filter(|id| !id.is_empty())?;
+    // Stages sharing a process also share its WORKER_ID
+    match SERVICE_NAME.with(|service| service.borrow().clone()) {
+        Some(service) => Some(format!("{}/{}", id, service)),
+        None => Some(id),
+    }
+}
+
+thread_local! {
+    static SERVICE_NAME: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Names the service running on the current thread, for a process that runs several stages
+/// (`all-in-one`): its log lines, claims and heartbeats then carry this name instead of the
+/// executable's.
+pub fn set_service_name(name: &str) {
+    SERVICE_NAME.with(|service| *service.borrow_mut() = Some(name.to_string()));
+}
+
+fn service_name() -> String {
+    if let Some(service) = SERVICE_NAME.with(|service| service.borrow().clone()) {
+        return service;
+    }
+    static EXE_NAME: OnceLock<String> = OnceLock::new();
+    EXE_NAME
+        .get_or_init(|| {
+            env::current_exe()
+                .ok()
+                .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+fn host_name() -> String {
+    static HOST_NAME: OnceLock<String> = OnceLock::new();
+    HOST_NAME
+        .get_or_init(|| {
+            env::var("HOSTNAME")
+                .ok()
+                .or_else(|| fs::read_to_string("/etc/hostname").ok())
+                .map(|host| host.trim().to_string())
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+/// Records that this worker is starting a cycle; services call it once per cycle of their main
+/// loop, which runs every `interval_secs`.
+pub fn heartbeat(conn: &Connection, interval_secs: u64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO worker_health (worker_id, service, host, interval_secs, beat_at) VALUES (?1, ?2, ?3, ?4, ?5) \
+         ON CONFLICT(worker_id) DO UPDATE SET interval_secs = ?4, beat_at = ?5",
+        params![worker_id(), service_name(), host_name(), interval_secs as i64, unix_now()],
+    )?;
+    Ok(())
+}
+
+/// `<service> healthcheck`, for Docker's HEALTHCHECK: succeeds if this service on this host
+/// (or the worker named by WORKER_ID) started a cycle recently enough. That is within
+/// HEALTHCHECK_MAX_AGE_SECS, by default three intervals but at least 15 minutes.
+pub fn healthcheck() -> Result<()> {
+    let conn = open_db()?;
+    let heartbeat = conn
+        .query_row(
+            "SELECT beat_at, interval_secs FROM worker_health \
+             WHERE (?1 IS NOT NULL AND worker_id = ?1) OR (?1 IS NULL AND service = ?2 AND host = ?3) \
+             ORDER BY beat_at DESC LIMIT 1",
+            params![explicit_worker_id(), service_name(), host_name()],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)? as u64)),
+        )
+        .optional()?;
+    let Some((beat_at, interval_secs)) = heartbeat else {
+        return Err(anyhow!("{} has not recorded a heartbeat on {}", service_name(), host_name()));
+    };
+    let max_age = read_interval_from_env("HEALTHCHECK_MAX_AGE_SECS", (interval_secs * 3).max(MIN_HEARTBEAT_AGE_SECS))?;
+    let age = unix_now() - beat_at;
+    if age > max_age as i64 {
+        return Err(anyhow!("Last heartbeat was {}s ago, more than {}s", age, max_age));
+    }
+    info!("Healthy: last heartbeat {}s ago", age);
+    Ok(())
+}
+
+/// Counts a failed attempt of the item and keeps its error message, so operators can see why it
+/// failed without the logs. Returns the number of failed attempts in the current stage.
+pub fn record_failure(conn: &Connection, id: &str, lang: Option<&str>, error: &str) -> Result<u32> {
+    let attempts = match lang {
+        Some(lang) => conn.query_row(
+            "UPDATE news_translations SET attempts = attempts + 1, error = ?, last_attempt_at = ? \
+             WHERE news_id = ? AND lang = ? RETURNING attempts",
+            params![error, unix_now(), id, lang],
+            |row| row.get(0),
+        )?,
+        None => conn.query_row(
+            "UPDATE news SET attempts = attempts + 1, error = ?, last_attempt_at = ? WHERE id = ? RETURNING attempts",
+            params![error, unix_now(), id],
+            |row| row.get(0),
+        )?,
+    };
+    Ok(attempts)
+}
+
+/// Clears the error of an item that got through its stage, so the next stage starts counting
+/// attempts from zero.
+pub fn reset_attempts(conn: &Connection, id: &str, lang: Option<&str>) -> Result<()> {
+    match lang {
+        Some(lang) => conn.execute(
+            "UPDATE news_translations SET attempts = 0, next_attempt_at = NULL, error = NULL, last_attempt_at = ? \
+             WHERE news_id = ? AND lang = ?",
+            params![unix_now(), id, lang],
+        )?,
+        None => conn.execute(
+            "UPDATE news SET attempts = 0, next_attempt_at = NULL, error = NULL, last_attempt_at = ? WHERE id = ?",
+            params![unix_now(), id],
+        )?,
+    };
+    Ok(())
+}
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Hash of a `publish_chain` record: its fields and the previous record's hash, so editing or
+/// deleting a record breaks every later one (written by the publisher, checked by `ctl verify-chain`).
+pub fn publish_record_hash(
+    prev_hash: &str,
+    news_id: &str,
+    lang: &str,
+    status: &str,
+    published_at: &str,
+    content_hash: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    for field in [prev_hash, news_id, lang, status, published_at, content_hash] {
+        hasher.update(field.as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Runs `f` inside a `BEGIN IMMEDIATE` transaction, so read-then-write steps take the write lock
+/// up front instead of failing on upgrade. Inside an already open transaction `f` simply joins it.
+pub fn with_immediate_transaction<T>(conn: &Connection, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+    if !conn.is_autocommit() {
+        return f(conn);
+    }
+    let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+    let result = f(&tx)?;
+    tx.commit()?;
+    Ok(result)
+}
+
+/// Reads a positive number of seconds from `var`, or `default` when it is unset or empty.
+pub fn read_interval_from_env(var: &str, default: u64) -> Result<u64> {
+    match env::var(var) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .ok_or_else(|| anyhow!("{} must be a positive integer, got '{}'", var, value.trim())),
+        _ => Ok(default),
+    }
+}
+
+/// Sets up logging for the process: text lines on stdout, or JSON objects with LOG_FORMAT=json
+/// for log aggregation. LOG_LEVEL filters them like RUST_LOG: `info` by default, `debug` for the
+/// request details, or per target such as `info,rewriter=debug`. Calling it again does nothing.
+pub fn init_logging() -> Result<()> {
+    let filter = match env::var("LOG_LEVEL") {
+        Ok(value) if !value.trim().is_empty() => {
+            EnvFilter::try_new(value.trim()).map_err(|e| anyhow!("LOG_LEVEL is not a valid filter ('{}'): {}", value.trim(), e))?
+        }
+        _ => EnvFilter::new(DEFAULT_LOG_LEVEL),
+    };
+    let json = match env::var("LOG_FORMAT") {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "" | "text" => false,
+            "json" => true,
+            _ => return Err(anyhow!("LOG_FORMAT must be text or json, got '{}'", value.trim())),
+        },
+        Err(_) => false,
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(false)
+        .with_writer(log_writer);
+    // The all-in-one binary sets it up once for all stages
+    let _ = if json { builder.json().try_init() } else { builder.try_init() };
+    Ok(())
+}
+
+/// Where log lines go: the container's main process output when running in Docker, stdout
+/// otherwise.
+fn log_writer() -> Box<dyn Write> {
+    // If /.dockerenv exist, write to /proc/1/fd/1.
+    // Note: This path might not be optimal for all container environments.
+    if Path::new("/.dockerenv").exists() {
+        match OpenOptions::new().append(true).open("/proc/1/fd/1") {
+            Ok(file) => return Box::new(Redacted(file)),
+            Err(e) => {
+                // Fallback to stdout if opening /proc/1/fd/1 fails
+                eprintln!("Failed to open /proc/1/fd/1 for logging: {}, falling back to stdout", e);
+            }
+        }
+    }
+    Box::new(Redacted(stdout()))
+}
+
+/// Span around the work of `stage` on one item: every log line inside it carries the stage and
+/// `item_id`.
+pub fn item_span(stage: &str, id: &str) -> Span {
+    info_span!("item", stage, item_id = id)
+}
@@ -0,0 +1,174 @@
+//! Why an AI provider call failed, stored on the news row by the translator, rewriter and
+//! illustrator so `ctl errors` can group failures by HTTP status, code and retryability.
+
+use crate::Cancelled;
+use anyhow::Result;
+use reqwest::StatusCode;
+use rusqlite::{params, Connection};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Error of one AI provider call in the translator, rewriter or illustrator.
+#[derive(Debug, Error, Clone)]
+pub enum ApiError {
+    #[error("Reqwest error: {0}")]
+    RequestError(#[from] Arc<reqwest::Error>),
+    #[error("Failed to parse API response: {0}")]
+    ParseError(#[from] Arc<anyhow::Error>),
+    #[error("Invalid AI provider configuration: {0}")]
+    ConfigurationError(String),
+    #[error("AI provider returned status {status} with finish_reason '{finish_reason:?}'. Body: {content}")]
+    ApiReturnedError {
+        status: StatusCode,
+        provider_error: Option<ProviderError>,
+        content: String, // Include the (potentially partial) content
+        finish_reason: Option<String>, // Include the finish reason if available
+    },
+    #[error("AI provider returned empty choices")]
+    EmptyChoices,
+    #[error("AI provider returned empty image data")]
+    EmptyImageData,
+    #[error("{0}")]
+    Cancelled(#[from] Cancelled),
+}
+
+impl ApiError {
+    /// What `record_provider_error` stores on the news row for this failure.
+    pub fn failure(&self) -> ProviderFailure {
+        match self {
+            ApiError::RequestError(e) => classify_request_failure(e),
+            ApiError::ParseError(e) => ProviderFailure::new("parse_error", e.to_string(), true),
+            ApiError::ConfigurationError(message) => ProviderFailure::new("configuration", message.clone(), false),
+            ApiError::ApiReturnedError {
+                status,
+                finish_reason,
+                provider_error,
+                ..
+            } => classify_provider_failure(*status, finish_reason.as_deref(), provider_error.as_ref()),
+            ApiError::EmptyChoices | ApiError::EmptyImageData => {
+                ProviderFailure::new("empty_response", self.to_string(), true)
+            }
+            ApiError::Cancelled(_) => ProviderFailure::new("cancelled", self.to_string(), true),
+        }
+    }
+}
+
+/// Error code and message from a provider's JSON error body.
+#[derive(Debug, Clone)]
+pub struct ProviderError {
+    pub code: Option<String>,
+    pub message: Option<String>,
+}
+
+/// Extracts the error code/message from the common provider error shapes:
+/// OpenAI/OpenRouter/Perplexity `{"error":{"code","message","type"}}`, Gemini `{"error":{"status","message"}}`
+/// (sometimes wrapped in an array) and Anthropic `{"type":"error","error":{"type","message"}}`.
+pub fn parse_provider_error(body: &str) -> Option<ProviderError> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let value = match value {
+        serde_json::Value::Array(mut items) if !items.is_empty() => items.swap_remove(0),
+        value => value,
+    };
+    let error = value.get("error")?;
+    if let Some(message) = error.as_str() {
+        return Some(ProviderError {
+            code: None,
+            message: Some(message.to_string()),
+        });
+    }
+
+    let as_text = |value: &serde_json::Value| match value {
+        serde_json::Value::String(text) => Some(text.clone()),
+        serde_json::Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    };
+    let code = ["status", "code", "type"]
+        .iter()
+        .filter_map(|key| error.get(*key).and_then(as_text))
+        .find(|code| code.parse::<u16>().is_err());
+    let message = error.get("message").and_then(as_text);
+
+    Some(ProviderError { code, message })
+}
+
+/// Failure details stored on the news row (`error_http_status`, `error_code`, `error_message`,
+/// `error_retryable`).
+#[derive(Debug, Clone)]
+pub struct ProviderFailure {
+    pub http_status: Option<u16>,
+    pub code: String,
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl ProviderFailure {
+    /// A failure without an HTTP response, e.g. `parse_error` or `cancelled`.
+    pub fn new(code: &str, message: String, retryable: bool) -> Self {
+        ProviderFailure { http_status: None, code: code.to_string(), message, retryable }
+    }
+}
+
+/// A request that got no usable response: `timeout`, `connection` or `request`, all retryable.
+pub fn classify_request_failure(error: &reqwest::Error) -> ProviderFailure {
+    let code = if error.is_timeout() {
+        "timeout"
+    } else if error.is_connect() {
+        "connection"
+    } else {
+        "request"
+    };
+    ProviderFailure {
+        http_status: error.status().map(|status| status.as_u16()),
+        code: code.to_string(),
+        message: error.to_string(),
+        retryable: true,
+    }
+}
+
+/// A provider response that was an error, or a 2xx without a usable result: the provider's own
+/// code when its body had one, otherwise one derived from the status. Server errors, timeouts and
+/// rate limits are retryable unless the code points at an exhausted quota.
+pub fn classify_provider_failure(
+    status: StatusCode,
+    finish_reason: Option<&str>,
+    provider_error: Option<&ProviderError>,
+) -> ProviderFailure {
+    let fallback_code = match status.as_u16() {
+        200..=299 if finish_reason == Some("length") => "length",
+        200..=299 => "invalid_output",
+        401 | 403 => "auth",
+        402 => "quota",
+        408 => "timeout",
+        429 => "rate_limit",
+        500..=599 => "server_error",
+        _ => "bad_request",
+    };
+    let code = provider_error
+        .and_then(|error| error.code.clone())
+        .unwrap_or_else(|| fallback_code.to_string());
+    let message = provider_error
+        .and_then(|error| error.message.clone())
+        .unwrap_or_else(|| format!("finish_reason={:?}", finish_reason));
+    let retryable = (status.is_success() || status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429)
+        && !code.to_ascii_lowercase().contains("quota");
+    ProviderFailure { http_status: Some(status.as_u16()), code, message, retryable }
+}
+
+/// Stores the provider failure on the news row (see `ctl errors`).
+pub fn record_provider_error(conn: &Connection, id: &str, stage: &str, failure: &ProviderFailure) -> Result<()> {
+    conn.execute(
+        "UPDATE news SET error_stage = ?, error_http_status = ?, error_code = ?, error_message = ?, error_retryable = ? WHERE id = ?",
+        params![stage, failure.http_status, failure.code, failure.message, failure.retryable, id],
+    )?;
+    Ok(())
+}
+
+/// Drops a failure recorded by this stage once the item went through.
+pub fn clear_provider_error(conn: &Connection, id: &str, stage: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE news SET error_stage = NULL, error_http_status = NULL, error_code = NULL, error_message = NULL, error_retryable = NULL \
+         WHERE id = ? AND error_stage = ?",
+        params![id, stage],
+    )?;
+    Ok(())
+}
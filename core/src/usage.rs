@@ -0,0 +1,226 @@
+//! Tokens the AI stages spend: counted per item in the task that processes it, stored with their
+//! estimated cost in `ai_usage` (see `ctl usage`) and capped by the daily budget.
+
+use crate::{cancellable, unix_now};
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection};
+use std::cell::Cell;
+use reqwest::Client;
+use std::env;
+use std::future::Future;
+use std::sync::atomic::{AtomicI64, Ordering};
+use tracing::{error, info, warn};
+
+tokio::task_local! {
+    /// Tokens reported by the provider for the item being processed in this task.
+    static ITEM_USAGE: Cell<(u64, u64)>;
+}
+
+/// The model an item's tokens went to, as stored in `ai_usage`, with its prices in USD per
+/// million tokens.
+#[derive(Debug, Clone)]
+pub struct UsageModel<'a> {
+    pub provider: String,
+    pub model: &'a str,
+    pub input_price: f64,
+    pub output_price: f64,
+}
+
+/// Runs `future`, the processing of one item, with a token count of its own, so that workers
+/// sharing a thread don't count each other's tokens.
+pub async fn track_usage<F: Future>(future: F) -> F::Output {
+    ITEM_USAGE.scope(Cell::new((0, 0)), future).await
+}
+
+/// Adds the token counts reported by one response to the usage of the item being processed.
+/// Outside `track_usage` there is no item to count them for.
+pub fn add_usage(prompt_tokens: u64, completion_tokens: u64) {
+    let _ = ITEM_USAGE.try_with(|usage| {
+        let (prompt, completion) = usage.get();
+        usage.set((prompt + prompt_tokens, completion + completion_tokens));
+    });
+}
+
+/// Returns and resets the tokens the item accumulated since the last call.
+pub fn take_usage() -> (u64, u64) {
+    ITEM_USAGE.try_with(|usage| usage.replace((0, 0))).unwrap_or_default()
+}
+
+/// Stores the tokens one item used in `stage` (all chunks of this run) with their estimated cost.
+pub fn record_usage(
+    conn: &Connection,
+    stage: &str,
+    news_id: &str,
+    lang: Option<&str>,
+    model: &UsageModel,
+    (prompt_tokens, completion_tokens): (u64, u64),
+) -> Result<()> {
+    if prompt_tokens == 0 && completion_tokens == 0 {
+        return Ok(());
+    }
+    let cost_usd = (prompt_tokens as f64 * model.input_price + completion_tokens as f64 * model.output_price) / 1_000_000.0;
+    conn.execute(
+        "INSERT INTO ai_usage (news_id, lang, stage, provider, model, prompt_tokens, completion_tokens, cost_usd, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            news_id,
+            lang,
+            stage,
+            model.provider,
+            model.model,
+            prompt_tokens as i64,
+            completion_tokens as i64,
+            cost_usd,
+            unix_now()
+        ],
+    )
+    .context(format!("Failed to record AI usage for item {}", news_id))?;
+    info!(
+        "Item {} used {} prompt + {} completion tokens (~${:.4})",
+        news_id, prompt_tokens, completion_tokens, cost_usd
+    );
+    Ok(())
+}
+
+/// Stores `images` images one item got from an image model in `stage`, at `price_per_image` USD
+/// each. Image models report no tokens, so the row only carries the cost.
+pub fn record_image_usage(
+    conn: &Connection,
+    stage: &str,
+    news_id: &str,
+    provider: &str,
+    model: &str,
+    images: u32,
+    price_per_image: f64,
+) -> Result<()> {
+    if images == 0 {
+        return Ok(());
+    }
+    let cost_usd = images as f64 * price_per_image;
+    conn.execute(
+        "INSERT INTO ai_usage (news_id, lang, stage, provider, model, prompt_tokens, completion_tokens, cost_usd, created_at)
+         VALUES (?1, NULL, ?2, ?3, ?4, 0, 0, ?5, ?6)",
+        params![news_id, stage, provider, model, cost_usd, unix_now()],
+    )
+    .context(format!("Failed to record AI usage for item {}", news_id))?;
+    info!("Item {} used {} image(s) of {} (~${:.4})", news_id, images, model, cost_usd);
+    Ok(())
+}
+
+/// USD per million tokens from a `..._PRICE_INPUT` / `..._PRICE_OUTPUT` variable (0 when unset).
+pub fn read_price_from_env(var: &str) -> Result<f64> {
+    match env::var(var) {
+        Ok(value) if !value.trim().is_empty() => {
+            let price = value
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| anyhow!("{} must be a number (USD per million tokens): {}", var, e))?;
+            if !price.is_finite() || price < 0.0 {
+                return Err(anyhow!("{} must not be negative (got {})", var, price));
+            }
+            Ok(price)
+        }
+        _ => Ok(0.0),
+    }
+}
+
+/// Daily limits on the AI usage recorded in `ai_usage` by all stages (AI_DAILY_TOKEN_BUDGET,
+/// AI_DAILY_COST_BUDGET_USD); unset limits are not enforced.
+pub struct DailyBudget {
+    max_tokens: Option<u64>,
+    max_cost_usd: Option<f64>,
+    /// Receives a JSON `{"text": ...}` POST when processing is paused (AI_BUDGET_ALERT_URL).
+    alert_url: Option<String>,
+    /// Start of the UTC day the pause was last alerted, shared by the stage's workers.
+    alerted_day: AtomicI64,
+}
+
+pub fn read_daily_budget_from_env() -> Result<DailyBudget> {
+    let max_tokens = match env::var("AI_DAILY_TOKEN_BUDGET") {
+        Ok(value) if !value.trim().is_empty() => Some(
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| anyhow!("AI_DAILY_TOKEN_BUDGET must be a positive integer: {}", e))?,
+        ),
+        _ => None,
+    };
+    let max_cost_usd = match env::var("AI_DAILY_COST_BUDGET_USD") {
+        Ok(value) if !value.trim().is_empty() => {
+            let cost = value
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| anyhow!("AI_DAILY_COST_BUDGET_USD must be a number: {}", e))?;
+            if !cost.is_finite() || cost < 0.0 {
+                return Err(anyhow!("AI_DAILY_COST_BUDGET_USD must not be negative (got {})", cost));
+            }
+            Some(cost)
+        }
+        _ => None,
+    };
+    let alert_url = env::var("AI_BUDGET_ALERT_URL")
+        .ok()
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty());
+
+    Ok(DailyBudget {
+        max_tokens,
+        max_cost_usd,
+        alert_url,
+        alerted_day: AtomicI64::new(i64::MIN),
+    })
+}
+
+/// Returns true when today's (UTC) usage has reached the daily budget, checked before every item
+/// so a cycle stops spending as soon as it runs out. The first time that happens on a day the
+/// pause of `stage` is logged as an error and sent to AI_BUDGET_ALERT_URL. A failed check is
+/// logged and doesn't pause.
+pub async fn check_daily_budget(conn: &Connection, stage: &str, budget: &DailyBudget, client: &Client) -> bool {
+    if budget.max_tokens.is_none() && budget.max_cost_usd.is_none() {
+        return false;
+    }
+    budget_exhausted(conn, stage, budget, client).await.unwrap_or_else(|e| {
+        warn!("Failed to check the daily AI budget: {}", e);
+        false
+    })
+}
+
+async fn budget_exhausted(conn: &Connection, stage: &str, budget: &DailyBudget, client: &Client) -> Result<bool> {
+    let now = unix_now();
+    let day_start = now - now.rem_euclid(86_400);
+    let (tokens, cost): (i64, f64) = conn.query_row(
+        "SELECT COALESCE(SUM(prompt_tokens + completion_tokens), 0), COALESCE(SUM(cost_usd), 0)
+         FROM ai_usage WHERE created_at >= ?1",
+        params![day_start],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let reason = if let Some(max_tokens) = budget.max_tokens.filter(|max| tokens as u64 >= *max) {
+        format!("{} tokens used today, budget is {}", tokens, max_tokens)
+    } else if let Some(max_cost) = budget.max_cost_usd.filter(|max| cost >= *max) {
+        format!("${:.2} spent today, budget is ${:.2}", cost, max_cost)
+    } else {
+        return Ok(false);
+    };
+
+    if budget.alerted_day.swap(day_start, Ordering::Relaxed) == day_start {
+        info!("Daily AI budget still exhausted ({}), skipping", reason);
+        return Ok(true);
+    }
+
+    let message = format!("{} paused: {}. Processing resumes at 00:00 UTC.", stage, reason);
+    error!("{}", message);
+    if let Some(url) = &budget.alert_url {
+        let request = client.post(url).json(&serde_json::json!({ "text": message }));
+        match cancellable(async { Ok::<_, anyhow::Error>(request.send().await?) }).await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => {
+                warn!("Budget alert returned status {}", response.status());
+            }
+            Err(e) => {
+                warn!("Failed to send budget alert: {}", e);
+            }
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,342 @@
+//! Parallel workers of the AI stages: how many the operator allows, a tuner that moves their
+//! number with the provider's latency and rate limits, the pool that runs them and the database
+//! connections they keep between cycles.
+
+use crate::{open_db, run_async, shutdown_requested, wait_for_change};
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
+use rusqlite::Connection;
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{error, info};
+
+// Provider calls the tuner looks at before changing the number of workers
+const TUNER_WINDOW_CALLS: u32 = 10;
+// Average latency over the fastest one seen that counts as the provider slowing down
+const TUNER_LATENCY_FACTOR: f64 = 1.5;
+// Longest wait of an idle worker before it looks at the limit again
+const IDLE_WAIT_SECS: u64 = 60;
+
+/// Workers of one stage: `<PREFIX>_CONCURRENCY` (default 1). With `<PREFIX>_ADAPTIVE_CONCURRENCY`
+/// the tuner starts at `<PREFIX>_MIN_CONCURRENCY` (default 1) and moves between the two.
+#[derive(Debug, Clone, Copy)]
+pub struct Concurrency {
+    pub max: usize,
+    pub adaptive: bool,
+    pub min: usize,
+}
+
+impl Concurrency {
+    /// The tuner for these bounds, or None when it is off or has no room to move.
+    pub fn tuner(&self, stage: &'static str) -> Option<ConcurrencyTuner> {
+        (self.adaptive && self.max > self.min).then(|| ConcurrencyTuner::new(stage, self.min, self.max))
+    }
+}
+
+/// Reads `<prefix>_CONCURRENCY`, `<prefix>_ADAPTIVE_CONCURRENCY` and `<prefix>_MIN_CONCURRENCY`,
+/// e.g. with the prefix `AI_PROVIDER_REWRITER`.
+pub fn read_concurrency_from_env(prefix: &str) -> Result<Concurrency> {
+    let max_var = format!("{}_CONCURRENCY", prefix);
+    let max = match env::var(&max_var) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|concurrency| *concurrency > 0)
+            .ok_or_else(|| anyhow!("{} must be a positive integer, got '{}'", max_var, value.trim()))?,
+        _ => 1,
+    };
+    let adaptive_var = format!("{}_ADAPTIVE_CONCURRENCY", prefix);
+    let adaptive = match env::var(&adaptive_var) {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "" | "0" | "false" | "no" | "off" => false,
+            "1" | "true" | "yes" | "on" => true,
+            other => return Err(anyhow!("{} must be true or false (got '{}')", adaptive_var, other)),
+        },
+        Err(_) => false,
+    };
+    let min_var = format!("{}_MIN_CONCURRENCY", prefix);
+    let min = match env::var(&min_var) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|min| *min > 0 && *min <= max)
+            .ok_or_else(|| {
+                anyhow!("{} must be a positive integer up to {} ({}), got '{}'", min_var, max_var, max, value.trim())
+            })?,
+        _ => 1,
+    };
+    Ok(Concurrency { max, adaptive, min })
+}
+
+/// Adjusts the number of busy workers of a stage after every TUNER_WINDOW_CALLS provider calls:
+/// halved when any of them was rate limited or failed, one less when the average latency went
+/// up, one more while the provider keeps up.
+pub struct ConcurrencyTuner {
+    stage: &'static str,
+    min: usize,
+    max: usize,
+    state: Mutex<TunerState>,
+}
+
+struct TunerState {
+    limit: usize,
+    /// Counts the changes of `limit`, which idle workers wait for.
+    changes: i64,
+    calls: u32,
+    overloaded: u32,
+    latency: Duration,
+    /// Lowest average latency of a window so far, how fast the provider answers without load.
+    fastest: Option<Duration>,
+}
+
+impl ConcurrencyTuner {
+    fn new(stage: &'static str, min: usize, max: usize) -> Self {
+        ConcurrencyTuner {
+            stage,
+            min,
+            max,
+            state: Mutex::new(TunerState {
+                limit: min,
+                changes: 0,
+                calls: 0,
+                overloaded: 0,
+                latency: Duration::ZERO,
+                fastest: None,
+            }),
+        }
+    }
+
+    /// Counts one provider call; `overloaded` for a 429 or a request that failed outright.
+    pub fn record(&self, latency: Duration, overloaded: bool) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.calls += 1;
+        if overloaded {
+            state.overloaded += 1;
+        } else {
+            state.latency += latency;
+        }
+        if state.calls < TUNER_WINDOW_CALLS {
+            return;
+        }
+
+        let answered = state.calls - state.overloaded;
+        let previous = state.limit;
+        let reason = if state.overloaded > 0 {
+            state.limit = (state.limit / 2).max(self.min);
+            format!("{} of {} calls rate limited or failed", state.overloaded, state.calls)
+        } else {
+            let average = state.latency / answered;
+            let fastest = *state.fastest.get_or_insert(average);
+            state.fastest = Some(fastest.min(average));
+            if average.as_secs_f64() > fastest.as_secs_f64() * TUNER_LATENCY_FACTOR {
+                state.limit = (state.limit - 1).max(self.min);
+                format!("average latency {:.1}s, fastest {:.1}s", average.as_secs_f64(), fastest.as_secs_f64())
+            } else {
+                state.limit = (state.limit + 1).min(self.max);
+                format!("average latency {:.1}s", average.as_secs_f64())
+            }
+        };
+        state.calls = 0;
+        state.overloaded = 0;
+        state.latency = Duration::ZERO;
+        if state.limit != previous {
+            state.changes += 1;
+            info!("{} concurrency {} -> {} ({})", self.stage, previous, state.limit, reason);
+        }
+    }
+
+    /// How many workers may take items right now.
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).limit
+    }
+
+    fn changes(&self) -> i64 {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).changes
+    }
+}
+
+/// Runs `work` on every item with `workers` workers and returns once the queue is done or the
+/// process is asked to stop. The workers are futures on the calling thread's runtime, so their
+/// requests overlap while the thread waits; each has its own connection from `open` and uses it
+/// in between (SQLite calls are short and don't yield). Workers above the tuner's limit take no
+/// items; they wait like a sleeping stage until the limit rises or the queue runs out.
+pub fn run_workers<I, C>(
+    items: Vec<I>,
+    workers: usize,
+    tuner: Option<&ConcurrencyTuner>,
+    open: impl Fn() -> Result<C>,
+    work: impl AsyncFn(&mut C, I),
+) {
+    let queue = RefCell::new(items.into_iter());
+    let drained = Cell::new(false);
+    // Changes whenever an idle worker may have something to do
+    let idle_seq = || tuner.map_or(0, ConcurrencyTuner::changes) + i64::from(drained.get());
+    let worker = async |worker: usize| {
+        let mut conn = match open() {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("Worker {} failed to open the database: {}", worker, e);
+                return;
+            }
+        };
+        while !shutdown_requested() {
+            if let Some(tuner) = tuner {
+                let seen = idle_seq();
+                if worker >= tuner.limit() {
+                    if drained.get() {
+                        break;
+                    }
+                    if let Err(e) = wait_for_change(IDLE_WAIT_SECS, seen, || Ok(idle_seq())).await {
+                        error!("Worker {} failed to wait for work: {}", worker, e);
+                        break;
+                    }
+                    continue;
+                }
+            }
+            let next = queue.borrow_mut().next();
+            let Some(item) = next else {
+                drained.set(true);
+                break;
+            };
+            work(&mut conn, item).await;
+        }
+    };
+    run_async(join_all((0..workers).map(worker)));
+}
+
+/// Database connections of the AI stages' workers, shared by every stage of the process (all of
+/// them in `all-in-one`), so a connection one stage's worker left idle serves the next one.
+pub static WORKER_CONNECTIONS: ConnectionPool<Connection> = ConnectionPool::new(open_db);
+
+/// Connections of a stage's workers, kept from one cycle to the next instead of opening (and
+/// migrating) the database for every worker of every cycle. Holds as many as workers ran at once.
+pub struct ConnectionPool<C> {
+    open: fn() -> Result<C>,
+    idle: Mutex<Vec<C>>,
+}
+
+impl<C> ConnectionPool<C> {
+    pub const fn new(open: fn() -> Result<C>) -> Self {
+        ConnectionPool { open, idle: Mutex::new(Vec::new()) }
+    }
+
+    /// An idle connection, or a new one from `open`; it goes back to the pool when dropped.
+    pub fn get(&self) -> Result<PooledConnection<'_, C>> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => (self.open)()?,
+        };
+        Ok(PooledConnection { pool: self, conn: Some(conn) })
+    }
+}
+
+pub struct PooledConnection<'a, C> {
+    pool: &'a ConnectionPool<C>,
+    conn: Option<C>,
+}
+
+impl<C> Deref for PooledConnection<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.conn.as_ref().expect("connection is only taken on drop")
+    }
+}
+
+impl<C> DerefMut for PooledConnection<'_, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        self.conn.as_mut().expect("connection is only taken on drop")
+    }
+}
+
+impl<C> Drop for PooledConnection<'_, C> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.idle.lock().unwrap_or_else(|e| e.into_inner()).push(conn);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(tuner: &ConcurrencyTuner, latency_ms: u64, overloaded: u32) {
+        for call in 0..TUNER_WINDOW_CALLS {
+            tuner.record(Duration::from_millis(latency_ms), call < overloaded);
+        }
+    }
+
+    #[test]
+    fn record_changes_the_limit_only_after_a_full_window() {
+        let tuner = ConcurrencyTuner::new("Test", 2, 8);
+        for _ in 1..TUNER_WINDOW_CALLS {
+            tuner.record(Duration::from_millis(100), false);
+        }
+        assert_eq!(tuner.limit(), 2);
+        tuner.record(Duration::from_millis(100), false);
+        assert_eq!(tuner.limit(), 3);
+        assert_eq!(tuner.changes(), 1);
+    }
+
+    #[test]
+    fn record_grows_up_to_max_while_the_provider_keeps_up() {
+        let tuner = ConcurrencyTuner::new("Test", 1, 3);
+        for _ in 0..5 {
+            window(&tuner, 100, 0);
+        }
+        assert_eq!(tuner.limit(), 3);
+        assert_eq!(tuner.changes(), 2);
+    }
+
+    #[test]
+    fn record_halves_on_rate_limits_but_not_below_min() {
+        let tuner = ConcurrencyTuner::new("Test", 2, 16);
+        for _ in 0..8 {
+            window(&tuner, 100, 0);
+        }
+        assert_eq!(tuner.limit(), 10);
+        window(&tuner, 100, 1);
+        assert_eq!(tuner.limit(), 5);
+        window(&tuner, 100, 10);
+        assert_eq!(tuner.limit(), 2);
+        window(&tuner, 100, 3);
+        assert_eq!(tuner.limit(), 2);
+    }
+
+    #[test]
+    fn record_steps_down_when_latency_rises_over_the_fastest_window() {
+        let tuner = ConcurrencyTuner::new("Test", 1, 8);
+        window(&tuner, 100, 0);
+        window(&tuner, 100, 0);
+        assert_eq!(tuner.limit(), 3);
+        // 1.5x the fastest window is still fine, more is a slowdown
+        window(&tuner, 150, 0);
+        assert_eq!(tuner.limit(), 4);
+        window(&tuner, 200, 0);
+        assert_eq!(tuner.limit(), 3);
+        // A slow window doesn't raise the baseline
+        window(&tuner, 160, 0);
+        assert_eq!(tuner.limit(), 2);
+    }
+
+    #[test]
+    fn run_workers_overlaps_the_items_of_one_thread() {
+        let done = RefCell::new(Vec::new());
+        let started = std::time::Instant::now();
+        run_workers(vec![1, 2, 3, 4], 4, None, || Ok(()), async |_: &mut (), item: i32| {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            done.borrow_mut().push(item);
+        });
+        let mut done = done.into_inner();
+        done.sort();
+        assert_eq!(done, [1, 2, 3, 4]);
+        assert!(started.elapsed() < Duration::from_millis(600), "{:?}", started.elapsed());
+    }
+}
@@ -0,0 +1,2 @@
+// `ctl` built under a second name; the commands and their usage are the same
+include!("../main.rs");
@@ -0,0 +1,1293 @@
+use anyhow::{Context, Result};
+use robo_news_core::{
+    artifact_exists, builtin_stage, init_logging, item_status, list_artifacts, load_artifact, load_config_file,
+    load_pipeline_stage, load_secret_files, open_db, publish_record_hash, remove_artifact, set_item_status,
+    stage_input_status, unix_now, PipelineStage, Status, GENESIS_HASH,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{error, info, warn};
+
+const DATA_DIR: &str = "data";
+const DEFAULT_SEED_FEED: &str = "feed1";
+const DEFAULT_SEED_LIMIT: usize = 5;
+const DEFAULT_USAGE_DAYS: i64 = 30;
+const DEFAULT_ENGAGEMENT_DAYS: i64 = 7;
+const DEFAULT_ENGAGEMENT_LIMIT: i64 = 10;
+const DEFAULT_LIST_LIMIT: i64 = 50;
+// Where `ctl replay` keeps the artifacts the replayed stages are about to overwrite
+const REPLAY_BACKUP_DIR: &str = "data/replay";
+// A Telegram media group holds at most 10 photos
+const MAX_ALBUM_IMAGES: usize = 10;
+
+const USAGE: &str = "Usage: ctl seed [--feed <feed>] [--limit <n>]
+       ctl errors [--group-by code|stage|http_status|retryable]
+       ctl verify-chain [--files]
+       ctl usage [--days <n>]
+       ctl review [--approve <id> [--lang <code>]]
+       ctl engagement [--days <n>] [--limit <n>]
+       ctl footer [--add <html> [--lang <code>] | --enable <id> | --disable <id> | --delete <id>]
+       ctl list [--status <status>] [--limit <n>]
+       ctl show <id>
+       ctl requeue <id> [--lang <code>] [--status <status>]
+       ctl publish <id> [--lang <code>]
+       ctl purge <id> [--yes]
+       ctl replay <stage> [<id>...] [--since <day>] [--until <day>] [--source <source>] [--status <status>]
+                  [--lang <code>] [--yes]
+       ctl stats
+
+Commands:
+  seed    Run parser, downloader and scraper once for a few items and stop before
+          translation and publishing, so extraction can be checked on a new deployment.
+  errors  Count the AI provider failures recorded on news items (grouped by error code by default).
+  verify-chain
+          Check the publisher's hash chain of published items; --files also re-hashes the
+          published HTML and illustrations.
+  usage   Sum the AI tokens and estimated cost per stage and model over the last days (30 by
+          default), and the average cost of a published post.
+  review  List the items the fact check, the illustrator's image checks or the publisher's moderation
+          held back; --approve passes one on to the next stage.
+  engagement
+          Show the most viewed posts of the last days (7 by default) and the average views of
+          the headline variants a and b, from the counts the publisher collects.
+  footer  List the footer blocks the publisher appends to posts, or add, enable, disable or
+          delete one; changes apply from the publisher's next cycle.
+  list    List the newest items (50 by default) with their status, attempts and last error;
+          --status only those in one status, including translations.
+  show    Show an item with its translations, timestamps, errors, AI calls, publications and
+          artifacts.
+  requeue Put an item (or one translation with --lang) back into a queue and reset its attempts.
+          Items in an *_error status go back to the input of the stage that failed; any other
+          status needs --status.
+  publish Send an item straight to the publisher's queue, skipping the stages it hasn't been
+          through and a moderation hold.
+  purge   Delete an item, its translations and its artifacts; without --yes only lists them.
+  replay  Run a stage (translator, rewriter, illustrator or publisher) and the ones after it again
+          for the given items, or for those created between --since and --until (YYYY-MM-DD, today
+          or yesterday, UTC), from --source or in --status; --lang picks that translation instead.
+          The item's artifacts are copied to data/replay/<time>/ first. Without --yes only lists them.
+  stats   Count the items and translations per status, with the age of the oldest one.";
+
+fn main() -> Result<()> {
+    load_config_file()?;
+    load_secret_files()?;
+    init_logging()?;
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("seed") => seed(args.collect()),
+        Some("errors") => errors(args.collect()),
+        Some("verify-chain") => verify_chain(args.collect()),
+        Some("usage") => usage(args.collect()),
+        Some("review") => review(args.collect()),
+        Some("engagement") => engagement(args.collect()),
+        Some("footer") => footer(args.collect()),
+        Some("list") => list(args.collect()),
+        Some("show") => show(args.collect()),
+        Some("requeue") => requeue(args.collect()),
+        Some("publish") => publish(args.collect()),
+        Some("purge") => purge(args.collect()),
+        Some("replay") => replay(args.collect()),
+        Some("stats") => stats(args.collect()),
+        Some("-h") | Some("--help") | None => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(other) => Err(anyhow::anyhow!("Unknown command: {}\n\n{}", other, USAGE)),
+    }
+}
+
+fn seed(args: Vec<String>) -> Result<()> {
+    let mut feed = DEFAULT_SEED_FEED.to_string();
+    let mut limit = DEFAULT_SEED_LIMIT;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--feed" => feed = args.next().ok_or_else(|| anyhow::anyhow!("--feed requires a value"))?,
+            "--limit" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--limit requires a value"))?;
+                limit = value
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("--limit must be a non-negative integer: {}", e))?;
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    let bin_dir = bin_dir()?;
+    let limit_arg = limit.to_string();
+    let steps = [
+        format!("parser-{}", feed),
+        format!("downloader-{}", feed),
+        "scraper".to_string(),
+    ];
+
+    info!("Seeding up to {} items from {}", limit, feed);
+    for step in &steps {
+        let binary = bin_dir.join(step);
+        info!("Running {} --once --limit {}", binary.display(), limit);
+        let status = Command::new(&binary)
+            .args(["--once", "--limit", &limit_arg])
+            .status()
+            .with_context(|| format!("Failed to start {}", binary.display()))?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("{} exited with {}", step, status));
+        }
+    }
+
+    report_seeded_items(limit)?;
+    info!("Seeding completed. Nothing was translated or published.");
+    Ok(())
+}
+
+/// Directory with the service binaries: CTL_BIN_DIR, or the directory of the ctl binary itself.
+fn bin_dir() -> Result<PathBuf> {
+    if let Ok(dir) = env::var("CTL_BIN_DIR") {
+        if !dir.trim().is_empty() {
+            return Ok(PathBuf::from(dir.trim()));
+        }
+    }
+    let exe = env::current_exe().context("Failed to locate the ctl binary")?;
+    exe.parent()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| anyhow::anyhow!("Failed to locate the ctl binary directory"))
+}
+
+/// Lists the freshly scraped items with the files worth checking before going live.
+fn report_seeded_items(limit: usize) -> Result<()> {
+    let stage = load_pipeline_stage(
+        "scraper",
+        PipelineStage {
+            input_status: Status::Downloaded,
+            output_status: Status::Scraper,
+            input_artifact: "news".to_string(),
+            output_artifact: "scraper".to_string(),
+        },
+    )?;
+
+    let conn = open_db()?;
+    let mut stmt = conn.prepare("SELECT id, title FROM news WHERE status = ? ORDER BY date DESC LIMIT ?")?;
+    let items = stmt
+        .query_map(params![stage.output_status, limit as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if items.is_empty() {
+        warn!("No scraped items found, check the service logs above");
+        return Ok(());
+    }
+
+    info!("{} scraped items ready for review:", items.len());
+    for (id, title) in items {
+        info!(
+            "{} | {}/{}_{}.html | {}/{}_{}.json | {}",
+            id, DATA_DIR, stage.output_artifact, id, DATA_DIR, stage.output_artifact, id, title
+        );
+    }
+
+    Ok(())
+}
+
+/// Summarizes the provider failures that translator, rewriter and illustrator store on news rows.
+fn errors(args: Vec<String>) -> Result<()> {
+    let mut column = "error_code";
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--group-by" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--group-by requires a value"))?;
+                column = match value.as_str() {
+                    "code" => "error_code",
+                    "stage" => "error_stage",
+                    "http_status" => "error_http_status",
+                    "retryable" => "error_retryable",
+                    other => {
+                        return Err(anyhow::anyhow!(
+                            "--group-by must be one of code, stage, http_status, retryable, got '{}'",
+                            other
+                        ))
+                    }
+                };
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    let conn = open_db()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT CAST({0} AS TEXT), COUNT(*), MAX(error_message) FROM news WHERE error_code IS NOT NULL GROUP BY {0} ORDER BY COUNT(*) DESC",
+        column
+    ))?;
+    let groups = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if groups.is_empty() {
+        info!("No provider errors recorded");
+        return Ok(());
+    }
+
+    for (key, count, message) in groups {
+        info!(
+            "{}={} | {} items | e.g. {}",
+            column,
+            key.as_deref().unwrap_or("-"),
+            count,
+            message.as_deref().unwrap_or("-")
+        );
+    }
+
+    Ok(())
+}
+
+/// Reports the `ai_usage` rows written by the translator and rewriter.
+fn usage(args: Vec<String>) -> Result<()> {
+    let mut days = DEFAULT_USAGE_DAYS;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--days" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--days requires a value"))?;
+                days = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|days| *days > 0)
+                    .ok_or_else(|| anyhow::anyhow!("--days must be a positive integer, got '{}'", value))?;
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    let conn = open_db()?;
+    let since = unix_now() - days * 86_400;
+
+    let mut stmt = conn.prepare(
+        "SELECT stage, model, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens), SUM(cost_usd)
+         FROM ai_usage WHERE created_at >= ?1 GROUP BY stage, model ORDER BY SUM(cost_usd) DESC",
+    )?;
+    let groups = stmt
+        .query_map(params![since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, f64>(5)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if groups.is_empty() {
+        info!("No AI usage recorded in the last {} days", days);
+        return Ok(());
+    }
+
+    for (stage, model, items, prompt_tokens, completion_tokens, cost) in groups {
+        info!(
+            "{} | {} | {} items | {} prompt + {} completion tokens | ${:.4}",
+            stage, model, items, prompt_tokens, completion_tokens, cost
+        );
+    }
+
+    // Everything spent on a post counts towards it, including failed attempts and extra languages
+    let (posts, cost): (i64, f64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(cost), 0)
+         FROM (SELECT u.news_id, SUM(u.cost_usd) AS cost
+               FROM ai_usage u JOIN news n ON n.id = u.news_id
+               WHERE n.status IN ('published', 'published_plain', 'updated')
+               GROUP BY u.news_id
+               HAVING MAX(u.created_at) >= ?1)",
+        params![since],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    if posts > 0 {
+        info!(
+            "{} published posts | ${:.4} total | ${:.4} per post",
+            posts,
+            cost,
+            cost / posts as f64
+        );
+    }
+
+    Ok(())
+}
+
+/// Reports the latest `post_engagement` counts of the posts published in the last days.
+fn engagement(args: Vec<String>) -> Result<()> {
+    let mut days = DEFAULT_ENGAGEMENT_DAYS;
+    let mut limit = DEFAULT_ENGAGEMENT_LIMIT;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--days" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--days requires a value"))?;
+                days = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|days| *days > 0)
+                    .ok_or_else(|| anyhow::anyhow!("--days must be a positive integer, got '{}'", value))?;
+            }
+            "--limit" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--limit requires a value"))?;
+                limit = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|limit| *limit > 0)
+                    .ok_or_else(|| anyhow::anyhow!("--limit must be a positive integer, got '{}'", value))?;
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    let conn = open_db()?;
+    let has_table: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'post_engagement')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        info!("No post engagement recorded; set ENGAGEMENT_INTERVAL_SECS for the publisher");
+        return Ok(());
+    }
+
+    // The latest counts of every post published in the period
+    let latest = "SELECT e.news_id, e.lang, e.views, e.forwards
+         FROM post_engagement e JOIN published_messages p ON p.news_id = e.news_id AND p.lang = e.lang
+         WHERE e.rowid IN (SELECT MAX(rowid) FROM post_engagement GROUP BY news_id, lang)
+           AND datetime(p.published_at) >= datetime('now', ?1)";
+    let period = format!("-{} days", days);
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT l.news_id, l.lang, n.title, l.views, l.forwards FROM ({}) l JOIN news n ON n.id = l.news_id
+         ORDER BY l.views DESC LIMIT ?2",
+        latest
+    ))?;
+    let top = stmt
+        .query_map(params![period, limit], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<i64>>(3)?,
+                row.get::<_, Option<i64>>(4)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if top.is_empty() {
+        info!("No engagement recorded for posts of the last {} days", days);
+        return Ok(());
+    }
+
+    info!("Top posts of the last {} days:", days);
+    for (news_id, lang, title, views, forwards) in top {
+        info!(
+            "{} views | {} forwards | {} ({}) {}",
+            views.map_or("-".to_string(), |views| views.to_string()),
+            forwards.map_or("-".to_string(), |forwards| forwards.to_string()),
+            news_id,
+            if lang.is_empty() { "default" } else { lang.as_str() },
+            title
+        );
+    }
+
+    // Variant b exists only with AI_PROVIDER_REWRITER_VARIANT_PROMPT
+    let mut stmt = conn.prepare(&format!(
+        "SELECT v.variant, COUNT(*), AVG(l.views), AVG(l.forwards) FROM ({}) l
+         JOIN publish_variants v ON v.news_id = l.news_id AND v.lang = l.lang
+         GROUP BY v.variant ORDER BY v.variant",
+        latest
+    ))?;
+    let variants = stmt
+        .query_map(params![period], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, Option<f64>>(2)?,
+                row.get::<_, Option<f64>>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (variant, posts, views, forwards) in variants {
+        info!(
+            "Variant {} | {} posts | {:.1} views | {:.1} forwards on average",
+            variant,
+            posts,
+            views.unwrap_or(0.0),
+            forwards.unwrap_or(0.0)
+        );
+    }
+
+    Ok(())
+}
+
+/// Manages the `post_footers` table the publisher picks footer blocks from.
+fn footer(args: Vec<String>) -> Result<()> {
+    let mut add: Option<String> = None;
+    let mut lang = String::new();
+    let mut change: Option<(&str, i64)> = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--add" => add = Some(args.next().ok_or_else(|| anyhow::anyhow!("--add requires the footer text"))?),
+            "--lang" => lang = args.next().ok_or_else(|| anyhow::anyhow!("--lang requires a language code"))?.to_ascii_lowercase(),
+            "--enable" | "--disable" | "--delete" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("{} requires a footer id", arg))?;
+                let id = value
+                    .parse::<i64>()
+                    .map_err(|_| anyhow::anyhow!("{} requires a footer id, got '{}'", arg, value))?;
+                change = Some((
+                    match arg.as_str() {
+                        "--enable" => "enable",
+                        "--disable" => "disable",
+                        _ => "delete",
+                    },
+                    id,
+                ));
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    let conn = open_db()?;
+    // Same schema as the publisher creates
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS post_footers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            text TEXT NOT NULL,
+            lang TEXT NOT NULL DEFAULT '',
+            active INTEGER NOT NULL DEFAULT 1
+        )",
+        [],
+    )?;
+
+    if let Some(text) = add {
+        if text.trim().is_empty() {
+            return Err(anyhow::anyhow!("The footer text is empty"));
+        }
+        conn.execute("INSERT INTO post_footers (text, lang) VALUES (?1, ?2)", params![text.trim(), lang])?;
+        info!("Added footer {}", conn.last_insert_rowid());
+        return Ok(());
+    }
+    if let Some((action, id)) = change {
+        let changed = match action {
+            "enable" => conn.execute("UPDATE post_footers SET active = 1 WHERE id = ?1", params![id])?,
+            "disable" => conn.execute("UPDATE post_footers SET active = 0 WHERE id = ?1", params![id])?,
+            _ => conn.execute("DELETE FROM post_footers WHERE id = ?1", params![id])?,
+        };
+        if changed == 0 {
+            return Err(anyhow::anyhow!("No footer with id {}", id));
+        }
+        info!("Footer {}: {}d", id, action);
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare("SELECT id, lang, active, text FROM post_footers ORDER BY id")?;
+    let footers = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, bool>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if footers.is_empty() {
+        info!("No footers defined");
+    }
+    for (id, lang, active, text) in footers {
+        info!(
+            "{} | {} | {} | {}",
+            id,
+            if lang.is_empty() { "all languages" } else { lang.as_str() },
+            if active { "active" } else { "disabled" },
+            text.replace('\n', " ")
+        );
+    }
+    Ok(())
+}
+
+/// Lists items in the `review` status set by the rewriter's fact check, in `illustrator_review` set
+/// by the illustrator's image safety or repetition checks or in `moderation_hold` set by the publisher, or releases
+/// one of them.
+fn review(args: Vec<String>) -> Result<()> {
+    let mut approve: Option<String> = None;
+    let mut lang: Option<String> = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--approve" => approve = Some(args.next().ok_or_else(|| anyhow::anyhow!("--approve requires an item id"))?),
+            "--lang" => lang = Some(args.next().ok_or_else(|| anyhow::anyhow!("--lang requires a language code"))?),
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    let conn = open_db()?;
+
+    let illustrator = builtin_stage("illustrator")?;
+
+    if let Some(id) = approve {
+        let rewriter = builtin_stage("rewriter")?;
+        let next_status = match item_status(&conn, &id, lang.as_deref())? {
+            Status::Review => rewriter.output_status,
+            Status::IllustratorReview => illustrator.output_status,
+            // Back to the publisher's queue; the approved hold keeps moderation from stopping it again
+            Status::ModerationHold => {
+                conn.execute(
+                    "UPDATE moderation_holds SET approved = 1 WHERE news_id = ?1 AND lang = ?2",
+                    params![id, lang.as_deref().unwrap_or("")],
+                )?;
+                stage_input_status("publisher", lang.is_some())?
+            }
+            _ => return Err(anyhow::anyhow!("Item {} is not waiting for review", id)),
+        };
+        set_item_status(&conn, &id, lang.as_deref(), &next_status)?;
+        info!("Item {} approved, status set to {}", id, next_status);
+        return Ok(());
+    }
+
+    let mut items = Vec::new();
+    let mut stmt = conn.prepare(
+        "SELECT id, NULL, title, status FROM news WHERE status IN ('review', 'illustrator_review', 'moderation_hold') ORDER BY id",
+    )?;
+    items.extend(
+        stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+            .collect::<rusqlite::Result<Vec<_>>>()?,
+    );
+    let mut stmt = conn.prepare(
+        "SELECT t.news_id, t.lang, n.title, t.status FROM news_translations t JOIN news n ON n.id = t.news_id
+         WHERE t.status IN ('review', 'moderation_hold') ORDER BY t.news_id, t.lang",
+    )?;
+    items.extend(
+        stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+            .collect::<rusqlite::Result<Vec<_>>>()?,
+    );
+
+    if items.is_empty() {
+        info!("No items waiting for review");
+        return Ok(());
+    }
+
+    for (id, lang, title, status) in items {
+        let findings = if status == "moderation_hold" {
+            conn.query_row(
+                "SELECT reason FROM moderation_holds WHERE news_id = ?1 AND lang = ?2",
+                params![id, lang.as_deref().unwrap_or("")],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .unwrap_or_default()
+        } else {
+            let artifact = match (status.as_str(), &lang) {
+                ("illustrator_review", _) => format!("{}_review_{}.txt", illustrator.output_artifact, id),
+                (_, Some(lang)) => format!("factcheck_{}_{}.txt", id, lang),
+                (_, None) => format!("factcheck_{}.txt", id),
+            };
+            load_artifact(&artifact)?
+                .map(|findings| String::from_utf8_lossy(&findings).into_owned())
+                .unwrap_or_default()
+        };
+        info!(
+            "{} ({}) | {} | {} | {}",
+            id,
+            lang.as_deref().unwrap_or("default"),
+            status,
+            title.as_deref().unwrap_or("-"),
+            findings.trim().replace('\n', "; ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Walks the `publish_chain` table written by the publisher and reports the first broken link.
+fn verify_chain(args: Vec<String>) -> Result<()> {
+    let mut check_files = false;
+    for arg in args {
+        match arg.as_str() {
+            "--files" => check_files = true,
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    let publisher = builtin_stage("publisher")?;
+
+    let conn = open_db()?;
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'publish_chain'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        info!("No publish chain recorded yet");
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT seq, news_id, lang, status, published_at, content_hash, prev_hash, record_hash FROM publish_chain ORDER BY seq",
+    )?;
+    let records = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, String>(7)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut expected_prev = GENESIS_HASH.to_string();
+    let mut broken = 0;
+    for (seq, news_id, lang, status, published_at, content_hash, prev_hash, record_hash) in &records {
+        if *prev_hash != expected_prev {
+            error!("Record {} ({}): prev_hash does not match the previous record", seq, news_id);
+            broken += 1;
+        }
+        if publish_record_hash(prev_hash, news_id, lang, status, published_at, content_hash) != *record_hash {
+            error!("Record {} ({}): record_hash does not match its contents", seq, news_id);
+            broken += 1;
+        }
+        if check_files {
+            let suffix = if lang.is_empty() { news_id.clone() } else { format!("{}_{}", news_id, lang) };
+            let html_artifact = format!("{}_{}.html", publisher.output_artifact, suffix);
+            match load_artifact(&html_artifact)? {
+                Some(html) => {
+                    let mut content = Sha256::new();
+                    content.update(html);
+                    if let Some(image) = load_artifact(&format!("illustrator_{}.png", news_id))? {
+                        content.update(image);
+                        // Album photos (ILLUSTRATOR_IMAGES_PER_ITEM) follow in order, like in the publisher
+                        for k in 2..=MAX_ALBUM_IMAGES {
+                            match load_artifact(&format!("illustrator_{}_{}.png", news_id, k))? {
+                                Some(image) => content.update(image),
+                                None => break,
+                            }
+                        }
+                    }
+                    if hex::encode(content.finalize()) != *content_hash {
+                        error!("Record {} ({}): {} changed since publishing", seq, news_id, html_artifact);
+                        broken += 1;
+                    }
+                }
+                None => warn!("Record {} ({}): {} is missing, content not checked", seq, news_id, html_artifact),
+            }
+        }
+        expected_prev = record_hash.clone();
+    }
+
+    if broken > 0 {
+        return Err(anyhow::anyhow!("Publish chain verification failed: {} problems in {} records", broken, records.len()));
+    }
+    info!("Publish chain is intact: {} records, head {}", records.len(), expected_prev);
+    Ok(())
+}
+
+/// Lists items, newest first, from `news` and (with --status) `news_translations`.
+fn list(args: Vec<String>) -> Result<()> {
+    let mut status: Option<String> = None;
+    let mut limit = DEFAULT_LIST_LIMIT;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--status" => status = Some(args.next().ok_or_else(|| anyhow::anyhow!("--status requires a value"))?),
+            "--limit" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--limit requires a value"))?;
+                limit = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|limit| *limit > 0)
+                    .ok_or_else(|| anyhow::anyhow!("--limit must be a positive integer, got '{}'", value))?;
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    let conn = open_db()?;
+    let mut sql = "SELECT id, NULL, status, attempts, error, title, date FROM news WHERE ?1 IS NULL OR status = ?1".to_string();
+    if status.is_some() {
+        sql.push_str(
+            " UNION ALL SELECT t.news_id, t.lang, t.status, t.attempts, t.error, n.title, n.date
+             FROM news_translations t JOIN news n ON n.id = t.news_id WHERE t.status = ?1",
+        );
+    }
+    sql.push_str(" ORDER BY 7 DESC LIMIT ?2");
+    let mut stmt = conn.prepare(&sql)?;
+    let items = stmt
+        .query_map(params![status, limit], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if items.is_empty() {
+        info!("No items found");
+        return Ok(());
+    }
+    for (id, lang, status, attempts, error, title) in items {
+        info!(
+            "{} ({}) | {} | {} attempts | {} | {}",
+            id,
+            lang.as_deref().unwrap_or("default"),
+            status,
+            attempts,
+            title,
+            error.as_deref().unwrap_or("-").replace('\n', " ")
+        );
+    }
+    Ok(())
+}
+
+/// Everything recorded about one item: its rows, what the stages and the publisher logged about
+/// it, and its artifacts.
+fn show(args: Vec<String>) -> Result<()> {
+    let id = item_id_argument(args, "show")?;
+    let conn = open_db()?;
+
+    let item = conn
+        .query_row(
+            "SELECT title, url, date, status, source, category, attempts, error,
+                    datetime(created_at, 'unixepoch'), datetime(updated_at, 'unixepoch'),
+                    datetime(last_attempt_at, 'unixepoch'), datetime(next_attempt_at, 'unixepoch'), claimed_by
+             FROM news WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok([
+                    ("title", row.get::<_, Option<String>>(0)?),
+                    ("url", row.get(1)?),
+                    ("date", row.get(2)?),
+                    ("status", row.get(3)?),
+                    ("source", row.get(4)?),
+                    ("category", row.get(5)?),
+                    ("attempts", row.get::<_, Option<i64>>(6)?.map(|attempts| attempts.to_string())),
+                    ("error", row.get(7)?),
+                    ("created", row.get(8)?),
+                    ("updated", row.get(9)?),
+                    ("last attempt", row.get(10)?),
+                    ("next attempt", row.get(11)?),
+                    ("claimed by", row.get(12)?),
+                ])
+            },
+        )
+        .optional()?
+        .ok_or_else(|| anyhow::anyhow!("No item with id {}", id))?;
+    for (field, value) in item {
+        if let Some(value) = value {
+            info!("{}: {}", field, value.replace('\n', " "));
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT lang, status, attempts, error, datetime(updated_at, 'unixepoch') FROM news_translations
+         WHERE news_id = ?1 ORDER BY lang",
+    )?;
+    let translations = stmt
+        .query_map(params![id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (lang, status, attempts, error, updated) in translations {
+        info!(
+            "translation {} | {} | {} attempts | updated {} | {}",
+            lang,
+            status,
+            attempts,
+            updated.as_deref().unwrap_or("-"),
+            error.as_deref().unwrap_or("-").replace('\n', " ")
+        );
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT datetime(created_at, 'unixepoch'), stage, COALESCE(lang, ''), model, prompt_tokens, completion_tokens, cost_usd
+         FROM ai_usage WHERE news_id = ?1 ORDER BY created_at",
+    )?;
+    let calls = stmt
+        .query_map(params![id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, f64>(6)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (at, stage, lang, model, prompt_tokens, completion_tokens, cost) in calls {
+        info!(
+            "{} | {} {} | {} | {} prompt + {} completion tokens | ${:.4}",
+            at, stage, lang, model, prompt_tokens, completion_tokens, cost
+        );
+    }
+
+    if has_table(&conn, "publish_chain")? {
+        let mut stmt = conn.prepare("SELECT published_at, lang, status FROM publish_chain WHERE news_id = ?1 ORDER BY seq")?;
+        let records = stmt
+            .query_map(params![id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for (published_at, lang, status) in records {
+            info!(
+                "{} | {} | {}",
+                published_at,
+                if lang.is_empty() { "default" } else { lang.as_str() },
+                status
+            );
+        }
+    }
+
+    let artifacts = list_artifacts()?;
+    let files = item_files(Path::new(DATA_DIR), &id, &artifacts)?;
+    let stored = stored_artifacts(&artifacts, &id);
+    if files.is_empty() && stored.is_empty() {
+        info!("No artifacts");
+    }
+    for path in files {
+        info!("file {} ({} bytes)", path.display(), fs::metadata(&path)?.len());
+    }
+    for name in stored {
+        let size = load_artifact(&name)?.map_or(0, |content| content.len());
+        info!("stored artifact {} ({} bytes)", name, size);
+    }
+    Ok(())
+}
+
+/// Sets the status of an item or a translation and clears its retry state and claim, so the
+/// stage that reads that status takes it up in its next cycle.
+fn requeue(args: Vec<String>) -> Result<()> {
+    let mut id: Option<String> = None;
+    let mut lang: Option<String> = None;
+    let mut status: Option<String> = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lang" => lang = Some(args.next().ok_or_else(|| anyhow::anyhow!("--lang requires a language code"))?),
+            "--status" => status = Some(args.next().ok_or_else(|| anyhow::anyhow!("--status requires a value"))?),
+            other if !other.starts_with('-') && id.is_none() => id = Some(other.to_string()),
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+    let id = id.ok_or_else(|| anyhow::anyhow!("requeue requires an item id\n\n{}", USAGE))?;
+
+    let conn = open_db()?;
+    let current = item_status(&conn, &id, lang.as_deref())?;
+    let next_status = match status {
+        Some(status) => Status::from(status.as_str()),
+        None => {
+            let stage = match current.as_str() {
+                "scraper_error" => "scraper",
+                "translator_error" => "translator",
+                "rewriter_error" => "rewriter",
+                "illustrator_error" => "illustrator",
+                "publish_error" => "publisher",
+                other => {
+                    return Err(anyhow::anyhow!(
+                        "Item {} is in status '{}', which no stage failed in; pass --status",
+                        id,
+                        other
+                    ))
+                }
+            };
+            stage_input_status(stage, lang.is_some())?
+        }
+    };
+
+    set_item_status(&conn, &id, lang.as_deref(), &next_status)?;
+    info!("Item {} requeued: {} -> {}", id, current, next_status);
+    Ok(())
+}
+
+/// Moves an item (or a translation) into the publisher's input status, whatever stage it is in.
+fn publish(args: Vec<String>) -> Result<()> {
+    let mut id: Option<String> = None;
+    let mut lang: Option<String> = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lang" => lang = Some(args.next().ok_or_else(|| anyhow::anyhow!("--lang requires a language code"))?),
+            other if !other.starts_with('-') && id.is_none() => id = Some(other.to_string()),
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+    let id = id.ok_or_else(|| anyhow::anyhow!("publish requires an item id\n\n{}", USAGE))?;
+
+    let conn = open_db()?;
+    let current = item_status(&conn, &id, lang.as_deref())?;
+    if matches!(current, Status::Published | Status::PublishedPlain | Status::Updated) {
+        return Err(anyhow::anyhow!("Item {} is already published ({})", id, current));
+    }
+
+    // The hold would stop it again; an approved one is skipped like after `ctl review --approve`
+    if has_table(&conn, "moderation_holds")? {
+        conn.execute(
+            "UPDATE moderation_holds SET approved = 1 WHERE news_id = ?1 AND lang = ?2",
+            params![id, lang.as_deref().unwrap_or("")],
+        )?;
+    }
+    let next_status = stage_input_status("publisher", lang.is_some())?;
+    set_item_status(&conn, &id, lang.as_deref(), &next_status)?;
+    info!("Item {} sent to the publisher: {} -> {}", id, current, next_status);
+    Ok(())
+}
+
+/// Deletes an item's rows and artifacts. The publish chain, AI usage and engagement records stay.
+fn purge(args: Vec<String>) -> Result<()> {
+    let mut id: Option<String> = None;
+    let mut confirmed = false;
+    for arg in args {
+        match arg.as_str() {
+            "--yes" => confirmed = true,
+            other if !other.starts_with('-') && id.is_none() => id = Some(other.to_string()),
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+    let id = id.ok_or_else(|| anyhow::anyhow!("purge requires an item id\n\n{}", USAGE))?;
+
+    let conn = open_db()?;
+    let status = item_status(&conn, &id, None)?;
+    let artifacts = list_artifacts()?;
+    let files = item_files(Path::new(DATA_DIR), &id, &artifacts)?;
+    let stored = stored_artifacts(&artifacts, &id);
+
+    if !confirmed {
+        info!("Would delete item {} ({})", id, status);
+        for path in &files {
+            info!("Would delete {}", path.display());
+        }
+        for name in &stored {
+            info!("Would delete stored artifact {}", name);
+        }
+        info!("Run again with --yes to delete them");
+        return Ok(());
+    }
+
+    conn.execute("DELETE FROM news_translations WHERE news_id = ?1", params![id])?;
+    conn.execute("DELETE FROM news WHERE id = ?1", params![id])?;
+    for name in &stored {
+        remove_artifact(name)?;
+    }
+    for path in &files {
+        fs::remove_file(path).with_context(|| format!("Failed to delete {}", path.display()))?;
+    }
+    info!("Purged item {} ({}) and {} artifacts", id, status, files.len() + stored.len());
+    Ok(())
+}
+
+/// Sends items back to the input status of a stage, so that stage and the ones after it process
+/// them again, e.g. to re-publish with a new template (the publisher then edits the posts) or to
+/// re-illustrate. Their artifacts are copied aside first, since the stages overwrite them.
+fn replay(args: Vec<String>) -> Result<()> {
+    let mut stage: Option<String> = None;
+    let mut ids: Vec<String> = Vec::new();
+    let mut since: Option<String> = None;
+    let mut until: Option<String> = None;
+    let mut source: Option<String> = None;
+    let mut status: Option<String> = None;
+    let mut lang: Option<String> = None;
+    let mut confirmed = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--since" => since = Some(args.next().ok_or_else(|| anyhow::anyhow!("--since requires a day"))?),
+            "--until" => until = Some(args.next().ok_or_else(|| anyhow::anyhow!("--until requires a day"))?),
+            "--source" => source = Some(args.next().ok_or_else(|| anyhow::anyhow!("--source requires a value"))?),
+            "--status" => status = Some(args.next().ok_or_else(|| anyhow::anyhow!("--status requires a value"))?),
+            "--lang" => lang = Some(args.next().ok_or_else(|| anyhow::anyhow!("--lang requires a language code"))?),
+            "--yes" => confirmed = true,
+            other if !other.starts_with('-') && stage.is_none() => stage = Some(other.to_string()),
+            other if !other.starts_with('-') => ids.push(other.to_string()),
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+    let stage_name = stage.ok_or_else(|| anyhow::anyhow!("replay requires a stage\n\n{}", USAGE))?;
+    if !matches!(stage_name.as_str(), "translator" | "rewriter" | "illustrator" | "publisher") {
+        return Err(anyhow::anyhow!(
+            "replay runs the translator, rewriter, illustrator or publisher again, not '{}'",
+            stage_name
+        ));
+    }
+    // Replaying the whole database by accident would cost a lot of AI calls
+    if ids.is_empty() && since.is_none() && until.is_none() && source.is_none() && status.is_none() {
+        return Err(anyhow::anyhow!(
+            "replay requires item ids or at least one of --since, --until, --source and --status"
+        ));
+    }
+
+    let conn = open_db()?;
+    let since = since.map(|day| day_argument(&conn, "--since", &day)).transpose()?;
+    let until = until.map(|day| day_argument(&conn, "--until", &day)).transpose()?;
+    let stage = builtin_stage(&stage_name)?;
+    let target = stage_input_status(&stage_name, lang.is_some())?;
+
+    let filters = "(?1 IS NULL OR date(n.created_at, 'unixepoch') >= ?1)
+         AND (?2 IS NULL OR date(n.created_at, 'unixepoch') <= ?2)
+         AND (?3 IS NULL OR n.source = ?3)";
+    let sql = match &lang {
+        Some(_) => format!(
+            "SELECT t.news_id, t.status, t.claimed_by FROM news_translations t JOIN news n ON n.id = t.news_id
+             WHERE {} AND (?4 IS NULL OR t.status = ?4) AND t.lang = ?5 ORDER BY n.created_at",
+            filters
+        ),
+        None => format!(
+            "SELECT n.id, n.status, n.claimed_by FROM news n
+             WHERE {} AND (?4 IS NULL OR n.status = ?4) AND ?5 IS NULL ORDER BY n.created_at",
+            filters
+        ),
+    };
+    let mut stmt = conn.prepare(&sql)?;
+    let mut items = stmt
+        .query_map(params![since, until, source, status, lang], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Status>(1)?, row.get::<_, Option<String>>(2)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if !ids.is_empty() {
+        items.retain(|(id, _, _)| ids.contains(id));
+        if let Some(missing) = ids.iter().find(|id| !items.iter().any(|(item, _, _)| item == *id)) {
+            return Err(anyhow::anyhow!("Item {} does not exist or does not match the other filters", missing));
+        }
+    }
+    if items.is_empty() {
+        info!("No items to replay");
+        return Ok(());
+    }
+
+    let backup_dir = Path::new(REPLAY_BACKUP_DIR).join(unix_now().to_string());
+    let artifacts = list_artifacts()?;
+    let mut replayed = 0;
+    for (id, current, claimed_by) in items {
+        let suffix = match &lang {
+            Some(lang) => format!("{}_{}", id, lang),
+            None => id.clone(),
+        };
+        if current == target {
+            info!("Skipping item {}: already waiting for the {}", suffix, stage_name);
+            continue;
+        }
+        // A worker is on it right now and would move it on after us
+        if let Some(worker) = claimed_by {
+            warn!("Skipping item {}: {} is processing it", suffix, worker);
+            continue;
+        }
+        let input = format!("{}_{}.html", stage.input_artifact, suffix);
+        if !artifact_exists(&input)? {
+            warn!("Skipping item {}: {} has no input {}", suffix, stage_name, input);
+            continue;
+        }
+
+        let files: Vec<PathBuf> = item_files(Path::new(DATA_DIR), &id, &artifacts)?
+            .into_iter()
+            .filter(|path| !path.starts_with(REPLAY_BACKUP_DIR))
+            .collect();
+        let stored = stored_artifacts(&artifacts, &id);
+        if !confirmed {
+            info!(
+                "Would replay the {} for item {} ({} -> {}), backing up {} artifacts",
+                stage_name,
+                suffix,
+                current,
+                target,
+                files.len() + stored.len()
+            );
+            replayed += 1;
+            continue;
+        }
+
+        for path in &files {
+            let copy = backup_dir.join(path.strip_prefix(DATA_DIR).unwrap_or(path));
+            if let Some(parent) = copy.parent() {
+                fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            fs::copy(path, &copy).with_context(|| format!("Failed to back up {}", path.display()))?;
+        }
+        for name in &stored {
+            let Some(content) = load_artifact(name)? else {
+                continue;
+            };
+            let copy = backup_dir.join(name);
+            fs::create_dir_all(&backup_dir).with_context(|| format!("Failed to create {}", backup_dir.display()))?;
+            fs::write(&copy, content).with_context(|| format!("Failed to back up stored artifact {}", name))?;
+        }
+        set_item_status(&conn, &id, lang.as_deref(), &target)?;
+        info!("Replaying the {} for item {}: {} -> {}", stage_name, suffix, current, target);
+        replayed += 1;
+    }
+
+    if !confirmed {
+        info!("Run again with --yes to replay {} items", replayed);
+    } else if replayed > 0 {
+        info!("Replayed {} items, previous artifacts are in {}", replayed, backup_dir.display());
+    }
+    Ok(())
+}
+
+/// A UTC day as `YYYY-MM-DD`: given like that, or `today` / `yesterday`.
+fn day_argument(conn: &Connection, flag: &str, value: &str) -> Result<String> {
+    let modifier = match value {
+        "today" => "+0 days",
+        "yesterday" => "-1 days",
+        _ => {
+            let valid = value.len() == 10
+                && value.char_indices().all(|(i, c)| if i == 4 || i == 7 { c == '-' } else { c.is_ascii_digit() });
+            let day: Option<String> = if valid {
+                conn.query_row("SELECT date(?1)", params![value], |row| row.get(0))?
+            } else {
+                None
+            };
+            return day
+                .filter(|day| day == value)
+                .ok_or_else(|| anyhow::anyhow!("{} must be a day as YYYY-MM-DD, today or yesterday, got '{}'", flag, value));
+        }
+    };
+    Ok(conn.query_row("SELECT date('now', ?1)", params![modifier], |row| row.get(0))?)
+}
+
+/// Counts items and translations per status; the age shows queues that stopped moving.
+fn stats(args: Vec<String>) -> Result<()> {
+    if let Some(other) = args.first() {
+        return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE));
+    }
+
+    let conn = open_db()?;
+    for (label, table) in [("items", "news"), ("translations", "news_translations")] {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT status, COUNT(*), CAST(strftime('%s', 'now') AS INTEGER) - MIN(updated_at) FROM {}
+             GROUP BY status ORDER BY COUNT(*) DESC",
+            table
+        ))?;
+        let groups = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, Option<i64>>(2)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let total: i64 = groups.iter().map(|(_, count, _)| count).sum();
+        info!("{} {}", total, label);
+        for (status, count, age) in groups {
+            info!(
+                "  {} | {} | oldest unchanged for {}",
+                status,
+                count,
+                age.map_or("-".to_string(), format_age)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn format_age(secs: i64) -> String {
+    match secs {
+        secs if secs < 3600 => format!("{}m", secs / 60),
+        secs if secs < 86_400 => format!("{}h", secs / 3600),
+        secs => format!("{}d", secs / 86_400),
+    }
+}
+
+fn item_id_argument(args: Vec<String>, command: &str) -> Result<String> {
+    match args.as_slice() {
+        [id] if !id.starts_with('-') => Ok(id.clone()),
+        _ => Err(anyhow::anyhow!("{} requires exactly one item id\n\n{}", command, USAGE)),
+    }
+}
+
+fn has_table(conn: &Connection, name: &str) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        params![name],
+        |row| row.get(0),
+    )?)
+}
+
+/// Files under `dir` with the item id as one of the `_`-separated parts of their name, like
+/// `rewriter_b_<id>_de.html` or `ai_log/rewriter_<id>.json`. Files directly in `dir` that are
+/// `artifacts` (ARTIFACT_STORE=files) are left out; `stored_artifacts` lists them.
+fn item_files(dir: &Path, id: &str, artifacts: &[String]) -> Result<Vec<PathBuf>> {
+    let root = dir;
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            if path.parent() == Some(root) && artifacts.iter().any(|artifact| artifact == name) {
+                continue;
+            }
+            if name_has_part(name, id) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The item's artifacts among all `artifacts` of the store ARTIFACT_STORE names (`data/`, the
+/// `artifacts` table or the S3 bucket).
+fn stored_artifacts(artifacts: &[String], id: &str) -> Vec<String> {
+    let mut names: Vec<String> = artifacts.iter().filter(|name| name_has_part(name, id)).cloned().collect();
+    names.sort();
+    names
+}
+
+fn name_has_part(name: &str, part: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name);
+    stem.split('_').any(|candidate| candidate == part)
+}
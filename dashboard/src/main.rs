@@ -0,0 +1,520 @@
+use anyhow::{Context, Result};
+use axum::extract::{Path as UrlPath, Request};
+use axum::http::header::{HOST, ORIGIN, REFERER};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use robo_news_core::{
+    builtin_stage, init_logging, item_status, load_config_file, load_secret_files, open_db, open_db_read_only,
+    set_item_status, stage_input_status, unix_now,
+};
+use rusqlite::{params, Connection};
+use std::env;
+use std::fmt::Write as _;
+use tracing::{error, info, warn};
+
+const DEFAULT_BIND_ADDR: &str = "127.0.0.1:8081";
+const RECENT_ERRORS: i64 = 20;
+const LATENCY_DAYS: i64 = 7;
+const SPEND_DAYS: i64 = 30;
+const REFRESH_SECS: u32 = 30;
+const MIN_HEARTBEAT_AGE_SECS: i64 = 900;
+
+/// Error statuses and the stage each one failed in.
+const ERROR_STATUSES: &[(&str, &str)] = &[
+    ("scraper_error", "scraper"),
+    ("translator_error", "translator"),
+    ("rewriter_error", "rewriter"),
+    ("illustrator_error", "illustrator"),
+    ("publish_error", "publisher"),
+];
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.3em 0.8em; text-align: left; vertical-align: top; }
+td.error { max-width: 40em; font-family: monospace; font-size: 0.85em; }
+form { margin: 0; }";
+
+fn main() -> Result<()> {
+    load_config_file()?;
+    load_secret_files()?;
+    init_logging()?;
+    serve()
+}
+
+#[tokio::main]
+async fn serve() -> Result<()> {
+    // Fail at startup rather than on the first button press
+    for (_, stage) in ERROR_STATUSES {
+        builtin_stage(stage)?;
+    }
+    open_db()?;
+
+    let bind_addr = match env::var("DASHBOARD_BIND_ADDR") {
+        Ok(addr) if !addr.trim().is_empty() => addr.trim().to_string(),
+        _ => DEFAULT_BIND_ADDR.to_string(),
+    };
+
+    let buttons = Router::new()
+        .route("/requeue/:id", post(requeue_item_handler))
+        .route("/requeue/:id/:lang", post(requeue_translation_handler))
+        .route("/requeue-errors", post(requeue_errors_handler))
+        .route_layer(middleware::from_fn(require_same_origin));
+    let app = Router::new().route("/", get(dashboard_handler)).merge(buttons);
+
+    let listener = tokio::net::TcpListener::bind(&bind_addr)
+        .await
+        .with_context(|| format!("Failed to bind {}", bind_addr))?;
+    info!("Serving dashboard on http://{}/", bind_addr);
+
+    axum::serve(listener, app).await.context("Dashboard server stopped")?;
+    Ok(())
+}
+
+async fn dashboard_handler() -> Response {
+    match with_database(render_dashboard).await {
+        Ok(html) => Html(html).into_response(),
+        Err(e) => {
+            error!("Failed to render dashboard: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response()
+        }
+    }
+}
+
+async fn requeue_item_handler(UrlPath(id): UrlPath<String>) -> Response {
+    requeue_response(with_database(move || requeue(&open_db()?, &id, None)).await)
+}
+
+async fn requeue_translation_handler(UrlPath((id, lang)): UrlPath<(String, String)>) -> Response {
+    requeue_response(with_database(move || requeue(&open_db()?, &id, Some(&lang))).await)
+}
+
+async fn requeue_errors_handler() -> Response {
+    requeue_response(with_database(|| requeue_all_errors(&open_db()?)).await)
+}
+
+/// Runs SQLite work on the blocking pool, so a locked database (`DB_BUSY_TIMEOUT_SECS`) doesn't
+/// hold up the server's other requests.
+async fn with_database<T: Send + 'static>(work: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(work).await.context("Database task failed")?
+}
+
+/// Refuses button presses from other sites' pages, which could otherwise submit the forms from a
+/// browser that can reach the dashboard.
+async fn require_same_origin(request: Request, next: Next) -> Response {
+    if is_same_origin(request.headers()) {
+        return next.run(request).await;
+    }
+    warn!("Refused a cross-origin request to {}", request.uri());
+    (StatusCode::FORBIDDEN, "Cross-origin request refused").into_response()
+}
+
+/// Whether the browser's `Origin` (or `Referer`) names the host the request was sent to, which is
+/// `X-Forwarded-Host` behind a reverse proxy.
+fn is_same_origin(headers: &HeaderMap) -> bool {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let host = header("x-forwarded-host")
+        .and_then(|hosts| hosts.split(',').next())
+        .or_else(|| header(HOST.as_str()));
+    let source = header(ORIGIN.as_str())
+        .filter(|origin| *origin != "null")
+        .or_else(|| header(REFERER.as_str()));
+    let (Some(host), Some(source)) = (host, source) else {
+        return false;
+    };
+    let authority = source.split_once("://").map_or("", |(_, rest)| rest).split('/').next().unwrap_or_default();
+    !authority.is_empty() && authority.eq_ignore_ascii_case(host.trim())
+}
+
+/// Back to the dashboard after a button, which then shows the new queue depths.
+fn requeue_response(result: Result<()>) -> Response {
+    match result {
+        Ok(()) => Redirect::to("/").into_response(),
+        Err(e) => {
+            error!("Requeue failed: {:#}", e);
+            (StatusCode::CONFLICT, format!("{:#}", e)).into_response()
+        }
+    }
+}
+
+fn render_dashboard() -> Result<String> {
+    // Rendering only reads; the buttons open their own connection
+    let conn = open_db_read_only()?;
+    let now = unix_now();
+
+    let mut html = String::new();
+    write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta http-equiv=\"refresh\" content=\"{}\">\n\
+         <title>Robo News dashboard</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<h1>Robo News</h1>\n",
+        REFRESH_SECS, STYLE
+    )?;
+    render_queues(&conn, now, &mut html)?;
+    render_workers(&conn, now, &mut html)?;
+    render_errors(&conn, now, &mut html)?;
+    render_latency(&conn, now, &mut html)?;
+    render_spend(&conn, now, &mut html)?;
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
+
+/// Items and translations per status; the age shows queues that stopped moving.
+fn render_queues(conn: &Connection, now: i64, html: &mut String) -> Result<()> {
+    html.push_str("<h2>Queues</h2>\n");
+    for (label, table) in [("Items", "news"), ("Translations", "news_translations")] {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT status, COUNT(*), MIN(updated_at) FROM {} GROUP BY status ORDER BY COUNT(*) DESC",
+            table
+        ))?;
+        let groups = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, Option<i64>>(2)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        write!(
+            html,
+            "<h3>{}</h3>\n<table>\n<tr><th>Status</th><th>Count</th><th>Oldest unchanged</th></tr>\n",
+            label
+        )?;
+        for (status, count, oldest) in groups {
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&status),
+                count,
+                oldest.map_or("-".to_string(), |oldest| format_age(now - oldest))
+            )?;
+        }
+        html.push_str("</table>\n");
+    }
+    Ok(())
+}
+
+/// Last cycle of every worker; a late one fails `<service> healthcheck` with its default limit.
+fn render_workers(conn: &Connection, now: i64, html: &mut String) -> Result<()> {
+    let mut stmt = conn.prepare("SELECT worker_id, service, interval_secs, beat_at FROM worker_health ORDER BY service, worker_id")?;
+    let workers = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if workers.is_empty() {
+        return Ok(());
+    }
+
+    html.push_str(
+        "<h2>Workers</h2>\n<table>\n<tr><th>Worker</th><th>Service</th><th>Interval</th><th>Last cycle</th><th>State</th></tr>\n",
+    );
+    for (worker_id, service, interval_secs, beat_at) in workers {
+        let late = now - beat_at > (interval_secs * 3).max(MIN_HEARTBEAT_AGE_SECS);
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}s</td><td>{} ago</td><td>{}</td></tr>",
+            escape_html(&worker_id),
+            escape_html(&service),
+            interval_secs,
+            format_age(now - beat_at),
+            if late { "late" } else { "ok" }
+        )?;
+    }
+    html.push_str("</table>\n");
+    Ok(())
+}
+
+/// Items that failed a stage or are waiting for a retry, most recent attempt first.
+fn render_errors(conn: &Connection, now: i64, html: &mut String) -> Result<()> {
+    html.push_str(
+        "<h2>Recent errors</h2>\n<form method=\"post\" action=\"/requeue-errors\">\
+         <button>Requeue all errors</button></form>\n",
+    );
+
+    let statuses = error_status_list();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, NULL, status, attempts, error, last_attempt_at FROM news WHERE status IN ({0}) OR error IS NOT NULL \
+         UNION ALL SELECT news_id, lang, status, attempts, error, last_attempt_at FROM news_translations \
+         WHERE status IN ({0}) OR error IS NOT NULL ORDER BY 6 DESC LIMIT ?1",
+        statuses
+    ))?;
+    let rows = stmt
+        .query_map(params![RECENT_ERRORS], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<i64>>(5)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if rows.is_empty() {
+        html.push_str("<p>No errors.</p>\n");
+        return Ok(());
+    }
+
+    html.push_str(
+        "<table>\n<tr><th>Item</th><th>Language</th><th>Status</th><th>Attempts</th><th>Last attempt</th>\
+         <th>Error</th><th></th></tr>\n",
+    );
+    for (id, lang, status, attempts, error, last_attempt) in rows {
+        // Items still retrying get no button: their stage takes them up again by itself
+        let button = if failed_stage(&status).is_some() {
+            let action = match &lang {
+                Some(lang) => format!("/requeue/{}/{}", id, lang),
+                None => format!("/requeue/{}", id),
+            };
+            format!(
+                "<form method=\"post\" action=\"{}\"><button>Requeue</button></form>",
+                escape_html(&action)
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"error\">{}</td><td>{}</td></tr>",
+            escape_html(&id),
+            escape_html(lang.as_deref().unwrap_or("-")),
+            escape_html(&status),
+            attempts,
+            last_attempt.map_or("-".to_string(), |at| format!("{} ago", format_age(now - at))),
+            escape_html(error.as_deref().unwrap_or("-")),
+            button
+        )?;
+    }
+    html.push_str("</table>\n");
+    Ok(())
+}
+
+/// Average time from an item's discovery to each stage: its first AI call there, or for the
+/// publisher the post itself.
+fn render_latency(conn: &Connection, now: i64, html: &mut String) -> Result<()> {
+    writeln!(html, "<h2>Stage latency (last {} days)</h2>", LATENCY_DAYS)?;
+    let since = now - LATENCY_DAYS * 86_400;
+
+    let mut stmt = conn.prepare(
+        "SELECT stage, COUNT(*), AVG(reached - found) FROM (
+             SELECT u.stage, MIN(u.created_at) AS reached, n.created_at AS found
+             FROM ai_usage u JOIN news n ON n.id = u.news_id
+             WHERE n.created_at IS NOT NULL
+             GROUP BY u.news_id, u.lang, u.stage
+             HAVING reached >= ?1)
+         GROUP BY stage",
+    )?;
+    let mut stages: Vec<(String, i64, f64)> = stmt
+        .query_map(params![since], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if has_table(conn, "publish_chain")? {
+        let (items, average): (i64, Option<f64>) = conn.query_row(
+            "SELECT COUNT(*), AVG(published - found) FROM (
+                 SELECT MIN(CAST(strftime('%s', p.published_at) AS INTEGER)) AS published, n.created_at AS found
+                 FROM publish_chain p JOIN news n ON n.id = p.news_id
+                 WHERE n.created_at IS NOT NULL
+                 GROUP BY p.news_id, p.lang
+                 HAVING published >= ?1)",
+            params![since],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if let Some(average) = average {
+            stages.push(("publisher".to_string(), items, average));
+        }
+    }
+
+    if stages.is_empty() {
+        html.push_str("<p>No items went through a stage.</p>\n");
+        return Ok(());
+    }
+
+    stages.sort_by(|a, b| a.2.total_cmp(&b.2));
+    html.push_str("<table>\n<tr><th>Stage</th><th>Items</th><th>Average time since found</th></tr>\n");
+    for (stage, items, average) in stages {
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&stage),
+            items,
+            format_age(average as i64)
+        )?;
+    }
+    html.push_str("</table>\n");
+    Ok(())
+}
+
+/// Cost of the `ai_usage` rows written by the translator, rewriter and illustrator.
+fn render_spend(conn: &Connection, now: i64, html: &mut String) -> Result<()> {
+    html.push_str("<h2>AI spend</h2>\n");
+    html.push_str("<table>\n<tr><th>Period</th><th>Calls</th><th>Cost</th></tr>\n");
+    for (label, days) in [("Last 24 hours", 1), ("Last 7 days", 7), ("Last 30 days", 30)] {
+        let (calls, cost): (i64, f64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(cost_usd), 0) FROM ai_usage WHERE created_at >= ?1",
+            params![now - days * 86_400],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        writeln!(html, "<tr><td>{}</td><td>{}</td><td>${:.4}</td></tr>", label, calls, cost)?;
+    }
+    html.push_str("</table>\n");
+
+    let mut stmt = conn.prepare(
+        "SELECT stage, model, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens), SUM(cost_usd)
+         FROM ai_usage WHERE created_at >= ?1 GROUP BY stage, model ORDER BY SUM(cost_usd) DESC",
+    )?;
+    let groups = stmt
+        .query_map(params![now - SPEND_DAYS * 86_400], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, f64>(5)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if groups.is_empty() {
+        return Ok(());
+    }
+
+    write!(
+        html,
+        "<h3>By stage and model (last {} days)</h3>\n<table>\n<tr><th>Stage</th><th>Model</th><th>Calls</th>\
+         <th>Prompt tokens</th><th>Completion tokens</th><th>Cost</th></tr>\n",
+        SPEND_DAYS
+    )?;
+    for (stage, model, calls, prompt_tokens, completion_tokens, cost) in groups {
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>${:.4}</td></tr>",
+            escape_html(&stage),
+            escape_html(&model),
+            calls,
+            prompt_tokens,
+            completion_tokens,
+            cost
+        )?;
+    }
+    html.push_str("</table>\n");
+    Ok(())
+}
+
+/// Sends an item (or a translation) that failed a stage back to that stage's input status and
+/// clears its retry state and claim, like `ctl requeue`.
+fn requeue(conn: &Connection, id: &str, lang: Option<&str>) -> Result<()> {
+    let current = item_status(conn, id, lang)?;
+    let stage = failed_stage(current.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Item {} is in status '{}', which no stage failed in", id, current))?;
+    let next_status = stage_input_status(stage, lang.is_some())?;
+
+    set_item_status(conn, id, lang, &next_status)?;
+    let label = match lang {
+        Some(lang) => format!("{} ({})", id, lang),
+        None => id.to_string(),
+    };
+    info!("Item {} requeued from the dashboard: {} -> {}", label, current, next_status);
+    Ok(())
+}
+
+fn requeue_all_errors(conn: &Connection) -> Result<()> {
+    let statuses = error_status_list();
+    let mut failed: Vec<(String, Option<String>)> = Vec::new();
+    let mut stmt = conn.prepare(&format!("SELECT id FROM news WHERE status IN ({})", statuses))?;
+    failed.extend(
+        stmt.query_map([], |row| Ok((row.get(0)?, None)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?,
+    );
+    let mut stmt = conn.prepare(&format!(
+        "SELECT news_id, lang FROM news_translations WHERE status IN ({})",
+        statuses
+    ))?;
+    failed.extend(
+        stmt.query_map([], |row| Ok((row.get(0)?, Some(row.get(1)?))))?
+            .collect::<rusqlite::Result<Vec<_>>>()?,
+    );
+
+    for (id, lang) in &failed {
+        requeue(conn, id, lang.as_deref())?;
+    }
+    info!("Requeued {} failed items from the dashboard", failed.len());
+    Ok(())
+}
+
+fn failed_stage(status: &str) -> Option<&'static str> {
+    ERROR_STATUSES
+        .iter()
+        .find(|(error_status, _)| *error_status == status)
+        .map(|(_, stage)| *stage)
+}
+
+/// `ERROR_STATUSES` as an SQL list, e.g. `'translator_error', 'rewriter_error'`.
+fn error_status_list() -> String {
+    ERROR_STATUSES
+        .iter()
+        .map(|(status, _)| format!("'{}'", status))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn has_table(conn: &Connection, name: &str) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        params![name],
+        |row| row.get(0),
+    )?)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_age(secs: i64) -> String {
+    match secs {
+        secs if secs < 3600 => format!("{}m", secs / 60),
+        secs if secs < 86_400 => format!("{}h", secs / 3600),
+        secs => format!("{}d", secs / 86_400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn is_same_origin_compares_the_origin_with_the_host() {
+        assert!(is_same_origin(&headers(&[("host", "127.0.0.1:8081"), ("origin", "http://127.0.0.1:8081")])));
+        assert!(!is_same_origin(&headers(&[("host", "127.0.0.1:8081"), ("origin", "https://evil.example")])));
+        assert!(!is_same_origin(&headers(&[("host", "127.0.0.1:8081"), ("origin", "http://127.0.0.1:8081.evil.example")])));
+    }
+
+    #[test]
+    fn is_same_origin_falls_back_to_the_referer() {
+        let referer = [("host", "localhost:8081"), ("origin", "null"), ("referer", "http://localhost:8081/?x=1")];
+        assert!(is_same_origin(&headers(&referer)));
+        assert!(!is_same_origin(&headers(&[("host", "localhost:8081"), ("referer", "http://other:8081/")])));
+        // Without either the request can't be told apart from a forged one
+        assert!(!is_same_origin(&headers(&[("host", "localhost:8081")])));
+    }
+
+    #[test]
+    fn is_same_origin_uses_the_forwarded_host_behind_a_proxy() {
+        let proxied = [
+            ("host", "127.0.0.1:8081"),
+            ("x-forwarded-host", "dash.example.org"),
+            ("origin", "https://dash.example.org"),
+        ];
+        assert!(is_same_origin(&headers(&proxied)));
+    }
+}
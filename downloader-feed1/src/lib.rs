@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use futures::future::join_all;
+use reqwest::Client;
+use robo_news_core::{
+    block_on, claim_item, fetch_items_in, heartbeat, http_timeout, item_span, listen_for_shutdown, load_pipeline_stage,
+    open_db, read_interval_from_env, record_failure, release_claim, reset_attempts, set_news_field, shutdown_requested,
+    transition, wait_for_wakeup, wakeup_seq, write_artifact, Cancelled, NewsField, NewsItem, PipelineStage, Status,
+};
+use rusqlite::Connection;
+use tracing::{error, info, warn, Instrument};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const DATA_DIR: &str = "data";
+const DEFAULT_DOWNLOAD_INTERVAL_SECS: u64 = 60;
+const DEFAULT_DOWNLOAD_CONCURRENCY: u64 = 4;
+
+/// Downloads the pages of new items every DOWNLOADER_INTERVAL_SECS, or a single time with `--once`.
+pub fn run(options: RunOptions) -> Result<()> {
+    let interval_secs = read_interval_from_env("DOWNLOADER_INTERVAL_SECS", DEFAULT_DOWNLOAD_INTERVAL_SECS)?;
+    // Pages downloaded at the same time
+    let concurrency = read_interval_from_env("DOWNLOADER_CONCURRENCY", DEFAULT_DOWNLOAD_CONCURRENCY)? as usize;
+
+    // Initialize database and data directory
+    let conn = open_db()?;
+    init_data_dir()?;
+    let client = Client::builder().timeout(http_timeout()?).build()?;
+
+    let stage = load_pipeline_stage(
+        "downloader",
+        PipelineStage {
+            input_status: Status::New,
+            output_status: Status::Downloaded,
+            input_artifact: "-".to_string(),
+            output_artifact: "news".to_string(),
+        },
+    )?;
+    
+    info!("Starting downloader...");
+    listen_for_shutdown()?;
+    
+    if options.once {
+        return run_downloader(&conn, &client, &stage, options.limit, concurrency);
+    }
+    
+    // Main loop - run every DOWNLOADER_INTERVAL_SECS seconds until SIGTERM or Ctrl-C
+    while !shutdown_requested() {
+        if let Err(e) = heartbeat(&conn, interval_secs) {
+            warn!("Failed to record heartbeat: {}", e);
+        }
+        // Read before the cycle, so items handed over during it end the next sleep early
+        let seen = wakeup_seq(&conn, &[&stage.input_status]).unwrap_or(0);
+        if let Err(e) = run_downloader(&conn, &client, &stage, options.limit, concurrency) {
+            error!("Error during downloading: {}", e);
+        }
+        
+        info!("Sleeping for up to {} seconds", interval_secs);
+        wait_for_wakeup(interval_secs, seen, || wakeup_seq(&conn, &[&stage.input_status]))?;
+    }
+    info!("Stopped");
+    Ok(())
+}
+
+/// Command-line options for one-off runs (used by `ctl seed` and cron or CI):
+/// `--once` runs a single cycle and exits, `--limit N` handles at most N items per cycle.
+pub struct RunOptions {
+    pub once: bool,
+    pub limit: Option<usize>,
+}
+
+pub fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { once: false, limit: None };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--once" => options.once = true,
+            "--limit" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--limit requires a value"))?;
+                options.limit = Some(
+                    value
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("--limit must be a non-negative integer: {}", e))?,
+                );
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn init_data_dir() -> Result<()> {
+    if !Path::new(DATA_DIR).exists() {
+        fs::create_dir_all(DATA_DIR).context("Failed to create data directory")?;
+    }
+    Ok(())
+}
+
+fn run_downloader(
+    conn: &Connection,
+    client: &Client,
+    stage: &PipelineStage,
+    limit: Option<usize>,
+    concurrency: usize,
+) -> Result<()> {
+    info!("Checking for new news items to download");
+    
+    // Fetch news items with the stage input status ("new" by default)
+    let mut news_items = fetch_items_in(conn, &stage.input_status)?;
+    if let Some(limit) = limit {
+        news_items.truncate(limit);
+    }
+    
+    if news_items.is_empty() {
+        info!("No new items to download");
+        return Ok(());
+    }
+    
+    info!("Found {} new items to download", news_items.len());
+    
+    // Download the items a batch at a time
+    for batch in news_items.chunks(concurrency) {
+        if shutdown_requested() {
+            break;
+        }
+        let mut claimed = Vec::new();
+        for item in batch {
+            // Another replica may have taken the item since it was fetched
+            if claim_item(conn, &item.id, None, &item.status)? {
+                claimed.push(item);
+            }
+        }
+        let pages = match block_on(async {
+            let downloads = claimed
+                .iter()
+                .map(|item| download_page(client, item).instrument(item_span("downloader-feed1", &item.id)));
+            Ok::<_, Cancelled>(join_all(downloads).await)
+        }) {
+            Ok(pages) => pages,
+            Err(Cancelled) => {
+                // Shutting down: the next start downloads them again
+                for item in &claimed {
+                    release_claim(conn, &item.id, None)?;
+                }
+                break;
+            }
+        };
+        for (item, page) in claimed.into_iter().zip(pages) {
+            let _span = item_span("downloader-feed1", &item.id).entered();
+            let result = page.and_then(|html| save_page(item, &html, &stage.output_artifact));
+            handle_download(conn, stage, item, result)?;
+        }
+    }
+    
+    info!("Download process completed");
+    Ok(())
+}
+
+/// Records the outcome of one download: the next status, or a failed attempt.
+fn handle_download(conn: &Connection, stage: &PipelineStage, item: &NewsItem, result: Result<Option<String>>) -> Result<()> {
+    match result {
+        Ok(lead_image_url) => {
+            // The illustrator can publish the article's own photo instead of generating one
+            set_news_field(conn, &item.id, NewsField::LeadImageUrl, lead_image_url.as_deref())?;
+            // Update status to the stage output status ("downloaded" by default)
+            reset_attempts(conn, &item.id, None)?;
+            transition(conn, &item.id, None, &item.status, &stage.output_status)?;
+            info!("Successfully downloaded news item: {}", item.title);
+        }
+        Err(e) => {
+            error!("Failed to download news item {}: {}", item.id, e);
+            record_failure(conn, &item.id, None, &e.to_string())?;
+            // Continue with the next item
+        }
+    }
+    Ok(())
+}
+
+async fn download_page(client: &Client, item: &NewsItem) -> Result<String> {
+    let response = client
+        .get(&item.url)
+        .send()
+        .await
+        .context("Failed to send request")?;
+    
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("HTTP error: {}", response.status()));
+    }
+    
+    response
+        .text()
+        .await
+        .context("Failed to get response text")
+}
+
+/// Stores the downloaded article page and returns the URL of its lead image (og:image), if it has one.
+fn save_page(item: &NewsItem, html: &str, output_artifact: &str) -> Result<Option<String>> {
+    write_artifact(&format!("{}_{}.html", output_artifact, item.id), html)
+        .context("Failed to save the downloaded HTML")?;
+    
+    let lead_image_url = extract_lead_image_url(html).and_then(|src| {
+        reqwest::Url::parse(&item.url)
+            .and_then(|page_url| page_url.join(&src))
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .map(String::from)
+    });
+    Ok(lead_image_url)
+}
+
+/// Finds the `og:image` (or `twitter:image`) meta tag of the page.
+fn extract_lead_image_url(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let mut fallback = None;
+    let mut position = 0;
+    while let Some(offset) = lower[position..].find("<meta") {
+        let start = position + offset;
+        let end = start + lower[start..].find('>')?;
+        position = end;
+
+        let tag = &html[start..end];
+        let name = meta_attribute(tag, "property").or_else(|| meta_attribute(tag, "name"));
+        let Some(content) = meta_attribute(tag, "content").filter(|content| !content.is_empty()) else {
+            continue;
+        };
+        match name.map(|name| name.to_ascii_lowercase()).as_deref() {
+            Some("og:image") | Some("og:image:url") | Some("og:image:secure_url") => return Some(content),
+            Some("twitter:image") | Some("twitter:image:src") => fallback = fallback.or(Some(content)),
+            _ => {}
+        }
+    }
+    fallback
+}
+
+fn meta_attribute(tag: &str, attribute: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut position = 0;
+    while let Some(offset) = lower[position..].find(attribute) {
+        let start = position + offset;
+        position = start + attribute.len();
+        // Must be a whole attribute name, e.g. not the "name" in "itemname"
+        if !lower[..start].ends_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let rest = lower[position..].trim_start();
+        let Some(rest) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let value_start = tag.len() - rest.trim_start().len();
+        let value = &tag[value_start..];
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+            _ => value.split(|c: char| c.is_ascii_whitespace()).next().unwrap_or("").trim_end_matches('/'),
+        };
+        return Some(value.trim().replace("&amp;", "&"));
+    }
+    None
+}
+
@@ -0,0 +1,389 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use robo_news_core::news_id;
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+mod serve;
+
+const DEFAULT_SEED: u64 = 1;
+const DEFAULT_COUNT: usize = 10;
+const DEFAULT_OUT_DIR: &str = "fixtures";
+const DEFAULT_BASE_URL: &str = "http://127.0.0.1:8000/";
+
+const USAGE: &str = "Usage: fixtures [--seed <n>] [--count <n>] [--out <dir>] [--base-url <url>]
+       fixtures serve [--dir <dir>] [--port <n>]
+
+Writes a reproducible set of pipeline inputs and outputs into <dir>:
+  feed1/index.html           feed page in the layout parser-feed1 expects
+  feed1/<slug>/index.html    article pages for downloader-feed1 and scraper
+  providers/                 chat completion, image and error responses of the AI providers
+  telegram/                  replies of the Telegram API for sent, pinned and rejected posts
+  manifest.json              items with their ids, URLs and file names
+
+Serve <dir> at --base-url with `fixtures serve`, which also answers the providers' and Telegram's
+POST requests (see `fixtures serve --help`), and point FEED1_URL at <base-url>feed1/.
+The same seed always produces the same files.";
+
+const SUBJECTS: &[&str] = &[
+    "City council", "Central bank", "National team", "Research institute", "Port authority",
+    "Health ministry", "Tech startup", "Railway operator", "University hospital", "Energy company",
+];
+const ACTIONS: &[&str] = &[
+    "approves", "delays", "announces", "expands", "reviews", "cancels", "funds", "launches",
+];
+const OBJECTS: &[&str] = &[
+    "new budget", "bridge renovation", "solar park", "rail link", "vaccination campaign",
+    "AI research lab", "water supply plan", "transfer window deal", "interest rate decision", "airport terminal",
+];
+const SENTENCES: &[&str] = &[
+    "Officials said the decision followed months of consultations with residents and experts.",
+    "The project is expected to take two years and will be financed from several sources.",
+    "Critics argue that the timeline is too optimistic given the current economic situation.",
+    "According to the statement, the first results should be visible before the end of the year.",
+    "Local businesses welcomed the news and expect more visitors in the coming months.",
+    "The opposition called for a public hearing and more transparency in the process.",
+    "Experts note that similar initiatives in neighbouring countries had mixed results.",
+    "A spokesperson declined to comment on the exact costs, citing ongoing negotiations.",
+    "The announcement was made at a press conference on Tuesday morning.",
+    "Further details will be published on the official website next week.",
+];
+
+/// splitmix64: small, fast and identical on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+struct Options {
+    seed: u64,
+    count: usize,
+    out_dir: String,
+    base_url: String,
+}
+
+struct Article {
+    slug: String,
+    url: String,
+    id: String,
+    title: String,
+    date: String,
+    paragraphs: Vec<String>,
+}
+
+fn main() -> Result<()> {
+    if env::args().nth(1).as_deref() == Some("serve") {
+        return serve::run(env::args().skip(2));
+    }
+
+    let options = parse_options()?;
+    let mut rng = Rng(options.seed);
+
+    let feed_url = format!("{}feed1/", options.base_url);
+    let articles: Vec<Article> = (0..options.count)
+        .map(|index| generate_article(&mut rng, &feed_url, index))
+        .collect();
+
+    let out = Path::new(&options.out_dir);
+    write(out, "feed1/index.html", &feed_page(&articles))?;
+    for article in &articles {
+        write(out, &format!("feed1/{}/index.html", article.slug), &article_page(article))?;
+    }
+
+    for (index, article) in articles.iter().enumerate() {
+        write(out, &format!("providers/chat_{}.json", index), &pretty(&chat_response(article, index, "stop"))?)?;
+        write(out, &format!("providers/image_{}.json", index), &pretty(&image_response(&mut rng))?)?;
+    }
+    if let Some(article) = articles.first() {
+        write(out, "providers/chat_length.json", &pretty(&chat_response(article, 0, "length"))?)?;
+    }
+    write(out, "providers/error_rate_limit.json", &pretty(&json!({
+        "error": {
+            "code": 429,
+            "message": "Rate limit exceeded: free-models-per-min.",
+            "metadata": { "headers": { "X-RateLimit-Limit": "20", "X-RateLimit-Remaining": "0", "X-RateLimit-Reset": "1700000060000" } }
+        }
+    }))?)?;
+    write(out, "providers/error_context_length.json", &pretty(&json!({
+        "error": {
+            "message": "This model's maximum context length is 128000 tokens.",
+            "type": "invalid_request_error",
+            "code": "context_length_exceeded"
+        }
+    }))?)?;
+    write(out, "providers/error_quota.json", &pretty(&json!({
+        "error": {
+            "message": "You exceeded your current quota, please check your plan and billing details.",
+            "type": "insufficient_quota",
+            "code": "insufficient_quota"
+        }
+    }))?)?;
+
+    for (index, article) in articles.iter().enumerate() {
+        write(out, &format!("telegram/send_message_{}.json", index), &pretty(&json!({
+            "ok": true,
+            "message_id": 1000 + index,
+            "date": article.date,
+            "text": article.title,
+        }))?)?;
+    }
+    write(out, "telegram/pin_message.json", &pretty(&json!({ "ok": true }))?)?;
+    write(out, "telegram/error_entity_bounds.json", &pretty(&json!({
+        "ok": false,
+        "error_code": 400,
+        "description": "Bad Request: ENTITY_BOUNDS_INVALID",
+    }))?)?;
+    write(out, "telegram/error_flood_wait.json", &pretty(&json!({
+        "ok": false,
+        "error_code": 420,
+        "description": "FLOOD_WAIT_30",
+    }))?)?;
+
+    let manifest = json!({
+        "seed": options.seed,
+        "feed_url": feed_url,
+        "items": articles.iter().enumerate().map(|(index, article)| json!({
+            "id": article.id,
+            "url": article.url,
+            "title": article.title,
+            "date": article.date,
+            "page": format!("feed1/{}/index.html", article.slug),
+            "chat_response": format!("providers/chat_{}.json", index),
+            "image_response": format!("providers/image_{}.json", index),
+            "telegram_reply": format!("telegram/send_message_{}.json", index),
+        })).collect::<Vec<_>>(),
+    });
+    write(out, "manifest.json", &pretty(&manifest)?)?;
+
+    println!(
+        "fixtures: [INFO] Wrote {} items (seed {}) to {}; serve it at {}",
+        articles.len(),
+        options.seed,
+        options.out_dir,
+        options.base_url
+    );
+    Ok(())
+}
+
+fn parse_options() -> Result<Options> {
+    let mut options = Options {
+        seed: DEFAULT_SEED,
+        count: DEFAULT_COUNT,
+        out_dir: DEFAULT_OUT_DIR.to_string(),
+        base_url: DEFAULT_BASE_URL.to_string(),
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| anyhow::anyhow!("{} requires a value", name));
+        match arg.as_str() {
+            "--seed" => options.seed = value("--seed")?.parse().context("--seed must be a non-negative integer")?,
+            "--count" => options.count = value("--count")?.parse().context("--count must be a non-negative integer")?,
+            "--out" => options.out_dir = value("--out")?,
+            "--base-url" => {
+                let url = value("--base-url")?;
+                options.base_url = format!("{}/", url.trim_end_matches('/'));
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn generate_article(rng: &mut Rng, feed_url: &str, index: usize) -> Article {
+    let title = format!("{} {} {}", rng.pick(SUBJECTS), rng.pick(ACTIONS), rng.pick(OBJECTS));
+    let slug = format!(
+        "{}-{}",
+        index,
+        title
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-")
+    );
+    let url = format!("{}{}/", feed_url, slug);
+
+    // Same id as parser-feed1: sha256 of the article URL
+    let id = news_id(&url);
+
+    // Fixed dates (counting back from 2025-01-31), so the output doesn't depend on the clock
+    let day = 31 - (index % 28);
+    let date = format!("2025-01-{:02}T{:02}:{:02}:00+02:00", day, 8 + rng.below(12), rng.below(60));
+
+    let paragraphs = (0..3 + rng.below(4))
+        .map(|_| {
+            (0..2 + rng.below(3))
+                .map(|_| rng.pick(SENTENCES))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+
+    Article {
+        slug,
+        url,
+        id,
+        title,
+        date,
+        paragraphs,
+    }
+}
+
+fn feed_page(articles: &[Article]) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html lang=\"sr\">\n<head><meta charset=\"utf-8\"><title>Feed 1</title></head>\n<body>\n");
+    for article in articles {
+        html.push_str(&format!(
+            "<div class=\"td_module_flex\">\n  <h3 class=\"entry-title td-module-title\"><a href=\"{}\">{}</a></h3>\n  <div class=\"td-editor-date\"><span class=\"td-post-date\"><time datetime=\"{}\">{}</time></span></div>\n</div>\n",
+            article.url, article.title, article.date, &article.date[..10]
+        ));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn article_page(article: &Article) -> String {
+    let body = article
+        .paragraphs
+        .iter()
+        .map(|paragraph| format!("    <p>{}</p>\n", paragraph))
+        .collect::<String>();
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"sr\">\n<head>\n  <meta charset=\"utf-8\">\n  <meta property=\"og:locale\" content=\"sr_RS\">\n  <title>{title}</title>\n</head>\n<body>\n  <nav><a href=\"/\">Home</a> | <a href=\"/feed1/\">News</a></nav>\n  <article>\n    <h1>{title}</h1>\n    <time datetime=\"{date}\">{day}</time>\n{body}  </article>\n  <footer>© Fixture News</footer>\n</body>\n</html>\n",
+        title = article.title,
+        date = article.date,
+        day = &article.date[..10],
+        body = body
+    )
+}
+
+/// OpenAI-style chat completion as returned by the translator's and rewriter's providers.
+fn chat_response(article: &Article, index: usize, finish_reason: &str) -> serde_json::Value {
+    let mut content = format!("<h1>{}</h1>", article.title);
+    for paragraph in &article.paragraphs {
+        content.push_str(&format!("<p>{}</p>", paragraph));
+    }
+    if finish_reason == "length" {
+        content.truncate(content.len() / 2);
+    }
+    json!({
+        "id": format!("chatcmpl-fixture-{}", index),
+        "object": "chat.completion",
+        "model": "fixture-model",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": finish_reason
+        }],
+        "usage": { "prompt_tokens": 100 + index, "completion_tokens": content.len() / 4, "total_tokens": 100 + index + content.len() / 4 }
+    })
+}
+
+/// OpenRouter-style image response carrying a small single-color PNG as a data URL.
+fn image_response(rng: &mut Rng) -> serde_json::Value {
+    let color = [rng.below(256) as u8, rng.below(256) as u8, rng.below(256) as u8];
+    let png = solid_png(16, 16, color);
+    json!({
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "images": [{
+                    "type": "image_url",
+                    "image_url": { "url": format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png)) }
+                }]
+            },
+            "finish_reason": "stop"
+        }]
+    })
+}
+
+/// Uncompressed (stored deflate blocks) RGB PNG, so no image or zlib crate is needed.
+fn solid_png(width: u32, height: u32, color: [u8; 3]) -> Vec<u8> {
+    let mut raw = Vec::new();
+    for _ in 0..height {
+        raw.push(0); // filter: none
+        raw.extend(color.iter().cycle().take(width as usize * 3));
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(0xFFFF).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        zlib.push(u8::from(index + 1 == blocks.len()));
+        let len = block.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, no interlace
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    for (kind, data) in [(b"IHDR", ihdr.as_slice()), (b"IDAT", zlib.as_slice()), (b"IEND", &[][..])] {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(data);
+        png.extend_from_slice(&chunk);
+        png.extend_from_slice(&crc32(&chunk).to_be_bytes());
+    }
+    png
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn pretty(value: &serde_json::Value) -> Result<String> {
+    Ok(serde_json::to_string_pretty(value)? + "\n")
+}
+
+fn write(out: &Path, relative: &str, content: &str) -> Result<()> {
+    let path = out.join(relative);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
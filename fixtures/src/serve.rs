@@ -0,0 +1,191 @@
+//! `fixtures serve`: a small HTTP server for a generated fixture directory. GET requests get the
+//! files, POSTs the canned provider and Telegram responses listed in manifest.json.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+const DEFAULT_PORT: u16 = 8000;
+
+pub const USAGE: &str = "Usage: fixtures serve [--dir <dir>] [--port <n>]
+
+Serves a directory written by `fixtures` on 127.0.0.1:<port> (8000 by default, 0 picks a free one):
+  GET  <path>                       the file, index.html for directories
+  POST .../chat/completions         providers/image_<i>.json when the request asks for image
+                                    output, otherwise providers/chat_<i>.json
+  POST /bot<token>/sendMessage      telegram/send_message_<i>.json
+  POST /bot<token>/pinChatMessage   telegram/pin_message.json
+<i> is the manifest item whose title appears in the request body (the first item if none does).
+Point FEED1_URL and the providers' *_API_URL at it, e.g. http://127.0.0.1:8000/v1/chat/completions.";
+
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+pub fn run(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut dir = crate::DEFAULT_OUT_DIR.to_string();
+    let mut port = DEFAULT_PORT;
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| anyhow!("{} requires a value", name));
+        match arg.as_str() {
+            "--dir" => dir = value("--dir")?,
+            "--port" => port = value("--port")?.parse().context("--port must be a port number")?,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            other => return Err(anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).with_context(|| format!("Failed to listen on port {}", port))?;
+    // Printed first and flushed, so scripts and tests started with --port 0 can read the address
+    println!("fixtures: [INFO] Serving {} at http://{}/", dir, listener.local_addr()?);
+    std::io::stdout().flush()?;
+
+    let root = PathBuf::from(dir);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("fixtures: [WARN] Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let root = root.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(&root, stream) {
+                eprintln!("fixtures: [WARN] {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(root: &Path, mut stream: TcpStream) -> Result<()> {
+    let request = read_request(&stream)?;
+    let (status, content_type, body) = match respond(root, &request) {
+        Ok(Some((file, content_type))) => ("200 OK", content_type, file),
+        Ok(None) => ("404 Not Found", "text/plain", b"Not found\n".to_vec()),
+        Err(e) => ("500 Internal Server Error", "text/plain", format!("{}\n", e).into_bytes()),
+    };
+    println!("fixtures: [INFO] {} {} -> {}", request.method, request.path, status);
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if request.method != "HEAD" {
+        stream.write_all(&body)?;
+    }
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).context("Failed to read the request line")?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or_else(|| anyhow!("Empty request"))?.to_string();
+    let target = parts.next().ok_or_else(|| anyhow!("Request line without a path"))?;
+    let path = target.split(['?', '#']).next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().context("Invalid Content-Length")?;
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).context("Failed to read the request body")?;
+    Ok(Request {
+        method,
+        path,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+/// The file to answer with and its content type, or `None` for a 404.
+fn respond(root: &Path, request: &Request) -> Result<Option<(Vec<u8>, &'static str)>> {
+    let path = match request.method.as_str() {
+        "GET" | "HEAD" => {
+            if request.path.split('/').any(|segment| segment == "..") {
+                return Ok(None);
+            }
+            let path = root.join(request.path.trim_start_matches('/'));
+            if path.is_dir() {
+                path.join("index.html")
+            } else {
+                path
+            }
+        }
+        "POST" => match post_response(root, request)? {
+            Some(relative) => root.join(relative),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content_type = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    };
+    let file = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(Some((file, content_type)))
+}
+
+/// The manifest entry (`chat_response`, `image_response` or `telegram_reply`) of the item the
+/// request is about, or a fixed file for requests that don't depend on the item.
+fn post_response(root: &Path, request: &Request) -> Result<Option<String>> {
+    let method = request.path.rsplit('/').next().unwrap_or_default();
+    let field = if request.path.ends_with("/chat/completions") {
+        // OpenRouter image models are asked for ["image", "text"] modalities
+        if request.body.contains("\"modalities\"") {
+            "image_response"
+        } else {
+            "chat_response"
+        }
+    } else if request.path.starts_with("/bot") && method == "sendMessage" {
+        "telegram_reply"
+    } else if request.path.starts_with("/bot") && method == "pinChatMessage" {
+        return Ok(Some("telegram/pin_message.json".to_string()));
+    } else {
+        return Ok(None);
+    };
+
+    let manifest_path = root.join("manifest.json");
+    let manifest: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(&manifest_path).with_context(|| format!("Failed to read {}", manifest_path.display()))?,
+    )
+    .context("Failed to parse manifest.json")?;
+    let items = manifest["items"].as_array().cloned().unwrap_or_default();
+    let item = items
+        .iter()
+        .find(|item| item["title"].as_str().is_some_and(|title| request.body.contains(title)))
+        .or_else(|| items.first());
+
+    Ok(item.and_then(|item| item[field].as_str()).map(str::to_string))
+}
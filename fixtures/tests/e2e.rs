@@ -0,0 +1,148 @@
+//! Generates a fixture set, serves it with `fixtures serve` and checks the pipeline's first stages
+//! and the mock providers against manifest.json.
+
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+const FIXTURES: &str = env!("CARGO_BIN_EXE_fixtures");
+
+/// Kills the server when the test ends, also on a failed assertion.
+struct Server {
+    child: Child,
+    base_url: String,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Starts `fixtures serve` on a free port and writes a fixture set for its address into `dir`.
+fn serve_fixtures(dir: &Path, count: usize) -> (Server, Value) {
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir).unwrap();
+
+    let mut child = Command::new(FIXTURES)
+        .args(["serve", "--port", "0", "--dir"])
+        .arg(dir)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    // Keep reading the request log, so the server's writes to stdout don't fail
+    thread::spawn(move || io::copy(&mut stdout, &mut io::sink()));
+    let base_url = line.trim().rsplit(' ').next().unwrap().to_string();
+    let server = Server { child, base_url };
+
+    let status = Command::new(FIXTURES)
+        .args(["--count", &count.to_string(), "--base-url", &server.base_url, "--out"])
+        .arg(dir)
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let manifest = serde_json::from_str(&fs::read_to_string(dir.join("manifest.json")).unwrap()).unwrap();
+    (server, manifest)
+}
+
+/// Sends a JSON POST over a plain socket and returns the response body.
+fn post(base_url: &str, path: &str, body: &Value) -> String {
+    let address = base_url.trim_start_matches("http://").trim_end_matches('/');
+    let body = body.to_string();
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        path,
+        address,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{} {}", path, head);
+    body.to_string()
+}
+
+fn work_dir(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+#[test]
+fn parser_and_downloader_store_the_manifest_items() {
+    let work = work_dir("e2e-pipeline");
+    // The database of an earlier run would still hold its items
+    let _ = fs::remove_dir_all(&work);
+    let fixtures = work.join("fixtures");
+    let (_server, manifest) = serve_fixtures(&fixtures, 5);
+    let items = manifest["items"].as_array().unwrap();
+
+    // The stages keep their database and artifacts in data/ under the working directory
+    fs::create_dir_all(work.join("data")).unwrap();
+    env::set_current_dir(&work).unwrap();
+    env::set_var("FEED1_URL", manifest["feed_url"].as_str().unwrap());
+    parser_feed1::run(parser_feed1::RunOptions { once: true, limit: None }).unwrap();
+    downloader_feed1::run(downloader_feed1::RunOptions { once: true, limit: None }).unwrap();
+
+    let conn = rusqlite::Connection::open(work.join("data/news.db")).unwrap();
+    let mut stmt = conn.prepare("SELECT id, title, url, status FROM news ORDER BY id").unwrap();
+    let stored: Vec<(String, String, String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    let mut expected: Vec<&Value> = items.iter().collect();
+    expected.sort_by_key(|item| item["id"].as_str().unwrap().to_string());
+    assert_eq!(stored.len(), expected.len());
+    for ((id, title, url, status), item) in stored.iter().zip(expected) {
+        assert_eq!(id, item["id"].as_str().unwrap());
+        assert_eq!(title, item["title"].as_str().unwrap());
+        assert_eq!(url, item["url"].as_str().unwrap());
+        assert_eq!(status, "downloaded");
+
+        let page = fs::read_to_string(fixtures.join(item["page"].as_str().unwrap())).unwrap();
+        assert_eq!(fs::read_to_string(work.join(format!("data/news_{}.html", id))).unwrap(), page);
+    }
+}
+
+#[test]
+fn mock_server_answers_with_the_manifest_responses() {
+    let fixtures = work_dir("e2e-responses");
+    let (server, manifest) = serve_fixtures(&fixtures, 3);
+    let item = &manifest["items"][1];
+    let title = item["title"].as_str().unwrap();
+    let file = |field: &str| fs::read_to_string(fixtures.join(item[field].as_str().unwrap())).unwrap();
+
+    let chat = json_request(title, false);
+    assert_eq!(post(&server.base_url, "/v1/chat/completions", &chat), file("chat_response"));
+    let image = json_request(title, true);
+    assert_eq!(post(&server.base_url, "/api/v1/chat/completions", &image), file("image_response"));
+
+    let message = serde_json::json!({ "chat_id": "-100", "text": format!("<b>{}</b>", title) });
+    assert_eq!(post(&server.base_url, "/bot123:token/sendMessage", &message), file("telegram_reply"));
+}
+
+fn json_request(title: &str, image: bool) -> Value {
+    let mut request = serde_json::json!({
+        "model": "fixture-model",
+        "messages": [{ "role": "user", "content": format!("<h1>{}</h1>", title) }],
+    });
+    if image {
+        request["modalities"] = serde_json::json!(["image", "text"]);
+    }
+    request
+}
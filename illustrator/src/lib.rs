@@ -0,0 +1,3051 @@
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageEncoder, ImageFormat};
+use rusqlite::{params, Connection, Row};
+use robo_news_core::{add_usage, block_on, cancellable, check_daily_budget, claim_item, clear_provider_error, heartbeat, item_span, listen_for_shutdown, load_artifact, load_pipeline_stage, open_db, parse_provider_error, read_artifact, read_concurrency_from_env, read_daily_budget_from_env, read_interval_from_env, read_attempt_policy_from_env, read_price_from_env, read_reasoning_config_from_env, record_failed_attempt, record_image_usage, record_provider_error, record_usage, release_claim, remove_artifact, reset_attempts, run_async, run_workers, send_dead_letter_alerts, shutdown_requested, take_usage, track_usage, unix_now, update_status, wait_for_wakeup, wakeup_seq, write_artifact, ApiError, AttemptPolicy, Cancelled, ConcurrencyTuner, DailyBudget, Message, NewsItem, PipelineStage, ReasoningConfig, Status, UsageModel, WORKER_CONNECTIONS};
+use tracing::{debug, error, info, warn, Instrument};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Instant;
+use std::time::Duration;
+use std::sync::{Arc, OnceLock};
+
+const DATA_DIR: &str = "data";
+const DEFAULT_ILLUSTRATE_INTERVAL_SECS: u64 = 60;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 120;
+const XAI_DEFAULT_ASPECT_RATIO: &str = "auto";
+const XAI_DEFAULT_RESOLUTION: &str = "1k";
+// Supported by gpt-image-1, DALL-E 3 and DALL-E 2 alike
+const OPENAI_DEFAULT_SIZE: &str = "1024x1024";
+// Stability AI rejects longer prompts
+const STABILITY_MAX_PROMPT_CHARS: usize = 10_000;
+// Asynchronous jobs (Replicate predictions, ComfyUI queue) are polled until they finish
+const POLL_INTERVAL_SECS: u64 = 2;
+// Telegram photo limits: 10 MB, and a side ratio of at most 20
+const DEFAULT_MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+const DEFAULT_MAX_IMAGE_DIMENSION: u32 = 2560;
+const MAX_ASPECT_RATIO: u32 = 20;
+// Finish reason for images flagged by the safety or repetition checks; such items wait in illustrator_review
+const REVIEW_FINISH_REASON: &str = "review";
+const DEFAULT_DEDUP_WINDOW: usize = 50;
+const DEFAULT_VARIATION_HINT: &str = "Use a clearly different composition, viewpoint and color palette \
+from a typical illustration of this topic.";
+const MODERATION_URL: &str = "https://api.openai.com/v1/moderations";
+const DEFAULT_MODERATION_MODEL: &str = "omni-moderation-latest";
+// Narrower lead images are thumbnails or logos rather than photos worth publishing
+const DEFAULT_LEAD_IMAGE_MIN_WIDTH: u32 = 600;
+const DEFAULT_PROMPT_SYNTHESIS_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
+const DEFAULT_PROMPT_SYNTHESIS_INSTRUCTION: &str = "Read the news article below and describe one image that \
+illustrates it, as a prompt for an image generation model. Describe the scene, subjects, setting, composition \
+and mood in at most 60 words of plain English. No text, captions, logos or recognizable real people in the \
+image. Reply with the prompt only.";
+const DEFAULT_MAX_WAIT_SECS: u64 = 300;
+// A Telegram media group holds at most 10 photos
+const MAX_ALBUM_IMAGES: usize = 10;
+
+// Set at startup with AI_PROVIDER_ILLUSTRATOR_ADAPTIVE_CONCURRENCY, fed by every image request
+static CONCURRENCY_TUNER: OnceLock<ConcurrencyTuner> = OnceLock::new();
+
+tokio::task_local! {
+    /// Images the primary and the fallback model returned for the item being processed in this task.
+    static GENERATED_IMAGES: Cell<(u32, u32)>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AiProviderType {
+    OpenRouter,
+    Gemini,
+    Xai,
+    OpenAi,
+    Stability,
+    Replicate,
+    A1111,
+    ComfyUi,
+}
+
+impl AiProviderType {
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "openrouter" => Ok(Self::OpenRouter),
+            "gemini" => Ok(Self::Gemini),
+            "xai" => Ok(Self::Xai),
+            "openai" => Ok(Self::OpenAi),
+            "stability" => Ok(Self::Stability),
+            "replicate" => Ok(Self::Replicate),
+            "a1111" => Ok(Self::A1111),
+            "comfyui" => Ok(Self::ComfyUi),
+            other => Err(anyhow!(
+                "AI_PROVIDER_ILLUSTRATOR_TYPE must be either 'OpenRouter', 'Gemini', 'XAI', 'OpenAI', 'Stability', 'Replicate', 'A1111', or 'ComfyUI' for illustrator service (got '{}')",
+                other
+            )),
+        }
+    }
+
+    /// Self-hosted Stable Diffusion servers, where the API key and model are optional.
+    fn is_local(self) -> bool {
+        matches!(self, Self::A1111 | Self::ComfyUi)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct XaiImageConfig {
+    aspect_ratio: String,
+    resolution: String,
+}
+
+#[derive(Debug, Clone)]
+struct OpenAiImageConfig {
+    size: String,
+    /// Omitted from the request when unset, so the model's own default applies.
+    quality: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct StabilityImageConfig {
+    /// Passed through as is; Stability AI validates it.
+    aspect_ratio: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct ReplicateConfig {
+    /// Passed through as is; the model validates it.
+    aspect_ratio: Option<String>,
+    /// How long a prediction may take before the item is retried (AI_PROVIDER_ILLUSTRATOR_MAX_WAIT_SECS).
+    max_wait_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+struct LocalSdConfig {
+    /// Server address from AI_PROVIDER_ILLUSTRATOR_API_URL, e.g. http://127.0.0.1:7860.
+    base_url: String,
+    /// AI_PROVIDER_ILLUSTRATOR_SIZE as WIDTHxHEIGHT (A1111 only; the server default if unset).
+    size: Option<(u32, u32)>,
+    /// AI_PROVIDER_ILLUSTRATOR_NEGATIVE_PROMPT.
+    negative_prompt: Option<String>,
+    /// ComfyUI workflow in API format (AI_PROVIDER_ILLUSTRATOR_WORKFLOW_FILE).
+    workflow: Option<String>,
+    /// How long a ComfyUI job may take before the item is retried (AI_PROVIDER_ILLUSTRATOR_MAX_WAIT_SECS).
+    max_wait_secs: u64,
+}
+
+/// Text model that turns the article into a short visual prompt before the image model is called
+/// (AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL).
+#[derive(Debug, Clone)]
+struct PromptSynthesisConfig {
+    model: String,
+    /// OpenAI-compatible chat completions URL (AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_API_URL, OpenRouter by default).
+    api_url: String,
+    /// AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_API_KEY, the illustrator's API key by default.
+    api_key: String,
+    /// AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_INSTRUCTION.
+    instruction: String,
+    /// AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_PRICE_INPUT / _PRICE_OUTPUT, USD per million tokens.
+    input_price: f64,
+    output_price: f64,
+}
+
+#[derive(Debug, Clone)]
+struct AiProviderConfig {
+    provider_type: AiProviderType,
+    api_key: String,
+    api_url: Option<String>,
+    model: String,
+    prompt: String,
+    reasoning: Option<ReasoningConfig>,
+    xai_image_config: Option<XaiImageConfig>,
+    openai_image_config: Option<OpenAiImageConfig>,
+    stability_image_config: Option<StabilityImageConfig>,
+    replicate_config: Option<ReplicateConfig>,
+    local_sd_config: Option<LocalSdConfig>,
+    prompt_synthesis: Option<PromptSynthesisConfig>,
+    /// Second image model tried when this one twice returns no usable image
+    /// (AI_PROVIDER_ILLUSTRATOR_FALLBACK_TYPE, _FALLBACK_MODEL, _FALLBACK_API_KEY, _FALLBACK_API_URL).
+    fallback: Option<Box<AiProviderConfig>>,
+    /// USD per generated image for `ai_usage` (AI_PROVIDER_ILLUSTRATOR_PRICE_IMAGE, _FALLBACK_PRICE_IMAGE).
+    image_price: f64,
+    /// Built once at startup with AI_PROVIDER_ILLUSTRATOR_TIMEOUT_SECS and shared by all requests.
+    client: Client,
+}
+
+/// Limits the published image is fitted into (ILLUSTRATOR_MAX_DIMENSION, ILLUSTRATOR_MAX_BYTES).
+struct ImageLimits {
+    /// Longest side in pixels.
+    max_dimension: u32,
+    max_bytes: usize,
+}
+
+/// Whether the article's own og:image (captured by the downloader) replaces AI generation
+/// (ILLUSTRATOR_USE_LEAD_IMAGE, ILLUSTRATOR_LEAD_IMAGE_MIN_WIDTH).
+struct LeadImagePolicy {
+    enabled: bool,
+    min_width: u32,
+}
+
+/// Screens generated images with the OpenAI moderation endpoint before they can be published
+/// (ILLUSTRATOR_SAFETY_CHECK, using MODERATION_OPENAI_API_KEY and MODERATION_MODEL like the publisher).
+struct SafetyCheck {
+    api_key: String,
+    model: String,
+}
+
+/// Items that are published without an illustration (ILLUSTRATOR_SKIP_MIN_WORDS,
+/// ILLUSTRATOR_SKIP_KEYWORDS, ILLUSTRATOR_SKIP_SOURCES).
+struct SkipRules {
+    /// Rewrites with fewer words are treated as briefs.
+    min_words: usize,
+    /// Lower-case keywords matched against the title and the URL path (where sites keep their sections).
+    keywords: Vec<String>,
+    /// Feed sources (`news.source`) that are never illustrated.
+    sources: Vec<String>,
+}
+
+/// Near-identical illustrations in a row are regenerated once with a variation hint, then held for
+/// review (ILLUSTRATOR_DEDUP_MAX_DISTANCE, ILLUSTRATOR_DEDUP_WINDOW, ILLUSTRATOR_DEDUP_VARIATION_HINT).
+struct DedupPolicy {
+    /// Largest Hamming distance between two 64-bit perceptual hashes that counts as a repeat.
+    max_distance: u32,
+    /// Number of recent illustrations compared against.
+    window: usize,
+    variation_hint: String,
+}
+
+/// Where illustrations come from and what they must satisfy before they are published.
+struct ImagePolicy {
+    /// Illustrations per item (ILLUSTRATOR_IMAGES_PER_ITEM); the extra ones are published as an album.
+    images_per_item: usize,
+    dedup: Option<DedupPolicy>,
+    skip_rules: SkipRules,
+    limits: ImageLimits,
+    lead_image: LeadImagePolicy,
+    safety_check: Option<SafetyCheck>,
+}
+
+#[derive(Serialize)]
+struct OpenRouterChatRequest {
+    model: String,
+    messages: Vec<Message>,
+    modalities: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<ReasoningConfig>,
+}
+
+#[derive(Serialize)]
+struct XaiImageGenerationRequest {
+    model: String,
+    prompt: String,
+    aspect_ratio: String,
+    resolution: String,
+    response_format: String,
+}
+
+#[derive(Serialize)]
+struct OpenAiImageGenerationRequest {
+    model: String,
+    prompt: String,
+    n: u32,
+    size: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<String>,
+    // gpt-image-1 always answers with b64_json and rejects this field; DALL-E defaults to URLs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ReplicatePredictionRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    input: ReplicateInput,
+}
+
+#[derive(Serialize)]
+struct ReplicateInput {
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aspect_ratio: Option<String>,
+    output_format: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReplicatePrediction {
+    id: String,
+    /// starting | processing | succeeded | failed | canceled
+    status: String,
+    /// A URL or a list of URLs once the prediction has succeeded.
+    #[serde(default)]
+    output: serde_json::Value,
+    #[serde(default)]
+    error: serde_json::Value,
+    urls: ReplicateUrls,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReplicateUrls {
+    get: String,
+}
+
+#[derive(Serialize)]
+struct A1111Txt2ImgRequest {
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    negative_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    override_settings: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct A1111Txt2ImgResponse {
+    /// Base64-encoded PNGs.
+    #[serde(default)]
+    images: Vec<String>,
+}
+
+// Gemini image generation (text-to-image) docs:
+// - https://ai.google.dev/gemini-api/docs/image-generation
+// Endpoint:
+//   POST https://generativelanguage.googleapis.com/v1beta/models/{model}:generateContent
+// Auth:
+//   x-goog-api-key: <API_KEY>
+// Request:
+//   generationConfig.responseModalities: ["TEXT", "IMAGE"]
+#[derive(Serialize)]
+struct GeminiGenerateContentRequest {
+    contents: Vec<GeminiContent>,
+    #[serde(rename = "generationConfig")]
+    generation_config: GeminiGenerationConfig,
+}
+
+#[derive(Serialize)]
+struct GeminiContent {
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Serialize)]
+struct GeminiPart {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct GeminiGenerationConfig {
+    #[serde(rename = "responseModalities")]
+    response_modalities: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiGenerateContentResponse {
+    candidates: Vec<GeminiCandidate>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiCandidate {
+    content: GeminiCandidateContent,
+    #[serde(default)]
+    #[serde(alias = "safetyRatings")]
+    safety_ratings: Vec<GeminiSafetyRating>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiSafetyRating {
+    #[serde(default)]
+    category: String,
+    /// NEGLIGIBLE | LOW | MEDIUM | HIGH
+    #[serde(default)]
+    probability: String,
+    #[serde(default)]
+    blocked: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiCandidateContent {
+    parts: Vec<GeminiResponsePart>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiResponsePart {
+    #[allow(dead_code)]
+    text: Option<String>,
+    #[serde(default)]
+    #[serde(alias = "inline_data")]
+    #[serde(alias = "inlineData")]
+    inline_data: Option<GeminiInlineData>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiInlineData {
+    #[serde(default)]
+    #[serde(alias = "mime_type")]
+    #[serde(alias = "mimeType")]
+    mime_type: Option<String>,
+    data: String,
+}
+
+#[derive(Serialize)]
+struct PromptSynthesisRequest {
+    model: String,
+    messages: Vec<Message>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct Choice {
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseMessage {
+    #[allow(dead_code)]
+    role: Option<String>,
+    content: Option<String>,
+    #[serde(default)]
+    images: Vec<ResponseImage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseImage {
+    #[serde(rename = "type")]
+    #[allow(dead_code)]
+    image_type: Option<String>,
+    #[serde(default)]
+    #[serde(alias = "imageUrl")]
+    image_url: Option<ResponseImageUrl>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseImageUrl {
+    url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ImageGenerationResponse {
+    data: Vec<GeneratedImage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeneratedImage {
+    #[serde(default)]
+    b64_json: Option<String>,
+    #[allow(dead_code)]
+    #[serde(default)]
+    url: Option<String>,
+    #[allow(dead_code)]
+    #[serde(default)]
+    revised_prompt: Option<String>,
+}
+
+/// Starts the illustrator with the image provider and policies from the environment and
+/// illustrates rewritten items until the process ends, or for one cycle with `--once`.
+pub fn run(options: RunOptions) -> Result<()> {
+    let interval_secs = read_interval_from_env("ILLUSTRATOR_INTERVAL_SECS", DEFAULT_ILLUSTRATE_INTERVAL_SECS)?;
+
+    // Check required environment variables
+    let provider_type = AiProviderType::parse(
+        &env::var("AI_PROVIDER_ILLUSTRATOR_TYPE")
+            .context("AI_PROVIDER_ILLUSTRATOR_TYPE environment variable not set")?,
+    )?;
+
+    // A local server uses its loaded checkpoint and may run without authentication
+    let model = match env::var("AI_PROVIDER_ILLUSTRATOR_MODEL") {
+        Ok(model) => model,
+        Err(_) if provider_type.is_local() => String::new(),
+        Err(_) => return Err(anyhow!("AI_PROVIDER_ILLUSTRATOR_MODEL environment variable not set")),
+    };
+    let prompt = env::var("AI_PROVIDER_ILLUSTRATOR_PROMPT")
+        .context("AI_PROVIDER_ILLUSTRATOR_PROMPT environment variable not set")?;
+    let api_key = match env::var("AI_PROVIDER_ILLUSTRATOR_API_KEY") {
+        Ok(api_key) => api_key,
+        Err(_) if provider_type.is_local() => String::new(),
+        Err(_) => return Err(anyhow!("AI_PROVIDER_ILLUSTRATOR_API_KEY environment variable not set")),
+    };
+    let api_url = env::var("AI_PROVIDER_ILLUSTRATOR_API_URL")
+        .ok()
+        .filter(|value| !value.trim().is_empty());
+
+    let reasoning = read_reasoning_config_from_env("AI_PROVIDER_ILLUSTRATOR");
+    let xai_image_config = read_xai_image_config_from_env(provider_type)?;
+    let openai_image_config = read_openai_image_config_from_env(provider_type)?;
+    let stability_image_config = (provider_type == AiProviderType::Stability).then(|| StabilityImageConfig {
+        aspect_ratio: read_optional_aspect_ratio_from_env(),
+    });
+    let replicate_config = read_replicate_config_from_env(provider_type)?;
+    let local_sd_config = read_local_sd_config_from_env(provider_type, api_url.as_deref())?;
+    let prompt_synthesis = read_prompt_synthesis_from_env(&api_key)?;
+    let image_price = read_price_from_env("AI_PROVIDER_ILLUSTRATOR_PRICE_IMAGE")?;
+
+    let timeout_secs = match env::var("AI_PROVIDER_ILLUSTRATOR_TIMEOUT_SECS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .ok_or_else(|| anyhow!("AI_PROVIDER_ILLUSTRATOR_TIMEOUT_SECS must be a positive integer, got '{}'", value.trim()))?,
+        _ => DEFAULT_REQUEST_TIMEOUT_SECS,
+    };
+    let client = Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .context("Failed to build HTTP client")?;
+
+    let fallback = read_fallback_provider_from_env(&prompt, reasoning.as_ref(), &client)?;
+    let provider = AiProviderConfig {
+        provider_type,
+        api_key,
+        api_url,
+        model,
+        prompt,
+        reasoning,
+        xai_image_config,
+        openai_image_config,
+        stability_image_config,
+        replicate_config,
+        local_sd_config,
+        prompt_synthesis,
+        fallback,
+        image_price,
+        client,
+    };
+    
+    let stage = load_pipeline_stage(
+        "illustrator",
+        PipelineStage {
+            input_status: Status::Rewriter,
+            output_status: Status::Illustrator,
+            input_artifact: "rewriter".to_string(),
+            output_artifact: "illustrator".to_string(),
+        },
+    )?;
+    
+    let attempt_policy = read_attempt_policy_from_env(Status::IllustratorRetry, Status::IllustratorError)?;
+    let image_policy = ImagePolicy {
+        images_per_item: read_images_per_item_from_env()?,
+        skip_rules: read_skip_rules_from_env()?,
+        dedup: read_dedup_policy_from_env()?,
+        limits: read_image_limits_from_env()?,
+        lead_image: read_lead_image_policy_from_env()?,
+        safety_check: read_safety_check_from_env()?,
+    };
+    let budget = read_daily_budget_from_env()?;
+    let concurrency = read_concurrency_from_env("AI_PROVIDER_ILLUSTRATOR")?;
+
+    // Initialize database and data directory
+    let conn = init_db()?;
+    init_data_dir()?;
+    if let Some(tuner) = concurrency.tuner("Illustrator") {
+        let _ = CONCURRENCY_TUNER.set(tuner);
+    }
+    
+    info!("Starting illustrator...");
+    listen_for_shutdown()?;
+    
+    // Main loop - run every ILLUSTRATOR_INTERVAL_SECS seconds until SIGTERM or Ctrl-C
+    while !shutdown_requested() {
+        if !options.once {
+            if let Err(e) = heartbeat(&conn, interval_secs) {
+                warn!("Failed to record heartbeat: {}", e);
+            }
+        }
+        // Read before the cycle, so items handed over during it end the next sleep early
+        let seen = wakeup_seq(&conn, &[&stage.input_status]).unwrap_or(0);
+        // Stop spending once today's AI budget is used up
+        if !run_async(check_daily_budget(&conn, "Illustrator", &budget, &provider.client)) {
+            if let Err(e) = run_illustrator(&conn, &provider, &stage, &attempt_policy, &image_policy, concurrency.max, &budget) {
+                if options.once {
+                    return Err(e);
+                }
+                error!("Error in run_illustrator loop: {}", e);
+            }
+        }
+        if let Err(e) = block_on(send_dead_letter_alerts(&conn)) {
+            warn!("Failed to send dead-letter alerts: {}", e);
+        }
+        if options.once {
+            return Ok(());
+        }
+        
+        info!(
+            "Sleeping for up to {} seconds",
+            interval_secs
+        );
+        wait_for_wakeup(interval_secs, seen, || wakeup_seq(&conn, &[&stage.input_status]))?;
+    }
+    info!("Stopped");
+    Ok(())
+}
+
+/// Command-line options: `--once` processes the current queue and exits (for cron- or CI-driven runs).
+pub struct RunOptions {
+    pub once: bool,
+}
+
+pub fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { once: false };
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--once" => options.once = true,
+            other => return Err(anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn init_db() -> Result<Connection> {
+    let conn = open_db()?;
+    
+    // Perceptual hashes of recent illustrations (ILLUSTRATOR_DEDUP_MAX_DISTANCE)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS illustration_hashes (
+            news_id TEXT PRIMARY KEY,
+            phash INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )
+    .context("Failed to create illustration_hashes table")?;
+    
+    Ok(conn)
+}
+
+fn init_data_dir() -> Result<()> {
+    if !Path::new(DATA_DIR).exists() {
+        fs::create_dir_all(DATA_DIR).context("Failed to create data directory")?;
+    }
+    Ok(())
+}
+
+fn run_illustrator(
+    conn: &Connection,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    attempt_policy: &AttemptPolicy,
+    image_policy: &ImagePolicy,
+    concurrency: usize,
+    budget: &DailyBudget,
+) -> Result<()> {
+    info!("Checking for news items to illustrate");
+    
+    // Fetch news items with "rewriter" or "illustrator_retry" status
+    let news_items = fetch_items_to_illustrate(conn, &stage.input_status)?;
+    
+    if news_items.is_empty() {
+        info!("No items to illustrate");
+        return Ok(());
+    }
+    
+    info!(
+        "Found {} items to illustrate",
+        news_items.len()
+    );
+    
+    let concurrency = concurrency.min(news_items.len());
+    if concurrency <= 1 {
+        run_async(async {
+            for item in news_items {
+                if shutdown_requested() || check_daily_budget(conn, "Illustrator", budget, &provider.client).await {
+                    break;
+                }
+                let span = item_span("illustrator", &item.id);
+                track_usage(illustrate_item(conn, item, provider, stage, attempt_policy, image_policy))
+                    .instrument(span)
+                    .await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })?;
+    } else {
+        info!("Illustrating with up to {} workers", concurrency);
+        run_workers(news_items, concurrency, CONCURRENCY_TUNER.get(), || WORKER_CONNECTIONS.get(), async |conn, item| {
+            if check_daily_budget(conn, "Illustrator", budget, &provider.client).await {
+                return;
+            }
+            let item_id = item.id.clone();
+            let span = item_span("illustrator", &item_id);
+            let illustrate = track_usage(illustrate_item(conn, item, provider, stage, attempt_policy, image_policy));
+            if let Err(e) = illustrate.instrument(span).await {
+                error!("Failed to illustrate item {}: {}", item_id, e);
+            }
+        });
+    }
+    
+    info!("Illustration cycle completed");
+    Ok(())
+}
+
+/// Illustrates one item and moves it on, to a retry or to review.
+async fn illustrate_item(
+    conn: &Connection,
+    item: NewsItem,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    attempt_policy: &AttemptPolicy,
+    image_policy: &ImagePolicy,
+) -> Result<()> {
+    let item_id = item.id.clone(); // Clone id for logging in case of error
+    // Another replica may have taken the item since it was fetched
+    if !claim_item(conn, &item_id, None, &item.status)? {
+        return Ok(());
+    }
+
+    take_usage();
+    let result = GENERATED_IMAGES
+        .scope(Cell::default(), async {
+            let result = process_news_item(conn, &item, provider, stage, image_policy).await;
+            record_illustrator_usage(conn, &item_id, provider).map(|()| result)
+        })
+        .await?;
+    match result {
+        Ok(finish_reason_opt) => {
+            let next_status = match finish_reason_opt.as_deref() {
+                Some(reason @ ("error" | "length")) => {
+                    let error = format!("Illustration failed with finish_reason={}", reason);
+                    let (next_status, attempts) = record_failed_attempt(conn, &item_id, None, attempt_policy, &error)?;
+                    let message = format!(
+                        "Illustration failed for item {} (finish_reason={:?}, attempt {}/{}). Setting status to {}.",
+                        item_id, finish_reason_opt, attempts, attempt_policy.max_attempts, next_status
+                    );
+                    if next_status == Status::IllustratorError {
+                        error!("{}", message);
+                    } else {
+                        warn!("{}", message);
+                    }
+                    next_status
+                }
+                Some(REVIEW_FINISH_REASON) => {
+                    warn!(
+                        "Image of item {} needs review. Setting status to {}.",
+                        item_id,
+                        Status::IllustratorReview
+                    );
+                    reset_attempts(conn, &item_id, None)?;
+                    Status::IllustratorReview
+                }
+                Some(_) | None => {
+                    info!(
+                        "Successfully processed news item: {}",
+                        item_id
+                    );
+                    reset_attempts(conn, &item_id, None)?;
+                    stage.output_status.clone()
+                }
+            };
+            update_status(conn, &item_id, None, &item.status, &next_status)?;
+        }
+        Err(e) if e.is::<Cancelled>() => {
+            // Shutting down: the next start illustrates it again
+            release_claim(conn, &item_id, None)?;
+        }
+        Err(e) => {
+            let (next_status, attempts) = record_failed_attempt(conn, &item_id, None, attempt_policy, &e.to_string())?;
+            error!(
+                "Critical error processing item {} (attempt {}/{}): {}. Setting status to {}.",
+                item_id, attempts, attempt_policy.max_attempts, e, next_status
+            );
+
+            update_status(conn, &item_id, None, &item.status, &next_status)?;
+        }
+    }
+    Ok(())
+}
+
+fn fetch_items_to_illustrate(conn: &Connection, input_status: &Status) -> Result<Vec<NewsItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, url, date, status FROM news \
+         WHERE status = ? OR (status = ? AND COALESCE(next_attempt_at, 0) <= ?) ORDER BY date ASC",
+    )?;
+    let news_iter = stmt.query_map(params![input_status, Status::IllustratorRetry, unix_now()], news_item_from_row)?;
+    
+    let mut news_items = Vec::new();
+    for item in news_iter {
+        news_items.push(item?);
+    }
+    
+    Ok(news_items)
+}
+
+fn news_item_from_row(row: &Row) -> rusqlite::Result<NewsItem> {
+    Ok(NewsItem {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        url: row.get(2)?,
+        date: row.get(3)?,
+        status: row.get(4)?,
+    })
+}
+
+async fn process_news_item(
+    conn: &Connection,
+    item: &NewsItem,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    image_policy: &ImagePolicy,
+) -> Result<Option<String>> {
+    let input_artifact = format!("{}_{}.html", stage.input_artifact, item.id);
+    let output_artifact = format!("{}_{}.png", stage.output_artifact, item.id);
+    let original_artifact = format!("{}_original_{}.png", stage.output_artifact, item.id);
+    
+    debug!("Processing item: {}", item.id);
+    
+    let html_content = match load_artifact(&input_artifact)? {
+        Some(bytes) => String::from_utf8(bytes)
+            .context(format!("Failed to read content from file: {}", input_artifact))?,
+        None => return Err(anyhow!("Input file not found: {}", input_artifact)),
+    };
+
+    // Album images of an earlier attempt must not be published with this one
+    remove_extra_illustrations(item, stage)?;
+
+    // Minor briefs go out as text-only posts; the publisher checks illustration_skipped
+    let skip_reason = illustration_skip_reason(conn, item, &html_content, &image_policy.skip_rules)?;
+    conn.execute(
+        "UPDATE news SET illustration_skipped = ? WHERE id = ?",
+        params![skip_reason, item.id],
+    )?;
+    if let Some(reason) = skip_reason {
+        info!("Skipping illustration of item {}: {}", item.id, reason);
+        return Ok(None);
+    }
+
+    // A real photo from the article beats a generated one; generate only when there is none
+    if image_policy.lead_image.enabled {
+        match fetch_lead_image(conn, item, provider, &image_policy.lead_image).await {
+            Ok(Some(image_bytes)) => {
+                save_illustration(item, &image_bytes, &original_artifact, &output_artifact, &image_policy.limits)?;
+                info!("Using the article's lead image for item {}", item.id);
+                return Ok(None);
+            }
+            Ok(None) => {}
+            Err(e) if e.is::<Cancelled>() => return Err(e),
+            Err(e) => {
+                warn!(
+                    "Failed to use the lead image of item {}: {}. Generating one instead.",
+                    item.id, e
+                );
+            }
+        }
+    }
+    
+    // Send to AI provider API and get image bytes + finish_reason
+    // With a prompt model the image model gets a short scene description instead of the article
+    let image_input = match &provider.prompt_synthesis {
+        Some(synthesis) => match cancellable(synthesize_visual_prompt(&html_content, provider, synthesis)).await {
+            Ok(visual_prompt) => {
+                let prompt_artifact = format!("{}_prompt_{}.txt", stage.output_artifact, item.id);
+                write_artifact(&prompt_artifact, &visual_prompt)
+                    .context(format!("Failed to write visual prompt: {}", prompt_artifact))?;
+                debug!("Visual prompt for item {}: {}", item.id, visual_prompt);
+                visual_prompt
+            }
+            Err(e) if e.is::<Cancelled>() => return Err(e),
+            Err(e) => {
+                warn!(
+                    "Failed to synthesize a visual prompt for item {}: {}. Sending the article instead.",
+                    item.id, e
+                );
+                html_content.clone()
+            }
+        },
+        None => html_content.clone(),
+    };
+
+    let mut illustrate_result = cancellable(generate_image(&image_input, provider, &provider.prompt)).await;
+
+    // Models like to repeat a composition; ask once for something different before holding it back
+    let mut repeated = None;
+    if let (Some(dedup), Ok((image_bytes, _))) = (&image_policy.dedup, &illustrate_result) {
+        if let Some((similar_id, distance)) = find_similar_illustration(conn, item, image_bytes, dedup)? {
+            info!(
+                "Image of item {} looks like the one of item {} (distance {}), regenerating with a variation hint",
+                item.id, similar_id, distance
+            );
+            let varied_prompt = format!("{}\n\n{}", provider.prompt, dedup.variation_hint);
+            illustrate_result = cancellable(generate_image(&image_input, provider, &varied_prompt)).await;
+            if let Ok((image_bytes, _)) = &illustrate_result {
+                repeated = find_similar_illustration(conn, item, image_bytes, dedup)?;
+            }
+        }
+    }
+    match &illustrate_result {
+        Ok(_) => clear_provider_error(conn, &item.id, "illustrator")?,
+        Err(ApiError::Cancelled(_)) => {}
+        Err(e) => record_provider_error(conn, &item.id, "illustrator", &e.failure())?,
+    }
+    
+    // Match on the actual Result, not a reference
+    match &illustrate_result {
+        Ok((ref original_bytes, ref finish_reason)) => {
+            save_illustration(item, original_bytes, &original_artifact, &output_artifact, &image_policy.limits)?;
+            if let Some((similar_id, distance)) = &repeated {
+                flag_for_review(
+                    item,
+                    stage,
+                    &format!("looks like the illustration of item {} (distance {}) even after a retry", similar_id, distance),
+                )?;
+                return Ok(Some(REVIEW_FINISH_REASON.to_string()));
+            }
+            if finish_reason.as_deref() == Some(REVIEW_FINISH_REASON) {
+                flag_for_review(item, stage, "provider safety ratings")?;
+                return Ok(Some(REVIEW_FINISH_REASON.to_string()));
+            }
+            if let Some(safety_check) = &image_policy.safety_check {
+                let published_bytes = read_artifact(&output_artifact).context("Failed to read image for the safety check")?;
+                match cancellable(check_image_safety(&provider.client, safety_check, &published_bytes)).await {
+                    Ok(Some(categories)) => {
+                        flag_for_review(item, stage, &format!("moderation endpoint: {}", categories))?;
+                        return Ok(Some(REVIEW_FINISH_REASON.to_string()));
+                    }
+                    Ok(None) => {}
+                    Err(e) if e.is::<Cancelled>() => return Err(e),
+                    Err(e) => {
+                        // Unchecked images are not published; the item is retried
+                        warn!("Image safety check failed for item {}: {}", item.id, e);
+                        return Ok(Some("error".to_string()));
+                    }
+                }
+            }
+            // Only images that are kept count against later ones; held or retried images don't
+            if image_policy.dedup.is_some() {
+                record_illustration_hash(conn, item, original_bytes)?;
+            }
+            if image_policy.images_per_item > 1 {
+                generate_extra_illustrations(item, provider, stage, &image_input, image_policy).await?;
+            }
+        }
+        Err(ref e @ ApiError::RequestError(_)) => {
+            // Borrow the error to avoid moving it
+            error!(
+                "API request failed for item {}: {}. No image to save.",
+                item.id, e
+            );
+            // Convert ApiError directly to anyhow::Error
+            return Err(anyhow!(e.clone()));
+        }
+        Err(ref e @ ApiError::ParseError(_)) => {
+            // Borrow the error
+             error!(
+                "Failed to parse API response for item {}: {}. No image to save.",
+                item.id, e
+            );
+            // Convert ApiError directly to anyhow::Error
+            return Err(anyhow!(e.clone()));
+        }
+        Err(ref e @ ApiError::ConfigurationError(_)) => {
+            error!(
+                "Invalid illustrator provider configuration for item {}: {}. No image to save.",
+                item.id, e
+            );
+            return Err(anyhow!(e.clone()));
+        }
+        Err(ApiError::ApiReturnedError { .. }) => {
+            // Controlled error: we return finish_reason to let caller set illustrator_retry.
+        }
+        Err(ref e @ (ApiError::EmptyImageData | ApiError::EmptyChoices)) => {
+            error!(
+                "AI provider returned empty image data for item {}: {}. No image to save.",
+                item.id, e
+            );
+            return Err(anyhow!(e.clone()));
+        }
+        Err(ApiError::Cancelled(cancelled)) => return Err((*cancelled).into()),
+    }
+
+    // Return the finish_reason if successful or if API returned a controlled error
+    match illustrate_result {
+        Ok((_, finish_reason)) => Ok(finish_reason),
+        Err(ApiError::ApiReturnedError { finish_reason, .. }) => Ok(finish_reason),
+        // Other errors were already returned as Err(anyhow::Error)
+        Err(e) => Err(anyhow!(e)), // Convert remaining ApiError variants
+    }
+}
+
+/// Returns why the item should be published without an illustration, if any skip rule matches.
+fn illustration_skip_reason(conn: &Connection, item: &NewsItem, html_content: &str, rules: &SkipRules) -> Result<Option<String>> {
+    if rules.min_words > 0 {
+        let words = strip_tags(html_content).split_whitespace().count();
+        if words < rules.min_words {
+            return Ok(Some(format!("{} words (ILLUSTRATOR_SKIP_MIN_WORDS={})", words, rules.min_words)));
+        }
+    }
+
+    if !rules.keywords.is_empty() {
+        let path = item
+            .url
+            .split_once("://")
+            .map_or(item.url.as_str(), |(_, rest)| rest.split_once('/').map_or("", |(_, path)| path));
+        let haystack = format!("{} {}", item.title, path).to_lowercase();
+        if let Some(keyword) = rules.keywords.iter().find(|keyword| haystack.contains(keyword.as_str())) {
+            return Ok(Some(format!("matches keyword '{}'", keyword)));
+        }
+    }
+
+    if !rules.sources.is_empty() {
+        let source: Option<String> =
+            conn.query_row("SELECT source FROM news WHERE id = ?", params![item.id], |row| row.get(0))?;
+        if let Some(source) = source.filter(|source| rules.sources.contains(&source.to_lowercase())) {
+            return Ok(Some(format!("source '{}'", source)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Drops HTML tags, keeping the text between them.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// 64-bit perceptual hash: the signs of the lowest 8x8 DCT frequencies of the 32x32 grayscale
+/// image relative to their median. Similar-looking images differ in only a few bits.
+fn perceptual_hash(image_bytes: &[u8]) -> Result<u64> {
+    const SIZE: usize = 32;
+    const LOW: usize = 8;
+
+    let image = image::load_from_memory(image_bytes)
+        .context("Failed to decode image for hashing")?
+        .resize_exact(SIZE as u32, SIZE as u32, FilterType::Triangle)
+        .to_luma8();
+    let pixels: Vec<f64> = image.pixels().map(|pixel| f64::from(pixel.0[0])).collect();
+
+    let mut cosines = [[0f64; SIZE]; LOW];
+    for (frequency, row) in cosines.iter_mut().enumerate() {
+        for (position, cosine) in row.iter_mut().enumerate() {
+            *cosine = ((2 * position + 1) as f64 * frequency as f64 * std::f64::consts::PI / (2 * SIZE) as f64).cos();
+        }
+    }
+
+    let mut coefficients = [0f64; LOW * LOW];
+    for v in 0..LOW {
+        for u in 0..LOW {
+            let mut sum = 0.0;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    sum += pixels[y * SIZE + x] * cosines[u][x] * cosines[v][y];
+                }
+            }
+            coefficients[v * LOW + u] = sum;
+        }
+    }
+
+    // The DC term only reflects overall brightness
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+
+    Ok(coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, coefficient)| **coefficient > median)
+        .fold(0u64, |hash, (bit, _)| hash | (1 << bit)))
+}
+
+/// Returns the recent illustration of another item closest to the image, with its Hamming distance, if it is
+/// within the dedup distance.
+fn find_similar_illustration(conn: &Connection, item: &NewsItem, image_bytes: &[u8], dedup: &DedupPolicy) -> Result<Option<(String, u32)>> {
+    let hash = perceptual_hash(image_bytes)?;
+    // A regenerated item's own earlier image is no repetition
+    let mut stmt = conn.prepare(
+        "SELECT news_id, phash FROM illustration_hashes WHERE news_id != ? ORDER BY created_at DESC LIMIT ?",
+    )?;
+    let recent = stmt
+        .query_map(params![item.id, dedup.window as i64], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(recent
+        .into_iter()
+        .map(|(news_id, recent_hash)| (news_id, (hash ^ recent_hash as u64).count_ones()))
+        .filter(|(_, distance)| *distance <= dedup.max_distance)
+        .min_by_key(|(_, distance)| *distance))
+}
+
+fn record_illustration_hash(conn: &Connection, item: &NewsItem, image_bytes: &[u8]) -> Result<()> {
+    let hash = perceptual_hash(image_bytes)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO illustration_hashes (news_id, phash, created_at) VALUES (?, ?, ?)",
+        params![item.id, hash as i64, unix_now()],
+    )?;
+    Ok(())
+}
+
+/// Records why the item's image was held back in `data/<output_artifact>_review_<id>.txt`, which
+/// `ctl review` shows next to the item.
+fn flag_for_review(item: &NewsItem, stage: &PipelineStage, reason: &str) -> Result<()> {
+    let review_artifact = format!("{}_review_{}.txt", stage.output_artifact, item.id);
+    write_artifact(&review_artifact, reason).context(format!("Failed to write review findings: {}", review_artifact))?;
+    warn!("Image of item {} held for review ({})", item.id, reason);
+    Ok(())
+}
+
+/// Sends the image to the OpenAI moderation endpoint; returns the flagged categories, or None
+/// when the image is safe.
+async fn check_image_safety(client: &Client, safety_check: &SafetyCheck, png_bytes: &[u8]) -> Result<Option<String>> {
+    let data_url = format!(
+        "data:image/png;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(png_bytes)
+    );
+    let response = client
+        .post(MODERATION_URL)
+        .header("Authorization", format!("Bearer {}", safety_check.api_key))
+        .json(&serde_json::json!({
+            "model": safety_check.model,
+            "input": [{ "type": "image_url", "image_url": { "url": data_url } }],
+        }))
+        .send()
+        .await
+        .context("Failed to call the moderation endpoint")?;
+    let status = response.status();
+    let response_text = response.text().await.context("Failed to read the moderation response")?;
+    if !status.is_success() {
+        return Err(anyhow!(
+            "moderation endpoint returned {}: {}",
+            status,
+            truncate_for_log(&response_text, 2000)
+        ));
+    }
+
+    let body: serde_json::Value =
+        serde_json::from_str(&response_text).context("Failed to parse the moderation response")?;
+    let result = &body["results"][0];
+    if !result["flagged"].as_bool().unwrap_or(false) {
+        return Ok(None);
+    }
+    let categories: Vec<&str> = result["categories"]
+        .as_object()
+        .map(|categories| {
+            categories
+                .iter()
+                .filter(|(_, flagged)| flagged.as_bool() == Some(true))
+                .map(|(category, _)| category.as_str())
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Some(if categories.is_empty() { "flagged".to_string() } else { categories.join(", ") }))
+}
+
+/// Keeps the image as is in `original_artifact` and writes the copy optimized for publishing to
+/// `output_artifact`.
+fn save_illustration(
+    item: &NewsItem,
+    original_bytes: &[u8],
+    original_artifact: &str,
+    output_artifact: &str,
+    image_limits: &ImageLimits,
+) -> Result<()> {
+    write_artifact(original_artifact, original_bytes)
+        .context(format!("Failed to write original image: {}", original_artifact))?;
+    let optimized = optimize_image(original_bytes, image_limits);
+    let image_bytes = match &optimized {
+        Ok(optimized_bytes) => optimized_bytes.as_slice(),
+        Err(e) => {
+            warn!(
+                "Failed to optimize image for item {}: {}. Using the original.",
+                item.id, e
+            );
+            original_bytes
+        }
+    };
+    debug!(
+        "Writing successful image to: {} ({} bytes, original {} bytes)",
+        output_artifact,
+        image_bytes.len(),
+        original_bytes.len()
+    );
+    write_artifact(output_artifact, image_bytes)
+        .context(format!(
+            "Failed to write image bytes to output file: {}",
+            output_artifact
+        ))?;
+    Ok(())
+}
+
+/// Generates illustrations 2..=N as `<output_artifact>_<id>_<k>.png` for the publisher's album. A
+/// failed or flagged extra image only makes the album shorter.
+async fn generate_extra_illustrations(
+    item: &NewsItem,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    image_input: &str,
+    image_policy: &ImagePolicy,
+) -> Result<()> {
+    let mut saved = 1;
+    for attempt in 2..=image_policy.images_per_item {
+        let (original_bytes, _) = match cancellable(generate_image(image_input, provider, &provider.prompt)).await {
+            Ok(result) => result,
+            Err(ApiError::Cancelled(cancelled)) => return Err(cancelled.into()),
+            Err(e) => {
+                warn!("Failed to generate image {} of item {}: {}", attempt, item.id, e);
+                continue;
+            }
+        };
+        let k = saved + 1;
+        let original_artifact = format!("{}_original_{}_{}.png", stage.output_artifact, item.id, k);
+        let output_artifact = format!("{}_{}_{}.png", stage.output_artifact, item.id, k);
+        save_illustration(item, &original_bytes, &original_artifact, &output_artifact, &image_policy.limits)?;
+
+        if let Some(safety_check) = &image_policy.safety_check {
+            let published_bytes = read_artifact(&output_artifact).context("Failed to read image for the safety check")?;
+            let finding = match cancellable(check_image_safety(&provider.client, safety_check, &published_bytes)).await {
+                Ok(None) => None,
+                Err(e) if e.is::<Cancelled>() => return Err(e),
+                Ok(Some(categories)) => Some(format!("flagged: {}", categories)),
+                Err(e) => Some(format!("check failed: {}", e)),
+            };
+            if let Some(finding) = finding {
+                warn!(
+                    "Dropping image {} of item {} from the album (safety check {})",
+                    attempt, item.id, finding
+                );
+                remove_artifact(&output_artifact).context(format!("Failed to remove {}", output_artifact))?;
+                continue;
+            }
+        }
+        saved = k;
+    }
+    info!("Generated {} illustrations for item {}", saved, item.id);
+    Ok(())
+}
+
+fn remove_extra_illustrations(item: &NewsItem, stage: &PipelineStage) -> Result<()> {
+    for k in 2..=MAX_ALBUM_IMAGES {
+        let output_artifact = format!("{}_{}_{}.png", stage.output_artifact, item.id, k);
+        remove_artifact(&output_artifact).context(format!("Failed to remove {}", output_artifact))?;
+    }
+    Ok(())
+}
+
+/// Downloads the og:image the downloader found for the item and converts it to PNG. Returns None
+/// when the article has no lead image or it is too small to publish.
+async fn fetch_lead_image(
+    conn: &Connection,
+    item: &NewsItem,
+    provider: &AiProviderConfig,
+    policy: &LeadImagePolicy,
+) -> Result<Option<Vec<u8>>> {
+    let lead_image_url: Option<String> = conn.query_row(
+        "SELECT lead_image_url FROM news WHERE id = ?",
+        params![item.id],
+        |row| row.get(0),
+    )?;
+    let Some(lead_image_url) = lead_image_url.filter(|url| !url.trim().is_empty()) else {
+        return Ok(None);
+    };
+
+    debug!("Downloading lead image of item {}: {}", item.id, lead_image_url);
+    let (bytes, mime_type) = cancellable(async {
+        let response = provider
+            .client
+            .get(&lead_image_url)
+            .send()
+            .await
+            .context("Failed to download lead image")?
+            .error_for_status()
+            .context("Lead image request failed")?;
+        let mime_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let bytes = response.bytes().await.context("Failed to read lead image")?;
+        Ok::<_, anyhow::Error>((bytes, mime_type))
+    })
+    .await?;
+
+    let png_bytes = normalize_image_bytes_with_mime_to_png(&bytes, mime_type.as_deref())?;
+    let image = image::load_from_memory_with_format(&png_bytes, ImageFormat::Png)
+        .context("Failed to decode lead image")?;
+    if image.width() < policy.min_width {
+        debug!(
+            "Lead image of item {} is only {}px wide, generating one instead",
+            item.id,
+            image.width()
+        );
+        return Ok(None);
+    }
+    Ok(Some(png_bytes))
+}
+
+/// Asks the text model for a concise image prompt describing the article.
+async fn synthesize_visual_prompt(content: &str, provider: &AiProviderConfig, synthesis: &PromptSynthesisConfig) -> Result<String> {
+    let request = PromptSynthesisRequest {
+        model: synthesis.model.clone(),
+        messages: vec![
+            Message {
+                role: "system".to_string(),
+                content: synthesis.instruction.clone(),
+            },
+            Message {
+                role: "user".to_string(),
+                content: content.to_string(),
+            },
+        ],
+    };
+
+    debug!(
+        "Requesting visual prompt from model '{}' (html_len={})",
+        synthesis.model,
+        content.len()
+    );
+
+    let response = provider
+        .client
+        .post(&synthesis.api_url)
+        .header("Authorization", format!("Bearer {}", synthesis.api_key))
+        .header("Content-Type", "application/json")
+        .json(&request)
+        .send()
+        .await
+        .context("Failed to send prompt synthesis request")?;
+    let status = response.status();
+    let response_text = response.text().await.context("Failed to read prompt synthesis response")?;
+    if !status.is_success() {
+        return Err(anyhow!(
+            "prompt model returned status {}: {}",
+            status,
+            truncate_for_log(&response_text, 2000)
+        ));
+    }
+
+    let response_data: ChatResponse =
+        serde_json::from_str(&response_text).context("Failed to parse prompt synthesis response")?;
+    if let Some(usage) = &response_data.usage {
+        add_usage(usage.prompt_tokens, usage.completion_tokens);
+    }
+    response_data
+        .choices
+        .first()
+        .and_then(|choice| choice.message.content.as_deref())
+        .map(|content| content.trim().to_string())
+        .filter(|content| !content.is_empty())
+        .ok_or_else(|| anyhow!("prompt model returned no text"))
+}
+
+/// Calls the image model, asking a second time when it returns no usable image, and then the
+/// fallback model if one is configured.
+async fn generate_image(content: &str, provider: &AiProviderConfig, prompt: &str) -> Result<(Vec<u8>, Option<String>), ApiError> {
+    let result = illustrate_content(content, provider, prompt).await;
+    count_generated_image(&result, false);
+    if !is_unusable_image(&result) {
+        return result;
+    }
+    warn!(
+        "{:?} model '{}' returned no usable image, asking again",
+        provider.provider_type, provider.model
+    );
+    let result = illustrate_content(content, provider, prompt).await;
+    count_generated_image(&result, false);
+    match &provider.fallback {
+        Some(fallback) if is_unusable_image(&result) => {
+            warn!(
+                "{:?} model '{}' returned no usable image twice, trying fallback {:?} model '{}'",
+                provider.provider_type, provider.model, fallback.provider_type, fallback.model
+            );
+            let result = illustrate_content(content, fallback, prompt).await;
+            count_generated_image(&result, true);
+            result
+        }
+        _ => result,
+    }
+}
+
+/// Counts an image the provider returned (and charged for), whether or not it turns out usable.
+fn count_generated_image(result: &Result<(Vec<u8>, Option<String>), ApiError>, fallback: bool) {
+    if result.is_err() {
+        return;
+    }
+    let _ = GENERATED_IMAGES.try_with(|images| {
+        let (primary, secondary) = images.get();
+        if fallback {
+            images.set((primary, secondary + 1));
+        } else {
+            images.set((primary + 1, secondary));
+        }
+    });
+}
+
+/// Stores the prompt model's tokens and the images generated for one item in `ai_usage`, and
+/// resets the counts.
+fn record_illustrator_usage(conn: &Connection, item_id: &str, provider: &AiProviderConfig) -> Result<()> {
+    let tokens = take_usage();
+    let (primary, secondary) = GENERATED_IMAGES.try_with(|images| images.replace((0, 0))).unwrap_or_default();
+    if let Some(synthesis) = &provider.prompt_synthesis {
+        let model = UsageModel {
+            provider: "OpenAiCompatible".to_string(),
+            model: &synthesis.model,
+            input_price: synthesis.input_price,
+            output_price: synthesis.output_price,
+        };
+        record_usage(conn, "illustrator", item_id, None, &model, tokens)?;
+    }
+    let provider_name = format!("{:?}", provider.provider_type);
+    record_image_usage(conn, "illustrator", item_id, &provider_name, &provider.model, primary, provider.image_price)?;
+    if let Some(fallback) = &provider.fallback {
+        let fallback_name = format!("{:?}", fallback.provider_type);
+        record_image_usage(conn, "illustrator", item_id, &fallback_name, &fallback.model, secondary, fallback.image_price)?;
+    }
+    Ok(())
+}
+
+/// Empty or undecodable output, as opposed to HTTP errors and refusals the provider reports itself.
+fn is_unusable_image(result: &Result<(Vec<u8>, Option<String>), ApiError>) -> bool {
+    match result {
+        Err(ApiError::EmptyImageData | ApiError::EmptyChoices | ApiError::ParseError(_)) => true,
+        Err(ApiError::ApiReturnedError {
+            status,
+            provider_error: None,
+            ..
+        }) => status.is_success(),
+        _ => false,
+    }
+}
+
+async fn illustrate_content(content: &str, provider: &AiProviderConfig, prompt: &str) -> Result<(Vec<u8>, Option<String>), ApiError> {
+    // Shared client: keeps connections and TLS sessions alive between items
+    let client = &provider.client;
+
+    match provider.provider_type {
+        AiProviderType::OpenRouter => {
+            // OpenRouter image generation (docs):
+            // - Request: POST https://openrouter.ai/api/v1/chat/completions with modalities including "image"
+            // - Response: choices[0].message.images[...].image_url.url containing a base64 data URL
+            // Sources:
+            // - https://openrouter.ai/docs/features/multimodal/image-generation
+            // - https://openrouter.ai/docs/guides/overview/multimodal/image-generation
+            // Some image-generation models may ignore system messages.
+            // To guarantee that AI_PROVIDER_ILLUSTRATOR_PROMPT is applied, embed it into the user prompt.
+            let user_prompt = format!("{}\n\n{}", prompt, content);
+            let messages = vec![Message {
+                role: "user".to_string(),
+                content: user_prompt,
+            }];
+
+            debug!(
+                "Request summary: model='{}', prompt_len={}, html_len={}",
+                provider.model,
+                prompt.len(),
+                content.len()
+            );
+
+            if let Some(reasoning) = &provider.reasoning {
+                debug!(
+                    "OpenRouter reasoning config: enabled={:?}, effort={:?}",
+                    reasoning.enabled, reasoning.effort
+                );
+            }
+
+            let request = OpenRouterChatRequest {
+                model: provider.model.clone(),
+                messages,
+                // Request only image output.
+                // Some models/providers may not support combined output modalities (image + text),
+                // which can lead to: "No endpoints found that support the requested output modalities".
+                modalities: vec!["image".to_string()],
+                reasoning: provider.reasoning.clone(),
+            };
+
+            debug!(
+                "Sending chat completion (image generation) request to OpenRouter with model: {}",
+                provider.model
+            );
+
+            let api_url = provider
+                .api_url
+                .as_deref()
+                .unwrap_or("https://openrouter.ai/api/v1/chat/completions");
+
+            let response = send_request(
+                client
+                    .post(api_url)
+                    .header("Authorization", format!("Bearer {}", provider.api_key))
+                    .header("Content-Type", "application/json")
+                    .json(&request),
+            )
+            .await?;
+
+            parse_openrouter_image_from_chat_response(client, response).await
+        }
+        AiProviderType::Gemini => {
+            // Gemini image generation uses models:generateContent and returns inlineData with base64 image bytes.
+            // Source: https://ai.google.dev/gemini-api/docs/image-generation
+
+            let user_prompt = format!("{}\n\n{}", prompt, content);
+            let request = GeminiGenerateContentRequest {
+                contents: vec![GeminiContent {
+                    parts: vec![GeminiPart { text: user_prompt }],
+                }],
+                generation_config: GeminiGenerationConfig {
+                    response_modalities: vec!["TEXT".to_string(), "IMAGE".to_string()],
+                },
+            };
+
+            debug!(
+                "Request summary: provider='Gemini', model='{}', prompt_len={}, html_len={}",
+                provider.model,
+                prompt.len(),
+                content.len()
+            );
+
+            let url = format!(
+                "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
+                provider.model
+            );
+            let api_url = provider.api_url.as_deref().unwrap_or(url.as_str());
+
+            let response = send_request(
+                client
+                    .post(api_url)
+                    .header("x-goog-api-key", provider.api_key.clone())
+                    .header("Content-Type", "application/json")
+                    .json(&request),
+            )
+            .await?;
+
+            parse_gemini_image_from_generate_content_response(response).await
+        }
+        AiProviderType::Xai => {
+            // xAI image generation docs:
+            // - POST https://api.x.ai/v1/images/generations
+            // - Request body supports: model, prompt, aspect_ratio, resolution, response_format
+            // - With response_format="b64_json", image data is returned in data[0].b64_json
+            // Sources:
+            // - https://docs.x.ai/developers/model-capabilities/images/generation
+            // - https://docs.x.ai/developers/rest-api-reference/inference/images
+            let xai_image_config = provider
+                .xai_image_config
+                .as_ref()
+                .ok_or_else(|| ApiError::ConfigurationError("XAI image configuration is missing".to_string()))?;
+
+            let user_prompt = format!("{}\n\n{}", prompt, content);
+            let request = XaiImageGenerationRequest {
+                model: provider.model.clone(),
+                prompt: user_prompt,
+                aspect_ratio: xai_image_config.aspect_ratio.clone(),
+                resolution: xai_image_config.resolution.clone(),
+                response_format: "b64_json".to_string(),
+            };
+
+            debug!(
+                "Request summary: provider='XAI', model='{}', aspect_ratio='{}', resolution='{}', prompt_len={}, html_len={}",
+                provider.model,
+                xai_image_config.aspect_ratio,
+                xai_image_config.resolution,
+                prompt.len(),
+                content.len()
+            );
+
+            let api_url = provider
+                .api_url
+                .as_deref()
+                .unwrap_or("https://api.x.ai/v1/images/generations");
+
+            let response = send_request(
+                client
+                    .post(api_url)
+                    .header("Authorization", format!("Bearer {}", provider.api_key))
+                    .header("Content-Type", "application/json")
+                    .json(&request),
+            )
+            .await?;
+
+            parse_image_generation_response(response, "XAI").await
+        }
+        AiProviderType::OpenAi => {
+            // OpenAI Images API docs:
+            // - POST https://api.openai.com/v1/images/generations
+            // - Request body supports: model, prompt, n, size, quality, response_format (DALL-E only)
+            // - Image data is returned in data[0].b64_json
+            // Source: https://platform.openai.com/docs/api-reference/images/create
+            let openai_image_config = provider
+                .openai_image_config
+                .as_ref()
+                .ok_or_else(|| ApiError::ConfigurationError("OpenAI image configuration is missing".to_string()))?;
+
+            let user_prompt = format!("{}\n\n{}", prompt, content);
+            let request = OpenAiImageGenerationRequest {
+                model: provider.model.clone(),
+                prompt: user_prompt,
+                n: 1,
+                size: openai_image_config.size.clone(),
+                quality: openai_image_config.quality.clone(),
+                response_format: provider
+                    .model
+                    .to_ascii_lowercase()
+                    .starts_with("dall-e")
+                    .then(|| "b64_json".to_string()),
+            };
+
+            debug!(
+                "Request summary: provider='OpenAI', model='{}', size='{}', quality={:?}, prompt_len={}, html_len={}",
+                provider.model,
+                openai_image_config.size,
+                openai_image_config.quality,
+                prompt.len(),
+                content.len()
+            );
+
+            let api_url = provider
+                .api_url
+                .as_deref()
+                .unwrap_or("https://api.openai.com/v1/images/generations");
+
+            let response = send_request(
+                client
+                    .post(api_url)
+                    .header("Authorization", format!("Bearer {}", provider.api_key))
+                    .header("Content-Type", "application/json")
+                    .json(&request),
+            )
+            .await?;
+
+            parse_image_generation_response(response, "OpenAI").await
+        }
+        AiProviderType::Stability => {
+            // Stability AI Stable Image docs:
+            // - POST https://api.stability.ai/v2beta/stable-image/generate/{ultra|core|sd3} as multipart/form-data
+            // - The sd3 endpoint takes the model (e.g. "sd3.5-large") as a form field
+            // - With "Accept: image/*" the response body is the image itself
+            // Source: https://platform.stability.ai/docs/api-reference#tag/Generate
+            let stability_image_config = provider
+                .stability_image_config
+                .as_ref()
+                .ok_or_else(|| ApiError::ConfigurationError("Stability image configuration is missing".to_string()))?;
+
+            let user_prompt: String = format!("{}\n\n{}", prompt, content)
+                .chars()
+                .take(STABILITY_MAX_PROMPT_CHARS)
+                .collect();
+            let mut form = reqwest::multipart::Form::new()
+                .text("prompt", user_prompt)
+                .text("output_format", "png");
+            let endpoint = match provider.model.as_str() {
+                "ultra" | "core" => provider.model.as_str(),
+                model => {
+                    form = form.text("model", model.to_string());
+                    "sd3"
+                }
+            };
+            if let Some(aspect_ratio) = &stability_image_config.aspect_ratio {
+                form = form.text("aspect_ratio", aspect_ratio.clone());
+            }
+
+            debug!(
+                "Request summary: provider='Stability', model='{}', aspect_ratio={:?}, prompt_len={}, html_len={}",
+                provider.model,
+                stability_image_config.aspect_ratio,
+                prompt.len(),
+                content.len()
+            );
+
+            let url = format!("https://api.stability.ai/v2beta/stable-image/generate/{}", endpoint);
+            let api_url = provider.api_url.as_deref().unwrap_or(url.as_str());
+
+            let response = send_request(
+                client
+                    .post(api_url)
+                    .header("Authorization", format!("Bearer {}", provider.api_key))
+                    .header("Accept", "image/*")
+                    .multipart(form),
+            )
+            .await?;
+
+            parse_stability_image_response(response).await
+        }
+        AiProviderType::Replicate => {
+            // Replicate predictions docs:
+            // - POST https://api.replicate.com/v1/models/{owner}/{name}/predictions for official models
+            //   (e.g. black-forest-labs/flux-schnell), POST /v1/predictions with a version otherwise
+            // - The prediction runs asynchronously; poll urls.get until status is succeeded/failed/canceled
+            // - output holds the image URL (a list of URLs for some models)
+            // Sources:
+            // - https://replicate.com/docs/reference/http#predictions.create
+            // - https://replicate.com/docs/topics/predictions/lifecycle
+            let replicate_config = provider
+                .replicate_config
+                .as_ref()
+                .ok_or_else(|| ApiError::ConfigurationError("Replicate configuration is missing".to_string()))?;
+
+            // "owner/name:version" pins a model version
+            let (url, version) = match provider.model.split_once(':') {
+                Some((_, version)) => ("https://api.replicate.com/v1/predictions".to_string(), Some(version.to_string())),
+                None => (format!("https://api.replicate.com/v1/models/{}/predictions", provider.model), None),
+            };
+            let request = ReplicatePredictionRequest {
+                version,
+                input: ReplicateInput {
+                    prompt: format!("{}\n\n{}", prompt, content),
+                    aspect_ratio: replicate_config.aspect_ratio.clone(),
+                    output_format: "png".to_string(),
+                },
+            };
+
+            debug!(
+                "Request summary: provider='Replicate', model='{}', aspect_ratio={:?}, prompt_len={}, html_len={}",
+                provider.model,
+                replicate_config.aspect_ratio,
+                prompt.len(),
+                content.len()
+            );
+
+            let api_url = provider.api_url.as_deref().unwrap_or(url.as_str());
+            let response = send_request(
+                client
+                    .post(api_url)
+                    .header("Authorization", format!("Bearer {}", provider.api_key))
+                    .header("Content-Type", "application/json")
+                    .json(&request),
+            )
+            .await?;
+
+            let prediction = parse_replicate_prediction(response).await?;
+            let prediction = wait_for_replicate_prediction(client, provider, replicate_config, prediction).await?;
+            download_replicate_output(client, &prediction).await
+        }
+        AiProviderType::A1111 => {
+            // AUTOMATIC1111 / Forge / SD.Next web UI started with --api:
+            // - POST {base}/sdapi/v1/txt2img with prompt, negative_prompt, width, height, override_settings
+            // - Response: images[] with base64-encoded PNGs
+            // Source: https://github.com/AUTOMATIC1111/stable-diffusion-webui/wiki/API
+            let local_sd_config = provider
+                .local_sd_config
+                .as_ref()
+                .ok_or_else(|| ApiError::ConfigurationError("Local Stable Diffusion configuration is missing".to_string()))?;
+
+            let request = A1111Txt2ImgRequest {
+                prompt: format!("{}\n\n{}", prompt, content),
+                negative_prompt: local_sd_config.negative_prompt.clone(),
+                width: local_sd_config.size.map(|(width, _)| width),
+                height: local_sd_config.size.map(|(_, height)| height),
+                override_settings: (!provider.model.is_empty())
+                    .then(|| serde_json::json!({ "sd_model_checkpoint": provider.model })),
+            };
+
+            debug!(
+                "Request summary: provider='A1111', model='{}', size={:?}, prompt_len={}, html_len={}",
+                provider.model,
+                local_sd_config.size,
+                prompt.len(),
+                content.len()
+            );
+
+            let response = with_local_auth(
+                client.post(format!("{}/sdapi/v1/txt2img", local_sd_config.base_url)),
+                &provider.api_key,
+            )
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+
+            parse_a1111_txt2img_response(response).await
+        }
+        AiProviderType::ComfyUi => {
+            // ComfyUI server API:
+            // - POST {base}/prompt with {"prompt": <workflow in API format>} returns prompt_id
+            // - GET {base}/history/{prompt_id} is empty until the job is done, then lists outputs[node].images[]
+            // - GET {base}/view?filename=..&subfolder=..&type=.. returns the image
+            // Source: https://docs.comfy.org/development/comfyui-server/comms_routes
+            let local_sd_config = provider
+                .local_sd_config
+                .as_ref()
+                .ok_or_else(|| ApiError::ConfigurationError("Local Stable Diffusion configuration is missing".to_string()))?;
+            let workflow = local_sd_config
+                .workflow
+                .as_deref()
+                .ok_or_else(|| ApiError::ConfigurationError("AI_PROVIDER_ILLUSTRATOR_WORKFLOW_FILE is not set".to_string()))?;
+
+            let workflow = fill_comfyui_workflow(
+                workflow,
+                &format!("{}\n\n{}", prompt, content),
+                local_sd_config.negative_prompt.as_deref().unwrap_or(""),
+                &provider.model,
+            )
+            .map_err(|e| ApiError::ConfigurationError(format!("Invalid ComfyUI workflow: {}", e)))?;
+
+            debug!(
+                "Request summary: provider='ComfyUI', model='{}', prompt_len={}, html_len={}",
+                provider.model,
+                prompt.len(),
+                content.len()
+            );
+
+            let response = with_local_auth(client.post(format!("{}/prompt", local_sd_config.base_url)), &provider.api_key)
+                .json(&serde_json::json!({ "prompt": workflow }))
+                .send()
+                .await
+                .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+            let queued = parse_local_json_response(response, "ComfyUI").await?;
+            let prompt_id = queued["prompt_id"]
+                .as_str()
+                .ok_or_else(|| ApiError::ParseError(Arc::new(anyhow!("ComfyUI response has no prompt_id: {}", queued))))?
+                .to_string();
+
+            let image = wait_for_comfyui_image(client, provider, local_sd_config, &prompt_id).await?;
+            let response = with_local_auth(client.get(format!("{}/view", local_sd_config.base_url)), &provider.api_key)
+                .query(&[
+                    ("filename", image["filename"].as_str().unwrap_or("")),
+                    ("subfolder", image["subfolder"].as_str().unwrap_or("")),
+                    ("type", image["type"].as_str().unwrap_or("output")),
+                ])
+                .send()
+                .await
+                .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+            let status = response.status();
+            let body = response
+                .bytes()
+                .await
+                .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+            if !status.is_success() {
+                return Err(ApiError::ApiReturnedError {
+                    status,
+                    provider_error: None,
+                    content: format!("Failed to download ComfyUI image {}", image),
+                    finish_reason: Some("error".to_string()),
+                });
+            }
+
+            let image_bytes = normalize_image_bytes_to_png(&body)
+                .map_err(|e| ApiError::ParseError(Arc::new(e)))?;
+            Ok((image_bytes, None))
+        }
+    }
+}
+
+/// `user:password` is sent as basic auth (A1111's --api-auth), anything else as a bearer token
+/// for servers behind an authenticating proxy.
+fn with_local_auth(request: reqwest::RequestBuilder, api_key: &str) -> reqwest::RequestBuilder {
+    match api_key.split_once(':') {
+        _ if api_key.is_empty() => request,
+        Some((user, password)) => request.basic_auth(user, Some(password)),
+        None => request.header("Authorization", format!("Bearer {}", api_key)),
+    }
+}
+
+async fn parse_local_json_response(
+    response: reqwest::Response,
+    provider_name: &str,
+) -> Result<serde_json::Value, ApiError> {
+    let status = response.status();
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+
+    if !status.is_success() {
+        warn!(
+            "{} returned non-success status: {}. Body: {}",
+            provider_name,
+            status,
+            truncate_for_log(&response_text, 2000)
+        );
+        return Err(ApiError::ApiReturnedError {
+            provider_error: parse_provider_error(&response_text),
+            status,
+            content: response_text,
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    serde_json::from_str(&response_text).map_err(|e| {
+        error!(
+            "Failed to parse {} JSON. Status: {}. Body: {}",
+            provider_name,
+            status,
+            truncate_for_log(&response_text, 2000)
+        );
+        ApiError::ParseError(Arc::new(e.into()))
+    })
+}
+
+async fn parse_a1111_txt2img_response(
+    response: reqwest::Response,
+) -> Result<(Vec<u8>, Option<String>), ApiError> {
+    let response_data: A1111Txt2ImgResponse = serde_json::from_value(parse_local_json_response(response, "A1111").await?)
+        .map_err(|e| ApiError::ParseError(Arc::new(e.into())))?;
+
+    debug!(
+        "A1111 response summary: images={}",
+        response_data.images.len()
+    );
+
+    let b64 = response_data.images.first().ok_or(ApiError::EmptyImageData)?;
+    let raw_image_bytes = base64::engine::general_purpose::STANDARD
+        .decode(b64)
+        .map_err(|e| ApiError::ParseError(Arc::new(anyhow!(e))))?;
+
+    let image_bytes = normalize_image_bytes_to_png(&raw_image_bytes)
+        .map_err(|e| ApiError::ParseError(Arc::new(e)))?;
+
+    Ok((image_bytes, None))
+}
+
+/// Puts the prompt, negative prompt and model into the `"%prompt%"`, `"%negative_prompt%"` and
+/// `"%model%"` placeholders of the workflow.
+fn fill_comfyui_workflow(workflow: &str, prompt: &str, negative_prompt: &str, model: &str) -> Result<serde_json::Value> {
+    let filled = workflow
+        .replace("\"%prompt%\"", &serde_json::to_string(prompt)?)
+        .replace("\"%negative_prompt%\"", &serde_json::to_string(negative_prompt)?)
+        .replace("\"%model%\"", &serde_json::to_string(model)?);
+    Ok(serde_json::from_str(&filled)?)
+}
+
+/// Polls the ComfyUI history until the job has produced an image or AI_PROVIDER_ILLUSTRATOR_MAX_WAIT_SECS pass.
+async fn wait_for_comfyui_image(
+    client: &Client,
+    provider: &AiProviderConfig,
+    local_sd_config: &LocalSdConfig,
+    prompt_id: &str,
+) -> Result<serde_json::Value, ApiError> {
+    let mut waited_secs = 0;
+    loop {
+        let response = with_local_auth(
+            client.get(format!("{}/history/{}", local_sd_config.base_url, prompt_id)),
+            &provider.api_key,
+        )
+        .send()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+        let history = parse_local_json_response(response, "ComfyUI").await?;
+        let job = &history[prompt_id];
+
+        if job["status"]["status_str"].as_str() == Some("error") {
+            return Err(ApiError::ApiReturnedError {
+                status: reqwest::StatusCode::OK,
+                provider_error: None,
+                content: format!("ComfyUI job {} failed: {}", prompt_id, job["status"]["messages"]),
+                finish_reason: Some("error".to_string()),
+            });
+        }
+        if let Some(outputs) = job["outputs"].as_object() {
+            if let Some(image) = outputs.values().filter_map(|output| output["images"].as_array()).flatten().next() {
+                return Ok(image.clone());
+            }
+            if job["status"]["completed"].as_bool() == Some(true) {
+                return Err(ApiError::EmptyImageData);
+            }
+        }
+
+        if waited_secs >= local_sd_config.max_wait_secs {
+            return Err(ApiError::ApiReturnedError {
+                status: reqwest::StatusCode::OK,
+                provider_error: None,
+                content: format!("ComfyUI job {} not finished after {}s", prompt_id, waited_secs),
+                finish_reason: Some("error".to_string()),
+            });
+        }
+
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+        waited_secs += POLL_INTERVAL_SECS;
+    }
+}
+
+async fn parse_stability_image_response(
+    response: reqwest::Response,
+) -> Result<(Vec<u8>, Option<String>), ApiError> {
+    let status = response.status();
+    // Set to CONTENT_FILTERED when the image was blurred by Stability's moderation
+    let finish_reason = response
+        .headers()
+        .get("finish-reason")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+
+    if !status.is_success() {
+        let response_text = String::from_utf8_lossy(&body).to_string();
+        warn!(
+            "Stability returned non-success status: {}. Body: {}",
+            status,
+            truncate_for_log(&response_text, 2000)
+        );
+        return Err(ApiError::ApiReturnedError {
+            provider_error: parse_provider_error(&response_text),
+            status,
+            content: response_text,
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    if finish_reason.as_deref() == Some("CONTENT_FILTERED") {
+        warn!("Stability filtered the generated image");
+        return Err(ApiError::ApiReturnedError {
+            provider_error: None,
+            status,
+            content: "Stability filtered the generated image (finish-reason: CONTENT_FILTERED)".to_string(),
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    debug!(
+        "Stability raw image bytes received: {}",
+        body.len()
+    );
+
+    let image_bytes = normalize_image_bytes_to_png(&body)
+        .map_err(|e| ApiError::ParseError(Arc::new(e)))?;
+
+    Ok((image_bytes, None))
+}
+
+async fn parse_replicate_prediction(response: reqwest::Response) -> Result<ReplicatePrediction, ApiError> {
+    let status = response.status();
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+
+    if !status.is_success() {
+        warn!(
+            "Replicate returned non-success status: {}. Body: {}",
+            status,
+            truncate_for_log(&response_text, 2000)
+        );
+        return Err(ApiError::ApiReturnedError {
+            provider_error: parse_provider_error(&response_text),
+            status,
+            content: response_text,
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    serde_json::from_str(&response_text).map_err(|e| {
+        error!(
+            "Failed to parse Replicate prediction JSON. Status: {}. Body: {}",
+            status,
+            truncate_for_log(&response_text, 2000)
+        );
+        ApiError::ParseError(Arc::new(e.into()))
+    })
+}
+
+/// Sends an image request and tells the concurrency tuner how long it took.
+async fn send_request(request: RequestBuilder) -> Result<Response, ApiError> {
+    let started = Instant::now();
+    let response = request.send().await;
+    if let Some(tuner) = CONCURRENCY_TUNER.get() {
+        // A failed request (mostly a timeout) tells the tuner the same as a 429: the provider has too much
+        let overloaded = response.as_ref().map_or(true, |response| response.status() == StatusCode::TOO_MANY_REQUESTS);
+        tuner.record(started.elapsed(), overloaded);
+    }
+    response.map_err(|e| ApiError::RequestError(Arc::new(e)))
+}
+
+/// Polls the prediction until Replicate reports a final status or AI_PROVIDER_ILLUSTRATOR_MAX_WAIT_SECS pass.
+async fn wait_for_replicate_prediction(
+    client: &Client,
+    provider: &AiProviderConfig,
+    replicate_config: &ReplicateConfig,
+    mut prediction: ReplicatePrediction,
+) -> Result<ReplicatePrediction, ApiError> {
+    let mut waited_secs = 0;
+    loop {
+        match prediction.status.as_str() {
+            "succeeded" => return Ok(prediction),
+            "failed" | "canceled" => {
+                warn!(
+                    "Replicate prediction {} ended with status '{}': {}",
+                    prediction.id, prediction.status, prediction.error
+                );
+                return Err(ApiError::ApiReturnedError {
+                    status: reqwest::StatusCode::OK,
+                    provider_error: None,
+                    content: format!("Replicate prediction {}: {}", prediction.status, prediction.error),
+                    finish_reason: Some("error".to_string()),
+                });
+            }
+            _ => {}
+        }
+
+        if waited_secs >= replicate_config.max_wait_secs {
+            return Err(ApiError::ApiReturnedError {
+                status: reqwest::StatusCode::OK,
+                provider_error: None,
+                content: format!(
+                    "Replicate prediction {} still '{}' after {}s",
+                    prediction.id, prediction.status, waited_secs
+                ),
+                finish_reason: Some("error".to_string()),
+            });
+        }
+
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+        waited_secs += POLL_INTERVAL_SECS;
+
+        let response = client
+            .get(&prediction.urls.get)
+            .header("Authorization", format!("Bearer {}", provider.api_key))
+            .send()
+            .await
+            .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+        prediction = parse_replicate_prediction(response).await?;
+        debug!(
+            "Replicate prediction {} is '{}' after {}s",
+            prediction.id, prediction.status, waited_secs
+        );
+    }
+}
+
+async fn download_replicate_output(
+    client: &Client,
+    prediction: &ReplicatePrediction,
+) -> Result<(Vec<u8>, Option<String>), ApiError> {
+    let output_url = match &prediction.output {
+        serde_json::Value::String(url) => Some(url.as_str()),
+        serde_json::Value::Array(urls) => urls.first().and_then(|url| url.as_str()),
+        _ => None,
+    }
+    .ok_or(ApiError::EmptyImageData)?;
+
+    let response = client
+        .get(output_url)
+        .send()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+    let status = response.status();
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+    if !status.is_success() {
+        return Err(ApiError::ApiReturnedError {
+            status,
+            provider_error: None,
+            content: format!("Failed to download Replicate output {}", output_url),
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    debug!(
+        "Replicate raw image bytes received: {}",
+        body.len()
+    );
+
+    let image_bytes = normalize_image_bytes_to_png(&body)
+        .map_err(|e| ApiError::ParseError(Arc::new(e)))?;
+
+    Ok((image_bytes, None))
+}
+
+/// Parses the `data[].b64_json` answer shared by the xAI and OpenAI image generation endpoints.
+async fn parse_image_generation_response(
+    response: reqwest::Response,
+    provider_name: &str,
+) -> Result<(Vec<u8>, Option<String>), ApiError> {
+    let status = response.status();
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+
+    if !status.is_success() {
+        warn!(
+            "{} returned non-success status: {}. Body: {}",
+            provider_name,
+            status,
+            truncate_for_log(&response_text, 2000)
+        );
+        return Err(ApiError::ApiReturnedError {
+            provider_error: parse_provider_error(&response_text),
+            status,
+            content: response_text,
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    let response_data: ImageGenerationResponse = match serde_json::from_str(&response_text) {
+        Ok(data) => data,
+        Err(e) => {
+            error!(
+                "Failed to parse {} image generation JSON. Status: {}. Body: {}",
+                provider_name,
+                status,
+                truncate_for_log(&response_text, 2000)
+            );
+            return Err(ApiError::ParseError(Arc::new(e.into())));
+        }
+    };
+
+    debug!(
+        "{} response summary: images={}",
+        provider_name,
+        response_data.data.len()
+    );
+
+    let image = response_data.data.first().ok_or(ApiError::EmptyImageData)?;
+    let b64_json = image.b64_json.as_deref().ok_or(ApiError::EmptyImageData)?;
+
+    let raw_image_bytes = base64::engine::general_purpose::STANDARD
+        .decode(b64_json)
+        .map_err(|e| ApiError::ParseError(Arc::new(anyhow!(e))))?;
+
+    debug!(
+        "{} raw image bytes received: {}",
+        provider_name,
+        raw_image_bytes.len()
+    );
+
+    let image_bytes = normalize_image_bytes_to_png(&raw_image_bytes)
+        .map_err(|e| ApiError::ParseError(Arc::new(e)))?;
+
+    debug!(
+        "{} normalized PNG bytes received: {}",
+        provider_name,
+        image_bytes.len()
+    );
+
+    Ok((image_bytes, None))
+}
+
+async fn parse_gemini_image_from_generate_content_response(
+    response: reqwest::Response,
+) -> Result<(Vec<u8>, Option<String>), ApiError> {
+    let status = response.status();
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+
+    if !status.is_success() {
+        warn!(
+            "Gemini returned non-success status: {}. Body: {}",
+            status,
+            truncate_for_log(&response_text, 2000)
+        );
+        return Err(ApiError::ApiReturnedError {
+            provider_error: parse_provider_error(&response_text),
+            status,
+            content: response_text,
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    let response_data: GeminiGenerateContentResponse = match serde_json::from_str(&response_text) {
+        Ok(data) => data,
+        Err(e) => {
+            error!(
+                "Failed to parse Gemini generateContent JSON. Status: {}. Body: {}",
+                status,
+                truncate_for_log(&response_text, 2000)
+            );
+            return Err(ApiError::ParseError(Arc::new(e.into())));
+        }
+    };
+
+    debug!(
+        "Gemini response summary: candidates={}",
+        response_data.candidates.len()
+    );
+
+    let candidate = response_data
+        .candidates
+        .first()
+        .ok_or(ApiError::EmptyImageData)?;
+
+    let image_part = candidate
+        .content
+        .parts
+        .iter()
+        .find(|p| p.inline_data.is_some())
+        .ok_or(ApiError::EmptyImageData)?;
+
+    let inline = image_part.inline_data.as_ref().ok_or(ApiError::EmptyImageData)?;
+    if let Some(mime) = inline.mime_type.as_deref() {
+        debug!("Gemini inlineData mime_type={}", mime);
+    }
+
+    debug!(
+        "Decoding Gemini inlineData base64 payload (chars={})",
+        inline.data.len()
+    );
+
+    let image_bytes = base64::engine::general_purpose::STANDARD
+        .decode(inline.data.as_str())
+        .map_err(|e| ApiError::ParseError(Arc::new(anyhow!(e))))?;
+
+    debug!("Image bytes received: {}", image_bytes.len());
+
+    let image_bytes = convert_to_png_or_retry(&image_bytes, inline.mime_type.as_deref(), "Gemini", status)?;
+
+    // Gemini rates its own output; a HIGH probability (or a blocked category) goes to review
+    let unsafe_ratings: Vec<&str> = candidate
+        .safety_ratings
+        .iter()
+        .filter(|rating| rating.blocked || rating.probability.eq_ignore_ascii_case("HIGH"))
+        .map(|rating| rating.category.as_str())
+        .collect();
+    if !unsafe_ratings.is_empty() {
+        warn!("Gemini safety ratings flagged the image: {}", unsafe_ratings.join(", "));
+        return Ok((image_bytes, Some(REVIEW_FINISH_REASON.to_string())));
+    }
+
+    Ok((image_bytes, None))
+}
+
+async fn parse_openrouter_image_from_chat_response(
+    client: &Client,
+    response: reqwest::Response,
+) -> Result<(Vec<u8>, Option<String>), ApiError> {
+    let status = response.status();
+
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+
+    if !status.is_success() {
+        warn!(
+            "AI provider returned non-success status: {}. Body: {}",
+            status,
+            truncate_for_log(&response_text, 2000)
+        );
+        return Err(ApiError::ApiReturnedError {
+            provider_error: parse_provider_error(&response_text),
+            status,
+            content: response_text,
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    let response_data: ChatResponse = match serde_json::from_str(&response_text) {
+        Ok(data) => data,
+        Err(e) => {
+            error!(
+                "Failed to parse AI provider image response JSON. Status: {}. Body: {}",
+                status,
+                truncate_for_log(&response_text, 2000)
+            );
+            return Err(ApiError::ParseError(Arc::new(e.into())));
+        }
+    };
+
+    debug!(
+        "Response summary: choices={}",
+        response_data.choices.len()
+    );
+
+    let choice = response_data.choices.first().ok_or(ApiError::EmptyImageData)?;
+    let image = choice.message.images.first().ok_or(ApiError::EmptyImageData)?;
+    let url = image
+        .image_url
+        .as_ref()
+        .map(|u| u.url.as_str())
+        .ok_or(ApiError::EmptyImageData)?;
+
+    debug!(
+        "Image URL kind: {}",
+        if url.to_ascii_lowercase().starts_with("data:image/") {
+            "data_url"
+        } else {
+            "http_url"
+        }
+    );
+
+    let (image_bytes, mime_type) = if let Some(b64) = extract_base64_from_data_url(url) {
+        debug!("Decoding base64 image payload (chars={})", b64.len());
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(b64)
+            .map_err(|e| ApiError::ParseError(Arc::new(anyhow!(e))))?;
+        // data:image/jpeg;base64,...
+        let mime_type = url
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split(';').next())
+            .map(str::to_string);
+        (bytes, mime_type)
+    } else {
+        debug!("Downloading image from URL: {}", url);
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+        let mime_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| ApiError::RequestError(Arc::new(e)))?
+            .to_vec();
+        (bytes, mime_type)
+    };
+
+    debug!("Image bytes received: {}", image_bytes.len());
+
+    let image_bytes = convert_to_png_or_retry(&image_bytes, mime_type.as_deref(), "AI provider", status)?;
+
+    Ok((image_bytes, None))
+}
+
+fn extract_base64_from_data_url(url: &str) -> Option<&str> {
+    // OpenRouter image generation commonly returns a base64 data URL, e.g.:
+    // data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAA...
+    // Source: https://openrouter.ai/docs/features/multimodal/image-generation
+    let lower = url.to_ascii_lowercase();
+    if !lower.starts_with("data:image/") {
+        return None;
+    }
+
+    // Standard data URL base64 marker is ';base64,' (per RFC 2397 style and OpenRouter examples).
+    let marker = ";base64,";
+    if let Some(idx) = lower.find(marker) {
+        return Some(&url[idx + marker.len()..]);
+    }
+
+    // Be permissive just in case a provider returns a non-standard ',base64,' marker.
+    let fallback_marker = ",base64,";
+    let idx = lower.find(fallback_marker)?;
+    Some(&url[idx + fallback_marker.len()..])
+}
+
+fn truncate_for_log(s: &str, max_len: usize) -> String {
+    if s.len() <= max_len {
+        return s.to_string();
+    }
+    format!(
+        "{}... [truncated, total_len={}]",
+        &s[..max_len],
+        s.len()
+    )
+}
+
+fn read_prompt_synthesis_from_env(illustrator_api_key: &str) -> Result<Option<PromptSynthesisConfig>> {
+    let Some(model) = env::var("AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL")
+        .ok()
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+    else {
+        return Ok(None);
+    };
+    let read = |var: &str| env::var(var).ok().filter(|value| !value.trim().is_empty());
+
+    Ok(Some(PromptSynthesisConfig {
+        model,
+        api_url: read("AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_API_URL")
+            .map(|url| url.trim().to_string())
+            .unwrap_or_else(|| DEFAULT_PROMPT_SYNTHESIS_URL.to_string()),
+        api_key: read("AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_API_KEY")
+            .map(|key| key.trim().to_string())
+            .unwrap_or_else(|| illustrator_api_key.to_string()),
+        instruction: read("AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_INSTRUCTION")
+            .unwrap_or_else(|| DEFAULT_PROMPT_SYNTHESIS_INSTRUCTION.to_string()),
+        input_price: read_price_from_env("AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_PRICE_INPUT")?,
+        output_price: read_price_from_env("AI_PROVIDER_ILLUSTRATOR_PROMPT_MODEL_PRICE_OUTPUT")?,
+    }))
+}
+
+fn read_dedup_policy_from_env() -> Result<Option<DedupPolicy>> {
+    let max_distance = match env::var("ILLUSTRATOR_DEDUP_MAX_DISTANCE") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|distance| *distance <= 64)
+            .ok_or_else(|| anyhow!("ILLUSTRATOR_DEDUP_MAX_DISTANCE must be an integer from 0 to 64, got '{}'", value.trim()))?,
+        _ => return Ok(None),
+    };
+    let window = match env::var("ILLUSTRATOR_DEDUP_WINDOW") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|window| *window > 0)
+            .ok_or_else(|| anyhow!("ILLUSTRATOR_DEDUP_WINDOW must be a positive integer, got '{}'", value.trim()))?,
+        _ => DEFAULT_DEDUP_WINDOW,
+    };
+    let variation_hint = env::var("ILLUSTRATOR_DEDUP_VARIATION_HINT")
+        .ok()
+        .filter(|hint| !hint.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_VARIATION_HINT.to_string());
+    Ok(Some(DedupPolicy {
+        max_distance,
+        window,
+        variation_hint,
+    }))
+}
+
+fn read_skip_rules_from_env() -> Result<SkipRules> {
+    let min_words = match env::var("ILLUSTRATOR_SKIP_MIN_WORDS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| anyhow!("ILLUSTRATOR_SKIP_MIN_WORDS must be a non-negative integer: {}", e))?,
+        _ => 0,
+    };
+    let read_list = |var: &str| -> Vec<String> {
+        env::var(var)
+            .unwrap_or_default()
+            .split(',')
+            .map(|entry| entry.trim().to_lowercase())
+            .filter(|entry| !entry.is_empty())
+            .collect()
+    };
+    Ok(SkipRules {
+        min_words,
+        keywords: read_list("ILLUSTRATOR_SKIP_KEYWORDS"),
+        sources: read_list("ILLUSTRATOR_SKIP_SOURCES"),
+    })
+}
+
+fn read_safety_check_from_env() -> Result<Option<SafetyCheck>> {
+    let enabled = match env::var("ILLUSTRATOR_SAFETY_CHECK") {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "" | "0" | "false" | "no" | "off" => false,
+            _ => return Err(anyhow!("ILLUSTRATOR_SAFETY_CHECK must be true or false, got '{}'", value.trim())),
+        },
+        Err(_) => false,
+    };
+    if !enabled {
+        return Ok(None);
+    }
+
+    let api_key = env::var("MODERATION_OPENAI_API_KEY")
+        .ok()
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| anyhow!("ILLUSTRATOR_SAFETY_CHECK requires MODERATION_OPENAI_API_KEY"))?;
+    let model = env::var("MODERATION_MODEL")
+        .ok()
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+        .unwrap_or_else(|| DEFAULT_MODERATION_MODEL.to_string());
+    Ok(Some(SafetyCheck { api_key, model }))
+}
+
+fn read_images_per_item_from_env() -> Result<usize> {
+    match env::var("ILLUSTRATOR_IMAGES_PER_ITEM") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|count| (1..=MAX_ALBUM_IMAGES).contains(count))
+            .ok_or_else(|| {
+                anyhow!(
+                    "ILLUSTRATOR_IMAGES_PER_ITEM must be an integer from 1 to {}, got '{}'",
+                    MAX_ALBUM_IMAGES,
+                    value.trim()
+                )
+            }),
+        _ => Ok(1),
+    }
+}
+
+fn read_lead_image_policy_from_env() -> Result<LeadImagePolicy> {
+    let enabled = match env::var("ILLUSTRATOR_USE_LEAD_IMAGE") {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "" | "0" | "false" | "no" | "off" => false,
+            _ => return Err(anyhow!("ILLUSTRATOR_USE_LEAD_IMAGE must be true or false, got '{}'", value.trim())),
+        },
+        Err(_) => false,
+    };
+    let min_width = match env::var("ILLUSTRATOR_LEAD_IMAGE_MIN_WIDTH") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| anyhow!("ILLUSTRATOR_LEAD_IMAGE_MIN_WIDTH must be a non-negative integer: {}", e))?,
+        _ => DEFAULT_LEAD_IMAGE_MIN_WIDTH,
+    };
+    Ok(LeadImagePolicy { enabled, min_width })
+}
+
+fn read_image_limits_from_env() -> Result<ImageLimits> {
+    let max_dimension = match env::var("ILLUSTRATOR_MAX_DIMENSION") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|pixels| *pixels > 0)
+            .ok_or_else(|| anyhow!("ILLUSTRATOR_MAX_DIMENSION must be a positive integer, got '{}'", value.trim()))?,
+        _ => DEFAULT_MAX_IMAGE_DIMENSION,
+    };
+    let max_bytes = match env::var("ILLUSTRATOR_MAX_BYTES") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|bytes| *bytes > 0)
+            .ok_or_else(|| anyhow!("ILLUSTRATOR_MAX_BYTES must be a positive integer, got '{}'", value.trim()))?,
+        _ => DEFAULT_MAX_IMAGE_BYTES,
+    };
+    Ok(ImageLimits {
+        max_dimension,
+        max_bytes,
+    })
+}
+
+fn read_xai_image_config_from_env(provider_type: AiProviderType) -> Result<Option<XaiImageConfig>> {
+    if provider_type != AiProviderType::Xai {
+        return Ok(None);
+    }
+
+    let aspect_ratio = read_xai_aspect_ratio_from_env()?;
+    let resolution = read_xai_resolution_from_env()?;
+
+    Ok(Some(XaiImageConfig {
+        aspect_ratio,
+        resolution,
+    }))
+}
+
+fn read_openai_image_config_from_env(provider_type: AiProviderType) -> Result<Option<OpenAiImageConfig>> {
+    if provider_type != AiProviderType::OpenAi {
+        return Ok(None);
+    }
+
+    let size = match env::var("AI_PROVIDER_ILLUSTRATOR_SIZE") {
+        Ok(value) => parse_openai_size(&value)?,
+        Err(_) => OPENAI_DEFAULT_SIZE.to_string(),
+    };
+    let quality = match env::var("AI_PROVIDER_ILLUSTRATOR_QUALITY") {
+        Ok(value) if !value.trim().is_empty() => Some(parse_openai_quality(&value)?),
+        _ => None,
+    };
+
+    Ok(Some(OpenAiImageConfig { size, quality }))
+}
+
+fn read_replicate_config_from_env(provider_type: AiProviderType) -> Result<Option<ReplicateConfig>> {
+    if provider_type != AiProviderType::Replicate {
+        return Ok(None);
+    }
+
+    Ok(Some(ReplicateConfig {
+        aspect_ratio: read_optional_aspect_ratio_from_env(),
+        max_wait_secs: read_max_wait_secs_from_env()?,
+    }))
+}
+
+fn read_local_sd_config_from_env(provider_type: AiProviderType, api_url: Option<&str>) -> Result<Option<LocalSdConfig>> {
+    if !provider_type.is_local() {
+        return Ok(None);
+    }
+
+    let base_url = api_url
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .ok_or_else(|| anyhow!("AI_PROVIDER_ILLUSTRATOR_API_URL must point to the Stable Diffusion server, e.g. http://127.0.0.1:7860"))?;
+
+    let size = match env::var("AI_PROVIDER_ILLUSTRATOR_SIZE") {
+        Ok(value) if !value.trim().is_empty() => Some(
+            value
+                .trim()
+                .to_ascii_lowercase()
+                .split_once('x')
+                .and_then(|(width, height)| Some((width.parse::<u32>().ok()?, height.parse::<u32>().ok()?)))
+                .ok_or_else(|| anyhow!("AI_PROVIDER_ILLUSTRATOR_SIZE must look like 1024x768, got '{}'", value.trim()))?,
+        ),
+        _ => None,
+    };
+
+    let negative_prompt = env::var("AI_PROVIDER_ILLUSTRATOR_NEGATIVE_PROMPT")
+        .ok()
+        .filter(|value| !value.trim().is_empty());
+
+    let workflow = match env::var("AI_PROVIDER_ILLUSTRATOR_WORKFLOW_FILE") {
+        Ok(path) if !path.trim().is_empty() => Some(
+            fs::read_to_string(path.trim())
+                .context(format!("Failed to read AI_PROVIDER_ILLUSTRATOR_WORKFLOW_FILE {}", path.trim()))?,
+        ),
+        _ if provider_type == AiProviderType::ComfyUi => {
+            return Err(anyhow!("AI_PROVIDER_ILLUSTRATOR_WORKFLOW_FILE must be set for the ComfyUI provider"));
+        }
+        _ => None,
+    };
+
+    Ok(Some(LocalSdConfig {
+        base_url,
+        size,
+        negative_prompt,
+        workflow,
+        max_wait_secs: read_max_wait_secs_from_env()?,
+    }))
+}
+
+/// The fallback image model shares the prompt, the HTTP client and the provider-specific settings
+/// (size, aspect ratio, ...) with the primary one.
+fn read_fallback_provider_from_env(
+    prompt: &str,
+    reasoning: Option<&ReasoningConfig>,
+    client: &Client,
+) -> Result<Option<Box<AiProviderConfig>>> {
+    let provider_type = match env::var("AI_PROVIDER_ILLUSTRATOR_FALLBACK_TYPE") {
+        Ok(value) if !value.trim().is_empty() => AiProviderType::parse(&value)
+            .context("Invalid AI_PROVIDER_ILLUSTRATOR_FALLBACK_TYPE")?,
+        _ => return Ok(None),
+    };
+    let model = match env::var("AI_PROVIDER_ILLUSTRATOR_FALLBACK_MODEL") {
+        Ok(model) => model,
+        Err(_) if provider_type.is_local() => String::new(),
+        Err(_) => return Err(anyhow!("AI_PROVIDER_ILLUSTRATOR_FALLBACK_MODEL environment variable not set")),
+    };
+    // Usually a second model of the same provider, so the key defaults to the primary one
+    let api_key = env::var("AI_PROVIDER_ILLUSTRATOR_FALLBACK_API_KEY")
+        .or_else(|_| env::var("AI_PROVIDER_ILLUSTRATOR_API_KEY"))
+        .unwrap_or_default();
+    let api_url = env::var("AI_PROVIDER_ILLUSTRATOR_FALLBACK_API_URL")
+        .ok()
+        .filter(|value| !value.trim().is_empty());
+
+    Ok(Some(Box::new(AiProviderConfig {
+        provider_type,
+        api_key,
+        model,
+        prompt: prompt.to_string(),
+        reasoning: reasoning.cloned(),
+        xai_image_config: read_xai_image_config_from_env(provider_type)?,
+        openai_image_config: read_openai_image_config_from_env(provider_type)?,
+        stability_image_config: (provider_type == AiProviderType::Stability).then(|| StabilityImageConfig {
+            aspect_ratio: read_optional_aspect_ratio_from_env(),
+        }),
+        replicate_config: read_replicate_config_from_env(provider_type)?,
+        local_sd_config: read_local_sd_config_from_env(provider_type, api_url.as_deref())?,
+        api_url,
+        prompt_synthesis: None,
+        fallback: None,
+        image_price: read_price_from_env("AI_PROVIDER_ILLUSTRATOR_FALLBACK_PRICE_IMAGE")?,
+        client: client.clone(),
+    })))
+}
+
+/// Upper bound for polling an asynchronous job (Replicate, ComfyUI).
+fn read_max_wait_secs_from_env() -> Result<u64> {
+    match env::var("AI_PROVIDER_ILLUSTRATOR_MAX_WAIT_SECS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .ok_or_else(|| anyhow!("AI_PROVIDER_ILLUSTRATOR_MAX_WAIT_SECS must be a positive integer, got '{}'", value.trim())),
+        _ => Ok(DEFAULT_MAX_WAIT_SECS),
+    }
+}
+
+fn read_optional_aspect_ratio_from_env() -> Option<String> {
+    env::var("AI_PROVIDER_ILLUSTRATOR_ASPECT_RATIO")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_openai_size(value: &str) -> Result<String> {
+    let normalized = value.trim().to_ascii_lowercase();
+
+    // gpt-image-1: 1024x1024|1536x1024|1024x1536|auto, DALL-E 3: 1024x1024|1792x1024|1024x1792,
+    // DALL-E 2: 256x256|512x512|1024x1024
+    const ALLOWED_SIZES: [&str; 8] = [
+        "auto",
+        "256x256",
+        "512x512",
+        "1024x1024",
+        "1536x1024",
+        "1024x1536",
+        "1792x1024",
+        "1024x1792",
+    ];
+
+    if ALLOWED_SIZES.contains(&normalized.as_str()) {
+        return Ok(normalized);
+    }
+
+    Err(anyhow!(
+        "AI_PROVIDER_ILLUSTRATOR_SIZE has invalid value '{}'. Allowed: auto|256x256|512x512|1024x1024|1536x1024|1024x1536|1792x1024|1024x1792",
+        value.trim()
+    ))
+}
+
+fn parse_openai_quality(value: &str) -> Result<String> {
+    let normalized = value.trim().to_ascii_lowercase();
+
+    // gpt-image-1: low|medium|high|auto, DALL-E 3: standard|hd
+    match normalized.as_str() {
+        "auto" | "low" | "medium" | "high" | "standard" | "hd" => Ok(normalized),
+        _ => Err(anyhow!(
+            "AI_PROVIDER_ILLUSTRATOR_QUALITY has invalid value '{}'. Allowed: auto|low|medium|high|standard|hd",
+            value.trim()
+        )),
+    }
+}
+
+fn read_xai_aspect_ratio_from_env() -> Result<String> {
+    match env::var("AI_PROVIDER_ILLUSTRATOR_ASPECT_RATIO") {
+        Ok(value) => parse_xai_aspect_ratio(&value),
+        Err(env::VarError::NotPresent) => Ok(XAI_DEFAULT_ASPECT_RATIO.to_string()),
+        Err(env::VarError::NotUnicode(_)) => Err(anyhow!(
+            "AI_PROVIDER_ILLUSTRATOR_ASPECT_RATIO contains invalid unicode"
+        )),
+    }
+}
+
+fn read_xai_resolution_from_env() -> Result<String> {
+    match env::var("AI_PROVIDER_ILLUSTRATOR_RESOLUTION") {
+        Ok(value) => parse_xai_resolution(&value),
+        Err(env::VarError::NotPresent) => Ok(XAI_DEFAULT_RESOLUTION.to_string()),
+        Err(env::VarError::NotUnicode(_)) => Err(anyhow!(
+            "AI_PROVIDER_ILLUSTRATOR_RESOLUTION contains invalid unicode"
+        )),
+    }
+}
+
+fn parse_xai_aspect_ratio(value: &str) -> Result<String> {
+    let normalized = value.trim().to_ascii_lowercase();
+    if normalized.is_empty() {
+        return Err(anyhow!(
+            "AI_PROVIDER_ILLUSTRATOR_ASPECT_RATIO must not be empty"
+        ));
+    }
+
+    const ALLOWED_ASPECT_RATIOS: [&str; 13] = [
+        "1:1",
+        "16:9",
+        "9:16",
+        "4:3",
+        "3:4",
+        "3:2",
+        "2:3",
+        "2:1",
+        "1:2",
+        "19.5:9",
+        "9:19.5",
+        "20:9",
+        "9:20",
+    ];
+
+    if normalized == XAI_DEFAULT_ASPECT_RATIO
+        || ALLOWED_ASPECT_RATIOS.contains(&normalized.as_str())
+    {
+        return Ok(normalized);
+    }
+
+    Err(anyhow!(
+        "AI_PROVIDER_ILLUSTRATOR_ASPECT_RATIO has invalid value '{}'. Allowed: auto|1:1|16:9|9:16|4:3|3:4|3:2|2:3|2:1|1:2|19.5:9|9:19.5|20:9|9:20",
+        value.trim()
+    ))
+}
+
+fn parse_xai_resolution(value: &str) -> Result<String> {
+    let normalized = value.trim().to_ascii_lowercase();
+    if normalized.is_empty() {
+        return Err(anyhow!(
+            "AI_PROVIDER_ILLUSTRATOR_RESOLUTION must not be empty"
+        ));
+    }
+
+    match normalized.as_str() {
+        "1k" | "2k" => Ok(normalized),
+        _ => Err(anyhow!(
+            "AI_PROVIDER_ILLUSTRATOR_RESOLUTION has invalid value '{}'. Allowed: 1k|2k",
+            value.trim()
+        )),
+    }
+}
+
+/// Converts the provider's image to PNG; bytes that can't be decoded force finish_reason='error'
+/// so the item is retried.
+fn convert_to_png_or_retry(
+    image_bytes: &[u8],
+    mime_type: Option<&str>,
+    provider_name: &str,
+    status: reqwest::StatusCode,
+) -> Result<Vec<u8>, ApiError> {
+    match normalize_image_bytes_with_mime_to_png(image_bytes, mime_type) {
+        Ok(png_bytes) => Ok(png_bytes),
+        Err(e) => {
+            warn!(
+                "{} returned image bytes that could not be converted to PNG ({}). Forcing finish_reason='error' to trigger retry.",
+                provider_name, e
+            );
+            Err(ApiError::ApiReturnedError {
+                provider_error: None,
+                status,
+                content: format!("Image bytes could not be converted to PNG: {}", e),
+                finish_reason: Some("error".to_string()),
+            })
+        }
+    }
+}
+
+fn normalize_image_bytes_to_png(image_bytes: &[u8]) -> Result<Vec<u8>> {
+    normalize_image_bytes_with_mime_to_png(image_bytes, None)
+}
+
+/// Detects the format from the magic bytes, falling back to the mime type reported by the
+/// provider, and transcodes JPEG, WebP and GIF to PNG. PNG bytes are kept as they are.
+fn normalize_image_bytes_with_mime_to_png(image_bytes: &[u8], mime_type: Option<&str>) -> Result<Vec<u8>> {
+    if looks_like_png(image_bytes) {
+        return Ok(image_bytes.to_vec());
+    }
+
+    let image_format = image::guess_format(image_bytes)
+        .ok()
+        .or_else(|| mime_type.and_then(|mime| ImageFormat::from_mime_type(mime.trim())))
+        .ok_or_else(|| anyhow!("Failed to determine image format from response bytes (mime type {:?})", mime_type))?;
+
+    match image_format {
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Gif => {}
+        other => {
+            return Err(anyhow!(
+                "Provider returned unsupported image format: {:?}. Expected PNG, JPEG, WebP or GIF",
+                other
+            ));
+        }
+    }
+
+    debug!("Converting {:?} image to PNG", image_format);
+
+    let decoded_image = image::load_from_memory_with_format(image_bytes, image_format)
+        .context("Failed to decode image bytes")?;
+    let mut png_bytes = Cursor::new(Vec::new());
+    decoded_image
+        .write_to(&mut png_bytes, ImageFormat::Png)
+        .context("Failed to encode image as PNG")?;
+
+    Ok(png_bytes.into_inner())
+}
+
+/// Fits the PNG into the image limits: crops extreme aspect ratios, downscales to the maximum
+/// dimension and further until the file is small enough. Re-encoding drops all metadata chunks,
+/// and the alpha channel when the image is fully opaque.
+fn optimize_image(png_bytes: &[u8], limits: &ImageLimits) -> Result<Vec<u8>> {
+    let mut image = image::load_from_memory_with_format(png_bytes, ImageFormat::Png)
+        .context("Failed to decode image for optimization")?;
+
+    let (width, height) = (image.width(), image.height());
+    if width > height.saturating_mul(MAX_ASPECT_RATIO) {
+        let cropped_width = height * MAX_ASPECT_RATIO;
+        image = image.crop_imm((width - cropped_width) / 2, 0, cropped_width, height);
+    } else if height > width.saturating_mul(MAX_ASPECT_RATIO) {
+        let cropped_height = width * MAX_ASPECT_RATIO;
+        image = image.crop_imm(0, (height - cropped_height) / 2, width, cropped_height);
+    }
+
+    if image.width().max(image.height()) > limits.max_dimension {
+        image = image.resize(limits.max_dimension, limits.max_dimension, FilterType::Lanczos3);
+    }
+
+    if image.color().has_alpha() && image.to_rgba8().pixels().all(|pixel| pixel.0[3] == u8::MAX) {
+        image = DynamicImage::ImageRgb8(image.to_rgb8());
+    }
+
+    let mut encoded = encode_png(&image)?;
+    // Each pass shrinks both sides by a fifth; a few passes cover any realistic overshoot
+    for _ in 0..8 {
+        if encoded.len() <= limits.max_bytes {
+            break;
+        }
+        let (width, height) = (image.width() * 4 / 5, image.height() * 4 / 5);
+        image = image.resize(width.max(1), height.max(1), FilterType::Lanczos3);
+        encoded = encode_png(&image)?;
+    }
+    if encoded.len() > limits.max_bytes {
+        return Err(anyhow!(
+            "image is still {} bytes at {}x{}",
+            encoded.len(),
+            image.width(),
+            image.height()
+        ));
+    }
+
+    debug!(
+        "Optimized image: {}x{} -> {}x{}, {} -> {} bytes",
+        width,
+        height,
+        image.width(),
+        image.height(),
+        png_bytes.len(),
+        encoded.len()
+    );
+    Ok(encoded)
+}
+
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut encoded = Vec::new();
+    PngEncoder::new_with_quality(&mut encoded, CompressionType::Best, PngFilterType::Adaptive)
+        .write_image(image.as_bytes(), image.width(), image.height(), image.color())
+        .context("Failed to encode optimized PNG")?;
+    Ok(encoded)
+}
+
+fn looks_like_png(bytes: &[u8]) -> bool {
+    const PNG_SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+    bytes.len() >= PNG_SIGNATURE.len() && bytes[..PNG_SIGNATURE.len()] == PNG_SIGNATURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    /// A PNG of `width`x`height` whose pixels `color` paints from their position relative to the size.
+    fn png(width: u32, height: u32, color: impl Fn(f64, f64) -> [u8; 3]) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb(color(f64::from(x) / f64::from(width), f64::from(y) / f64::from(height)))
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image).write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).unwrap();
+        bytes
+    }
+
+    fn distance(a: &[u8], b: &[u8]) -> u32 {
+        (perceptual_hash(a).unwrap() ^ perceptual_hash(b).unwrap()).count_ones()
+    }
+
+    fn scene(x: f64, y: f64) -> [u8; 3] {
+        let light = if (x - 0.3).powi(2) + (y - 0.4).powi(2) < 0.05 { 220.0 } else { 40.0 + 120.0 * x };
+        [light as u8, (light * 0.8) as u8, (255.0 * y) as u8]
+    }
+
+    #[test]
+    fn perceptual_hash_ignores_size_and_small_brightness_changes() {
+        let original = png(256, 192, scene);
+        assert!(distance(&original, &png(64, 48, scene)) <= 4);
+        let brighter = png(256, 192, |x, y| scene(x, y).map(|channel| channel.saturating_add(12)));
+        assert!(distance(&original, &brighter) <= 4);
+    }
+
+    #[test]
+    fn perceptual_hash_tells_different_pictures_apart() {
+        let horizontal = png(128, 128, |x, _| [(255.0 * x) as u8; 3]);
+        let vertical = png(128, 128, |_, y| [(255.0 * y) as u8; 3]);
+        assert!(distance(&png(128, 128, scene), &horizontal) > 10);
+        assert!(distance(&horizontal, &vertical) > 10);
+    }
+
+    #[test]
+    fn perceptual_hash_rejects_data_that_is_not_an_image() {
+        assert!(perceptual_hash(b"<html>not an image</html>").is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use anyhow::{Context, Result};
+use robo_news_core::{
+    healthcheck, heartbeat, init_logging, list_artifacts, listen_for_shutdown, load_artifact, load_config_file, load_secret_files, load_pipeline_stage, open_db,
+    read_interval_from_env, remove_artifact, shutdown_requested, sleep, unix_now, with_immediate_transaction, PipelineStage, Status,
+};
+use tracing::{error, info, warn};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, Row};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DATA_DIR: &str = "data";
+const DEFAULT_JANITOR_INTERVAL_SECS: u64 = 3600;
+const DEFAULT_RETENTION_DAYS: u64 = 30;
+
+fn main() -> Result<()> {
+    load_config_file()?;
+    load_secret_files()?;
+    init_logging()?;
+    // Docker HEALTHCHECK: `<service> healthcheck`
+    if env::args().nth(1).as_deref() == Some("healthcheck") {
+        return healthcheck();
+    }
+    let options = parse_run_options()?;
+    let interval_secs = read_interval_from_env("JANITOR_INTERVAL_SECS", DEFAULT_JANITOR_INTERVAL_SECS)?;
+    let retention = read_retention_from_env()?;
+
+    let conn = open_db()?;
+
+    // Published items keep what the site, the API and the mailer read: the publisher's output
+    let publisher = load_pipeline_stage(
+        "publisher",
+        PipelineStage {
+            input_status: Status::Illustrator,
+            output_status: Status::Published,
+            input_artifact: "rewriter".to_string(),
+            output_artifact: "publisher".to_string(),
+        },
+    )?;
+
+    info!("Starting janitor...");
+
+    if options.once {
+        return run_janitor(&conn, &publisher, &retention, options.dry_run);
+    }
+
+    listen_for_shutdown()?;
+
+    // Main loop - run every JANITOR_INTERVAL_SECS seconds until SIGTERM or Ctrl-C
+    while !shutdown_requested() {
+        if let Err(e) = heartbeat(&conn, interval_secs) {
+            warn!("Failed to record heartbeat: {}", e);
+        }
+        if let Err(e) = run_janitor(&conn, &publisher, &retention, options.dry_run) {
+            error!("Error during cleanup: {}", e);
+        }
+
+        info!("Sleeping for {} seconds", interval_secs);
+        // Ends early on shutdown
+        let _ = sleep(Duration::from_secs(interval_secs));
+    }
+    info!("Stopped");
+    Ok(())
+}
+
+/// Command-line options: `--once` runs a single cycle and exits, `--dry-run` only logs what
+/// would be removed.
+struct RunOptions {
+    once: bool,
+    dry_run: bool,
+}
+
+fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { once: false, dry_run: false };
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--once" => options.once = true,
+            "--dry-run" => options.dry_run = true,
+            other => return Err(anyhow::anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+/// How long finished items are kept (JANITOR_RETENTION_DAYS) and where they go afterwards:
+/// into JANITOR_ARCHIVE_DIR if set, otherwise they are deleted.
+struct Retention {
+    max_age_secs: i64,
+    archive_dir: Option<PathBuf>,
+}
+
+fn read_retention_from_env() -> Result<Retention> {
+    let days = read_interval_from_env("JANITOR_RETENTION_DAYS", DEFAULT_RETENTION_DAYS)?;
+    let archive_dir = env::var("JANITOR_ARCHIVE_DIR")
+        .ok()
+        .map(|dir| dir.trim().to_string())
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from);
+    Ok(Retention {
+        max_age_secs: days.saturating_mul(24 * 60 * 60) as i64,
+        archive_dir,
+    })
+}
+
+struct ExpiredItem {
+    id: String,
+    status: Status,
+}
+
+fn run_janitor(conn: &Connection, publisher: &PipelineStage, retention: &Retention, dry_run: bool) -> Result<()> {
+    info!("Looking for expired items");
+
+    let cutoff = unix_now() - retention.max_age_secs;
+    // Every restart without WORKER_ID leaves the heartbeat of the old process behind
+    if !dry_run {
+        let workers = conn.execute("DELETE FROM worker_health WHERE beat_at < ?", params![cutoff])?;
+        if workers > 0 {
+            info!("Removed the heartbeats of {} workers that stopped before the retention period", workers);
+        }
+        // Alerts the admin chat never accepted, or about items that are gone
+        let alerts = conn.execute(
+            "DELETE FROM dead_letters WHERE created_at < ? OR news_id NOT IN (SELECT id FROM news)",
+            params![cutoff],
+        )?;
+        if alerts > 0 {
+            warn!("Dropped {} dead-letter alerts that could not be sent", alerts);
+        }
+    }
+
+    let items = fetch_expired_items(conn, publisher, cutoff)?;
+    if items.is_empty() {
+        info!("No expired items");
+        return Ok(());
+    }
+
+    let artifacts = list_artifacts()?;
+    let files = index_data_files(Path::new(DATA_DIR), retention.archive_dir.as_deref(), &artifacts)?;
+    let stored = index_stored_artifacts(artifacts);
+    let action = if retention.archive_dir.is_some() { "archive" } else { "delete" };
+    let mut removed_items = 0;
+    let mut removed_files = 0;
+
+    for item in &items {
+        let published = is_published(&item.status, publisher);
+        // Rows go first: an item an operator just brought back keeps its files
+        if !published {
+            if dry_run {
+                info!("Dry run: would {} item {} ({})", action, item.id, item.status);
+            } else if !remove_item(conn, item, cutoff, retention)? {
+                warn!("Item {} changed in the meantime, leaving it as it is", item.id);
+                continue;
+            }
+            removed_items += 1;
+        }
+
+        for path in files.get(&item.id).into_iter().flatten() {
+            if published && is_published_artifact(path, &item.id, publisher) {
+                continue;
+            }
+            if dry_run {
+                info!("Dry run: would {} {}", action, path.display());
+            } else {
+                remove_file(path, retention)?;
+            }
+            removed_files += 1;
+        }
+
+        for name in stored.get(&item.id).into_iter().flatten() {
+            if published && is_published_artifact(&Path::new(DATA_DIR).join(name), &item.id, publisher) {
+                continue;
+            }
+            if dry_run {
+                info!("Dry run: would {} stored artifact {}", action, name);
+            } else {
+                remove_stored_artifact(name, retention)?;
+            }
+            removed_files += 1;
+        }
+    }
+
+    info!(
+        "Cleanup completed: {} {} items and {} files{}",
+        if retention.archive_dir.is_some() { "archived" } else { "deleted" },
+        removed_items,
+        removed_files,
+        if dry_run { " (dry run)" } else { "" }
+    );
+    Ok(())
+}
+
+/// Items in a final status that haven't changed since `cutoff`, nor have their translations.
+/// A published item also waits for its translations to reach a final status, since they are
+/// still published after it; the translations of an item that failed never will be.
+fn fetch_expired_items(conn: &Connection, publisher: &PipelineStage, cutoff: i64) -> Result<Vec<ExpiredItem>> {
+    let mut stmt = conn.prepare("SELECT id, status FROM news WHERE COALESCE(updated_at, 0) < ? ORDER BY date ASC")?;
+    let candidates = stmt
+        .query_map(params![cutoff], |row| {
+            Ok(ExpiredItem {
+                id: row.get(0)?,
+                status: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut translations = conn.prepare("SELECT status, COALESCE(updated_at, 0) FROM news_translations WHERE news_id = ?")?;
+    let mut expired = Vec::new();
+    for item in candidates.into_iter().filter(|item| item.status.is_final()) {
+        let published = is_published(&item.status, publisher);
+        let in_progress = translations
+            .query_map(params![item.id], |row| Ok((row.get::<_, Status>(0)?, row.get::<_, i64>(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .any(|(status, updated_at)| updated_at >= cutoff || (published && !status.is_final()));
+        if !in_progress {
+            expired.push(item);
+        }
+    }
+    Ok(expired)
+}
+
+fn is_published(status: &Status, publisher: &PipelineStage) -> bool {
+    *status == publisher.output_status || matches!(status, Status::Published | Status::PublishedPlain | Status::Updated)
+}
+
+/// The published text of every language (`<publisher artifact>_<id>[_<lang>].html`) and the
+/// illustration, as read by the site, the API and the mailer.
+fn is_published_artifact(path: &Path, id: &str, publisher: &PipelineStage) -> bool {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    path.parent() == Some(Path::new(DATA_DIR))
+        && (name == format!("illustrator_{}.png", id)
+            || (name.starts_with(&format!("{}_{}", publisher.output_artifact, id)) && name.ends_with(".html")))
+}
+
+/// Maps every `_`-separated part of the file names under `dir` to its files, so that e.g.
+/// `rewriter_b_<id>_de.html` and `ai_log/rewriter_<id>.json` are both found by the item id.
+/// Files directly in `dir` that are `artifacts` (ARTIFACT_STORE=files) are left to the store.
+fn index_data_files(dir: &Path, skip: Option<&Path>, artifacts: &[String]) -> Result<HashMap<String, Vec<PathBuf>>> {
+    let artifacts: HashSet<&str> = artifacts.iter().map(String::as_str).collect();
+    let mut index: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let root = dir;
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).context(format!("Failed to read {}", dir.display()))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if Some(path.as_path()) != skip {
+                    dirs.push(path);
+                }
+                continue;
+            }
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if path.parent() == Some(root) && artifacts.contains(name) {
+                continue;
+            }
+            for part in name_parts(name) {
+                index.entry(part.to_string()).or_default().push(path.clone());
+            }
+        }
+    }
+    Ok(index)
+}
+
+/// The same index over the artifacts in the store ARTIFACT_STORE names (`data/`, the
+/// `artifacts` table or the S3 bucket).
+fn index_stored_artifacts(artifacts: Vec<String>) -> HashMap<String, Vec<String>> {
+    let mut index: HashMap<String, Vec<String>> = HashMap::new();
+    for name in artifacts {
+        for part in name_parts(&name) {
+            index.entry(part.to_string()).or_default().push(name.clone());
+        }
+    }
+    index
+}
+
+fn name_parts(name: &str) -> impl Iterator<Item = &str> {
+    let stem = name.split('.').next().unwrap_or(name);
+    stem.split('_').filter(|part| !part.is_empty())
+}
+
+/// Deletes the rows of the item and its translations, first appending them to `news.jsonl` and
+/// `news_translations.jsonl` in the archive directory if there is one. Returns false if the item
+/// is no longer the expired one, e.g. because an operator requeued it.
+fn remove_item(conn: &Connection, item: &ExpiredItem, cutoff: i64, retention: &Retention) -> Result<bool> {
+    with_immediate_transaction(conn, |conn| {
+        let mut stmt = conn.prepare("SELECT * FROM news WHERE id = ? AND status = ? AND COALESCE(updated_at, 0) < ?")?;
+        let rows = stmt
+            .query_map(params![item.id, item.status, cutoff], row_to_json)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if rows.is_empty() {
+            return Ok(false);
+        }
+
+        if let Some(archive_dir) = &retention.archive_dir {
+            let mut stmt = conn.prepare("SELECT * FROM news_translations WHERE news_id = ?")?;
+            let translations = stmt
+                .query_map(params![item.id], row_to_json)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            append_json_lines(&archive_dir.join("news.jsonl"), &rows)?;
+            append_json_lines(&archive_dir.join("news_translations.jsonl"), &translations)?;
+        }
+
+        conn.execute("DELETE FROM news_translations WHERE news_id = ?", params![item.id])?;
+        conn.execute("DELETE FROM news WHERE id = ?", params![item.id])?;
+        info!("Removed item {} ({})", item.id, item.status);
+        Ok(true)
+    })
+}
+
+fn row_to_json(row: &Row) -> rusqlite::Result<Value> {
+    let mut object = serde_json::Map::new();
+    for (i, name) in row.as_ref().column_names().into_iter().enumerate() {
+        let value = match row.get_ref(i)? {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(number) => number.into(),
+            ValueRef::Real(number) => number.into(),
+            ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned().into(),
+            ValueRef::Blob(bytes) => bytes.to_vec().into(),
+        };
+        object.insert(name.to_string(), value);
+    }
+    Ok(Value::Object(object))
+}
+
+fn append_json_lines(path: &Path, values: &[Value]) -> Result<()> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Failed to open {}", path.display()))?;
+    for value in values {
+        writeln!(file, "{}", value).context(format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Deletes a file, or moves it to the same place under the archive directory.
+fn remove_file(path: &Path, retention: &Retention) -> Result<()> {
+    let Some(archive_dir) = &retention.archive_dir else {
+        return fs::remove_file(path).context(format!("Failed to delete {}", path.display()));
+    };
+
+    let target = archive_dir.join(path.strip_prefix(DATA_DIR).unwrap_or(path));
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+    }
+    // A rename doesn't work across file systems, e.g. into a separate archive volume
+    if fs::rename(path, &target).is_err() {
+        fs::copy(path, &target).context(format!("Failed to copy {} to {}", path.display(), target.display()))?;
+        fs::remove_file(path).context(format!("Failed to delete {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Deletes an artifact from the store, first writing it to the archive directory under its name
+/// if there is one.
+fn remove_stored_artifact(name: &str, retention: &Retention) -> Result<()> {
+    if let Some(archive_dir) = &retention.archive_dir {
+        if let Some(content) = load_artifact(name)? {
+            let target = archive_dir.join(name);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+            }
+            fs::write(&target, content).context(format!("Failed to write {}", target.display()))?;
+        }
+    }
+    remove_artifact(name)
+}
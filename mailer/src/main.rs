@@ -0,0 +1,392 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveTime};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use robo_news_core::{
+    builtin_stage, init_logging, load_config_file, load_secret_files, open_db, read_artifact_string,
+    read_interval_from_env, PipelineStage,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::{thread, time::Duration};
+use tracing::{error, info, warn};
+
+const DATA_DIR: &str = "data";
+const DEFAULT_MAILER_INTERVAL_SECS: u64 = 600;
+const DEFAULT_DIGEST_TIME: &str = "08:00";
+const DEFAULT_DIGEST_SUBJECT: &str = "News digest for {{date}}";
+// Characters of text shown for each item in the digest
+const EXCERPT_LENGTH: usize = 300;
+
+const DIGEST_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<body style="max-width: 40rem; margin: 0 auto; font-family: sans-serif; line-height: 1.5;">
+<h1>{{subject}}</h1>
+<p>{{count}} new items</p>
+{{items}}
+</body>
+</html>
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SmtpSecurity {
+    /// Plain connection upgraded with STARTTLS (port 587).
+    StartTls,
+    /// TLS from the first byte (port 465).
+    Tls,
+    /// No encryption, e.g. a relay on localhost (port 25).
+    None,
+}
+
+/// Where and to whom the digest is sent.
+struct MailerConfig {
+    smtp_host: String,
+    smtp_port: u16,
+    security: SmtpSecurity,
+    /// SMTP_USERNAME and SMTP_PASSWORD; the relay is used without authentication if unset.
+    credentials: Option<Credentials>,
+    from: Mailbox,
+    recipients: Vec<Mailbox>,
+    /// Local time of day after which the day's digest goes out (DIGEST_TIME).
+    send_after: NaiveTime,
+    /// Subject with a `{{date}}` placeholder (DIGEST_SUBJECT).
+    subject: String,
+    /// HTML layout with `{{subject}}`, `{{date}}`, `{{count}}` and `{{items}}` (DIGEST_TEMPLATE).
+    template: String,
+}
+
+/// One published item in the digest.
+struct DigestItem {
+    id: String,
+    title: String,
+    url: String,
+    /// Last `publish_chain` record of the item.
+    seq: i64,
+}
+
+fn main() -> Result<()> {
+    load_config_file()?;
+    load_secret_files()?;
+    init_logging()?;
+    let options = parse_run_options()?;
+    let interval_secs = read_interval_from_env("MAILER_INTERVAL_SECS", DEFAULT_MAILER_INTERVAL_SECS)?;
+    let config = read_mailer_config_from_env()?;
+
+    let publisher = builtin_stage("publisher")?;
+
+    let conn = init_db()?;
+
+    info!(
+        "Starting mailer: daily digest after {} to {} recipients",
+        config.send_after.format("%H:%M"),
+        config.recipients.len()
+    );
+
+    // Main loop - run every MAILER_INTERVAL_SECS seconds
+    loop {
+        if let Err(e) = run_mailer(&conn, &config, &publisher) {
+            if options.once {
+                return Err(e);
+            }
+            error!("Error during mailing: {}", e);
+        }
+        if options.once {
+            return Ok(());
+        }
+
+        info!("Sleeping for {} seconds", interval_secs);
+        thread::sleep(Duration::from_secs(interval_secs));
+    }
+}
+
+/// Command-line options: `--once` processes the current queue and exits (for cron- or CI-driven runs).
+struct RunOptions {
+    once: bool,
+}
+
+fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { once: false };
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--once" => options.once = true,
+            other => return Err(anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn read_mailer_config_from_env() -> Result<MailerConfig> {
+    let smtp_host = env::var("SMTP_HOST")
+        .context("SMTP_HOST environment variable is not set")?
+        .trim()
+        .to_string();
+    let security = match env::var("SMTP_SECURITY") {
+        Ok(value) if !value.trim().is_empty() => match value.trim().to_ascii_lowercase().as_str() {
+            "starttls" => SmtpSecurity::StartTls,
+            "tls" => SmtpSecurity::Tls,
+            "none" => SmtpSecurity::None,
+            other => return Err(anyhow!("SMTP_SECURITY must be one of starttls, tls, none (got '{}')", other)),
+        },
+        _ => SmtpSecurity::StartTls,
+    };
+    let smtp_port = match env::var("SMTP_PORT") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u16>()
+            .map_err(|e| anyhow!("SMTP_PORT must be a port number: {}", e))?,
+        _ => match security {
+            SmtpSecurity::StartTls => 587,
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::None => 25,
+        },
+    };
+    let credentials = match env::var("SMTP_USERNAME") {
+        Ok(username) if !username.trim().is_empty() => Some(Credentials::new(
+            username.trim().to_string(),
+            env::var("SMTP_PASSWORD").context("SMTP_PASSWORD is not set (required by SMTP_USERNAME)")?,
+        )),
+        _ => None,
+    };
+
+    let from = env::var("DIGEST_FROM")
+        .context("DIGEST_FROM environment variable is not set")?
+        .trim()
+        .parse::<Mailbox>()
+        .map_err(|e| anyhow!("DIGEST_FROM is not a valid address: {}", e))?;
+    let recipients = env::var("DIGEST_RECIPIENTS")
+        .context("DIGEST_RECIPIENTS environment variable is not set")?
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| anyhow!("DIGEST_RECIPIENTS: '{}' is not a valid address: {}", address, e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if recipients.is_empty() {
+        return Err(anyhow!("DIGEST_RECIPIENTS must list at least one address"));
+    }
+
+    let send_after = env::var("DIGEST_TIME")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_DIGEST_TIME.to_string());
+    let send_after = NaiveTime::parse_from_str(send_after.trim(), "%H:%M")
+        .map_err(|e| anyhow!("DIGEST_TIME must be HH:MM, got '{}': {}", send_after.trim(), e))?;
+    let subject = env::var("DIGEST_SUBJECT")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_DIGEST_SUBJECT.to_string());
+    let template = match env::var("DIGEST_TEMPLATE") {
+        Ok(path) if !path.trim().is_empty() => fs::read_to_string(path.trim())
+            .with_context(|| format!("Failed to read DIGEST_TEMPLATE: {}", path.trim()))?,
+        _ => DIGEST_TEMPLATE.to_string(),
+    };
+
+    Ok(MailerConfig {
+        smtp_host,
+        smtp_port,
+        security,
+        credentials,
+        from,
+        recipients,
+        send_after,
+        subject,
+        template,
+    })
+}
+
+fn init_db() -> Result<Connection> {
+    if !Path::new(DATA_DIR).exists() {
+        fs::create_dir_all(DATA_DIR).context("Failed to create data directory")?;
+    }
+    let conn = open_db()?;
+
+    // One row per day a digest went out; last_seq is the newest publish_chain record it covered
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS email_digests (
+            digest_date TEXT PRIMARY KEY,
+            sent_at TEXT NOT NULL,
+            items INTEGER NOT NULL,
+            last_seq INTEGER NOT NULL
+        )",
+        [],
+    )
+    .context("Failed to create email_digests table")?;
+
+    Ok(conn)
+}
+
+/// Sends the day's digest once the local time passed DIGEST_TIME, covering everything published
+/// since the previous digest (the last 24 hours for the first one).
+fn run_mailer(conn: &Connection, config: &MailerConfig, publisher: &PipelineStage) -> Result<()> {
+    let now = Local::now();
+    if now.time() < config.send_after {
+        return Ok(());
+    }
+    let today = now.format("%Y-%m-%d").to_string();
+    let sent: Option<String> = conn
+        .query_row("SELECT sent_at FROM email_digests WHERE digest_date = ?", params![today], |row| row.get(0))
+        .optional()?;
+    if sent.is_some() {
+        return Ok(());
+    }
+
+    let has_chain: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'publish_chain'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_chain {
+        info!("Nothing has been published yet, no digest today");
+        return Ok(());
+    }
+
+    let last_seq: Option<i64> = conn.query_row("SELECT MAX(last_seq) FROM email_digests", [], |row| row.get(0))?;
+    let items = fetch_digest_items(conn, last_seq, now)?;
+    let covered_seq = items.iter().map(|item| item.seq).max().or(last_seq).unwrap_or(0);
+
+    if items.is_empty() {
+        info!("No items published since the last digest, skipping today's");
+    } else {
+        let subject = config.subject.replace("{{date}}", &today);
+        let html = render_digest(config, publisher, &items, &subject, &today);
+        let plain = items
+            .iter()
+            .map(|item| format!("{}\n{}", item.title, item.url))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        send_digest(config, &subject, plain, html)?;
+        info!(
+            "Sent the digest of {} items to {} recipients",
+            items.len(),
+            config.recipients.len()
+        );
+    }
+
+    conn.execute(
+        "INSERT INTO email_digests (digest_date, sent_at, items, last_seq) VALUES (?, ?, ?, ?)",
+        params![today, now.to_rfc3339(), items.len() as i64, covered_seq],
+    )?;
+    Ok(())
+}
+
+/// Main channel items published after `last_seq`, oldest first. Edits of already published posts
+/// (status `updated`) are left out.
+fn fetch_digest_items(conn: &Connection, last_seq: Option<i64>, now: DateTime<Local>) -> Result<Vec<DigestItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT c.news_id, n.title, n.url, MAX(c.seq), MIN(c.published_at) FROM publish_chain c JOIN news n ON n.id = c.news_id \
+         WHERE c.lang = '' AND c.status != 'updated' AND c.seq > ? GROUP BY c.news_id ORDER BY MAX(c.seq)",
+    )?;
+    let rows = stmt
+        .query_map(params![last_seq.unwrap_or(0)], |row| {
+            Ok((
+                DigestItem {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    url: row.get(2)?,
+                    seq: row.get(3)?,
+                },
+                row.get::<_, String>(4)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    // Without an earlier digest only the last day is sent, not the whole history
+    let day_ago = now - ChronoDuration::hours(24);
+    Ok(rows
+        .into_iter()
+        .filter(|(_, published_at)| {
+            last_seq.is_some()
+                || DateTime::parse_from_rfc3339(published_at).is_ok_and(|time| time >= day_ago)
+        })
+        .map(|(item, _)| item)
+        .collect())
+}
+
+fn render_digest(config: &MailerConfig, publisher: &PipelineStage, items: &[DigestItem], subject: &str, date: &str) -> String {
+    let entries: String = items
+        .iter()
+        .map(|item| {
+            let body = read_artifact_string(&format!("{}_{}.html", publisher.output_artifact, item.id)).unwrap_or_default();
+            format!(
+                "<h2><a href=\"{}\">{}</a></h2>\n<p>{}</p>\n",
+                escape_html(&item.url),
+                escape_html(&item.title),
+                excerpt(&body)
+            )
+        })
+        .collect();
+    config
+        .template
+        .replace("{{subject}}", &escape_html(subject))
+        .replace("{{date}}", date)
+        .replace("{{count}}", &items.len().to_string())
+        .replace("{{items}}", &entries)
+}
+
+/// Sends one message per recipient, so that recipients don't see each other's addresses.
+fn send_digest(config: &MailerConfig, subject: &str, plain: String, html: String) -> Result<()> {
+    let builder = match config.security {
+        SmtpSecurity::StartTls => SmtpTransport::starttls_relay(&config.smtp_host)?,
+        SmtpSecurity::Tls => SmtpTransport::relay(&config.smtp_host)?,
+        SmtpSecurity::None => SmtpTransport::builder_dangerous(&config.smtp_host),
+    };
+    let builder = builder.port(config.smtp_port);
+    let transport = match &config.credentials {
+        Some(credentials) => builder.credentials(credentials.clone()).build(),
+        None => builder.build(),
+    };
+
+    let mut failed = Vec::new();
+    for recipient in &config.recipients {
+        let message = Message::builder()
+            .from(config.from.clone())
+            .to(recipient.clone())
+            .subject(subject)
+            .multipart(MultiPart::alternative_plain_html(plain.clone(), html.clone()))
+            .context("Failed to build the digest message")?;
+        if let Err(e) = transport.send(&message) {
+            warn!("Failed to send the digest to {}: {}", recipient, e);
+            failed.push(recipient.to_string());
+        }
+    }
+
+    if failed.len() == config.recipients.len() {
+        return Err(anyhow!("The digest could not be sent to any recipient"));
+    }
+    Ok(())
+}
+
+/// Start of the item's text without its markup, cut at a word boundary.
+fn excerpt(body: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in body.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(if c == '\n' { ' ' } else { c }),
+            _ => {}
+        }
+    }
+    let text = text.trim();
+    if text.chars().count() <= EXCERPT_LENGTH {
+        return text.to_string();
+    }
+    // The body is already escaped; cutting at a space keeps entities such as &amp; whole
+    let cut: String = text.chars().take(EXCERPT_LENGTH).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(head, _)| head);
+    format!("{}…", cut.trim_end())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use chrono::{FixedOffset, Utc};
+use reqwest::Client;
+use robo_news_core::{
+    block_on, heartbeat, http_timeout, insert_news, item_span, listen_for_shutdown, load_pipeline_stage, news_exists,
+    news_id, open_db, read_interval_from_env, shutdown_requested, sleep, NewsItem, PipelineStage, Status,
+};
+use rusqlite::Connection;
+use tracing::{error, info, warn};
+use scraper::{Html, Selector};
+use std::env;
+use std::time::Duration;
+
+const DEFAULT_PARSE_INTERVAL_SECS: u64 = 600;
+
+// Value of the news.source column for items of this feed, unless FEED1_SOURCE overrides it
+const DEFAULT_SOURCE: &str = "feed1";
+
+/// Reads FEED1_URL every PARSER_INTERVAL_SECS and stores the new items, or a single time with `--once`.
+pub fn run(options: RunOptions) -> Result<()> {
+    let interval_secs = read_interval_from_env("PARSER_INTERVAL_SECS", DEFAULT_PARSE_INTERVAL_SECS)?;
+
+    // Initialize database
+    let conn = open_db()?;
+    let client = Client::builder().timeout(http_timeout()?).build()?;
+
+    let stage = load_pipeline_stage(
+        "parser",
+        PipelineStage {
+            input_status: Status::from("-"),
+            output_status: Status::New,
+            input_artifact: "-".to_string(),
+            output_artifact: "-".to_string(),
+        },
+    )?;
+
+    let feed1_url = env::var("FEED1_URL").context("FEED1_URL environment variable is not set")?;
+    let feed1_url = feed1_url.trim().to_string();
+    if feed1_url.is_empty() {
+        return Err(anyhow::anyhow!("FEED1_URL environment variable is empty"));
+    }
+    
+    info!("Starting...");
+    listen_for_shutdown()?;
+    
+    if options.once {
+        return run_parser(&conn, &client, &feed1_url, &stage, options.limit);
+    }
+    
+    // Main loop - run every PARSER_INTERVAL_SECS seconds until SIGTERM or Ctrl-C
+    while !shutdown_requested() {
+        if let Err(e) = heartbeat(&conn, interval_secs) {
+            warn!("Failed to record heartbeat: {}", e);
+        }
+        if let Err(e) = run_parser(&conn, &client, &feed1_url, &stage, options.limit) {
+            error!("Error during parsing: {}", e);
+        }
+        
+        info!("Sleeping for {} seconds", interval_secs);
+        let _ = sleep(Duration::from_secs(interval_secs));
+    }
+    info!("Stopped");
+    Ok(())
+}
+
+/// Command-line options for one-off runs (used by `ctl seed` and cron or CI):
+/// `--once` runs a single cycle and exits, `--limit N` handles at most N items per cycle.
+pub struct RunOptions {
+    pub once: bool,
+    pub limit: Option<usize>,
+}
+
+pub fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { once: false, limit: None };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--once" => options.once = true,
+            "--limit" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--limit requires a value"))?;
+                options.limit = Some(
+                    value
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("--limit must be a non-negative integer: {}", e))?,
+                );
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn run_parser(conn: &Connection, client: &Client, feed_url: &str, stage: &PipelineStage, limit: Option<usize>) -> Result<()> {
+    info!("Starting parsing {}\"", feed_url);
+    
+    // Fetch and parse the webpage
+    let news_items = fetch_news(client, feed_url, &stage.output_status).context("Failed to fetch news")?;
+    
+    // Process and store new items
+    let mut new_count = 0;
+    for item in news_items {
+        if limit.is_some_and(|limit| new_count >= limit) || shutdown_requested() {
+            break;
+        }
+        let _span = item_span("parser-feed1", &item.id).entered();
+        if !news_exists(conn, &item.id)? {
+            store_news(conn, &item)?;
+            new_count += 1;
+            info!("Added new news: {}", item.title);
+        }
+    }
+    
+    info!("Parsing completed. Added {} new items", new_count);
+    Ok(())
+}
+
+fn fetch_news(client: &Client, feed_url: &str, new_status: &Status) -> Result<Vec<NewsItem>> {
+    let html = block_on(async {
+        let response = client
+            .get(feed_url)
+            .send()
+            .await
+            .context("Failed to send request")?;
+
+        response
+            .text()
+            .await
+            .context("Failed to get response text")
+    })?;
+    
+    let document = Html::parse_document(&html);
+    
+    // Select headlines - handle error conversion manually
+    let headline_selector = match Selector::parse("h3.entry-title.td-module-title") {
+        Ok(selector) => selector,
+        Err(e) => return Err(anyhow::anyhow!("Failed to create headline selector: {:?}", e)),
+    };
+    
+    // Select dates - handle error conversion manually
+    let date_selector = match Selector::parse("div.td-editor-date span.td-post-date time") {
+        Ok(selector) => selector,
+        Err(e) => return Err(anyhow::anyhow!("Failed to create date selector: {:?}", e)),
+    };
+    
+    let headlines: Vec<_> = document.select(&headline_selector).collect();
+    let dates: Vec<_> = document.select(&date_selector).collect();
+    
+    let mut news_items = Vec::new();
+    
+    // Create a fixed UTC+02:00 timezone offset for Belgrade/Serbia
+    let belgrade_offset = FixedOffset::east_opt(2 * 3600).unwrap();
+    
+    for (i, headline) in headlines.iter().enumerate() {
+        // Create link selector (this won't fail for a simple tag)
+        let link_selector = Selector::parse("a").unwrap();
+        
+        // Extract title and URL
+        if let Some(link) = headline.select(&link_selector).next() {
+            // Get title from text content only
+            let title = headline.text().collect::<Vec<_>>().join(" ").trim().to_string();
+            
+            // Skip news items without proper titles
+            if title.is_empty() {
+                continue;
+            }
+            
+            let url = link.value().attr("href").unwrap_or("").to_string();
+            
+            // Skip news items with URLs that don't start with the base URL
+            if !url.starts_with(feed_url) {
+                continue;
+            }
+            
+            // Generate ID from URL
+            let id = news_id(&url);
+            
+            // Extract date from datetime attribute if available
+            let date = if i < dates.len() {
+                // Try to get the datetime attribute first
+                match dates[i].value().attr("datetime") {
+                    Some(datetime_str) if !datetime_str.is_empty() => datetime_str.to_string(),
+                    _ => {
+                        // Fallback: Use current time in Belgrade timezone (UTC+02:00)
+                        Utc::now()
+                            .with_timezone(&belgrade_offset)
+                            .to_rfc3339()
+                    }
+                }
+            } else {
+                // Fallback: Use current time in Belgrade timezone (UTC+02:00)
+                Utc::now()
+                    .with_timezone(&belgrade_offset)
+                    .to_rfc3339()
+            };
+            
+            news_items.push(NewsItem {
+                id,
+                title,
+                url,
+                date,
+                status: new_status.clone(),
+            });
+        }
+    }
+    
+    // Reverse the order to match the behavior of the old script
+    news_items.reverse();
+    
+    Ok(news_items)
+}
+
+fn store_news(conn: &Connection, item: &NewsItem) -> Result<()> {
+    let source = env::var("FEED1_SOURCE")
+        .ok()
+        .map(|source| source.trim().to_string())
+        .filter(|source| !source.is_empty())
+        .unwrap_or_else(|| DEFAULT_SOURCE.to_string());
+    insert_news(conn, item, &source)
+}
+
@@ -0,0 +1,4596 @@
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use robo_news_core::{artifact_exists, claim_item, heartbeat, http_timeout, item_span, listen_for_shutdown, load_artifact, load_pipeline_stage, open_db, publish_record_hash, read_artifact, read_artifact_string, read_interval_from_env, read_pipeline_config, record_failure, reset_attempts, send_dead_letter_alerts, shutdown_requested, shutdown_signal, update_status, wakeup_poll_interval, wakeup_seq, with_immediate_transaction, write_artifact, GENESIS_HASH, PipelineStage, Status};
+use tracing::{error, info, warn, Instrument};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::time::{sleep, Duration};
+use scraper::{Html, Selector, ElementRef};
+use chrono::{DateTime, Local, NaiveDateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use sha2::{Digest, Sha256};
+
+use grammers_client::{Client as TgClient, InputMedia, InputMessage, SignInError};
+use grammers_mtsender::SenderPool;
+use grammers_session::types::PeerRef;
+use grammers_session::Session;
+use grammers_session::SessionData;
+use grammers_session::types::{ChannelState, DcOption, PeerId, PeerInfo, UpdateState, UpdatesState};
+
+const DATA_DIR: &str = "data";
+const DEFAULT_PUBLISH_INTERVAL_SECS: u64 = 60;
+// How many of the most recently published titles are checked for duplicates (0 disables the check)
+const DEFAULT_DEDUP_WINDOW: usize = 50;
+// How long an item waits for earlier items still in the pipeline (PUBLISHER_STRICT_ORDER)
+const DEFAULT_ORDER_TIMEOUT_SECS: i64 = 1800;
+// Failed posts are retried with exponential backoff (PUBLISHER_MAX_ATTEMPTS, PUBLISHER_RETRY_*)
+const DEFAULT_PUBLISH_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_PUBLISH_RETRY_DELAY_SECS: u64 = 60;
+const DEFAULT_PUBLISH_RETRY_MAX_DELAY_SECS: u64 = 3600;
+// How long after publishing a post's views are still collected (ENGAGEMENT_DAYS)
+const DEFAULT_ENGAGEMENT_DAYS: i64 = 7;
+// How long a breaking news post stays pinned (PIN_DURATION_SECS)
+const DEFAULT_PIN_DURATION_SECS: i64 = 3600;
+// Messages per messages.getMessages request
+const ENGAGEMENT_BATCH_SIZE: usize = 100;
+
+// A Telegram media group holds at most 10 photos
+const MAX_ALBUM_IMAGES: usize = 10;
+
+const MODERATION_URL: &str = "https://api.openai.com/v1/moderations";
+const DEFAULT_MODERATION_MODEL: &str = "omni-moderation-latest";
+const TELEGRAPH_CREATE_PAGE_URL: &str = "https://api.telegra.ph/createPage";
+const TELEGRAPH_EDIT_PAGE_URL: &str = "https://api.telegra.ph/editPage";
+const TELEGRAPH_UPLOAD_URL: &str = "https://telegra.ph/upload";
+// Telegraph accepts images of up to 5 MB
+const TELEGRAPH_MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+// Telegram limits, counted in UTF-16 code units
+const TG_MESSAGE_LIMIT: usize = 4096;
+const TG_CAPTION_LIMIT: usize = 1024;
+const TG_BOT_API_URL: &str = "https://api.telegram.org";
+// Tags that stay inside a line of text when the article HTML is flattened for Telegram
+const INLINE_TAGS: &[&str] = &["span", "a", "strong", "b", "i", "em"];
+// Discord embed limits
+const DISCORD_TITLE_LIMIT: usize = 256;
+const DISCORD_DESCRIPTION_LIMIT: usize = 4096;
+const DEFAULT_BLUESKY_PDS_URL: &str = "https://bsky.social";
+// Bluesky posts hold up to 300 characters (graphemes); link cards have no hard limit but get cut off
+const BLUESKY_POST_LIMIT: usize = 300;
+const BLUESKY_CARD_DESCRIPTION_LIMIT: usize = 300;
+// uploadBlob takes images of up to 1 MB for a link card thumbnail
+const BLUESKY_MAX_THUMB_BYTES: usize = 1_000_000;
+const SLACK_API_URL: &str = "https://slack.com/api";
+// Block Kit limits of header and section text
+const SLACK_HEADER_LIMIT: usize = 150;
+const SLACK_SECTION_LIMIT: usize = 3000;
+// Telegram post layout unless POST_TEMPLATES sets one
+const DEFAULT_POST_TEMPLATE: &str = "{{emoji}} {{text}}\n\n{{published_label}}: {{date}}\n{{link}}";
+// Categories the rewriter assigns unless CATEGORIES lists others
+const DEFAULT_CATEGORIES: &str = "politics,tech,sport,economy";
+// Words per minute behind {{reading_time}}
+const READING_WORDS_PER_MINUTE: usize = 200;
+// How long a Nostr relay gets to confirm a note
+const NOSTR_RELAY_TIMEOUT_SECS: u64 = 15;
+// Requests carrying an image (sendPhoto, Telegraph uploads, mirror posts) may take longer than HTTP_TIMEOUT_SECS
+const UPLOAD_TIMEOUT_SECS: u64 = 60;
+// Telegram user API (grammers) session storage
+const TG_SESSION_PATH: &str = "data/telegram.session";
+
+struct TelegramContext {
+    client: TgClient,
+    target_chat: PeerRef,
+    /// Channels for the extra TARGET_LANGUAGES (TG_CHAT_ID_<LANG>).
+    language_chats: HashMap<String, PeerRef>,
+    /// Channels for categories of main-language items (TG_CHAT_ID_CATEGORY_<CATEGORY>).
+    category_chats: HashMap<String, PeerRef>,
+    /// Forum topic routing for TG_CHAT_ID.
+    topics: TopicRouting,
+    /// Pinned daily header posted before the day's first item (PUBLISHER_DAILY_HEADER).
+    daily_header: Option<DailyHeader>,
+    /// Breaking news posts that are pinned for a while (PIN_KEYWORDS).
+    pinning: Option<PinRule>,
+    /// Checks run on the text before it is posted.
+    moderation: Moderation,
+    /// Chat notified about items held by moderation (TG_MODERATION_CHAT_ID).
+    moderation_chat: Option<PeerRef>,
+    /// Telegraph account for the full text of posts too long for Telegram (TELEGRAPH_ACCESS_TOKEN).
+    telegraph: Option<Telegraph>,
+    /// Admin chat that approves every post before it is published (APPROVAL_BOT_TOKEN).
+    approval: Option<Approval>,
+    /// Posting hours and quiet hours.
+    schedule: Schedule,
+    /// Other services every post of a channel is mirrored to, keyed by language ("" for TG_CHAT_ID).
+    mirrors: HashMap<String, Vec<MirrorTarget>>,
+    /// How posts are laid out (POST_TEMPLATES, POST_HASHTAGS).
+    templates: PostTemplates,
+    /// Share of posts that get a footer block from `post_footers` (POST_FOOTER_RATIO).
+    footer_ratio: f64,
+    /// Short links for the source URL in posts (SHORTENER).
+    shortener: Option<Shortener>,
+    /// Cover image and link preview of each channel, keyed by language ("" for TG_CHAT_ID).
+    previews: HashMap<String, PreviewOptions>,
+    #[allow(dead_code)]
+    session: Arc<FileSession>,
+}
+
+/// Posts an item into a topic of a forum-type supergroup when one of its tags matches.
+struct TopicRule {
+    topic_id: i32,
+    tags: Vec<String>,
+}
+
+/// Optional checks of the text before it goes to Telegram; flagged items get the status
+/// `moderation_hold` until an operator releases them with `ctl review --approve`.
+struct Moderation {
+    /// Lower-case phrases from MODERATION_KEYWORDS_FILE, one per line (`#` starts a comment).
+    keywords: Vec<String>,
+    /// Key for the OpenAI moderation endpoint (MODERATION_OPENAI_API_KEY).
+    openai_api_key: Option<String>,
+    /// MODERATION_MODEL, `omni-moderation-latest` by default.
+    model: String,
+}
+
+/// Drafts are sent to an admin chat by a bot with Approve/Reject/Edit buttons (a user account
+/// can't attach inline buttons) and posted only once approved (APPROVAL_BOT_TOKEN, APPROVAL_CHAT_ID).
+#[derive(Clone)]
+struct Approval {
+    bot_token: String,
+    /// Bot API id of the admin chat, e.g. -1001234567890.
+    chat_id: i64,
+}
+
+/// A service posts are mirrored to besides Telegram.
+enum MirrorTarget {
+    /// Discord webhook (DISCORD_WEBHOOK_URL).
+    Discord { webhook_url: String },
+    /// Bluesky account (BLUESKY_HANDLE); main channel only.
+    Bluesky(Bluesky),
+    /// Slack incoming webhook (SLACK_WEBHOOK_URL).
+    SlackWebhook { webhook_url: String },
+    /// Slack app posting with chat.postMessage (SLACK_BOT_TOKEN, SLACK_CHANNEL).
+    SlackBot { bot_token: String, channel: String },
+    /// Matrix room (MATRIX_ROOM_ID).
+    Matrix(Matrix),
+    /// Nostr relays (NOSTR_RELAYS).
+    Nostr(Nostr),
+}
+
+impl MirrorTarget {
+    fn name(&self) -> &'static str {
+        match self {
+            MirrorTarget::Discord { .. } => "discord",
+            MirrorTarget::Bluesky(_) => "bluesky",
+            MirrorTarget::SlackWebhook { .. } | MirrorTarget::SlackBot { .. } => "slack",
+            MirrorTarget::Matrix(_) => "matrix",
+            MirrorTarget::Nostr(_) => "nostr",
+        }
+    }
+
+    /// Whether a message there can be changed once it went out.
+    fn can_edit(&self) -> bool {
+        match self {
+            MirrorTarget::Discord { .. } | MirrorTarget::SlackBot { .. } | MirrorTarget::Matrix(_) => true,
+            MirrorTarget::Bluesky(_) | MirrorTarget::SlackWebhook { .. } | MirrorTarget::Nostr(_) => false,
+        }
+    }
+}
+
+/// What a post shows besides its text (POST_COVER, LINK_PREVIEW, LINK_PREVIEW_ABOVE_TEXT,
+/// CAPTION_ABOVE_PHOTO, each with an optional `_<LANG>` suffix).
+#[derive(Clone, Copy)]
+struct PreviewOptions {
+    cover: Cover,
+    /// Let Telegram show a preview of the first link in text posts.
+    link_preview: bool,
+    /// The preview goes above the text instead of below it.
+    preview_above_text: bool,
+    /// The caption of a single-photo post goes above the photo.
+    caption_above_photo: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Cover {
+    /// The illustration, as the photo the text is its caption of (default).
+    Photo,
+    /// The link preview of the source page; illustrations are not posted.
+    Preview,
+    /// Text only.
+    None,
+}
+
+impl PreviewOptions {
+    /// Applies the options to a message; `photo` tells whether it is a photo caption.
+    fn apply(&self, message: InputMessage, photo: bool) -> InputMessage {
+        if photo {
+            message.invert_media(self.caption_above_photo)
+        } else {
+            message.link_preview(self.link_preview).invert_media(self.link_preview && self.preview_above_text)
+        }
+    }
+}
+
+/// Where source links are shortened.
+enum Shortener {
+    /// Shlink server with an API key (SHORTENER_URL, SHORTENER_API_KEY).
+    Shlink { base_url: String, api_key: String },
+    /// YOURLS installation with a signature token (SHORTENER_URL, SHORTENER_API_KEY).
+    Yourls { base_url: String, signature: String },
+    /// `short_links` table, redirected and counted by `api` at `<SHORTENER_URL>/r/<code>`.
+    Internal { base_url: String },
+}
+
+/// Post templates with `{{placeholder}}` fields, keyed by `<target>` or `<target>/<source>`, where
+/// the target is `telegram` or a mirror target's name and `*` stands for any target.
+struct PostTemplates {
+    templates: HashMap<String, String>,
+    /// Words that become hashtags when the title or URL contains them.
+    hashtags: Vec<String>,
+    /// Emoji per category for `{{emoji}}` (POST_CATEGORY_EMOJI).
+    category_emoji: HashMap<String, String>,
+}
+
+impl PostTemplates {
+    /// The most specific template for the target and the item's source.
+    fn find(&self, target: &str, source: Option<&str>) -> Option<&str> {
+        let mut keys = Vec::new();
+        if let Some(source) = source {
+            keys.push(format!("{}/{}", target, source));
+            keys.push(format!("*/{}", source));
+        }
+        keys.push(target.to_string());
+        keys.iter().find_map(|key| self.templates.get(key)).map(String::as_str)
+    }
+}
+
+/// Values of the template placeholders for one item.
+struct PostFields {
+    title: String,
+    /// The published text (HTML).
+    text: String,
+    /// The published text without markup (the `.txt` next to it).
+    plain_text: String,
+    /// The rewriter's summary (HTML), or the published text if there is none.
+    summary: String,
+    date: String,
+    url: String,
+    source: String,
+    /// Category assigned by the rewriter, empty for uncategorized items.
+    category: String,
+    emoji: String,
+    hashtags: String,
+    /// Footer block (HTML) chosen for the post, empty for none.
+    footer: String,
+    /// Minutes to read the full article.
+    reading_time: usize,
+    published_label: &'static str,
+    original_label: &'static str,
+}
+
+/// What the mirror targets show of a post.
+#[derive(Clone)]
+struct MirrorPost {
+    id: String,
+    title: String,
+    /// The published text without markup.
+    text: String,
+    url: String,
+    /// Publication date line in the item's language.
+    footer: String,
+    /// Label of the source link in the item's language.
+    link_label: String,
+    /// The illustration in the artifact store, if the item has one.
+    image: Option<String>,
+    /// The post rendered with the target's template (POST_TEMPLATES), as plain text.
+    template_text: Option<String>,
+}
+
+/// Matrix user posting with an access token (MATRIX_HOMESERVER_URL, MATRIX_ACCESS_TOKEN).
+struct Matrix {
+    homeserver_url: String,
+    access_token: String,
+    room_id: String,
+}
+
+/// Nostr identity that signs the notes (NOSTR_SECRET_KEY) and the relays they are sent to.
+struct Nostr {
+    keypair: secp256k1::Keypair,
+    relays: Vec<String>,
+}
+
+/// Bluesky account, signed in with an app password for every post.
+struct Bluesky {
+    /// Personal data server of the account (BLUESKY_PDS_URL).
+    pds_url: String,
+    handle: String,
+    app_password: String,
+}
+
+/// How the queue is worked through.
+struct PublishPolicy {
+    /// Recently published titles checked for duplicates (PUBLISHER_DEDUP_WINDOW).
+    dedup_window: usize,
+    /// alternate, a or b (PUBLISHER_VARIANT_STRATEGY).
+    variant_strategy: String,
+    /// Posts to the main channel per rolling hour (PUBLISHER_MAX_POSTS_PER_HOUR).
+    max_posts_per_hour: Option<usize>,
+    /// Publish in article-date order (PUBLISHER_STRICT_ORDER).
+    ordering: Option<OrderingPolicy>,
+    /// When a failed post is tried again.
+    retry: RetryPolicy,
+}
+
+/// A failed post moves to `publisher_retry` and is tried again after `retry_delay_secs`, doubling
+/// with every further failure up to `max_delay_secs`; after `max_attempts` it is a `publish_error`.
+struct RetryPolicy {
+    max_attempts: u32,
+    retry_delay_secs: u64,
+    max_delay_secs: u64,
+}
+
+impl RetryPolicy {
+    fn delay_before(&self, attempts: u32) -> i64 {
+        let delay = self
+            .retry_delay_secs
+            .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+            .min(self.max_delay_secs);
+        delay.min(i64::MAX as u64) as i64
+    }
+}
+
+/// Periodic collection of view and forward counts of published posts (ENGAGEMENT_INTERVAL_SECS).
+struct EngagementPolicy {
+    interval_secs: u64,
+    /// Posts published within this many days are tracked (ENGAGEMENT_DAYS).
+    days: i64,
+}
+
+/// An item waits while an older item is still in one of the `in_flight` statuses, for at most
+/// `timeout_secs` (PUBLISHER_ORDER_TIMEOUT_SECS).
+struct OrderingPolicy {
+    in_flight: Vec<Status>,
+    timeout_secs: i64,
+}
+
+/// When posts go out (PUBLISHER_HOURS, PUBLISHER_QUIET_HOURS, PUBLISHER_TIMEZONE). Ranges are
+/// minutes since midnight and may wrap past it, e.g. 22:00-07:00.
+struct Schedule {
+    /// IANA time zone; the host's local time if unset.
+    timezone: Option<Tz>,
+    /// Items ready outside these hours wait in `ready_to_publish`.
+    hours: Option<(u32, u32)>,
+    /// Posts in these hours are sent without a notification sound.
+    quiet_hours: Option<(u32, u32)>,
+}
+
+impl Schedule {
+    fn minute_of_day(&self) -> u32 {
+        let time = match self.timezone {
+            Some(timezone) => Utc::now().with_timezone(&timezone).time(),
+            None => Local::now().time(),
+        };
+        time.hour() * 60 + time.minute()
+    }
+
+    fn is_open(&self) -> bool {
+        self.hours.is_none_or(|range| in_time_range(range, self.minute_of_day()))
+    }
+
+    fn is_quiet(&self) -> bool {
+        self.quiet_hours.is_some_and(|range| in_time_range(range, self.minute_of_day()))
+    }
+}
+
+fn in_time_range((start, end): (u32, u32), minute: u32) -> bool {
+    if start <= end {
+        start <= minute && minute < end
+    } else {
+        minute >= start || minute < end
+    }
+}
+
+/// A draft sent to the approval chat (`approval_drafts`).
+struct ApprovalDraft {
+    /// `approved`, `rejected`, or None while waiting.
+    decision: Option<String>,
+    /// Text an admin replied to the draft with; replaces the post's text.
+    edited_text: Option<String>,
+}
+
+#[derive(Clone)]
+struct Telegraph {
+    access_token: String,
+    /// Shown under the page title (TELEGRAPH_AUTHOR_NAME).
+    author_name: Option<String>,
+    /// Every post links to a Telegraph page with the full text, not only the shortened ones
+    /// (TELEGRAPH_ALWAYS).
+    always: bool,
+}
+
+/// Main-language posts whose title contains one of `keywords` are pinned after sending and
+/// unpinned after `duration_secs` or when the next one is pinned in the same channel.
+struct PinRule {
+    keywords: Vec<String>,
+    duration_secs: i64,
+}
+
+struct DailyHeader {
+    /// Header text; `{date}` is replaced with today's date in `date_format`.
+    template: String,
+    date_format: String,
+}
+
+#[derive(Default)]
+struct TopicRouting {
+    rules: Vec<TopicRule>,
+    /// Topic per category (TG_TOPIC_CATEGORIES); checked before the tag rules.
+    category_topics: HashMap<String, i32>,
+    default_topic: Option<i32>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PersistedSessionData {
+    home_dc: i32,
+    dc_options: HashMap<i32, DcOption>,
+    peer_infos: HashMap<PeerId, PeerInfo>,
+    updates_state: UpdatesState,
+}
+
+impl Default for PersistedSessionData {
+    fn default() -> Self {
+        let data = SessionData::default();
+        Self {
+            home_dc: data.home_dc,
+            dc_options: data.dc_options,
+            peer_infos: data.peer_infos,
+            updates_state: data.updates_state,
+        }
+    }
+}
+
+/// JSON-backed session storage for grammers.
+///
+/// We use this to persist the session under `data/telegram.session` without introducing a second
+/// native sqlite3 dependency (rusqlite already links sqlite3).
+struct FileSession {
+    path: PathBuf,
+    data: std::sync::Mutex<PersistedSessionData>,
+}
+
+impl FileSession {
+    fn load_or_create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let data = if path.exists() {
+            let raw = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read telegram session file: {}", path.display()))?;
+            serde_json::from_str::<PersistedSessionData>(&raw)
+                .with_context(|| format!("Failed to parse telegram session JSON: {}", path.display()))?
+        } else {
+            PersistedSessionData::default()
+        };
+
+        let session = Self {
+            path,
+            data: std::sync::Mutex::new(data),
+        };
+
+        // Ensure the file exists on disk even before first login.
+        session.save().ok();
+
+        Ok(session)
+    }
+
+    fn save(&self) -> Result<()> {
+        let tmp_path = self.path.with_extension("session.tmp");
+        let data = self.data.lock().unwrap();
+        let json = serde_json::to_string_pretty(&*data).context("Failed to serialize telegram session")?;
+        fs::write(&tmp_path, json)
+            .with_context(|| format!("Failed to write tmp telegram session: {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to move tmp session into place: {}", self.path.display()))?;
+        Ok(())
+    }
+
+    fn save_best_effort(&self) {
+        if let Err(e) = self.save() {
+            warn!("Failed to persist telegram session: {}", e);
+        }
+    }
+}
+
+impl Session for FileSession {
+    fn home_dc_id(&self) -> i32 {
+        self.data.lock().unwrap().home_dc
+    }
+
+    fn set_home_dc_id(&self, dc_id: i32) {
+        self.data.lock().unwrap().home_dc = dc_id;
+        self.save_best_effort();
+    }
+
+    fn dc_option(&self, dc_id: i32) -> Option<DcOption> {
+        self.data.lock().unwrap().dc_options.get(&dc_id).cloned()
+    }
+
+    fn set_dc_option(&self, dc_option: &DcOption) {
+        self.data
+            .lock()
+            .unwrap()
+            .dc_options
+            .insert(dc_option.id, dc_option.clone());
+        self.save_best_effort();
+    }
+
+    fn peer(&self, peer: PeerId) -> Option<PeerInfo> {
+        self.data.lock().unwrap().peer_infos.get(&peer).cloned()
+    }
+
+    fn cache_peer(&self, peer: &PeerInfo) {
+        self.data
+            .lock()
+            .unwrap()
+            .peer_infos
+            .insert(peer.id(), peer.clone());
+        self.save_best_effort();
+    }
+
+    fn updates_state(&self) -> UpdatesState {
+        self.data.lock().unwrap().updates_state.clone()
+    }
+
+    fn set_update_state(&self, update: UpdateState) {
+        let mut data = self.data.lock().unwrap();
+        match update {
+            UpdateState::All(updates_state) => {
+                data.updates_state = updates_state;
+            }
+            UpdateState::Primary { pts, date, seq } => {
+                data.updates_state.pts = pts;
+                data.updates_state.date = date;
+                data.updates_state.seq = seq;
+            }
+            UpdateState::Secondary { qts } => {
+                data.updates_state.qts = qts;
+            }
+            UpdateState::Channel { id, pts } => {
+                data.updates_state.channels.retain(|c| c.id != id);
+                data.updates_state.channels.push(ChannelState { id, pts });
+            }
+        }
+        drop(data);
+        self.save_best_effort();
+    }
+}
+
+struct NewsItem {
+    id: String,
+    title: String,
+    url: String,
+    #[allow(dead_code)]
+    date: String,
+    status: Status,
+    #[allow(dead_code)]
+    error: Option<String>,
+    /// Extra target language of a `news_translations` row; `None` for the main `news` row.
+    lang: Option<String>,
+    /// The illustrator's skip rules left this item without an image.
+    illustration_skipped: bool,
+    /// Feed the item came from (`news.source`), used to pick a per-source template.
+    source: Option<String>,
+    /// Category assigned by the rewriter's classifier (`news.category`).
+    category: Option<String>,
+    /// Short link posted instead of `url` (SHORTENER).
+    short_url: Option<String>,
+    /// Footer block from `post_footers` appended to this post (POST_FOOTER_RATIO).
+    footer: Option<String>,
+}
+
+impl NewsItem {
+    /// The source link as it appears in posts.
+    fn link_url(&self) -> &str {
+        self.short_url.as_deref().unwrap_or(&self.url)
+    }
+}
+
+/// Telegram message an item was published as (`published_messages`).
+struct PublishedPost {
+    /// The message holding the post's text; the first photo of an album carries the caption.
+    message_id: i32,
+    /// Telegraph page with the full text of a shortened post.
+    telegraph_path: Option<String>,
+}
+
+/// Result of sending a new post.
+struct SentPost {
+    /// Telegram rejected the formatting and the post went out as plain text.
+    plain: bool,
+    message_id: Option<i32>,
+}
+
+/// HTTP client of the Bot API, moderation, Telegraph and mirror requests, built once in `run`.
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn http_client() -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Connects to Telegram (or renders to `data/` with PUBLISH_DRY_RUN) and publishes finished items
+/// until SIGTERM or Ctrl-C, or for one cycle with `--once`.
+#[tokio::main(flavor = "current_thread")]
+pub async fn run(options: RunOptions) -> Result<()> {
+    let interval_secs = read_interval_from_env("PUBLISHER_INTERVAL_SECS", DEFAULT_PUBLISH_INTERVAL_SECS)?;
+    let client = reqwest::Client::builder()
+        .timeout(http_timeout()?)
+        .build()
+        .context("Failed to build HTTP client")?;
+    let _ = HTTP_CLIENT.set(client);
+
+    // Initialize database and data directory
+    let conn = init_db()?;
+    init_data_dir()?;
+
+    let dry_run = match env::var("PUBLISH_DRY_RUN") {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "" | "0" | "false" | "no" | "off" => false,
+            _ => return Err(anyhow!("PUBLISH_DRY_RUN must be true or false, got '{}'", value.trim())),
+        },
+        Err(_) => false,
+    };
+    
+    // Check required environment variables; a dry run doesn't talk to Telegram
+    if !dry_run {
+        check_env_vars()?;
+    }
+
+    let stage = load_pipeline_stage(
+        "publisher",
+        PipelineStage {
+            input_status: Status::Illustrator,
+            output_status: Status::Published,
+            input_artifact: "rewriter".to_string(),
+            output_artifact: "publisher".to_string(),
+        },
+    )?;
+
+    // Without an illustrator stage in the pipeline, items are published as text-only posts.
+    let illustrated = match read_pipeline_config()? {
+        Some(stages) => {
+            // Another stage reading the same status would race the publisher for its items
+            if let Some((name, _)) = stages
+                .iter()
+                .find(|(name, other)| name != "publisher" && other.input_status == stage.input_status)
+            {
+                return Err(anyhow!(
+                    "PIPELINE_CONFIG: publisher and {} both take items in status '{}'; point the publisher at the last stage's output_status",
+                    name,
+                    stage.input_status
+                ));
+            }
+            stages.iter().any(|(name, _)| name == "illustrator")
+        }
+        None => true,
+    };
+    info!(
+        "Publishing items in status '{}' from data/{}_<id>.html",
+        stage.input_status, stage.input_artifact
+    );
+
+    // Extra-language versions are ready once the rewriter has produced them.
+    let translation_status = load_pipeline_stage(
+        "rewriter",
+        PipelineStage {
+            input_status: Status::Translated,
+            output_status: Status::Rewriter,
+            input_artifact: "translator".to_string(),
+            output_artifact: "rewriter".to_string(),
+        },
+    )?
+    .output_status;
+
+    let ordering = read_ordering_policy_from_env()?;
+
+    let dedup_window = match env::var("PUBLISHER_DEDUP_WINDOW") {
+        Ok(value) => value
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| anyhow!("PUBLISHER_DEDUP_WINDOW must be a non-negative integer: {}", e))?,
+        Err(_) => DEFAULT_DEDUP_WINDOW,
+    };
+
+    let variant_strategy = match env::var("PUBLISHER_VARIANT_STRATEGY") {
+        Ok(value) if !value.trim().is_empty() => {
+            let value = value.trim().to_ascii_lowercase();
+            if !["alternate", "a", "b"].contains(&value.as_str()) {
+                return Err(anyhow!(
+                    "PUBLISHER_VARIANT_STRATEGY must be one of alternate, a, b (got '{}')",
+                    value
+                ));
+            }
+            value
+        }
+        _ => "alternate".to_string(),
+    };
+
+    let max_posts_per_hour = match env::var("PUBLISHER_MAX_POSTS_PER_HOUR") {
+        Ok(value) if !value.trim().is_empty() => Some(
+            value
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|posts| *posts > 0)
+                .ok_or_else(|| anyhow!("PUBLISHER_MAX_POSTS_PER_HOUR must be a positive integer, got '{}'", value.trim()))?,
+        ),
+        _ => None,
+    };
+    let retry = RetryPolicy {
+        max_attempts: match env::var("PUBLISHER_MAX_ATTEMPTS") {
+            Ok(value) if !value.trim().is_empty() => value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|attempts| *attempts > 0)
+                .ok_or_else(|| anyhow!("PUBLISHER_MAX_ATTEMPTS must be a positive integer, got '{}'", value.trim()))?,
+            _ => DEFAULT_PUBLISH_MAX_ATTEMPTS,
+        },
+        retry_delay_secs: read_interval_from_env("PUBLISHER_RETRY_DELAY_SECS", DEFAULT_PUBLISH_RETRY_DELAY_SECS)?,
+        max_delay_secs: read_interval_from_env("PUBLISHER_RETRY_MAX_DELAY_SECS", DEFAULT_PUBLISH_RETRY_MAX_DELAY_SECS)?,
+    };
+    let policy = PublishPolicy {
+        dedup_window,
+        variant_strategy,
+        max_posts_per_hour,
+        ordering,
+        retry,
+    };
+
+    let engagement = match env::var("ENGAGEMENT_INTERVAL_SECS") {
+        Ok(value) if !value.trim().is_empty() => Some(EngagementPolicy {
+            interval_secs: read_interval_from_env("ENGAGEMENT_INTERVAL_SECS", 0)?,
+            days: match env::var("ENGAGEMENT_DAYS") {
+                Ok(value) if !value.trim().is_empty() => value
+                    .trim()
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|days| *days > 0)
+                    .ok_or_else(|| anyhow!("ENGAGEMENT_DAYS must be a positive integer, got '{}'", value.trim()))?,
+                _ => DEFAULT_ENGAGEMENT_DAYS,
+            },
+        }),
+        _ => None,
+    };
+    let mut engagement_collected_at: Option<std::time::Instant> = None;
+
+    listen_for_shutdown()?;
+
+    if dry_run {
+        info!("PUBLISH_DRY_RUN is set: posts are rendered to data/ instead of being sent");
+        while !shutdown_requested() {
+            if !options.once {
+                if let Err(e) = heartbeat(&conn, interval_secs) {
+                    warn!("Failed to record heartbeat: {}", e);
+                }
+            }
+            let seen = wakeup_seq(&conn, &[&stage.input_status, &translation_status]).unwrap_or(0);
+            if let Err(e) = run_dry_run(&conn, &stage, illustrated, &translation_status, &policy) {
+                if options.once {
+                    return Err(e);
+                }
+                error!("Error during the dry run: {}", e);
+            }
+            if options.once {
+                return Ok(());
+            }
+            info!("Sleeping for up to {} seconds", interval_secs);
+            wait_for_wakeup(&conn, &[&stage.input_status, &translation_status], interval_secs, seen).await?;
+        }
+        info!("Stopped");
+        return Ok(());
+    }
+
+    // Initialize Telegram client (user API) and authorize if needed
+    let tg = init_telegram().await?;
+    
+    info!("Starting publisher...");
+    
+    // Main loop - run every PUBLISHER_INTERVAL_SECS seconds until SIGTERM or Ctrl-C; a post
+    // being sent is never cut short, so an item is not posted twice
+    while !shutdown_requested() {
+        if !options.once {
+            if let Err(e) = heartbeat(&conn, interval_secs) {
+                warn!("Failed to record heartbeat: {}", e);
+            }
+        }
+        // Read before the cycle, so items handed over during it end the next sleep early
+        let seen = wakeup_seq(&conn, &[&stage.input_status, &translation_status]).unwrap_or(0);
+        if let Err(e) = run_publisher(&conn, &tg, &stage, illustrated, &translation_status, &policy).await {
+            if options.once {
+                return Err(e);
+            }
+            error!("Error during publishing: {}", e);
+        }
+        if let Some(engagement) = &engagement {
+            let due = engagement_collected_at.is_none_or(|at| at.elapsed().as_secs() >= engagement.interval_secs);
+            if due {
+                engagement_collected_at = Some(std::time::Instant::now());
+                // Statistics never hold up publishing
+                if let Err(e) = collect_engagement(&conn, &tg, engagement).await {
+                    warn!("Failed to collect post engagement: {}", e);
+                }
+            }
+        }
+        if let Err(e) = send_dead_letter_alerts(&conn).await {
+            warn!("Failed to send dead-letter alerts: {}", e);
+        }
+        if options.once {
+            return Ok(());
+        }
+        
+        info!("Sleeping for up to {} seconds", interval_secs);
+        wait_for_wakeup(&conn, &[&stage.input_status, &translation_status], interval_secs, seen).await?;
+    }
+    info!("Stopped");
+    Ok(())
+}
+
+/// `robo_news_core::wait_for_wakeup` on the runtime: sleeps up to `interval_secs`, until an
+/// item enters one of `statuses` or the process is asked to stop.
+async fn wait_for_wakeup(conn: &Connection, statuses: &[&Status], interval_secs: u64, seen: i64) -> Result<()> {
+    let poll = wakeup_poll_interval()?;
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(interval_secs);
+    loop {
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return Ok(());
+        }
+        tokio::select! {
+            _ = sleep(poll.min(deadline - now)) => {}
+            _ = shutdown_signal() => return Ok(()),
+        }
+        match wakeup_seq(conn, statuses) {
+            Ok(current) if current != seen => {
+                info!("New items arrived, waking up");
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to check for new items: {}", e),
+        }
+    }
+}
+
+fn check_env_vars() -> Result<()> {
+    let api_id = env::var("TG_API_ID").context("TG_API_ID environment variable is not set")?;
+    let api_hash = env::var("TG_API_HASH").context("TG_API_HASH environment variable is not set")?;
+    let tg_chat_id =
+        env::var("TG_CHAT_ID").context("TG_CHAT_ID environment variable is not set")?;
+
+    if api_id.trim().is_empty() {
+        return Err(anyhow!("TG_API_ID environment variable is empty"));
+    }
+
+    if api_hash.trim().is_empty() {
+        return Err(anyhow!("TG_API_HASH environment variable is empty"));
+    }
+
+    if tg_chat_id.trim().is_empty() {
+        return Err(anyhow!("TG_CHAT_ID environment variable is empty"));
+    }
+
+    for lang in extra_languages() {
+        let var = language_chat_var(&lang);
+        match env::var(&var) {
+            Ok(value) if !value.trim().is_empty() => {}
+            _ => return Err(anyhow!("{} environment variable is not set (required by TARGET_LANGUAGES)", var)),
+        }
+    }
+
+    Ok(())
+}
+
+async fn init_telegram() -> Result<TelegramContext> {
+    // NOTE: API hash is required by the sign-in flow in the current grammers API.
+    // Source evidence:
+    // - https://github.com/Lonami/grammers/blob/master/grammers-client/src/client/auth.rs
+    let api_id: i32 = env::var("TG_API_ID")
+        .context("TG_API_ID is not set")?
+        .trim()
+        .parse()
+        .context("TG_API_ID must be an integer")?;
+    let api_hash = env::var("TG_API_HASH").context("TG_API_HASH is not set")?;
+
+    // Ensure data/ exists (also used for telegram.session)
+    init_data_dir()?;
+
+    // Persistent session storage (JSON file, path name as requested)
+    let session = Arc::new(
+        FileSession::load_or_create(TG_SESSION_PATH)
+            .context(format!("Failed to open telegram session at {}", TG_SESSION_PATH))?,
+    );
+
+    // Sender pool drives network I/O.
+    let pool = SenderPool::new(Arc::clone(&session), api_id);
+    let client = TgClient::new(&pool);
+    let grammers_mtsender::SenderPool { runner, updates, .. } = pool;
+
+    // We don't consume updates in this service. Dropping the receiver makes the sender side
+    // stop delivering them (send() will fail), avoiding unbounded growth.
+    drop(updates);
+
+    tokio::spawn(async move {
+        runner.run().await;
+    });
+
+    ensure_telegram_authorized(&client, &api_hash).await?;
+
+    // Force a final best-effort save after authorization.
+    session.save_best_effort();
+
+    let target_chat = resolve_target_chat(&client, "TG_CHAT_ID").await?;
+    let topics = read_topic_routing_from_env()?;
+    let daily_header = env::var("PUBLISHER_DAILY_HEADER")
+        .ok()
+        .filter(|template| !template.trim().is_empty())
+        .map(|template| DailyHeader {
+            template,
+            date_format: env::var("PUBLISHER_DAILY_HEADER_DATE_FORMAT")
+                .ok()
+                .filter(|format| !format.trim().is_empty())
+                .unwrap_or_else(|| "%B %-d".to_string()),
+        });
+
+    let pinning = match env::var("PIN_KEYWORDS") {
+        Ok(raw) if !raw.trim().is_empty() => Some(PinRule {
+            keywords: raw.split(',').map(normalize_title).filter(|keyword| !keyword.is_empty()).collect(),
+            duration_secs: match env::var("PIN_DURATION_SECS") {
+                Ok(value) if !value.trim().is_empty() => value
+                    .trim()
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| anyhow!("PIN_DURATION_SECS must be a positive integer, got '{}'", value.trim()))?,
+                _ => DEFAULT_PIN_DURATION_SECS,
+            },
+        }),
+        _ => None,
+    };
+
+    let mut language_chats = HashMap::new();
+    for lang in extra_languages() {
+        let chat = resolve_target_chat(&client, &language_chat_var(&lang)).await?;
+        language_chats.insert(lang, chat);
+    }
+    let mut category_chats = HashMap::new();
+    for category in categories() {
+        let var = category_chat_var(&category);
+        if env::var(&var).is_ok_and(|value| !value.trim().is_empty()) {
+            let chat = resolve_target_chat(&client, &var).await?;
+            category_chats.insert(category, chat);
+        }
+    }
+
+    let moderation = read_moderation_from_env()?;
+    let moderation_chat = match env::var("TG_MODERATION_CHAT_ID") {
+        Ok(value) if !value.trim().is_empty() => Some(resolve_target_chat(&client, "TG_MODERATION_CHAT_ID").await?),
+        _ => None,
+    };
+    let telegraph = match env::var("TELEGRAPH_ACCESS_TOKEN") {
+        Ok(token) if !token.trim().is_empty() => Some(Telegraph {
+            access_token: token.trim().to_string(),
+            author_name: env::var("TELEGRAPH_AUTHOR_NAME")
+                .ok()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty()),
+            always: match env::var("TELEGRAPH_ALWAYS") {
+                Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" => true,
+                    "" | "0" | "false" | "no" | "off" => false,
+                    _ => return Err(anyhow!("TELEGRAPH_ALWAYS must be true or false, got '{}'", value.trim())),
+                },
+                Err(_) => false,
+            },
+        }),
+        _ => None,
+    };
+    let approval = match env::var("APPROVAL_BOT_TOKEN") {
+        Ok(token) if !token.trim().is_empty() => Some(Approval {
+            bot_token: token.trim().to_string(),
+            chat_id: env::var("APPROVAL_CHAT_ID")
+                .context("APPROVAL_CHAT_ID is not set (required by APPROVAL_BOT_TOKEN)")?
+                .trim()
+                .parse()
+                .context("APPROVAL_CHAT_ID must be a Bot API chat id, e.g. -1001234567890")?,
+        }),
+        _ => None,
+    };
+
+    let schedule = read_schedule_from_env()?;
+    let mirrors = read_mirror_targets_from_env()?;
+    let templates = read_post_templates_from_env()?;
+    let shortener = read_shortener_from_env()?;
+    let previews = read_preview_options_from_env()?;
+    let footer_ratio = read_footer_ratio_from_env()?;
+
+    Ok(TelegramContext {
+        client,
+        target_chat,
+        language_chats,
+        category_chats,
+        topics,
+        daily_header,
+        pinning,
+        moderation,
+        moderation_chat,
+        telegraph,
+        approval,
+        schedule,
+        mirrors,
+        templates,
+        footer_ratio,
+        shortener,
+        previews,
+        session,
+    })
+}
+
+async fn ensure_telegram_authorized(client: &TgClient, api_hash: &str) -> Result<()> {
+    if client.is_authorized().await.context("Telegram authorization check failed")? {
+        return Ok(());
+    }
+
+    info!("Telegram session is not authorized yet; starting first-run login flow");
+
+    let phone = match env::var("TG_PHONE") {
+        Ok(p) if !p.trim().is_empty() => p,
+        _ => prompt_line("Enter phone number (international format, e.g. +14155550132): ")?,
+    };
+
+    let token = client
+        .request_login_code(phone.trim(), api_hash)
+        .await
+        .context("Failed to request Telegram login code")?;
+
+    let code = prompt_line("Enter the login code you received: ")?;
+
+    match client.sign_in(&token, code.trim()).await {
+        Ok(user) => {
+            if let Some(first_name) = user.first_name() {
+                info!("Telegram authorized as {}", first_name);
+            } else {
+                info!("Telegram authorized");
+            }
+            Ok(())
+        }
+        Err(SignInError::PasswordRequired(password_token)) => {
+            info!("Telegram 2FA password required");
+            let password = prompt_line("Enter 2FA password: ")?;
+            let user = client
+                .check_password(password_token, password.trim().as_bytes())
+                .await
+                .context("Failed to sign in with 2FA password")?;
+            if let Some(first_name) = user.first_name() {
+                info!("Telegram authorized as {}", first_name);
+            } else {
+                info!("Telegram authorized");
+            }
+            Ok(())
+        }
+        Err(SignInError::SignUpRequired { .. }) => Err(anyhow!(
+            "Telegram sign-up required. Please log in with an official Telegram client first, then rerun publisher."
+        )),
+        Err(e) => Err(anyhow!("Telegram sign-in failed: {}", e)),
+    }
+}
+
+async fn resolve_target_chat(client: &TgClient, var: &str) -> Result<PeerRef> {
+    let raw = env::var(var).context(format!("{} is not set", var))?;
+    let s = raw.trim();
+    if s.is_empty() {
+        return Err(anyhow!("{} is empty", var));
+    }
+
+    // If it looks like a username (recommended), resolve it once.
+    let looks_like_username = s.starts_with('@') || s.chars().any(|c| c.is_ascii_alphabetic());
+    if looks_like_username {
+        let username = s.trim_start_matches('@');
+        let peer = client
+            .resolve_username(username)
+            .await
+            .context(format!("Failed to resolve {} as username", var))?
+            .ok_or_else(|| anyhow!("Chat @{} not found ({})", username, var))?;
+
+        return Ok((&peer).into());
+    }
+
+    // Otherwise, attempt to find by numeric ID in dialogs.
+    let wanted_id: i64 = if let Some(rest) = s.strip_prefix("-100") {
+        rest.parse().context(format!("{} '-100...' is not numeric", var))?
+    } else if let Some(rest) = s.strip_prefix('-') {
+        rest.parse().context(format!("{} '-...' is not numeric", var))?
+    } else {
+        s.parse().context(format!("{} is not numeric", var))?
+    };
+
+    let mut dialogs = client.iter_dialogs();
+    while let Some(dialog) = dialogs
+        .next()
+        .await
+        .context("Failed while iterating Telegram dialogs")?
+    {
+        let peer = dialog.peer();
+        if peer.id().bare_id() == wanted_id {
+            return Ok(peer.into());
+        }
+    }
+
+    Err(anyhow!(
+        "{}={} was not found in your dialogs. Use a public username (e.g. @channel) in {}.",
+        var, wanted_id, var
+    ))
+}
+
+/// Reads forum topic routing for TG_CHAT_ID.
+///
+/// TG_TOPIC_RULES holds one rule per line, `<topic_id> <tag>[,<tag>...]`; the first rule with a tag
+/// found among the item's tags wins. TG_TOPIC_DEFAULT is the topic for items matching no rule
+/// (without it they go to the general topic).
+fn read_ordering_policy_from_env() -> Result<Option<OrderingPolicy>> {
+    match env::var("PUBLISHER_STRICT_ORDER") {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => {}
+            "" | "0" | "false" | "no" | "off" => return Ok(None),
+            _ => return Err(anyhow!("PUBLISHER_STRICT_ORDER must be true or false, got '{}'", value.trim())),
+        },
+        Err(_) => return Ok(None),
+    }
+    let timeout_secs = match env::var("PUBLISHER_ORDER_TIMEOUT_SECS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|secs| *secs >= 0)
+            .ok_or_else(|| anyhow!("PUBLISHER_ORDER_TIMEOUT_SECS must be a non-negative integer, got '{}'", value.trim()))?,
+        _ => DEFAULT_ORDER_TIMEOUT_SECS,
+    };
+
+    // Items waiting for one of the stages before the publisher, or for their retry
+    let stages: Vec<(String, Status)> = match read_pipeline_config()? {
+        Some(stages) => stages
+            .into_iter()
+            .filter(|(name, stage)| name != "publisher" && stage.input_status.as_str() != "-")
+            .map(|(name, stage)| (name, stage.input_status))
+            .collect(),
+        None => [
+            ("downloader", Status::New),
+            ("scraper", Status::Downloaded),
+            ("translator", Status::Scraper),
+            ("rewriter", Status::Translated),
+            ("illustrator", Status::Rewriter),
+        ]
+        .into_iter()
+        .map(|(name, status)| (name.to_string(), status))
+        .collect(),
+    };
+    // A post waiting for its own retry holds back the newer ones as well
+    let in_flight = stages
+        .into_iter()
+        .flat_map(|(name, status)| [status, Status::from(format!("{}_retry", name).as_str())])
+        .chain([Status::PublisherRetry])
+        .collect();
+
+    Ok(Some(OrderingPolicy { in_flight, timeout_secs }))
+}
+
+fn read_schedule_from_env() -> Result<Schedule> {
+    let timezone = match env::var("PUBLISHER_TIMEZONE") {
+        Ok(value) if !value.trim().is_empty() => Some(
+            value
+                .trim()
+                .parse::<Tz>()
+                .map_err(|e| anyhow!("PUBLISHER_TIMEZONE must be an IANA time zone like Europe/Belgrade: {}", e))?,
+        ),
+        _ => None,
+    };
+    Ok(Schedule {
+        timezone,
+        hours: read_time_range_from_env("PUBLISHER_HOURS")?,
+        quiet_hours: read_time_range_from_env("PUBLISHER_QUIET_HOURS")?,
+    })
+}
+
+/// Parses `HH:MM-HH:MM` into minutes since midnight.
+fn read_time_range_from_env(var: &str) -> Result<Option<(u32, u32)>> {
+    let value = match env::var(var) {
+        Ok(value) if !value.trim().is_empty() => value.trim().to_string(),
+        _ => return Ok(None),
+    };
+    let minutes = |time: &str| {
+        let (hour, minute) = time.trim().split_once(':')?;
+        let (hour, minute) = (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?);
+        (hour <= 24 && minute < 60 && hour * 60 + minute <= 24 * 60).then_some(hour * 60 + minute)
+    };
+    value
+        .split_once('-')
+        .and_then(|(start, end)| Some((minutes(start)?, minutes(end)?)))
+        .map(Some)
+        .ok_or_else(|| anyhow!("{} must look like 08:00-22:00, got '{}'", var, value))
+}
+
+fn read_topic_routing_from_env() -> Result<TopicRouting> {
+    let mut routing = TopicRouting::default();
+
+    if let Ok(raw) = env::var("TG_TOPIC_RULES") {
+        for line in raw.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (topic, tags) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("TG_TOPIC_RULES: expected '<topic_id> <tags>', got '{}'", line))?;
+            let topic_id = topic
+                .parse::<i32>()
+                .map_err(|e| anyhow!("TG_TOPIC_RULES: invalid topic id '{}': {}", topic, e))?;
+            let tags = tags
+                .split(',')
+                .map(normalize_title)
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            routing.rules.push(TopicRule { topic_id, tags });
+        }
+    }
+
+    if let Ok(raw) = env::var("TG_TOPIC_CATEGORIES") {
+        for entry in raw.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (category, topic) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("TG_TOPIC_CATEGORIES: expected '<category>=<topic_id>', got '{}'", entry))?;
+            let topic_id = topic
+                .trim()
+                .parse::<i32>()
+                .map_err(|e| anyhow!("TG_TOPIC_CATEGORIES: invalid topic id '{}': {}", topic.trim(), e))?;
+            routing.category_topics.insert(category.trim().to_lowercase(), topic_id);
+        }
+    }
+
+    if let Ok(raw) = env::var("TG_TOPIC_DEFAULT") {
+        if !raw.trim().is_empty() {
+            routing.default_topic = Some(
+                raw.trim()
+                    .parse::<i32>()
+                    .map_err(|e| anyhow!("TG_TOPIC_DEFAULT must be a topic id: {}", e))?,
+            );
+        }
+    }
+
+    Ok(routing)
+}
+
+/// Topic for an item: the topic of its category, otherwise the first rule matching its tags,
+/// which are the words of the title and of the URL path.
+fn route_to_topic(routing: &TopicRouting, item: &NewsItem) -> Option<i32> {
+    if let Some(topic_id) = item.category.as_ref().and_then(|category| routing.category_topics.get(category)) {
+        return Some(*topic_id);
+    }
+    let path = item
+        .url
+        .split_once("://")
+        .map_or(item.url.as_str(), |(_, rest)| rest.split_once('/').map_or("", |(_, path)| path));
+    let item_tags = format!(" {} {} ", normalize_title(&item.title), normalize_title(path));
+
+    routing
+        .rules
+        .iter()
+        .find(|rule| rule.tags.iter().any(|tag| item_tags.contains(&format!(" {} ", tag))))
+        .map(|rule| rule.topic_id)
+        .or(routing.default_topic)
+}
+
+/// Extra target languages: every TARGET_LANGUAGES entry after the first one, which is published
+/// to TG_CHAT_ID as before.
+fn extra_languages() -> Vec<String> {
+    env::var("TARGET_LANGUAGES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|code| code.trim().to_ascii_lowercase())
+        .filter(|code| !code.is_empty())
+        .skip(1)
+        .collect()
+}
+
+fn language_chat_var(lang: &str) -> String {
+    format!("TG_CHAT_ID_{}", lang.to_ascii_uppercase().replace('-', "_"))
+}
+
+/// Categories the rewriter's classifier picks from (CATEGORIES, as set for the rewriter).
+fn categories() -> Vec<String> {
+    env::var("CATEGORIES")
+        .unwrap_or_else(|_| DEFAULT_CATEGORIES.to_string())
+        .split(',')
+        .map(|category| category.trim().to_lowercase())
+        .filter(|category| !category.is_empty())
+        .collect()
+}
+
+fn category_chat_var(category: &str) -> String {
+    format!("TG_CHAT_ID_CATEGORY_{}", category.to_ascii_uppercase().replace(['-', ' '], "_"))
+}
+
+/// Mirror targets of every channel. Per-channel settings take a `_<LANG>` suffix for the extra
+/// TARGET_LANGUAGES; Bluesky mirrors the main channel only.
+fn read_mirror_targets_from_env() -> Result<HashMap<String, Vec<MirrorTarget>>> {
+    let read = |var: &str| {
+        env::var(var)
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    let mut mirrors = HashMap::new();
+    for lang in std::iter::once(String::new()).chain(extra_languages()) {
+        let suffix = match lang.as_str() {
+            "" => String::new(),
+            lang => format!("_{}", lang.to_ascii_uppercase().replace('-', "_")),
+        };
+        let mut targets = Vec::new();
+
+        if let Some(url) = read(&format!("DISCORD_WEBHOOK_URL{}", suffix)) {
+            targets.push(MirrorTarget::Discord {
+                webhook_url: url.trim_end_matches('/').to_string(),
+            });
+        }
+        if lang.is_empty() {
+            if let Some(handle) = read("BLUESKY_HANDLE") {
+                targets.push(MirrorTarget::Bluesky(Bluesky {
+                    pds_url: read("BLUESKY_PDS_URL")
+                        .map(|url| url.trim_end_matches('/').to_string())
+                        .unwrap_or_else(|| DEFAULT_BLUESKY_PDS_URL.to_string()),
+                    handle: handle.trim_start_matches('@').to_string(),
+                    app_password: read("BLUESKY_APP_PASSWORD")
+                        .ok_or_else(|| anyhow!("BLUESKY_APP_PASSWORD is not set (required by BLUESKY_HANDLE)"))?,
+                }));
+            }
+        }
+        if let Some(url) = read(&format!("SLACK_WEBHOOK_URL{}", suffix)) {
+            targets.push(MirrorTarget::SlackWebhook { webhook_url: url });
+        }
+        if let Some(channel) = read(&format!("SLACK_CHANNEL{}", suffix)) {
+            targets.push(MirrorTarget::SlackBot {
+                bot_token: read("SLACK_BOT_TOKEN")
+                    .ok_or_else(|| anyhow!("SLACK_BOT_TOKEN is not set (required by SLACK_CHANNEL{})", suffix))?,
+                channel,
+            });
+        }
+        if let Some(room_id) = read(&format!("MATRIX_ROOM_ID{}", suffix)) {
+            targets.push(MirrorTarget::Matrix(Matrix {
+                homeserver_url: read("MATRIX_HOMESERVER_URL")
+                    .map(|url| url.trim_end_matches('/').to_string())
+                    .ok_or_else(|| anyhow!("MATRIX_HOMESERVER_URL is not set (required by MATRIX_ROOM_ID{})", suffix))?,
+                access_token: read("MATRIX_ACCESS_TOKEN")
+                    .ok_or_else(|| anyhow!("MATRIX_ACCESS_TOKEN is not set (required by MATRIX_ROOM_ID{})", suffix))?,
+                room_id,
+            }));
+        }
+
+        if let Some(relays) = read(&format!("NOSTR_RELAYS{}", suffix)) {
+            let secret_key = read("NOSTR_SECRET_KEY")
+                .ok_or_else(|| anyhow!("NOSTR_SECRET_KEY is not set (required by NOSTR_RELAYS{})", suffix))?;
+            targets.push(MirrorTarget::Nostr(Nostr {
+                keypair: parse_nostr_secret_key(&secret_key)?,
+                relays: relays
+                    .split(',')
+                    .map(|relay| relay.trim().to_string())
+                    .filter(|relay| !relay.is_empty())
+                    .collect(),
+            }));
+        }
+
+        if !targets.is_empty() {
+            mirrors.insert(lang, targets);
+        }
+    }
+    Ok(mirrors)
+}
+
+fn read_footer_ratio_from_env() -> Result<f64> {
+    match env::var("POST_FOOTER_RATIO") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|ratio| (0.0..=1.0).contains(ratio))
+            .ok_or_else(|| anyhow!("POST_FOOTER_RATIO must be a number from 0 to 1, got '{}'", value.trim())),
+        _ => Ok(1.0),
+    }
+}
+
+/// Cover and link preview settings of every channel; a `_<LANG>` setting overrides the main one
+/// for that language's channel.
+fn read_preview_options_from_env() -> Result<HashMap<String, PreviewOptions>> {
+    let read_bool = |var: &str, default: bool| -> Result<bool> {
+        match env::var(var) {
+            Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => Ok(true),
+                "0" | "false" | "no" | "off" => Ok(false),
+                "" => Ok(default),
+                _ => Err(anyhow!("{} must be true or false, got '{}'", var, value.trim())),
+            },
+            Err(_) => Ok(default),
+        }
+    };
+
+    let mut previews = HashMap::new();
+    let mut main: Option<PreviewOptions> = None;
+    for lang in std::iter::once(String::new()).chain(extra_languages()) {
+        let suffix = match lang.as_str() {
+            "" => String::new(),
+            lang => format!("_{}", lang.to_ascii_uppercase().replace('-', "_")),
+        };
+        let cover_var = format!("POST_COVER{}", suffix);
+        let cover = match env::var(&cover_var).unwrap_or_default().trim().to_ascii_lowercase().as_str() {
+            "" => main.map_or(Cover::Photo, |main| main.cover),
+            "photo" => Cover::Photo,
+            "preview" => Cover::Preview,
+            "none" => Cover::None,
+            other => return Err(anyhow!("{} must be photo, preview or none, got '{}'", cover_var, other)),
+        };
+        let options = PreviewOptions {
+            cover,
+            // A preview cover needs the preview; otherwise text posts go without one as before
+            link_preview: read_bool(
+                &format!("LINK_PREVIEW{}", suffix),
+                main.map_or(cover == Cover::Preview, |main| main.link_preview),
+            )?,
+            preview_above_text: read_bool(
+                &format!("LINK_PREVIEW_ABOVE_TEXT{}", suffix),
+                main.is_some_and(|main| main.preview_above_text),
+            )?,
+            caption_above_photo: read_bool(
+                &format!("CAPTION_ABOVE_PHOTO{}", suffix),
+                main.is_some_and(|main| main.caption_above_photo),
+            )?,
+        };
+        if lang.is_empty() {
+            main = Some(options);
+        }
+        previews.insert(lang, options);
+    }
+    Ok(previews)
+}
+
+/// Reads SHORTENER (`shlink`, `yourls` or `internal`) and the server it needs.
+fn read_shortener_from_env() -> Result<Option<Shortener>> {
+    let kind = env::var("SHORTENER").unwrap_or_default().trim().to_ascii_lowercase();
+    if kind.is_empty() {
+        return Ok(None);
+    }
+    let base_url = env::var("SHORTENER_URL")
+        .ok()
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+        .context("SHORTENER_URL is not set (required by SHORTENER)")?;
+    let api_key = || {
+        env::var("SHORTENER_API_KEY")
+            .ok()
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .context(format!("SHORTENER_API_KEY is not set (required by SHORTENER={})", kind))
+    };
+    match kind.as_str() {
+        "shlink" => Ok(Some(Shortener::Shlink { base_url, api_key: api_key()? })),
+        "yourls" => Ok(Some(Shortener::Yourls { base_url, signature: api_key()? })),
+        "internal" => Ok(Some(Shortener::Internal { base_url })),
+        _ => Err(anyhow!("SHORTENER must be shlink, yourls or internal, got '{}'", kind)),
+    }
+}
+
+/// Reads POST_TEMPLATES: comma-separated `key=template_file` pairs, e.g.
+/// `telegram=/etc/robo-news/post.html,telegram/press=/etc/robo-news/press.html,*/blog=/etc/robo-news/blog.txt`.
+fn read_post_templates_from_env() -> Result<PostTemplates> {
+    let mut templates = HashMap::new();
+    for entry in env::var("POST_TEMPLATES").unwrap_or_default().split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (key, path) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow!("POST_TEMPLATES entry '{}' must be target[/source]=template_file", entry))?;
+        let template = fs::read_to_string(path.trim())
+            .context(format!("Failed to read template file {} for {}", path.trim(), key.trim()))?;
+        if template.trim().is_empty() {
+            return Err(anyhow!("Template file {} for {} is empty", path.trim(), key.trim()));
+        }
+        templates.insert(key.trim().to_ascii_lowercase(), template.trim().to_string());
+    }
+    let hashtags = env::var("POST_HASHTAGS")
+        .unwrap_or_default()
+        .split(',')
+        .map(normalize_title)
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    let mut category_emoji = HashMap::new();
+    for entry in env::var("POST_CATEGORY_EMOJI").unwrap_or_default().split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (category, emoji) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow!("POST_CATEGORY_EMOJI entry '{}' must be category=emoji", entry))?;
+        category_emoji.insert(category.trim().to_lowercase(), emoji.trim().to_string());
+    }
+    Ok(PostTemplates { templates, hashtags, category_emoji })
+}
+
+/// NOSTR_SECRET_KEY as 64 hex characters or a NIP-19 `nsec1...` string.
+fn parse_nostr_secret_key(value: &str) -> Result<secp256k1::Keypair> {
+    let bytes = if value.starts_with("nsec1") {
+        let (hrp, bytes) = decode_bech32(value).context("NOSTR_SECRET_KEY is not a valid nsec key")?;
+        if hrp != "nsec" {
+            return Err(anyhow!("NOSTR_SECRET_KEY must be an nsec key, got '{}1...'", hrp));
+        }
+        bytes
+    } else {
+        hex::decode(value).context("NOSTR_SECRET_KEY must be 64 hex characters or an nsec1... key")?
+    };
+    secp256k1::Keypair::from_seckey_slice(&secp256k1::Secp256k1::new(), &bytes)
+        .map_err(|e| anyhow!("NOSTR_SECRET_KEY is not a valid secret key: {}", e))
+}
+
+/// Decodes a bech32 string (BIP-173, as used by NIP-19) into its human-readable part and data.
+fn decode_bech32(value: &str) -> Result<(String, Vec<u8>)> {
+    const CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    let value = value.to_ascii_lowercase();
+    let (hrp, data) = value.rsplit_once('1').ok_or_else(|| anyhow!("missing separator"))?;
+    let values = data
+        .chars()
+        .map(|c| CHARSET.find(c).map(|value| value as u8).ok_or_else(|| anyhow!("invalid character '{}'", c)))
+        .collect::<Result<Vec<u8>>>()?;
+    if values.len() < 6 {
+        return Err(anyhow!("too short"));
+    }
+
+    let mut checked: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    checked.push(0);
+    checked.extend(hrp.bytes().map(|b| b & 31));
+    checked.extend(&values);
+    if bech32_polymod(&checked) != 1 {
+        return Err(anyhow!("invalid checksum"));
+    }
+
+    // 5-bit groups, without the 6 checksum groups, to bytes
+    let mut bytes = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for value in &values[..values.len() - 6] {
+        acc = ((acc << 5) | u32::from(*value)) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+    Ok((hrp.to_string(), bytes))
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum: u32 = 1;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ u32::from(*value);
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn prompt_line(prompt: &str) -> Result<String> {
+    // NOTE: Console prompts are used only for the first-run login.
+    print!("{}", prompt);
+    std::io::stdout().flush().ok();
+
+    let mut line = String::new();
+    std::io::stdin()
+        .read_line(&mut line)
+        .context("Failed to read from stdin")?;
+    Ok(line)
+}
+
+/// Command-line options: `--once` processes the current queue and exits (for cron- or CI-driven runs).
+pub struct RunOptions {
+    pub once: bool,
+}
+
+pub fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { once: false };
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--once" => options.once = true,
+            other => return Err(anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn init_db() -> Result<Connection> {
+    let conn = open_db()?;
+    
+    // One row per day that got a pinned header, so restarts don't post it twice
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS daily_headers (
+            day TEXT PRIMARY KEY,
+            message_id INTEGER NOT NULL
+        )",
+        [],
+    )
+    .context("Failed to create daily_headers table")?;
+
+    // Breaking news posts that are pinned (PIN_KEYWORDS); chat is the category with its own
+    // channel, or '' for TG_CHAT_ID
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pinned_posts (
+            news_id TEXT PRIMARY KEY,
+            chat TEXT NOT NULL,
+            message_id INTEGER NOT NULL,
+            pinned_at INTEGER NOT NULL
+        )",
+        [],
+    )
+    .context("Failed to create pinned_posts table")?;
+
+    // Tamper-evident log of published items: every record hash covers the previous one
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS publish_chain (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            news_id TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            status TEXT NOT NULL,
+            published_at TEXT NOT NULL,
+            content_hash TEXT NOT NULL,
+            prev_hash TEXT NOT NULL,
+            record_hash TEXT NOT NULL
+        )",
+        [],
+    )
+    .context("Failed to create publish_chain table")?;
+
+    // Headline/lead variant (a or b) each post was published with, to compare their performance
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS publish_variants (
+            news_id TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            variant TEXT NOT NULL,
+            published_at TEXT NOT NULL,
+            PRIMARY KEY (news_id, lang)
+        )",
+        [],
+    )
+    .context("Failed to create publish_variants table")?;
+
+    // Where each item was posted, so that a re-processed item edits its post instead of posting again
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS published_messages (
+            news_id TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            message_id INTEGER NOT NULL,
+            telegraph_path TEXT,
+            published_at TEXT NOT NULL,
+            PRIMARY KEY (news_id, lang)
+        )",
+        [],
+    )
+    .context("Failed to create published_messages table")?;
+
+    // View and forward counts of published posts, one row per collection (ENGAGEMENT_INTERVAL_SECS)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS post_engagement (
+            news_id TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            message_id INTEGER NOT NULL,
+            views INTEGER,
+            forwards INTEGER,
+            collected_at TEXT NOT NULL
+        )",
+        [],
+    )
+    .context("Failed to create post_engagement table")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS post_engagement_news ON post_engagement (news_id, lang, collected_at)",
+        [],
+    )
+    .context("Failed to create post_engagement index")?;
+
+    // Messages items were mirrored to on other services; message_id is NULL where it can't be edited
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS mirrored_posts (
+            news_id TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            target TEXT NOT NULL,
+            message_id TEXT,
+            posted_at TEXT NOT NULL,
+            PRIMARY KEY (news_id, lang, target)
+        )",
+        [],
+    )
+    .context("Failed to create mirrored_posts table")?;
+
+    // Short links of source URLs (SHORTENER); clicks are counted by `api` for internal links
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS short_links (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            url TEXT NOT NULL UNIQUE,
+            short_url TEXT,
+            code TEXT UNIQUE,
+            news_id TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            clicks INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .context("Failed to create short_links table")?;
+
+    // Rotating footer blocks (promo text, links to other channels), edited with `ctl footer` while
+    // the publisher runs; lang '' applies to every channel
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS post_footers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            text TEXT NOT NULL,
+            lang TEXT NOT NULL DEFAULT '',
+            active INTEGER NOT NULL DEFAULT 1
+        )",
+        [],
+    )
+    .context("Failed to create post_footers table")?;
+    // The footer each post got (footer_id NULL: none), so an updated post keeps it
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS post_footer_uses (
+            news_id TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            footer_id INTEGER,
+            PRIMARY KEY (news_id, lang)
+        )",
+        [],
+    )
+    .context("Failed to create post_footer_uses table")?;
+
+    // When items first waited for an older item (PUBLISHER_STRICT_ORDER)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS publish_waits (
+            news_id TEXT PRIMARY KEY,
+            since INTEGER NOT NULL
+        )",
+        [],
+    )
+    .context("Failed to create publish_waits table")?;
+
+    // Drafts sent to the approval chat; the id goes into the buttons' callback data
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS approval_drafts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            news_id TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            message_id INTEGER NOT NULL,
+            decision TEXT,
+            edited_text TEXT,
+            sent_at TEXT NOT NULL,
+            UNIQUE (news_id, lang)
+        )",
+        [],
+    )
+    .context("Failed to create approval_drafts table")?;
+    // getUpdates offset of the approval bot, so restarts don't replay button presses
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS approval_updates (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            next_offset INTEGER NOT NULL
+        )",
+        [],
+    )
+    .context("Failed to create approval_updates table")?;
+
+    // Items stopped by moderation; `approved` lets a released item through without a second check
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS moderation_holds (
+            news_id TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            held_at TEXT NOT NULL,
+            approved INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (news_id, lang)
+        )",
+        [],
+    )
+    .context("Failed to create moderation_holds table")?;
+    
+    Ok(conn)
+}
+
+fn init_data_dir() -> Result<()> {
+    if !Path::new(DATA_DIR).exists() {
+        fs::create_dir_all(DATA_DIR).context("Failed to create data directory")?;
+    }
+    Ok(())
+}
+
+async fn run_publisher(
+    conn: &Connection,
+    tg: &TelegramContext,
+    stage: &PipelineStage,
+    illustrated: bool,
+    translation_status: &Status,
+    policy: &PublishPolicy,
+) -> Result<()> {
+    info!("Checking for illustrator news items to publish");
+
+    // Decisions taken in the approval chat since the last cycle; approved items are picked up below
+    if let Some(approval) = &tg.approval {
+        if let Err(e) = poll_approvals(conn, approval, &stage.input_status, translation_status).await {
+            warn!("Failed to read decisions from the approval chat: {}", e);
+        }
+    }
+    
+    // Pins expire even in cycles without anything to publish
+    if let Some(rule) = &tg.pinning {
+        if let Err(e) = unpin_expired_posts(conn, tg, rule).await {
+            warn!("Failed to unpin expired breaking news posts: {}", e);
+        }
+    }
+
+    // Fetch news items with the stage input status ("illustrator" by default)
+    let mut news_items = fetch_illustrator_items(conn, &stage.input_status)?;
+    if !tg.language_chats.is_empty() {
+        news_items.extend(fetch_translation_items(conn, translation_status, &stage.output_status)?);
+    }
+    
+    if news_items.is_empty() {
+        info!("No illustrator items to publish");
+        return Ok(());
+    }
+    
+    info!("Found {} illustrator items to publish", news_items.len());
+
+    // Outside the posting hours items only queue up
+    if !tg.schedule.is_open() {
+        for item in news_items.iter().filter(|item| item.status != Status::ReadyToPublish) {
+            update_status(conn, &item.id, item.lang.as_deref(), &item.status, &Status::ReadyToPublish)?;
+        }
+        info!(
+            "Outside the posting hours, {} items wait in {}",
+            news_items.len(),
+            Status::ReadyToPublish
+        );
+        return Ok(());
+    }
+
+    if let Some(header) = &tg.daily_header {
+        if let Err(e) = ensure_daily_header(conn, tg, header).await {
+            warn!("Failed to post daily header: {}", e);
+        }
+    }
+    
+    // Process and publish each news item
+    for item in news_items {
+        if shutdown_requested() {
+            break;
+        }
+        let span = item_span("publisher", &item.id);
+        publish_item(conn, tg, stage, illustrated, policy, item).instrument(span).await?;
+    }
+    
+    info!("Publish process completed");
+    Ok(())
+}
+
+/// Publishes one item of the cycle, or leaves it for a later cycle when pacing, ordering,
+/// moderation or approval hold it back.
+async fn publish_item(
+    conn: &Connection,
+    tg: &TelegramContext,
+    stage: &PipelineStage,
+    illustrated: bool,
+    policy: &PublishPolicy,
+    mut item: NewsItem,
+) -> Result<()> {
+    info!("Processing item: {} (language: {})", item.id, item.lang.as_deref().unwrap_or("default"));
+    let lang = item.lang.as_deref();
+
+    // An item that was published before and went through the pipeline again edits its post
+    let previous_post = find_published_post(conn, &item)?;
+
+    // Pacing applies to new posts in the main channel; translations follow their main item
+    if lang.is_none() && previous_post.is_none() {
+        if let Some(max_posts) = policy.max_posts_per_hour {
+            let posted = posts_in_last_hour(conn, max_posts)?;
+            if posted >= max_posts {
+                info!(
+                    "{} posts in the last hour (PUBLISHER_MAX_POSTS_PER_HOUR={}), item {} waits",
+                    posted, max_posts, item.id
+                );
+                return Ok(());
+            }
+        }
+        if let Some(ordering) = &policy.ordering {
+            if let Some(earlier_id) = wait_for_earlier_item(conn, &item, ordering)? {
+                info!(
+                    "Item {} waits for the older item {} still in the pipeline",
+                    item.id, earlier_id
+                );
+                return Ok(());
+            }
+        }
+    }
+
+    // Another replica may have taken the item since it was fetched
+    if !claim_item(conn, &item.id, lang, &item.status)? {
+        return Ok(());
+    }
+
+    // Last line of defense against upstream dedup misses: skip titles we have just published.
+    // Translations only follow an already published main item, so they are not checked again.
+    let duplicate = match lang {
+        Some(_) => None,
+        None if previous_post.is_some() => None,
+        None => find_recent_duplicate(conn, &item, &stage.output_status, policy.dedup_window)?,
+    };
+    if let Some(duplicate_id) = duplicate {
+        warn!(
+            "Item {} duplicates recently published item {} (title: '{}'), skipping",
+            item.id, duplicate_id, item.title
+        );
+        update_status(conn, &item.id, lang, &item.status, &Status::PublishDuplicate)?;
+        return Ok(());
+    }
+    
+    // Process the HTML
+    let variant = choose_variant(conn, &item, stage, &policy.variant_strategy)?;
+    match moderate_item(conn, &tg.moderation, &item, stage, variant).await {
+        Ok(Some(reason)) => {
+            hold_for_moderation(conn, tg, &item, &reason).await?;
+            return Ok(());
+        }
+        Ok(None) => {}
+        Err(e) => {
+            warn!(
+                "Moderation check failed for item {}: {}. Trying again next cycle.",
+                item.id, e
+            );
+            return Ok(());
+        }
+    }
+    match choose_footer(conn, &item, tg.footer_ratio) {
+        Ok(footer) => item.footer = footer,
+        Err(e) => warn!("Failed to choose a footer for item {}: {}", item.id, e),
+    }
+    if let Some(shortener) = &tg.shortener {
+        match short_link(conn, shortener, &item).await {
+            Ok(short_url) => item.short_url = Some(short_url),
+            Err(e) => warn!(
+                "Failed to shorten the link of item {}: {}. Posting the full URL.",
+                item.id, e
+            ),
+        }
+    }
+    match process_html_file(&item, stage, variant) {
+        Ok(_) => {
+            // With an approval chat nothing goes out before an admin approved this very draft
+            let mut edited_text = None;
+            if let Some(approval) = &tg.approval {
+                match take_approval(conn, &item)? {
+                    Some(draft) => {
+                        if let Some(text) = draft.edited_text {
+                            let output_artifact = format!("{}_{}.html", stage.output_artifact, artifact_suffix(&item));
+                            write_artifact(&output_artifact, escape_html(&text)).context(format!("Failed to write {}", output_artifact))?;
+                            let text_artifact = format!("{}_{}.txt", stage.output_artifact, artifact_suffix(&item));
+                            write_artifact(&text_artifact, &text).context(format!("Failed to write {}", text_artifact))?;
+                            info!("Publishing item {} with the text edited in the approval chat", item.id);
+                            edited_text = Some(text);
+                        }
+                    }
+                    None => {
+                        if let Err(e) = preview_shortened_post(tg, &item, stage, illustrated) {
+                            warn!("Failed to shorten the draft of long item {}: {}. Sending the full text.", item.id, e);
+                        }
+                        if let Err(e) = send_for_approval(conn, approval, &tg.templates, &item, stage).await {
+                            let error_msg = format!("Failed to send the draft for approval: {}", e);
+                            fail_publish(conn, &item, &policy.retry, &error_msg)?;
+                        }
+                        return Ok(());
+                    }
+                }
+            }
+
+            // Measured after the approval, so an edited text is shortened if it grew too long
+            let previous_telegraph_path = previous_post.as_ref().and_then(|post| post.telegraph_path.as_deref());
+            let telegraph_path = match shorten_long_post(tg, &item, stage, variant, illustrated, previous_telegraph_path, edited_text.as_deref()).await {
+                Ok(path) => path.or_else(|| previous_telegraph_path.map(str::to_string)),
+                Err(e) => {
+                    warn!("Failed to shorten long item {}: {}. Posting the full text.", item.id, e);
+                    previous_telegraph_path.map(str::to_string)
+                }
+            };
+
+            if let Some(post) = &previous_post {
+                match edit_telegram_post(tg, &item, stage, post.message_id).await {
+                    Ok(plain) => {
+                        reset_attempts(conn, &item.id, lang)?;
+                        update_status(conn, &item.id, lang, &item.status, &Status::Updated)?;
+                        append_to_publish_chain(conn, &item, stage, &Status::Updated)?;
+                        record_variant(conn, &item, variant)?;
+                        record_published_post(conn, &item, post.message_id, telegraph_path.as_deref())?;
+                        mirror_post(conn, tg, &item, stage).await?;
+                        if plain {
+                            warn!(
+                                "Updated the post of news item {} (message {}) as plain text, formatting needs review",
+                                item.id, post.message_id
+                            );
+                        } else {
+                            info!("Updated the post of news item {} (message {})", item.id, post.message_id);
+                        }
+                    }
+                    Err(e) => {
+                        let error_msg = format!("Failed to edit the Telegram post: {}", e);
+                        fail_publish(conn, &item, &policy.retry, &error_msg)?;
+                    }
+                }
+                return Ok(());
+            }
+
+            // Send to Telegram
+            match send_to_telegram(tg, &item, stage, illustrated).await {
+                Ok(SentPost { plain: false, message_id }) => {
+                    reset_attempts(conn, &item.id, lang)?;
+                    // Update status to the stage output status ("published" by default)
+                    update_status(conn, &item.id, lang, &item.status, &stage.output_status)?;
+                    append_to_publish_chain(conn, &item, stage, &stage.output_status)?;
+                    record_variant(conn, &item, variant)?;
+                    if let Some(message_id) = message_id {
+                        record_published_post(conn, &item, message_id, telegraph_path.as_deref())?;
+                        pin_breaking_post(conn, tg, &item, message_id).await?;
+                    }
+                    mirror_post(conn, tg, &item, stage).await?;
+                    info!("Successfully published news item: {}", item.id);
+                }
+                Ok(SentPost { plain: true, message_id }) => {
+                    reset_attempts(conn, &item.id, lang)?;
+                    // Posted without formatting: flag the item for formatting review
+                    update_status(conn, &item.id, lang, &item.status, &Status::PublishedPlain)?;
+                    append_to_publish_chain(conn, &item, stage, &Status::PublishedPlain)?;
+                    record_variant(conn, &item, variant)?;
+                    if let Some(message_id) = message_id {
+                        record_published_post(conn, &item, message_id, telegraph_path.as_deref())?;
+                        pin_breaking_post(conn, tg, &item, message_id).await?;
+                    }
+                    mirror_post(conn, tg, &item, stage).await?;
+                    warn!("Published news item {} as plain text, formatting needs review", item.id);
+                }
+                Err(e) => {
+                    let error_msg = format!("Failed to send to Telegram: {}", e);
+                    fail_publish(conn, &item, &policy.retry, &error_msg)?;
+                }
+            }
+        }
+        Err(e) => {
+            let error_msg = format!("Failed to process HTML: {}", e);
+            error!("{}", error_msg);
+            record_failure(conn, &item.id, lang, &error_msg)?;
+            update_status(conn, &item.id, lang, &item.status, &Status::PublishError)?;
+        }
+    }
+    Ok(())
+}
+
+/// PUBLISH_DRY_RUN: renders every item the publisher would post to `data/publisher_dryrun_<id>.html`
+/// (the message) and `data/publisher_dryrun_<id>.json` (chat, topic, photos and preview settings)
+/// and sets it to `published_dryrun`. Telegram, Telegraph, mirrors and shorteners are not called.
+fn run_dry_run(
+    conn: &Connection,
+    stage: &PipelineStage,
+    illustrated: bool,
+    translation_status: &Status,
+    policy: &PublishPolicy,
+) -> Result<()> {
+    let templates = read_post_templates_from_env()?;
+    let previews = read_preview_options_from_env()?;
+    let topics = read_topic_routing_from_env()?;
+    let footer_ratio = read_footer_ratio_from_env()?;
+
+    let mut news_items = fetch_illustrator_items(conn, &stage.input_status)?;
+    if !extra_languages().is_empty() {
+        // Translations follow their main item, which a dry run leaves in published_dryrun
+        news_items.extend(fetch_translation_items(conn, translation_status, &Status::PublishedDryrun)?);
+    }
+    if news_items.is_empty() {
+        info!("No illustrator items to render");
+        return Ok(());
+    }
+
+    for mut item in news_items {
+        let _span = item_span("publisher", &item.id).entered();
+        let lang = item.lang.as_deref().unwrap_or("");
+        let variant = choose_variant(conn, &item, stage, &policy.variant_strategy)?;
+        match choose_footer(conn, &item, footer_ratio) {
+            Ok(footer) => item.footer = footer,
+            Err(e) => warn!("Failed to choose a footer for item {}: {}", item.id, e),
+        }
+        let rendered = process_html_file(&item, stage, variant).and_then(|_| compose_post(&item, stage, &templates));
+        let ComposedPost { content, plain_text, length } = match rendered {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                error!("Failed to render item {}: {}", item.id, e);
+                continue;
+            }
+        };
+
+        let preview = channel_preview(&previews, lang);
+        let image = format!("illustrator_{}.png", item.id);
+        let photos: Vec<String> = if preview.cover == Cover::Photo && artifact_exists(&image)? {
+            std::iter::once(image).chain(album_images(&item)?).collect()
+        } else {
+            if preview.cover == Cover::Photo && illustrated && !item.illustration_skipped {
+                warn!("Illustrator image not found for item {}, the post would fail", item.id);
+            }
+            Vec::new()
+        };
+        let chat = match &item.lang {
+            Some(lang) => language_chat_var(lang),
+            None => item
+                .category
+                .as_deref()
+                .map(category_chat_var)
+                .filter(|var| env::var(var).is_ok_and(|value| !value.trim().is_empty()))
+                .unwrap_or_else(|| "TG_CHAT_ID".to_string()),
+        };
+        let topic = match &item.lang {
+            None if chat == "TG_CHAT_ID" => route_to_topic(&topics, &item),
+            _ => None,
+        };
+        let limit = if photos.is_empty() { TG_MESSAGE_LIMIT } else { TG_CAPTION_LIMIT };
+        let payload = serde_json::json!({
+            "id": item.id,
+            "lang": item.lang,
+            "chat": chat,
+            "topic": topic,
+            "variant": variant,
+            "photos": photos,
+            "link_preview": photos.is_empty() && preview.link_preview,
+            "preview_above_text": preview.preview_above_text,
+            "caption_above_photo": preview.caption_above_photo,
+            "length": length,
+            // Over the limit the real run shortens the post via Telegraph or sends the text separately
+            "over_limit": length > limit,
+            "plain_text": plain_text,
+        });
+
+        let suffix = artifact_suffix(&item);
+        let html_path = format!("{}/publisher_dryrun_{}.html", DATA_DIR, suffix);
+        let json_path = format!("{}/publisher_dryrun_{}.json", DATA_DIR, suffix);
+        fs::write(&html_path, &content).context(format!("Failed to write {}", html_path))?;
+        fs::write(&json_path, serde_json::to_string_pretty(&payload)?).context(format!("Failed to write {}", json_path))?;
+        update_status(conn, &item.id, item.lang.as_deref(), &item.status, &Status::PublishedDryrun)?;
+        info!("Rendered item {} to {} ({} characters, {} photos)", item.id, html_path, length, photos.len());
+    }
+    Ok(())
+}
+
+fn fetch_illustrator_items(conn: &Connection, input_status: &Status) -> Result<Vec<NewsItem>> {
+    let mut stmt = conn.prepare("SELECT id, title, url, date, status, illustration_skipped IS NOT NULL, source, category FROM news \
+         WHERE status IN (?, ?) OR (status = ? AND COALESCE(next_attempt_at, 0) <= ?) ORDER BY date ASC")?;
+    let news_iter = stmt.query_map(
+        params![input_status, Status::ReadyToPublish, Status::PublisherRetry, Utc::now().timestamp()],
+        |row| {
+            Ok(NewsItem {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                url: row.get(2)?,
+                date: row.get(3)?,
+                status: row.get(4)?,
+                error: None,
+                lang: None,
+                illustration_skipped: row.get(5)?,
+                source: row.get(6)?,
+                category: row.get(7)?,
+                short_url: None,
+                footer: None,
+            })
+        },
+    )?;
+    
+    let mut news_items = Vec::new();
+    for item in news_iter {
+        news_items.push(item?);
+    }
+    
+    Ok(news_items)
+}
+
+/// Posts and pins today's header in TG_CHAT_ID unless it's already there, unpinning the previous one.
+async fn ensure_daily_header(conn: &Connection, tg: &TelegramContext, header: &DailyHeader) -> Result<()> {
+    let today = Local::now().date_naive();
+    let day = today.format("%Y-%m-%d").to_string();
+
+    let posted: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM daily_headers WHERE day = ?",
+        params![day],
+        |row| row.get(0),
+    )?;
+    if posted > 0 {
+        return Ok(());
+    }
+
+    let previous: Option<i32> = conn
+        .query_row(
+            "SELECT message_id FROM daily_headers ORDER BY day DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .ok();
+    if let Some(message_id) = previous {
+        if let Err(e) = tg.client.unpin_message(tg.target_chat, message_id).await {
+            warn!("Failed to unpin previous daily header {}: {}", message_id, e);
+        }
+    }
+
+    let text = header
+        .template
+        .replace("{date}", &today.format(&header.date_format).to_string());
+    let message = tg
+        .client
+        .send_message(tg.target_chat, InputMessage::new().html(&text))
+        .await
+        .context("Failed to send daily header")?;
+
+    // Record the header before pinning so a failed pin never leads to a second header
+    conn.execute(
+        "INSERT INTO daily_headers (day, message_id) VALUES (?, ?)",
+        params![day, message.id()],
+    )?;
+
+    tg.client
+        .pin_message(tg.target_chat, message.id())
+        .await
+        .context("Failed to pin daily header")?;
+
+    info!("Posted daily header for {} (message {})", day, message.id());
+    Ok(())
+}
+
+/// Pins a new main-language post whose title contains one of PIN_KEYWORDS, unpinning the breaking
+/// news post pinned before in the same channel. A failed pin is only logged.
+async fn pin_breaking_post(conn: &Connection, tg: &TelegramContext, item: &NewsItem, message_id: i32) -> Result<()> {
+    let Some(rule) = &tg.pinning else {
+        return Ok(());
+    };
+    let title = format!(" {} ", normalize_title(&item.title));
+    if item.lang.is_some() || !rule.keywords.iter().any(|keyword| title.contains(&format!(" {} ", keyword))) {
+        return Ok(());
+    }
+
+    let chat_key = pin_chat_key(tg, item.category.as_deref());
+    let chat = post_chat(tg, None, item.category.as_deref())?;
+    let previous = {
+        let mut stmt = conn.prepare("SELECT news_id, message_id FROM pinned_posts WHERE chat = ?")?;
+        let rows = stmt
+            .query_map(params![chat_key], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i32>(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows
+    };
+    for (news_id, previous_id) in previous {
+        if let Err(e) = tg.client.unpin_message(chat, previous_id).await {
+            warn!("Failed to unpin the post of item {}: {}", news_id, e);
+        }
+        conn.execute("DELETE FROM pinned_posts WHERE news_id = ?", params![news_id])?;
+    }
+
+    if let Err(e) = tg.client.pin_message(chat, message_id).await {
+        warn!("Failed to pin breaking news item {}: {}", item.id, e);
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT OR REPLACE INTO pinned_posts (news_id, chat, message_id, pinned_at) VALUES (?, ?, ?, ?)",
+        params![item.id, chat_key, message_id, Utc::now().timestamp()],
+    )?;
+    info!("Pinned breaking news item {} (message {})", item.id, message_id);
+    Ok(())
+}
+
+/// Unpins the breaking news posts that have been pinned for PIN_DURATION_SECS.
+async fn unpin_expired_posts(conn: &Connection, tg: &TelegramContext, rule: &PinRule) -> Result<()> {
+    let expired = {
+        let mut stmt = conn.prepare("SELECT news_id, chat, message_id FROM pinned_posts WHERE pinned_at <= ?")?;
+        let rows = stmt
+            .query_map(params![Utc::now().timestamp() - rule.duration_secs], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i32>(2)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows
+    };
+    for (news_id, chat_key, message_id) in expired {
+        let category = (!chat_key.is_empty()).then_some(chat_key.as_str());
+        let chat = post_chat(tg, None, category)?;
+        // A post deleted in the meantime can't be unpinned; it is forgotten all the same
+        match tg.client.unpin_message(chat, message_id).await {
+            Ok(()) => info!("Unpinned breaking news item {} (message {})", news_id, message_id),
+            Err(e) => warn!("Failed to unpin the post of item {}: {}", news_id, e),
+        }
+        conn.execute("DELETE FROM pinned_posts WHERE news_id = ?", params![news_id])?;
+    }
+    Ok(())
+}
+
+/// The category of a main-language post if it has a channel of its own, otherwise ''.
+fn pin_chat_key(tg: &TelegramContext, category: Option<&str>) -> String {
+    category
+        .filter(|category| tg.category_chats.contains_key(*category))
+        .unwrap_or("")
+        .to_string()
+}
+
+/// Extra-language versions that are rewritten and whose main item is already published.
+fn fetch_translation_items(conn: &Connection, ready_status: &Status, published_status: &Status) -> Result<Vec<NewsItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title, n.url, n.date, t.status, t.lang, n.illustration_skipped IS NOT NULL, n.source, n.category \
+         FROM news_translations t JOIN news n ON n.id = t.news_id \
+         WHERE (t.status IN (?, ?) OR (t.status = ? AND COALESCE(t.next_attempt_at, 0) <= ?)) \
+         AND n.status IN (?, ?, ?) ORDER BY n.date ASC",
+    )?;
+    let news_iter = stmt.query_map(
+        params![
+            ready_status,
+            Status::ReadyToPublish,
+            Status::PublisherRetry,
+            Utc::now().timestamp(),
+            published_status,
+            Status::PublishedPlain,
+            Status::Updated
+        ],
+        |row| {
+            Ok(NewsItem {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                url: row.get(2)?,
+                date: row.get(3)?,
+                status: row.get(4)?,
+                error: None,
+                lang: Some(row.get(5)?),
+                illustration_skipped: row.get(6)?,
+                source: row.get(7)?,
+                category: row.get(8)?,
+                short_url: None,
+                footer: None,
+            })
+        },
+    )?;
+
+    let mut news_items = Vec::new();
+    for item in news_iter {
+        news_items.push(item?);
+    }
+
+    Ok(news_items)
+}
+
+/// File name part of an item's artifacts: `<id>` or `<id>_<lang>` for extra languages.
+fn artifact_suffix(item: &NewsItem) -> String {
+    match &item.lang {
+        Some(lang) => format!("{}_{}", item.id, lang),
+        None => item.id.clone(),
+    }
+}
+
+/// Posts to the main channel in the last 60 minutes, counted up to `max_posts`.
+fn posts_in_last_hour(conn: &Connection, max_posts: usize) -> Result<usize> {
+    let hour_ago = Local::now() - chrono::Duration::hours(1);
+    let mut stmt = conn.prepare("SELECT published_at FROM publish_chain WHERE lang = '' ORDER BY seq DESC LIMIT ?")?;
+    let published_at = stmt
+        .query_map(params![max_posts as i64], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(published_at
+        .iter()
+        .filter_map(|time| DateTime::parse_from_rfc3339(time).ok())
+        .filter(|time| *time >= hour_ago)
+        .count())
+}
+
+/// Returns an older item that is still in the pipeline if the item should keep waiting for it.
+/// The wait starts when the item is first held back and ends after the ordering timeout.
+fn wait_for_earlier_item(conn: &Connection, item: &NewsItem, ordering: &OrderingPolicy) -> Result<Option<String>> {
+    let placeholders = vec!["?"; ordering.in_flight.len()].join(", ");
+    let mut values: Vec<&dyn rusqlite::ToSql> = vec![&item.date, &item.id];
+    values.extend(ordering.in_flight.iter().map(|status| status as &dyn rusqlite::ToSql));
+    let earlier: Option<String> = conn
+        .query_row(
+            &format!(
+                "SELECT id FROM news WHERE date < ? AND id != ? AND status IN ({}) ORDER BY date ASC LIMIT 1",
+                placeholders
+            ),
+            values.as_slice(),
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(earlier_id) = earlier else {
+        conn.execute("DELETE FROM publish_waits WHERE news_id = ?", params![item.id])?;
+        return Ok(None);
+    };
+
+    let now = Utc::now().timestamp();
+    conn.execute(
+        "INSERT OR IGNORE INTO publish_waits (news_id, since) VALUES (?, ?)",
+        params![item.id, now],
+    )?;
+    let since: i64 = conn.query_row("SELECT since FROM publish_waits WHERE news_id = ?", params![item.id], |row| row.get(0))?;
+    if now - since >= ordering.timeout_secs {
+        warn!(
+            "Item {} waited {} seconds for the older item {}, publishing it anyway",
+            item.id,
+            now - since,
+            earlier_id
+        );
+        conn.execute("DELETE FROM publish_waits WHERE news_id = ?", params![item.id])?;
+        return Ok(None);
+    }
+    Ok(Some(earlier_id))
+}
+
+/// Returns the id of a published item among the last `window` ones whose normalized title
+/// matches the item's title.
+fn find_recent_duplicate(
+    conn: &Connection,
+    item: &NewsItem,
+    published_status: &Status,
+    window: usize,
+) -> Result<Option<String>> {
+    let title = normalize_title(&item.title);
+    if window == 0 || title.is_empty() {
+        return Ok(None);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, title FROM news WHERE status IN (?, ?, ?) AND id != ? ORDER BY date DESC LIMIT ?",
+    )?;
+    let rows = stmt.query_map(
+        params![published_status, Status::PublishedPlain, Status::Updated, item.id, window as i64],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+    )?;
+
+    for row in rows {
+        let (id, published_title) = row?;
+        if normalize_title(&published_title) == title {
+            return Ok(Some(id));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Lowercases the title and collapses punctuation and whitespace so that titles differing only
+/// in quotes, dashes or spacing compare equal.
+fn normalize_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Picks the rewrite variant to publish: `a` is the main rewrite, `b` the alternative headline/lead
+/// written by the rewriter when AI_PROVIDER_REWRITER_VARIANT_PROMPT is set. `alternate` publishes
+/// whichever variant has been used less so far.
+fn choose_variant(conn: &Connection, item: &NewsItem, stage: &PipelineStage, strategy: &str) -> Result<&'static str> {
+    if !artifact_exists(&format!("{}_b_{}.html", stage.input_artifact, artifact_suffix(item)))? {
+        return Ok("a");
+    }
+    match strategy {
+        "a" => Ok("a"),
+        "b" => Ok("b"),
+        _ => {
+            let (a, b): (i64, i64) = conn.query_row(
+                "SELECT COUNT(CASE WHEN variant = 'a' THEN 1 END), COUNT(CASE WHEN variant = 'b' THEN 1 END) FROM publish_variants",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            Ok(if b < a { "b" } else { "a" })
+        }
+    }
+}
+
+fn read_moderation_from_env() -> Result<Moderation> {
+    let keywords = match env::var("MODERATION_KEYWORDS_FILE") {
+        Ok(path) if !path.trim().is_empty() => fs::read_to_string(path.trim())
+            .context(format!("Failed to read MODERATION_KEYWORDS_FILE {}", path.trim()))?
+            .lines()
+            .map(|line| line.trim().to_lowercase())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let openai_api_key = env::var("MODERATION_OPENAI_API_KEY")
+        .ok()
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty());
+    let model = env::var("MODERATION_MODEL")
+        .ok()
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+        .unwrap_or_else(|| DEFAULT_MODERATION_MODEL.to_string());
+    Ok(Moderation {
+        keywords,
+        openai_api_key,
+        model,
+    })
+}
+
+/// Returns why the item must not be published automatically, or None when it passes (or was
+/// already released by an operator).
+async fn moderate_item(
+    conn: &Connection,
+    moderation: &Moderation,
+    item: &NewsItem,
+    stage: &PipelineStage,
+    variant: &str,
+) -> Result<Option<String>> {
+    if moderation.keywords.is_empty() && moderation.openai_api_key.is_none() {
+        return Ok(None);
+    }
+    let approved: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM moderation_holds WHERE news_id = ? AND lang = ? AND approved = 1)",
+        params![item.id, item.lang.as_deref().unwrap_or("")],
+        |row| row.get(0),
+    )?;
+    if approved {
+        return Ok(None);
+    }
+
+    let html = read_artifact_string(&variant_artifact(item, stage, variant))?;
+    let text = Html::parse_document(&html).root_element().text().collect::<Vec<_>>().join(" ");
+
+    let lower = text.to_lowercase();
+    let matched: Vec<&str> = moderation
+        .keywords
+        .iter()
+        .filter(|keyword| lower.contains(keyword.as_str()))
+        .map(String::as_str)
+        .collect();
+    if !matched.is_empty() {
+        return Ok(Some(format!("keywords: {}", matched.join(", "))));
+    }
+
+    let Some(api_key) = &moderation.openai_api_key else {
+        return Ok(None);
+    };
+    check_with_moderation_endpoint(api_key, &moderation.model, &text).await
+}
+
+async fn check_with_moderation_endpoint(api_key: &str, model: &str, text: &str) -> Result<Option<String>> {
+    let response = http_client()
+        .post(MODERATION_URL)
+        .bearer_auth(api_key)
+        .json(&serde_json::json!({ "model": model, "input": text }))
+        .send()
+        .await
+        .context("Failed to call the moderation endpoint")?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().await.context("Failed to parse the moderation response")?;
+    if !status.is_success() {
+        return Err(anyhow!("moderation endpoint returned {}: {}", status, body));
+    }
+
+    let result = &body["results"][0];
+    if !result["flagged"].as_bool().unwrap_or(false) {
+        return Ok(None);
+    }
+    let categories: Vec<&str> = result["categories"]
+        .as_object()
+        .map(|categories| {
+            categories
+                .iter()
+                .filter(|(_, flagged)| flagged.as_bool() == Some(true))
+                .map(|(category, _)| category.as_str())
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Some(format!(
+        "moderation endpoint: {}",
+        if categories.is_empty() { "flagged".to_string() } else { categories.join(", ") }
+    )))
+}
+
+async fn hold_for_moderation(conn: &Connection, tg: &TelegramContext, item: &NewsItem, reason: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO moderation_holds (news_id, lang, reason, held_at, approved) VALUES (?, ?, ?, ?, 0)",
+        params![item.id, item.lang.as_deref().unwrap_or(""), reason, Local::now().to_rfc3339()],
+    )?;
+    update_status(conn, &item.id, item.lang.as_deref(), &item.status, &Status::ModerationHold)?;
+    warn!("Item {} held for moderation ({})", item.id, reason);
+
+    if let Some(chat) = tg.moderation_chat {
+        let text = format!(
+            "Held for moderation: {} ({})\n{}\nReason: {}\nRelease with: ctl review --approve {}{}",
+            item.title,
+            item.lang.as_deref().unwrap_or("default"),
+            item.url,
+            reason,
+            item.id,
+            item.lang.as_deref().map(|lang| format!(" --lang {}", lang)).unwrap_or_default()
+        );
+        if let Err(e) = tg.client.send_message(chat, InputMessage::new().text(&text)).await {
+            warn!("Failed to send moderation notification for item {}: {}", item.id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the approved draft of the item and forgets it, so that a later update of the post needs
+/// a new approval. None if there is no approved draft.
+fn take_approval(conn: &Connection, item: &NewsItem) -> Result<Option<ApprovalDraft>> {
+    let lang = item.lang.as_deref().unwrap_or("");
+    let draft = conn
+        .query_row(
+            "SELECT decision, edited_text FROM approval_drafts WHERE news_id = ? AND lang = ?",
+            params![item.id, lang],
+            |row| {
+                Ok(ApprovalDraft {
+                    decision: row.get(0)?,
+                    edited_text: row.get(1)?,
+                })
+            },
+        )
+        .optional()?
+        .filter(|draft| draft.decision.as_deref() == Some("approved"));
+    if draft.is_some() {
+        conn.execute("DELETE FROM approval_drafts WHERE news_id = ? AND lang = ?", params![item.id, lang])?;
+    }
+    Ok(draft)
+}
+
+/// Sends the post as it would be published to the approval chat, with Approve/Reject/Edit buttons.
+async fn send_for_approval(
+    conn: &Connection,
+    approval: &Approval,
+    templates: &PostTemplates,
+    item: &NewsItem,
+    stage: &PipelineStage,
+) -> Result<()> {
+    let ComposedPost { content, plain_text, length } = compose_post(item, stage, templates)?;
+    let lang = item.lang.as_deref().unwrap_or("");
+    // Reserve the draft id for the callback data; the message id is filled in once it is sent
+    conn.execute(
+        "INSERT OR REPLACE INTO approval_drafts (news_id, lang, message_id, decision, edited_text, sent_at) VALUES (?, ?, 0, NULL, NULL, ?)",
+        params![item.id, lang, Local::now().to_rfc3339()],
+    )?;
+    let draft_id = conn.last_insert_rowid();
+    let keyboard = serde_json::json!({
+        "inline_keyboard": [[
+            { "text": "Approve", "callback_data": format!("approve:{}", draft_id) },
+            { "text": "Reject", "callback_data": format!("reject:{}", draft_id) },
+            { "text": "Edit", "callback_data": format!("edit:{}", draft_id) },
+        ]]
+    });
+
+    let image = format!("illustrator_{}.png", item.id);
+    let has_image = artifact_exists(&image)?;
+    let captioned = has_image && length <= TG_CAPTION_LIMIT;
+    if has_image && !captioned {
+        bot_send_photo(approval, &image, None).await?;
+    }
+    let draft = if captioned {
+        bot_send_photo(approval, &image, Some((content.as_str(), &keyboard))).await
+    } else {
+        bot_api(
+            approval,
+            "sendMessage",
+            serde_json::json!({
+                "chat_id": approval.chat_id,
+                "text": content,
+                "parse_mode": "HTML",
+                "reply_markup": keyboard,
+            }),
+        )
+        .await
+    };
+    // The admins should see the draft even if the Bot API rejects our markup
+    let draft = match draft {
+        Ok(message) => message,
+        Err(e) => {
+            warn!("Approval bot could not send the draft of item {} as HTML ({}), sending plain text", item.id, e);
+            bot_api(
+                approval,
+                "sendMessage",
+                serde_json::json!({ "chat_id": approval.chat_id, "text": plain_text, "reply_markup": keyboard }),
+            )
+            .await?
+        }
+    };
+
+    let message_id = draft["message_id"]
+        .as_i64()
+        .ok_or_else(|| anyhow!("Bot API response has no message_id"))?;
+    conn.execute("UPDATE approval_drafts SET message_id = ? WHERE id = ?", params![message_id, draft_id])?;
+    update_status(conn, &item.id, item.lang.as_deref(), &item.status, &Status::PendingApproval)?;
+    info!("Sent item {} to the approval chat (draft {})", item.id, draft_id);
+    Ok(())
+}
+
+/// Applies the button presses and edit replies the approval bot received since the last cycle.
+async fn poll_approvals(conn: &Connection, approval: &Approval, input_status: &Status, translation_status: &Status) -> Result<()> {
+    let offset: i64 = conn
+        .query_row("SELECT next_offset FROM approval_updates WHERE id = 1", [], |row| row.get(0))
+        .optional()?
+        .unwrap_or(0);
+    let updates = bot_api(
+        approval,
+        "getUpdates",
+        serde_json::json!({ "offset": offset, "timeout": 0, "allowed_updates": ["callback_query", "message"] }),
+    )
+    .await?;
+
+    for update in updates.as_array().into_iter().flatten() {
+        if let Err(e) = handle_approval_update(conn, approval, update, input_status, translation_status).await {
+            warn!("Failed to handle approval update {}: {}", update["update_id"], e);
+        }
+        conn.execute(
+            "INSERT OR REPLACE INTO approval_updates (id, next_offset) VALUES (1, ?)",
+            params![update["update_id"].as_i64().unwrap_or(offset) + 1],
+        )?;
+    }
+    Ok(())
+}
+
+async fn handle_approval_update(
+    conn: &Connection,
+    approval: &Approval,
+    update: &serde_json::Value,
+    input_status: &Status,
+    translation_status: &Status,
+) -> Result<()> {
+    // A reply to a draft carries the corrected text
+    let message = &update["message"];
+    if message.is_object() {
+        if message["chat"]["id"].as_i64() != Some(approval.chat_id) {
+            return Ok(());
+        }
+        let (Some(reply_to), Some(text)) = (message["reply_to_message"]["message_id"].as_i64(), message["text"].as_str()) else {
+            return Ok(());
+        };
+        let updated = conn.execute(
+            "UPDATE approval_drafts SET edited_text = ? WHERE message_id = ? AND decision IS NULL",
+            params![text, reply_to],
+        )?;
+        if updated > 0 {
+            bot_api(
+                approval,
+                "sendMessage",
+                serde_json::json!({
+                    "chat_id": approval.chat_id,
+                    "text": "Edit saved. Press Approve to publish it.",
+                    "reply_to_message_id": message["message_id"],
+                }),
+            )
+            .await?;
+        }
+        return Ok(());
+    }
+
+    let query = &update["callback_query"];
+    if query["message"]["chat"]["id"].as_i64() != Some(approval.chat_id) {
+        return Ok(());
+    }
+    let data = query["data"].as_str().unwrap_or_default();
+    let Some((action, draft_id)) = data.split_once(':') else {
+        return Ok(());
+    };
+    let draft = conn
+        .query_row(
+            "SELECT news_id, lang, decision FROM approval_drafts WHERE id = ?",
+            params![draft_id.parse::<i64>().unwrap_or(-1)],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?)),
+        )
+        .optional()?;
+    let admin = query["from"]["username"]
+        .as_str()
+        .or(query["from"]["first_name"].as_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    let answer = match (&draft, action) {
+        (None, _) => "This draft is outdated.".to_string(),
+        (Some((_, _, Some(decision))), _) => format!("Already {}.", decision),
+        (Some(_), "edit") => "Reply to the draft with the corrected text, then press Approve.".to_string(),
+        (Some((news_id, lang, None)), "approve" | "reject") => {
+            let lang = (!lang.is_empty()).then_some(lang.as_str());
+            let (decision, status) = match (action, lang) {
+                ("approve", None) => ("approved", input_status.clone()),
+                ("approve", Some(_)) => ("approved", translation_status.clone()),
+                _ => ("rejected", Status::Rejected),
+            };
+            conn.execute("UPDATE approval_drafts SET decision = ? WHERE id = ?", params![decision, draft_id])?;
+            update_status(conn, news_id, lang, &Status::PendingApproval, &status)?;
+            info!("Item {} {} by {} in the approval chat", news_id, decision, admin);
+            // Drop the buttons so the draft shows it has been handled
+            bot_api(
+                approval,
+                "editMessageReplyMarkup",
+                serde_json::json!({
+                    "chat_id": approval.chat_id,
+                    "message_id": query["message"]["message_id"],
+                    "reply_markup": { "inline_keyboard": [] },
+                }),
+            )
+            .await?;
+            format!("{} by {}.", if decision == "approved" { "Approved" } else { "Rejected" }, admin)
+        }
+        (Some(_), _) => return Ok(()),
+    };
+    bot_api(
+        approval,
+        "answerCallbackQuery",
+        serde_json::json!({ "callback_query_id": query["id"], "text": answer }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Calls a Bot API method of the approval bot and returns its `result`.
+async fn bot_api(approval: &Approval, method: &str, body: serde_json::Value) -> Result<serde_json::Value> {
+    let url = format!("{}/bot{}/{}", TG_BOT_API_URL, approval.bot_token, method);
+    let response: serde_json::Value = http_client()
+        .post(&url)
+        .json(&body)
+        .send()
+        .await
+        .context(format!("Failed to call {}", method))?
+        .json()
+        .await
+        .context(format!("Failed to parse the {} response", method))?;
+    bot_api_result(method, response)
+}
+
+/// Uploads a photo to the approval chat, optionally with an HTML caption and buttons.
+async fn bot_send_photo(
+    approval: &Approval,
+    image: &str,
+    caption: Option<(&str, &serde_json::Value)>,
+) -> Result<serde_json::Value> {
+    let url = format!("{}/bot{}/sendPhoto", TG_BOT_API_URL, approval.bot_token);
+    let photo = reqwest::multipart::Part::bytes(read_artifact(image)?).file_name(image.to_string());
+    let mut form = reqwest::multipart::Form::new()
+        .text("chat_id", approval.chat_id.to_string())
+        .part("photo", photo);
+    if let Some((caption, keyboard)) = caption {
+        form = form
+            .text("caption", caption.to_string())
+            .text("parse_mode", "HTML")
+            .text("reply_markup", keyboard.to_string());
+    }
+    let response: serde_json::Value = http_client()
+        .post(&url)
+        .timeout(Duration::from_secs(UPLOAD_TIMEOUT_SECS))
+        .multipart(form)
+        .send()
+        .await
+        .context("Failed to call sendPhoto")?
+        .json()
+        .await
+        .context("Failed to parse the sendPhoto response")?;
+    bot_api_result("sendPhoto", response)
+}
+
+fn bot_api_result(method: &str, response: serde_json::Value) -> Result<serde_json::Value> {
+    if !response["ok"].as_bool().unwrap_or(false) {
+        return Err(anyhow!("{} failed: {}", method, response["description"]));
+    }
+    Ok(response["result"].clone())
+}
+
+fn find_published_post(conn: &Connection, item: &NewsItem) -> Result<Option<PublishedPost>> {
+    conn.query_row(
+        "SELECT message_id, telegraph_path FROM published_messages WHERE news_id = ? AND lang = ?",
+        params![item.id, item.lang.as_deref().unwrap_or("")],
+        |row| {
+            Ok(PublishedPost {
+                message_id: row.get(0)?,
+                telegraph_path: row.get(1)?,
+            })
+        },
+    )
+    .optional()
+    .context("Failed to look up the published message")
+}
+
+fn record_published_post(conn: &Connection, item: &NewsItem, message_id: i32, telegraph_path: Option<&str>) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO published_messages (news_id, lang, message_id, telegraph_path, published_at) VALUES (?, ?, ?, ?, ?)",
+        params![item.id, item.lang.as_deref().unwrap_or(""), message_id, telegraph_path, Local::now().to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+fn record_variant(conn: &Connection, item: &NewsItem, variant: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO publish_variants (news_id, lang, variant, published_at) VALUES (?, ?, ?, ?)",
+        params![item.id, item.lang.as_deref().unwrap_or(""), variant, Local::now().to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+/// The rewrite of the chosen variant: `<input_artifact>_<id>.html` for `a`,
+/// `<input_artifact>_b_<id>.html` for `b`.
+fn variant_artifact(item: &NewsItem, stage: &PipelineStage, variant: &str) -> String {
+    match variant {
+        "b" => format!("{}_b_{}.html", stage.input_artifact, artifact_suffix(item)),
+        _ => format!("{}_{}.html", stage.input_artifact, artifact_suffix(item)),
+    }
+}
+
+fn process_html_file(item: &NewsItem, stage: &PipelineStage, variant: &str) -> Result<()> {
+    // Read the input file
+    let html_content = read_artifact_string(&variant_artifact(item, stage, variant))
+        .context("Failed to read HTML file")?;
+    
+    // Process the HTML
+    let processed_html = transform_html(&html_content)?;
+    
+    // Write the processed HTML to the output file
+    write_artifact(&format!("{}_{}.html", stage.output_artifact, artifact_suffix(item)), processed_html)
+        .context("Failed to write processed HTML to file")?;
+    // The rewriter's plaintext goes next to it, for the length checks and the mirrors
+    let text = artifact_text(&variant_artifact(item, stage, variant))?;
+    write_artifact(&format!("{}_{}.txt", stage.output_artifact, artifact_suffix(item)), text)
+        .context("Failed to write plaintext to file")?;
+    
+    Ok(())
+}
+
+fn transform_html(html_content: &str) -> Result<String> {
+    // Parse the HTML document
+    let document = Html::parse_document(html_content);
+    
+    // Select the body element
+    let body_selector = Selector::parse("body").map_err(|e| anyhow!("Invalid selector: {}", e))?;
+    
+    // Extract the body content or return an error if not found
+    let body = document.select(&body_selector).next()
+        .ok_or_else(|| anyhow!("Body tag not found in HTML"))?;
+    
+    let mut result = String::new();
+    
+    // Process all elements in the body
+    process_element(&mut result, &body);
+    
+    // Clean up multiple consecutive newlines and whitespace
+    let cleaned = result
+        .replace("\n\n\n", "\n\n")  // Replace triple newlines with double
+        .replace("  ", " ");         // Replace double spaces with single
+    
+    Ok(cleaned)
+}
+
+fn process_element(result: &mut String, element: &ElementRef) {
+    let tag_name = element.value().name();
+    
+    // Handle specific tags
+    match tag_name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            // Convert heading to bold and add double newline
+            result.push_str("<b>");
+            process_element_children(result, element);
+            result.push_str("</b>\n\n");
+        },
+        "p" => {
+            // Extract paragraph content and add double newline
+            process_element_children(result, element);
+            result.push_str("\n\n");
+        },
+        "strong" | "b" => {
+            // Bold text
+            result.push_str("<b>");
+            process_element_children(result, element);
+            result.push_str("</b>");
+        },
+        "a" => {
+            // Hyperlinks
+            if let Some(href) = element.value().attr("href") {
+                result.push_str(&format!("<a href=\"{}\">", escape_html(href)));
+                process_element_children(result, element);
+                result.push_str("</a>");
+            } else {
+                process_element_children(result, element);
+            }
+        },
+        "br" => {
+            // Line break
+            result.push('\n');
+        },
+        // Skip html, head, etc.
+        "html" | "head" | "meta" | "title" | "style" | "script" => {},
+        // Process other elements
+        _ => {
+            process_element_children(result, element);
+            
+            // Add spacing for block elements
+            if !INLINE_TAGS.contains(&tag_name)
+                && !result.ends_with("\n\n")
+                && !result.is_empty()
+            {
+                result.push_str("\n\n");
+            }
+        }
+    }
+}
+
+/// Length of a post in Telegram's HTML parse mode as Telegram counts it against its limits: the
+/// text left after the tags are parsed and the entities decoded, trimmed, in UTF-16 code units.
+fn telegram_text_length(html: &str) -> usize {
+    Html::parse_fragment(html)
+        .root_element()
+        .text()
+        .collect::<String>()
+        .trim()
+        .encode_utf16()
+        .count()
+}
+
+/// Escapes the characters Telegram's HTML parse mode treats as markup. Only the tags emitted by
+/// `process_element` reach Telegram; all others are replaced by their content.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Whether text or an inline element added to `result` needs a space before it: not at the start
+/// of a line, after a space or right after an opening tag such as `<b>`.
+fn needs_space(result: &str) -> bool {
+    let after_opening_tag =
+        result.ends_with('>') && result.rfind('<').is_some_and(|start| !result[start..].starts_with("</"));
+    !result.is_empty() && !result.ends_with(' ') && !result.ends_with('\n') && !after_opening_tag
+}
+
+fn process_element_children(result: &mut String, element: &ElementRef) {
+    for child in element.children() {
+        match child.value() {
+            scraper::node::Node::Text(text) => {
+                // Add text content, collapsing whitespace
+                let text = text.text.trim();
+                if !text.is_empty() {
+                    if needs_space(result) {
+                        result.push(' ');
+                    }
+                    // The parser decoded the entities; Telegram needs them back
+                    result.push_str(&escape_html(text));
+                }
+            },
+            scraper::node::Node::Element(_) => {
+                if let Some(child_element) = ElementRef::wrap(child) {
+                    // The space goes before the tag, so that it doesn't end up inside it
+                    let inline = INLINE_TAGS.contains(&child_element.value().name());
+                    if inline && child_element.text().any(|text| !text.trim().is_empty()) && needs_space(result) {
+                        result.push(' ');
+                    }
+                    process_element(result, &child_element);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// How a post is cut down to Telegram's limits, decided from its measured length alone.
+enum Shortening {
+    /// Keeps its text and gains a link to the full text (TELEGRAPH_ALWAYS).
+    KeepText,
+    /// Is replaced with the rewriter's summary and a link to the full text.
+    Summary,
+}
+
+/// Measures the post as it will be sent, with the date and source footer, against the message
+/// (4096) or photo caption (1024) limit. None when it fits and TELEGRAPH_ALWAYS is off.
+fn plan_shortening(tg: &TelegramContext, item: &NewsItem, stage: &PipelineStage, illustrated: bool) -> Result<Option<Shortening>> {
+    let image = format!("illustrator_{}.png", item.id);
+    let length = compose_post(item, stage, &tg.templates)?.length;
+
+    let photo = illustrated && preview_options(tg, item).cover == Cover::Photo && artifact_exists(&image)?;
+    let limit = if photo { TG_CAPTION_LIMIT } else { TG_MESSAGE_LIMIT };
+    if length <= limit && !tg.telegraph.as_ref().is_some_and(|telegraph| telegraph.always) {
+        return Ok(None);
+    }
+
+    // With TELEGRAPH_ALWAYS a post that fits keeps its text and only gains the link
+    let label = full_version_label(item.lang.as_deref());
+    if length + 2 + label.encode_utf16().count() <= limit {
+        return Ok(Some(Shortening::KeepText));
+    }
+    let summary_artifact = format!("{}_summary_{}.html", stage.input_artifact, artifact_suffix(item));
+    if !artifact_exists(&summary_artifact)? {
+        if length <= limit {
+            return Ok(None);
+        }
+        return Err(anyhow!("post is {} characters long and there is no summary", length));
+    }
+    if tg.telegraph.is_none() {
+        return Err(anyhow!("post is {} characters long and TELEGRAPH_ACCESS_TOKEN is not set", length));
+    }
+    Ok(Some(Shortening::Summary))
+}
+
+/// Writes the shortened post, ending in `link`, over the item's output artifacts.
+fn write_shortened_post(item: &NewsItem, stage: &PipelineStage, shortening: &Shortening, link: &str) -> Result<()> {
+    let output_artifact = format!("{}_{}.html", stage.output_artifact, artifact_suffix(item));
+    let text = match shortening {
+        Shortening::KeepText => read_artifact_string(&output_artifact)?,
+        Shortening::Summary => transform_html(&read_artifact_string(&format!(
+            "{}_summary_{}.html",
+            stage.input_artifact,
+            artifact_suffix(item)
+        ))?)?,
+    };
+    let shortened = format!("{}\n\n{}", text.trim_end(), link);
+    let text_artifact = format!("{}_{}.txt", stage.output_artifact, artifact_suffix(item));
+    let shortened_text = Html::parse_fragment(&shortened).root_element().text().collect::<String>();
+    write_artifact(&text_artifact, shortened_text.trim()).context(format!("Failed to write {}", text_artifact))?;
+    write_artifact(&output_artifact, shortened).context(format!("Failed to write {}", output_artifact))?;
+    Ok(())
+}
+
+/// Shortens the draft sent to the approval chat like `shorten_long_post` would, with the link
+/// label unlinked: the Telegraph page is only created once an admin approved the post.
+fn preview_shortened_post(tg: &TelegramContext, item: &NewsItem, stage: &PipelineStage, illustrated: bool) -> Result<()> {
+    if let Some(shortening) = plan_shortening(tg, item, stage, illustrated)? {
+        write_shortened_post(item, stage, &shortening, full_version_label(item.lang.as_deref()))?;
+    }
+    Ok(())
+}
+
+/// Replaces a post that would not fit into a Telegram message (or photo caption) with the
+/// rewriter's summary and a link to the full text published on Telegraph. With `edited_text`
+/// from the approval chat, that text is what gets measured and published on Telegraph.
+async fn shorten_long_post(
+    tg: &TelegramContext,
+    item: &NewsItem,
+    stage: &PipelineStage,
+    variant: &str,
+    illustrated: bool,
+    telegraph_path: Option<&str>,
+    edited_text: Option<&str>,
+) -> Result<Option<String>> {
+    let Some(shortening) = plan_shortening(tg, item, stage, illustrated)? else {
+        return Ok(None);
+    };
+    let Some(telegraph) = &tg.telegraph else {
+        return Err(anyhow!("TELEGRAPH_ACCESS_TOKEN is not set"));
+    };
+
+    let full_html = match edited_text {
+        Some(text) => format!(
+            "<html><body>{}</body></html>",
+            text.split("\n\n")
+                .map(|paragraph| format!("<p>{}</p>", escape_html(paragraph.trim())))
+                .collect::<String>()
+        ),
+        None => read_artifact_string(&variant_artifact(item, stage, variant))?,
+    };
+    let full = Html::parse_document(&full_html);
+    let body_selector = Selector::parse("body").map_err(|e| anyhow!("Invalid selector: {}", e))?;
+    let body = full.select(&body_selector).next().ok_or_else(|| anyhow!("Body tag not found in HTML"))?;
+    let mut nodes = telegraph_nodes(&body);
+
+    // Telegraph titles are limited to 256 characters
+    let title: String = item.title.chars().take(256).collect();
+    // Telegraph pages show images hosted on telegra.ph itself most reliably
+    rehost_telegraph_images(&mut nodes).await;
+    let image = format!("illustrator_{}.png", item.id);
+    if artifact_exists(&image)? {
+        match upload_to_telegraph(read_artifact(&image)?, "image/png").await {
+            Ok(src) => nodes.insert(
+                0,
+                serde_json::json!({ "tag": "figure", "children": [{ "tag": "img", "attrs": { "src": src } }] }),
+            ),
+            Err(e) => {
+                warn!("Failed to upload the illustration to Telegraph: {}", e);
+            }
+        }
+    }
+    // An updated post keeps its Telegraph page
+    let (url, path) = create_telegraph_page(telegraph, &title, nodes, telegraph_path).await?;
+
+    let link = format!("<a href=\"{}\">{}</a>", url, full_version_label(item.lang.as_deref()));
+    write_shortened_post(item, stage, &shortening, &link)?;
+    info!(
+        "Item {} is too long, posting {} with the full text at {}",
+        item.id,
+        match shortening {
+            Shortening::KeepText => "it",
+            Shortening::Summary => "the summary",
+        },
+        url
+    );
+    Ok(Some(path))
+}
+
+/// Converts HTML into Telegraph's content nodes; tags Telegraph doesn't support are replaced by
+/// their children.
+fn telegraph_nodes(element: &ElementRef) -> Vec<serde_json::Value> {
+    let mut nodes = Vec::new();
+    for child in element.children() {
+        match child.value() {
+            scraper::node::Node::Text(text) if !text.text.is_empty() => {
+                nodes.push(serde_json::Value::String(text.text.to_string()));
+            }
+            scraper::node::Node::Element(_) => {
+                let Some(child_element) = ElementRef::wrap(child) else {
+                    continue;
+                };
+                let name = child_element.value().name();
+                let tag = match name {
+                    "h1" | "h2" => "h3",
+                    "h5" | "h6" => "h4",
+                    "script" | "style" => continue,
+                    // Only web images can be put on the page (and rehosted)
+                    "img" => match child_element.value().attr("src").filter(|src| src.starts_with("http")) {
+                        Some(src) => {
+                            nodes.push(serde_json::json!({ "tag": "img", "attrs": { "src": src } }));
+                            continue;
+                        }
+                        None => continue,
+                    },
+                    "a" | "aside" | "b" | "blockquote" | "br" | "code" | "em" | "figcaption" | "figure" | "h3" | "h4"
+                    | "hr" | "i" | "li" | "ol" | "p" | "pre" | "s" | "strong" | "u" | "ul" => name,
+                    _ => {
+                        nodes.extend(telegraph_nodes(&child_element));
+                        continue;
+                    }
+                };
+                let mut node = serde_json::json!({ "tag": tag });
+                if let Some(href) = child_element.value().attr("href").filter(|_| tag == "a") {
+                    node["attrs"] = serde_json::json!({ "href": href });
+                }
+                let children = telegraph_nodes(&child_element);
+                if !children.is_empty() {
+                    node["children"] = serde_json::Value::Array(children);
+                }
+                nodes.push(node);
+            }
+            _ => {}
+        }
+    }
+    nodes
+}
+
+/// Replaces the sources of the article's images with copies uploaded to Telegraph; an image that
+/// can't be copied keeps its original URL.
+async fn rehost_telegraph_images(nodes: &mut [serde_json::Value]) {
+    for node in nodes {
+        if node["tag"] == "img" {
+            let Some(src) = node["attrs"]["src"].as_str().map(str::to_string) else {
+                continue;
+            };
+            match rehost_image(&src).await {
+                Ok(rehosted) => node["attrs"]["src"] = serde_json::Value::String(rehosted),
+                Err(e) => {
+                    warn!("Failed to copy image {} to Telegraph: {}", src, e);
+                }
+            }
+        } else if let Some(children) = node.get_mut("children").and_then(serde_json::Value::as_array_mut) {
+            Box::pin(rehost_telegraph_images(children)).await;
+        }
+    }
+}
+
+async fn rehost_image(url: &str) -> Result<String> {
+    let response = http_client().get(url).send().await?.error_for_status()?;
+    let mime_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or(value).trim().to_string())
+        .filter(|value| value.starts_with("image/"))
+        .ok_or_else(|| anyhow!("not an image"))?;
+    let bytes = response.bytes().await?;
+    upload_to_telegraph(bytes.to_vec(), &mime_type).await
+}
+
+/// Uploads an image to telegra.ph and returns its URL there.
+async fn upload_to_telegraph(bytes: Vec<u8>, mime_type: &str) -> Result<String> {
+    if bytes.len() > TELEGRAPH_MAX_IMAGE_BYTES {
+        return Err(anyhow!("image is {} bytes, Telegraph takes up to {}", bytes.len(), TELEGRAPH_MAX_IMAGE_BYTES));
+    }
+    let extension = mime_type.trim_start_matches("image/");
+    let part = reqwest::multipart::Part::bytes(bytes)
+        .file_name(format!("image.{}", extension))
+        .mime_str(mime_type)?;
+    let body: serde_json::Value = http_client()
+        .post(TELEGRAPH_UPLOAD_URL)
+        .timeout(Duration::from_secs(UPLOAD_TIMEOUT_SECS))
+        .multipart(reqwest::multipart::Form::new().part("file", part))
+        .send()
+        .await
+        .context("Failed to upload to Telegraph")?
+        .json()
+        .await
+        .context("Failed to parse the Telegraph upload response")?;
+    body[0]["src"]
+        .as_str()
+        .map(|src| format!("https://telegra.ph{}", src))
+        .ok_or_else(|| anyhow!("Telegraph rejected the upload: {}", body["error"]))
+}
+
+/// Creates a Telegraph page, or replaces the content of the page at `path`. Returns the page's URL
+/// and path.
+async fn create_telegraph_page(
+    telegraph: &Telegraph,
+    title: &str,
+    nodes: Vec<serde_json::Value>,
+    path: Option<&str>,
+) -> Result<(String, String)> {
+    let mut request = serde_json::json!({
+        "access_token": telegraph.access_token,
+        "title": title,
+        "content": nodes,
+    });
+    if let Some(author_name) = &telegraph.author_name {
+        request["author_name"] = serde_json::Value::String(author_name.clone());
+    }
+
+    let body: serde_json::Value = http_client()
+        .post(match path {
+            Some(path) => format!("{}/{}", TELEGRAPH_EDIT_PAGE_URL, path),
+            None => TELEGRAPH_CREATE_PAGE_URL.to_string(),
+        })
+        .json(&request)
+        .send()
+        .await
+        .context("Failed to call Telegraph")?
+        .json()
+        .await
+        .context("Failed to parse the Telegraph response")?;
+    if !body["ok"].as_bool().unwrap_or(false) {
+        return Err(anyhow!("Telegraph rejected the page: {}", body["error"]));
+    }
+    match (body["result"]["url"].as_str(), body["result"]["path"].as_str()) {
+        (Some(url), Some(path)) => Ok((url.to_string(), path.to_string())),
+        _ => Err(anyhow!("Telegraph response has no page URL")),
+    }
+}
+
+/// A post laid out by its Telegram template.
+struct ComposedPost {
+    content: String,
+    /// For when Telegram rejects the formatting entities.
+    plain_text: String,
+    /// What Telegram counts against its limits (see `telegram_text_length`).
+    length: usize,
+}
+
+/// The post's HTML laid out by its Telegram template (with the date and source footer by
+/// default), its plain-text variant and its length.
+fn compose_post(item: &NewsItem, stage: &PipelineStage, templates: &PostTemplates) -> Result<ComposedPost> {
+    let fields = post_fields(item, stage, templates)?;
+    let mut template = templates.find("telegram", item.source.as_deref()).unwrap_or(DEFAULT_POST_TEMPLATE).to_string();
+    // Footer blocks end the Telegram post unless the template places them
+    if !fields.footer.is_empty() && !template.contains("{{footer}}") {
+        template.push_str("\n\n{{footer}}");
+    }
+    let content = render_post(&template, &fields, false);
+    let plain_text = Html::parse_fragment(&render_post(&template, &fields, true))
+        .root_element()
+        .text()
+        .collect::<String>()
+        .trim()
+        .to_string();
+    // Measured with the article's plaintext in place of its HTML; only the template's markup is parsed
+    let measured = render_post(&template, &PostFields { text: escape_html(&fields.plain_text), ..fields }, false);
+    Ok(ComposedPost { content, plain_text, length: telegram_text_length(&measured) })
+}
+
+fn post_fields(item: &NewsItem, stage: &PipelineStage, templates: &PostTemplates) -> Result<PostFields> {
+    // Read the file content
+    let output_artifact = format!("{}_{}.html", stage.output_artifact, artifact_suffix(item));
+    let text = read_artifact_string(&output_artifact).context("Failed to read HTML content for Telegram")?;
+    let plain_text = artifact_text(&output_artifact)?;
+
+    let summary = read_artifact_string(&format!("{}_summary_{}.html", stage.input_artifact, artifact_suffix(item)))
+        .unwrap_or_else(|_| text.clone());
+
+    // Reading time of the full article, before any shortening
+    let words = artifact_text(&format!("{}_{}.html", stage.input_artifact, artifact_suffix(item)))
+        .map(|full| full.split_whitespace().count())
+        .unwrap_or_else(|_| plain_text.split_whitespace().count());
+
+    let path = item
+        .url
+        .split_once("://")
+        .map_or(item.url.as_str(), |(_, rest)| rest.split_once('/').map_or("", |(_, path)| path));
+    let item_words = format!(" {} {} ", normalize_title(&item.title), normalize_title(path));
+    let mut hashtags: Vec<String> = templates
+        .hashtags
+        .iter()
+        .filter(|tag| item_words.contains(&format!(" {} ", tag)))
+        .map(|tag| format!("#{}", tag.replace(' ', "_")))
+        .collect();
+    let category = item.category.clone().unwrap_or_default();
+    if !category.is_empty() {
+        let tag = format!("#{}", category.replace(' ', "_"));
+        if !hashtags.contains(&tag) {
+            hashtags.insert(0, tag);
+        }
+    }
+    let emoji = templates.category_emoji.get(&category).cloned().unwrap_or_default();
+
+    let (published_label, original_label) = footer_labels(item.lang.as_deref());
+    Ok(PostFields {
+        title: item.title.clone(),
+        text,
+        plain_text,
+        summary,
+        // Parse date from database format to display format
+        date: parse_and_format_date(&item.date)?,
+        url: item.link_url().to_string(),
+        source: item.source.clone().unwrap_or_default(),
+        category,
+        emoji,
+        hashtags: hashtags.join(" "),
+        footer: item.footer.clone().unwrap_or_default(),
+        reading_time: words.div_ceil(READING_WORDS_PER_MINUTE).max(1),
+        published_label,
+        original_label,
+    })
+}
+
+/// Fills in the template. `{{link}}` is the labelled source link, or the bare URL for the
+/// plain-text variant.
+fn render_post(template: &str, fields: &PostFields, plain: bool) -> String {
+    let link = if plain {
+        escape_html(&fields.url)
+    } else {
+        format!("<a href=\"{}\">{}</a>", escape_html(&fields.url), fields.original_label)
+    };
+    // Without an emoji, the space that separates it from the text goes as well
+    let template = if fields.emoji.is_empty() {
+        template.replace("{{emoji}} ", "")
+    } else {
+        template.to_string()
+    };
+    // {{text}} and {{summary}} go last, so that braces in the article are left alone
+    template
+        .replace("{{title}}", &escape_html(&fields.title))
+        .replace("{{date}}", &fields.date)
+        .replace("{{url}}", &escape_html(&fields.url))
+        .replace("{{link}}", &link)
+        .replace("{{source}}", &escape_html(&fields.source))
+        .replace("{{category}}", &escape_html(&fields.category))
+        .replace("{{emoji}}", &fields.emoji)
+        .replace("{{hashtags}}", &fields.hashtags)
+        .replace("{{reading_time}}", &fields.reading_time.to_string())
+        .replace("{{published_label}}", fields.published_label)
+        .replace("{{original_label}}", fields.original_label)
+        .replace("{{footer}}", &fields.footer)
+        .replace("{{summary}}", fields.summary.trim())
+        .replace("{{text}}", &if plain { escape_html(&fields.plain_text) } else { fields.text.clone() })
+}
+
+/// The plaintext version of an HTML artifact: the `.txt` written next to it, or the text of the
+/// HTML for artifacts from before there were any.
+fn artifact_text(html_artifact: &str) -> Result<String> {
+    let text_artifact = format!("{}.txt", html_artifact.strip_suffix(".html").unwrap_or(html_artifact));
+    if let Some(text) = load_artifact(&text_artifact)? {
+        return String::from_utf8(text).context(format!("Artifact {} is not valid UTF-8", text_artifact));
+    }
+    let html = read_artifact_string(html_artifact)?;
+    Ok(Html::parse_document(&html).root_element().text().collect::<String>().trim().to_string())
+}
+
+/// Chat (and forum topic) the item is posted to.
+fn post_destination(tg: &TelegramContext, item: &NewsItem) -> Result<(PeerRef, Option<i32>)> {
+    let target_chat = post_chat(tg, item.lang.as_deref(), item.category.as_deref())?;
+    // Topic ids belong to TG_CHAT_ID, so category and extra-language channels are posted to directly
+    let category_chat = item.category.as_ref().is_some_and(|category| tg.category_chats.contains_key(category));
+    let topic = match &item.lang {
+        None if !category_chat => route_to_topic(&tg.topics, item),
+        _ => None,
+    };
+    Ok((target_chat, topic))
+}
+
+/// Channel of an item's language, or of its category for main-language items.
+fn post_chat(tg: &TelegramContext, lang: Option<&str>, category: Option<&str>) -> Result<PeerRef> {
+    match lang {
+        Some(lang) => tg
+            .language_chats
+            .get(lang)
+            .copied()
+            .ok_or_else(|| anyhow!("No Telegram chat configured for language '{}'", lang)),
+        None => Ok(category
+            .and_then(|category| tg.category_chats.get(category))
+            .copied()
+            .unwrap_or(tg.target_chat)),
+    }
+}
+
+/// Reads the view and forward counts of the posts published in the last ENGAGEMENT_DAYS days and
+/// appends them to `post_engagement`.
+async fn collect_engagement(conn: &Connection, tg: &TelegramContext, engagement: &EngagementPolicy) -> Result<()> {
+    let since = (Local::now() - chrono::Duration::days(engagement.days)).to_rfc3339();
+    let mut stmt = conn.prepare(
+        "SELECT p.news_id, p.lang, p.message_id, n.category FROM published_messages p JOIN news n ON n.id = p.news_id \
+         WHERE p.published_at >= ? ORDER BY p.published_at",
+    )?;
+    let posts = stmt
+        .query_map(params![since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i32>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    drop(stmt);
+    if posts.is_empty() {
+        return Ok(());
+    }
+
+    // Message ids are per channel, so posts are looked up channel by channel
+    let mut by_chat: HashMap<_, Vec<(String, String, i32)>> = HashMap::new();
+    for (news_id, lang, message_id, category) in posts {
+        // Only categories with a channel of their own matter here
+        let category = category.filter(|category| lang.is_empty() && tg.category_chats.contains_key(category));
+        by_chat.entry((lang.clone(), category)).or_default().push((news_id, lang, message_id));
+    }
+
+    let collected_at = Local::now().to_rfc3339();
+    let mut collected = 0;
+    for ((lang, category), chat_posts) in by_chat {
+        let lang_opt = (!lang.is_empty()).then_some(lang.as_str());
+        let chat = match post_chat(tg, lang_opt, category.as_deref()) {
+            Ok(chat) => chat,
+            Err(e) => {
+                warn!("Skipping engagement of {} posts: {}", chat_posts.len(), e);
+                continue;
+            }
+        };
+        for batch in chat_posts.chunks(ENGAGEMENT_BATCH_SIZE) {
+            let ids: Vec<i32> = batch.iter().map(|(_, _, message_id)| *message_id).collect();
+            let messages = tg
+                .client
+                .get_messages_by_id(chat, &ids)
+                .await
+                .context("Failed to read published messages from Telegram")?;
+            for ((news_id, lang, message_id), message) in batch.iter().zip(messages) {
+                // Deleted posts come back empty
+                let Some(message) = message else {
+                    continue;
+                };
+                conn.execute(
+                    "INSERT INTO post_engagement (news_id, lang, message_id, views, forwards, collected_at) VALUES (?, ?, ?, ?, ?, ?)",
+                    params![news_id, lang, message_id, message.view_count(), message.forward_count(), collected_at],
+                )?;
+                collected += 1;
+            }
+        }
+    }
+    info!("Collected engagement of {} posts", collected);
+    Ok(())
+}
+
+/// Replaces the text (or photo caption) of an already published post with the item's current
+/// version; the photos stay as they are. Returns true if it had to fall back to plain text.
+async fn edit_telegram_post(tg: &TelegramContext, item: &NewsItem, stage: &PipelineStage, message_id: i32) -> Result<bool> {
+    let (target_chat, _) = post_destination(tg, item)?;
+    let ComposedPost { content, plain_text, .. } = compose_post(item, stage, &tg.templates)?;
+    let preview = preview_options(tg, item);
+    let photo = preview.cover == Cover::Photo && artifact_exists(&format!("illustrator_{}.png", item.id))?;
+    match tg.client.edit_message(target_chat, message_id, preview.apply(InputMessage::new().html(&content), photo)).await {
+        Ok(()) => Ok(false),
+        Err(e) if e.to_string().to_ascii_uppercase().contains("MESSAGE_NOT_MODIFIED") => {
+            info!("Post of item {} is unchanged", item.id);
+            Ok(false)
+        }
+        Err(e) if is_entity_parse_error(&e.to_string()) => {
+            warn!(
+                "Telegram could not parse entities for item {} ({}), retrying as plain text",
+                item.id, e
+            );
+            tg.client
+                .edit_message(target_chat, message_id, preview.apply(InputMessage::new().text(&plain_text), photo))
+                .await
+                .context("Failed to edit message in Telegram as plain text")?;
+            Ok(true)
+        }
+        Err(e) => Err(anyhow!(e).context("Failed to edit message in Telegram")),
+    }
+}
+
+async fn send_to_telegram(
+    tg: &TelegramContext,
+    item: &NewsItem,
+    stage: &PipelineStage,
+    illustrated: bool,
+) -> Result<SentPost> {
+    let image = format!("illustrator_{}.png", item.id);
+    let (target_chat, topic) = post_destination(tg, item)?;
+    if let Some(topic_id) = topic {
+        info!("Posting item {} into topic {}", item.id, topic_id);
+    }
+    let ComposedPost { content, plain_text, length } = compose_post(item, stage, &tg.templates)?;
+    // Quiet hours: subscribers get the post without a notification sound
+    let silent = tg.schedule.is_quiet();
+    let preview = preview_options(tg, item);
+
+    // POST_COVER=preview or none: the illustration is not posted
+    if preview.cover != Cover::Photo {
+        return send_text_post(tg, target_chat, topic, item, &content, &plain_text).await;
+    }
+
+    if !artifact_exists(&image)? {
+        if illustrated && !item.illustration_skipped {
+            return Err(anyhow!("Illustrator image not found: {}", image));
+        }
+
+        // No illustrator stage configured: publish a text-only post.
+        return send_text_post(tg, target_chat, topic, item, &content, &plain_text).await;
+    }
+
+    // Post photo + HTML caption in a single message (user API via grammers).
+    // Evidence (pinned grammers git revision used by Cargo):
+    // - InputMessage::new().html(...).photo(...):
+    //   https://github.com/Lonami/grammers/blob/e15d820169462839173b60c3b69e0aebbeae848d/grammers-client/src/types/input_message.rs
+    // - Client::send_message(peer, message):
+    //   https://github.com/Lonami/grammers/blob/e15d820169462839173b60c3b69e0aebbeae848d/grammers-client/src/client/messages.rs
+    let uploaded = upload_photo(tg, &image).await.context("Failed to upload photo to Telegram")?;
+
+    // Extra illustrations (ILLUSTRATOR_IMAGES_PER_ITEM) turn the post into an album captioned by its first photo
+    let album_paths = album_images(item)?;
+    let mut album_photos = Vec::new();
+    for path in &album_paths {
+        album_photos.push(
+            upload_photo(tg, path)
+                .await
+                .context(format!("Failed to upload album photo to Telegram: {}", path))?,
+        );
+    }
+    let album = |caption: InputMedia| {
+        std::iter::once(caption.photo(uploaded.clone()).reply_to(topic))
+            .chain(album_photos.iter().map(|photo| InputMedia::new().photo(photo.clone())))
+            .collect::<Vec<_>>()
+    };
+
+    // A post that still doesn't fit into a caption (no summary to shorten it to) follows its photo
+    if length > TG_CAPTION_LIMIT {
+        warn!(
+            "Item {} is {} characters long, too long for a photo caption; posting the text separately",
+            item.id, length
+        );
+        if album_photos.is_empty() {
+            tg.client
+                .send_message(target_chat, InputMessage::new().photo(uploaded.clone()).reply_to(topic).silent(silent))
+                .await
+                .context("Failed to send photo to Telegram")?;
+        } else {
+            tg.client
+                .send_album(target_chat, album(InputMedia::new()))
+                .await
+                .context("Failed to send album to Telegram")?;
+        }
+        return send_text_post(tg, target_chat, topic, item, &content, &plain_text).await;
+    }
+
+    if !album_photos.is_empty() {
+        info!("Posting item {} as an album of {} photos", item.id, album_paths.len() + 1);
+        return match tg.client.send_album(target_chat, album(InputMedia::new().html(&content))).await {
+            Ok(messages) => Ok(SentPost {
+                plain: false,
+                // The album's caption is on its first photo
+                message_id: messages.first().and_then(Option::as_ref).map(|message| message.id()),
+            }),
+            Err(e) if is_entity_parse_error(&e.to_string()) => {
+                warn!(
+                    "Telegram could not parse entities for item {} ({}), retrying as plain text",
+                    item.id, e
+                );
+                let messages = tg
+                    .client
+                    .send_album(target_chat, album(InputMedia::new().caption(&plain_text)))
+                    .await
+                    .context("Failed to send plain-text album to Telegram")?;
+                Ok(SentPost {
+                    plain: true,
+                    message_id: messages.first().and_then(Option::as_ref).map(|message| message.id()),
+                })
+            }
+            Err(e) => Err(anyhow!(e).context("Failed to send album to Telegram")),
+        };
+    }
+
+    // Replying to the topic's root message posts into that forum topic (message_thread_id).
+    let message = preview.apply(InputMessage::new().html(&content).photo(uploaded.clone()).reply_to(topic).silent(silent), true);
+    match tg.client.send_message(target_chat, message).await {
+        Ok(message) => Ok(SentPost {
+            plain: false,
+            message_id: Some(message.id()),
+        }),
+        Err(e) if is_entity_parse_error(&e.to_string()) => {
+            warn!(
+                "Telegram could not parse entities for item {} ({}), retrying as plain text",
+                item.id, e
+            );
+            let message = preview.apply(InputMessage::new().text(&plain_text).photo(uploaded).reply_to(topic).silent(silent), true);
+            let message = tg
+                .client
+                .send_message(target_chat, message)
+                .await
+                .context("Failed to send plain-text message to Telegram")?;
+            Ok(SentPost {
+                plain: true,
+                message_id: Some(message.id()),
+            })
+        }
+        Err(e) => Err(anyhow!(e).context("Failed to send message to Telegram")),
+    }
+}
+
+/// Sends the post as a text message, falling back to plain text if Telegram rejects the formatting.
+async fn send_text_post(
+    tg: &TelegramContext,
+    target_chat: PeerRef,
+    topic: Option<i32>,
+    item: &NewsItem,
+    content: &str,
+    plain_text: &str,
+) -> Result<SentPost> {
+    let preview = preview_options(tg, item);
+    let result = tg
+        .client
+        .send_message(target_chat, preview.apply(InputMessage::new().html(content).reply_to(topic).silent(tg.schedule.is_quiet()), false))
+        .await;
+    match result {
+        Ok(message) => Ok(SentPost {
+            plain: false,
+            message_id: Some(message.id()),
+        }),
+        Err(e) if is_entity_parse_error(&e.to_string()) => {
+            warn!(
+                "Telegram could not parse entities for item {} ({}), retrying as plain text",
+                item.id, e
+            );
+            let message = tg
+                .client
+                .send_message(target_chat, preview.apply(InputMessage::new().text(plain_text).reply_to(topic).silent(tg.schedule.is_quiet()), false))
+                .await
+                .context("Failed to send plain-text message to Telegram")?;
+            Ok(SentPost {
+                plain: true,
+                message_id: Some(message.id()),
+            })
+        }
+        Err(e) => Err(anyhow!(e).context("Failed to send message to Telegram")),
+    }
+}
+
+/// Cover and link preview settings of the item's channel.
+fn preview_options(tg: &TelegramContext, item: &NewsItem) -> PreviewOptions {
+    channel_preview(&tg.previews, item.lang.as_deref().unwrap_or(""))
+}
+
+fn channel_preview(previews: &HashMap<String, PreviewOptions>, lang: &str) -> PreviewOptions {
+    previews
+        .get(lang)
+        .or_else(|| previews.get(""))
+        .copied()
+        .unwrap_or(PreviewOptions {
+            cover: Cover::Photo,
+            link_preview: false,
+            preview_above_text: false,
+            caption_above_photo: false,
+        })
+}
+
+/// `illustrator_<id>_<k>.png` for k = 2, 3, ... as written by the illustrator; the first photo is
+/// `illustrator_<id>.png`.
+fn album_images(item: &NewsItem) -> Result<Vec<String>> {
+    let mut images = Vec::new();
+    for k in 2..=MAX_ALBUM_IMAGES {
+        let image = format!("illustrator_{}_{}.png", item.id, k);
+        if !artifact_exists(&image)? {
+            break;
+        }
+        images.push(image);
+    }
+    Ok(images)
+}
+
+/// Uploads an image from the artifact store to Telegram.
+async fn upload_photo(tg: &TelegramContext, image: &str) -> Result<grammers_client::types::Uploaded> {
+    let bytes = read_artifact(image)?;
+    Ok(tg.client.upload_stream(&mut bytes.as_slice(), bytes.len(), image.to_string()).await?)
+}
+
+/// Telegram rejects formatting it can't render with ENTITIES_TOO_LONG or ENTITY_TEXTURL_INVALID;
+/// other errors that merely mention entities (e.g. an invalid peer) aren't fixed by plain text.
+fn is_entity_parse_error(error: &str) -> bool {
+    let error = error.to_ascii_uppercase();
+    error.contains("ENTITIES_TOO_LONG") || error.contains("ENTITY_TEXTURL_INVALID")
+}
+
+/// Footer labels ("published" and "read the original") for the post language.
+fn footer_labels(lang: Option<&str>) -> (&'static str, &'static str) {
+    match lang {
+        Some("en") => ("Published", "Read the original"),
+        Some("de") => ("Veröffentlicht", "Original lesen"),
+        Some("fr") => ("Publié", "Lire l'original"),
+        Some("es") => ("Publicado", "Leer el original"),
+        Some("uk") => ("Опубліковано", "Читати оригінал"),
+        _ => ("Опубликовано", "Читать оригинал"),
+    }
+}
+
+/// Link text pointing to the full text on Telegraph, in the post language.
+fn full_version_label(lang: Option<&str>) -> &'static str {
+    match lang {
+        Some("en") => "Full version",
+        Some("de") => "Vollständige Version",
+        Some("fr") => "Version complète",
+        Some("es") => "Versión completa",
+        Some("uk") => "Повна версія",
+        _ => "Полная версия",
+    }
+}
+
+// Function to parse and format the date
+fn parse_and_format_date(date_str: &str) -> Result<String> {
+    // First try to parse as a full RFC3339 date with timezone
+    if let Ok(dt) = DateTime::parse_from_rfc3339(date_str) {
+        // Use the original time instead of converting to UTC
+        return Ok(dt.format("%Y-%m-%d %H:%M:%S").to_string());
+    }
+    
+    // Try other common formats without timezone
+    let formats = [
+        "%Y-%m-%dT%H:%M:%S%.fZ",       // ISO 8601 with milliseconds
+        "%Y-%m-%dT%H:%M:%SZ",          // ISO 8601 without milliseconds
+        "%Y-%m-%d %H:%M:%S%.f",        // Standard format with milliseconds
+        "%Y-%m-%d %H:%M:%S",           // Standard format without milliseconds
+    ];
+    
+    for format in formats {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(date_str, format) {
+            return Ok(dt.format("%Y-%m-%d %H:%M:%S").to_string());
+        }
+    }
+    
+    // If parsing fails, use the original date string
+    warn!("Could not parse date: {}, using as is", date_str);
+    Ok(date_str.to_string())
+}
+
+/// Footer block for the post: the one it got before, otherwise the least used active footer of
+/// its language for a POST_FOOTER_RATIO share of the posts, spread evenly over them.
+fn choose_footer(conn: &Connection, item: &NewsItem, ratio: f64) -> Result<Option<String>> {
+    let lang = item.lang.as_deref().unwrap_or("");
+    let previous: Option<Option<i64>> = conn
+        .query_row(
+            "SELECT footer_id FROM post_footer_uses WHERE news_id = ? AND lang = ?",
+            params![item.id, lang],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(footer_id) = previous {
+        let Some(footer_id) = footer_id else {
+            return Ok(None);
+        };
+        // A footer deleted since then is dropped from the post
+        return Ok(conn
+            .query_row("SELECT text FROM post_footers WHERE id = ?", params![footer_id], |row| row.get(0))
+            .optional()?);
+    }
+
+    let footer: Option<(i64, String)> = conn
+        .query_row(
+            "SELECT f.id, f.text FROM post_footers f \
+             LEFT JOIN post_footer_uses u ON u.footer_id = f.id \
+             WHERE f.active = 1 AND f.lang IN ('', ?) \
+             GROUP BY f.id ORDER BY COUNT(u.footer_id), f.id LIMIT 1",
+            params![lang],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((footer_id, text)) = footer else {
+        return Ok(None);
+    };
+
+    let (posts, with_footer): (i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COUNT(footer_id) FROM post_footer_uses WHERE lang = ?",
+        params![lang],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let chosen = (with_footer as f64) < ratio * (posts + 1) as f64;
+    conn.execute(
+        "INSERT INTO post_footer_uses (news_id, lang, footer_id) VALUES (?, ?, ?)",
+        params![item.id, lang, chosen.then_some(footer_id)],
+    )?;
+    Ok(chosen.then_some(text))
+}
+
+/// Short link for the item's source URL. Every URL is shortened once and the link is kept in
+/// `short_links`, so an updated post keeps its link and its click count.
+async fn short_link(conn: &Connection, shortener: &Shortener, item: &NewsItem) -> Result<String> {
+    let known: Option<Option<String>> = conn
+        .query_row("SELECT short_url FROM short_links WHERE url = ?", params![item.url], |row| row.get(0))
+        .optional()?;
+    if let Some(Some(short_url)) = known {
+        return Ok(short_url);
+    }
+
+    let short_url = match shortener {
+        Shortener::Shlink { base_url, api_key } => {
+            let (endpoint, api_key) = (format!("{}/rest/v3/short-urls", base_url), api_key.clone());
+            let body = serde_json::json!({ "longUrl": item.url, "findIfExists": true });
+            let response = mirror_request(
+                move |client| Ok(client.post(endpoint).header("X-Api-Key", api_key).json(&body)),
+                "Shlink",
+            )
+            .await?;
+            response["shortUrl"].as_str().context("Shlink response has no shortUrl")?.to_string()
+        }
+        Shortener::Yourls { base_url, signature } => {
+            let endpoint = format!("{}/yourls-api.php", base_url);
+            let form = [
+                ("signature", signature.clone()),
+                ("action", "shorturl".to_string()),
+                ("format", "json".to_string()),
+                ("url", item.url.clone()),
+            ];
+            let response = mirror_request(move |client| Ok(client.post(endpoint).form(&form)), "YOURLS").await?;
+            response["shorturl"].as_str().context("YOURLS response has no shorturl")?.to_string()
+        }
+        Shortener::Internal { base_url } => {
+            conn.execute(
+                "INSERT OR IGNORE INTO short_links (url, news_id, created_at) VALUES (?, ?, ?)",
+                params![item.url, item.id, Local::now().to_rfc3339()],
+            )?;
+            let id: i64 = conn.query_row("SELECT id FROM short_links WHERE url = ?", params![item.url], |row| row.get(0))?;
+            let code = short_link_code(id);
+            let short_url = format!("{}/r/{}", base_url, code);
+            conn.execute(
+                "UPDATE short_links SET code = ?, short_url = ? WHERE id = ?",
+                params![code, short_url, id],
+            )?;
+            return Ok(short_url);
+        }
+    };
+    conn.execute(
+        "INSERT INTO short_links (url, short_url, news_id, created_at) VALUES (?, ?, ?, ?) \
+         ON CONFLICT(url) DO UPDATE SET short_url = excluded.short_url",
+        params![item.url, short_url, item.id, Local::now().to_rfc3339()],
+    )?;
+    Ok(short_url)
+}
+
+/// Base-36 code of a `short_links` row id.
+fn short_link_code(id: i64) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut id = id.max(0) as u64;
+    let mut code = Vec::new();
+    loop {
+        code.push(DIGITS[(id % 36) as usize]);
+        id /= 36;
+        if id == 0 {
+            break;
+        }
+    }
+    code.reverse();
+    String::from_utf8(code).unwrap_or_default()
+}
+
+/// Mirrors the item to the other services of its channel: posts it, or edits the message posted
+/// there before where the service allows it. Telegram stays the primary target: a failure here is
+/// only logged.
+async fn mirror_post(conn: &Connection, tg: &TelegramContext, item: &NewsItem, stage: &PipelineStage) -> Result<()> {
+    let lang = item.lang.as_deref().unwrap_or("");
+    let Some(targets) = tg.mirrors.get(lang) else {
+        return Ok(());
+    };
+
+    let fields = post_fields(item, stage, &tg.templates)?;
+    let (published_label, original_label) = footer_labels(item.lang.as_deref());
+    let image = format!("illustrator_{}.png", item.id);
+    let post = MirrorPost {
+        id: item.id.clone(),
+        title: item.title.clone(),
+        text: fields.plain_text.trim().to_string(),
+        url: item.link_url().to_string(),
+        footer: format!("{}: {}", published_label, parse_and_format_date(&item.date)?),
+        link_label: original_label.to_string(),
+        image: if artifact_exists(&image)? { Some(image) } else { None },
+        template_text: None,
+    };
+
+    for target in targets {
+        let template_text = tg.templates.find(target.name(), item.source.as_deref()).map(|template| {
+            let rendered = render_post(template, &fields, true);
+            Html::parse_fragment(&rendered).root_element().text().collect::<String>().trim().to_string()
+        });
+        let post = MirrorPost { template_text, ..post.clone() };
+
+        // A post that can't be edited is left as it is
+        let previous: Option<Option<String>> = conn
+            .query_row(
+                "SELECT message_id FROM mirrored_posts WHERE news_id = ? AND lang = ? AND target = ?",
+                params![item.id, lang, target.name()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let message_id = match &previous {
+            None => None,
+            Some(Some(message_id)) if target.can_edit() => Some(message_id.as_str()),
+            Some(_) => continue,
+        };
+
+        match send_to_mirror(target, &post, message_id).await {
+            Ok(sent_id) => {
+                conn.execute(
+                    "INSERT OR REPLACE INTO mirrored_posts (news_id, lang, target, message_id, posted_at) VALUES (?, ?, ?, ?, ?)",
+                    params![item.id, lang, target.name(), sent_id.as_deref().or(message_id), Local::now().to_rfc3339()],
+                )?;
+                info!(
+                    "{} item {} on {}",
+                    if message_id.is_some() { "Updated" } else { "Posted" },
+                    item.id,
+                    target.name()
+                );
+            }
+            Err(e) => warn!("Failed to post item {} to {}: {}", item.id, target.name(), e),
+        }
+    }
+    Ok(())
+}
+
+/// Posts to one mirror target, or edits `message_id` there. Returns the id of the new message if
+/// the target can edit it later.
+async fn send_to_mirror(target: &MirrorTarget, post: &MirrorPost, message_id: Option<&str>) -> Result<Option<String>> {
+    match target {
+        MirrorTarget::Discord { webhook_url } => send_discord_embed(webhook_url, post, message_id).await.map(Some),
+        MirrorTarget::Bluesky(bluesky) => send_bluesky_post(bluesky, post).await.map(Some),
+        MirrorTarget::SlackWebhook { webhook_url } => {
+            let payload = slack_message(post);
+            let webhook_url = webhook_url.clone();
+            mirror_request(move |client| Ok(client.post(&webhook_url).json(&payload)), "Slack webhook").await?;
+            Ok(None)
+        }
+        MirrorTarget::SlackBot { bot_token, channel } => {
+            let mut payload = slack_message(post);
+            payload["channel"] = serde_json::json!(channel);
+            let method = match message_id {
+                Some(ts) => {
+                    payload["ts"] = serde_json::json!(ts);
+                    "chat.update"
+                }
+                None => "chat.postMessage",
+            };
+            let url = format!("{}/{}", SLACK_API_URL, method);
+            let bot_token = bot_token.clone();
+            let response = mirror_request(move |client| Ok(client.post(&url).bearer_auth(&bot_token).json(&payload)), method).await?;
+            if !response["ok"].as_bool().unwrap_or(false) {
+                return Err(anyhow!("{} failed: {}", method, response["error"]));
+            }
+            response["ts"]
+                .as_str()
+                .map(|ts| Some(ts.to_string()))
+                .ok_or_else(|| anyhow!("{} returned no ts: {}", method, response))
+        }
+        MirrorTarget::Matrix(matrix) => send_matrix_message(matrix, post, message_id).await.map(Some),
+        MirrorTarget::Nostr(nostr) => send_nostr_note(nostr, post).await.map(Some),
+    }
+}
+
+/// Sends a request built on the shared client and returns its JSON response (`null` for an empty
+/// or non-JSON success body, such as Slack's `ok`).
+async fn mirror_request<F>(build: F, what: &str) -> Result<serde_json::Value>
+where
+    F: FnOnce(&reqwest::Client) -> Result<reqwest::RequestBuilder>,
+{
+    let response = build(http_client())?
+        .timeout(Duration::from_secs(UPLOAD_TIMEOUT_SECS))
+        .send()
+        .await
+        .context(format!("Failed to call {}", what))?;
+    let status = response.status();
+    let body = response.text().await.context(format!("Failed to read the {} response", what))?;
+    if !status.is_success() {
+        return Err(anyhow!("{} returned {}: {}", what, status, body));
+    }
+    Ok(serde_json::from_str(&body).unwrap_or(serde_json::Value::Null))
+}
+
+/// Sends the item as a rich embed (title, text, illustration, source link) through the webhook,
+/// or replaces the embed of `message_id`. Returns the Discord message id.
+async fn send_discord_embed(webhook_url: &str, post: &MirrorPost, message_id: Option<&str>) -> Result<String> {
+    let mut embed = serde_json::json!({
+        "title": truncate_chars(&post.title, DISCORD_TITLE_LIMIT),
+        "description": truncate_chars(post.template_text.as_ref().unwrap_or(&post.text), DISCORD_DESCRIPTION_LIMIT),
+        "url": post.url,
+        "footer": { "text": post.footer },
+    });
+    let mut payload = serde_json::json!({ "embeds": [] });
+    let image = match &post.image {
+        Some(image) => {
+            // The embed shows the file uploaded with the message; listing it also replaces the old one on edit
+            embed["image"] = serde_json::json!({ "url": "attachment://illustration.png" });
+            payload["attachments"] = serde_json::json!([{ "id": 0, "filename": "illustration.png" }]);
+            Some(read_artifact(image)?)
+        }
+        None => {
+            payload["attachments"] = serde_json::json!([]);
+            None
+        }
+    };
+    payload["embeds"] = serde_json::json!([embed]);
+
+    let url = match message_id {
+        Some(id) => format!("{}/messages/{}", webhook_url, id),
+        None => webhook_url.to_string(),
+    };
+    let editing = message_id.is_some();
+    let response = mirror_request(
+        move |client| {
+            let request = if editing { client.patch(&url) } else { client.post(&url) }.query(&[("wait", "true")]);
+            Ok(match image {
+                Some(bytes) => request.multipart(
+                    reqwest::multipart::Form::new()
+                        .text("payload_json", payload.to_string())
+                        .part(
+                            "files[0]",
+                            reqwest::multipart::Part::bytes(bytes)
+                                .file_name("illustration.png")
+                                .mime_str("image/png")?,
+                        ),
+                ),
+                None => request.json(&payload),
+            })
+        },
+        "Discord webhook",
+    )
+    .await?;
+    response["id"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Discord webhook response has no message id: {}", response))
+}
+
+/// Creates a Bluesky post through the AT protocol: a link card for the source with the
+/// illustration as its thumbnail, and the title plus the source URL as the text. Returns its
+/// at:// URI.
+async fn send_bluesky_post(bluesky: &Bluesky, post: &MirrorPost) -> Result<String> {
+    let description = truncate_chars(&post.text, BLUESKY_CARD_DESCRIPTION_LIMIT);
+
+    // The source URL ends the text unless a template places it; the link facet makes it clickable
+    // (offsets are UTF-8 bytes)
+    let text = match &post.template_text {
+        Some(template_text) => truncate_chars(template_text, BLUESKY_POST_LIMIT),
+        None => {
+            let title_limit = BLUESKY_POST_LIMIT.saturating_sub(post.url.chars().count() + 2).max(1);
+            format!("{}\n\n{}", truncate_chars(&post.title, title_limit), post.url)
+        }
+    };
+    let facets: Vec<serde_json::Value> = text
+        .find(post.url.as_str())
+        .map(|url_start| {
+            serde_json::json!({
+                "index": { "byteStart": url_start, "byteEnd": url_start + post.url.len() },
+                "features": [{ "$type": "app.bsky.richtext.facet#link", "uri": post.url }],
+            })
+        })
+        .into_iter()
+        .collect();
+
+    let thumb = match post.image.as_deref().map(read_artifact) {
+        Some(Ok(bytes)) if bytes.len() <= BLUESKY_MAX_THUMB_BYTES => Some(bytes),
+        Some(Ok(bytes)) => {
+            warn!(
+                "Illustration of item {} is {} bytes, too large for a Bluesky link card; posting it without one",
+                post.id,
+                bytes.len()
+            );
+            None
+        }
+        _ => None,
+    };
+
+    let client = http_client();
+    let session = bluesky_call(
+        client
+            .post(format!("{}/xrpc/com.atproto.server.createSession", bluesky.pds_url))
+            .json(&serde_json::json!({ "identifier": bluesky.handle, "password": bluesky.app_password })),
+        "createSession",
+    )
+    .await?;
+    let access_jwt = session["accessJwt"].as_str().ok_or_else(|| anyhow!("createSession returned no accessJwt"))?;
+    let did = session["did"].as_str().ok_or_else(|| anyhow!("createSession returned no did"))?;
+
+    let mut external = serde_json::json!({ "uri": post.url, "title": post.title, "description": description });
+    if let Some(bytes) = thumb {
+        let uploaded = bluesky_call(
+            client
+                .post(format!("{}/xrpc/com.atproto.repo.uploadBlob", bluesky.pds_url))
+                .timeout(Duration::from_secs(UPLOAD_TIMEOUT_SECS))
+                .bearer_auth(access_jwt)
+                .header(reqwest::header::CONTENT_TYPE, "image/png")
+                .body(bytes),
+            "uploadBlob",
+        )
+        .await?;
+        external["thumb"] = uploaded["blob"].clone();
+    }
+
+    let record = serde_json::json!({
+        "$type": "app.bsky.feed.post",
+        "text": text,
+        "createdAt": Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        "facets": facets,
+        "embed": { "$type": "app.bsky.embed.external", "external": external },
+    });
+    let created = bluesky_call(
+        client
+            .post(format!("{}/xrpc/com.atproto.repo.createRecord", bluesky.pds_url))
+            .bearer_auth(access_jwt)
+            .json(&serde_json::json!({ "repo": did, "collection": "app.bsky.feed.post", "record": record })),
+        "createRecord",
+    )
+    .await?;
+    created["uri"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("createRecord returned no uri: {}", created))
+}
+
+async fn bluesky_call(request: reqwest::RequestBuilder, method: &str) -> Result<serde_json::Value> {
+    let response = request.send().await.context(format!("Failed to call {}", method))?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().await.context(format!("Failed to parse the {} response", method))?;
+    if !status.is_success() {
+        return Err(anyhow!("{} failed with {}: {} {}", method, status, body["error"], body["message"]));
+    }
+    Ok(body)
+}
+
+/// Block Kit message: the title as a header, the text, and the date with the source link. Slack
+/// blocks only show images from public URLs, so the illustration is left out.
+fn slack_message(post: &MirrorPost) -> serde_json::Value {
+    let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    serde_json::json!({
+        "text": post.title,
+        "blocks": [
+            { "type": "header", "text": { "type": "plain_text", "text": truncate_chars(&post.title, SLACK_HEADER_LIMIT) } },
+            { "type": "section", "text": { "type": "mrkdwn", "text": truncate_chars(&escape(post.template_text.as_ref().unwrap_or(&post.text)), SLACK_SECTION_LIMIT) } },
+            { "type": "context", "elements": [{
+                "type": "mrkdwn",
+                "text": format!("{} · <{}|{}>", escape(&post.footer), post.url, escape(&post.link_label)),
+            }] },
+        ],
+    })
+}
+
+/// Posts the illustration and an HTML message to the Matrix room, or replaces the text of the
+/// message `event_id` (images are not replaced). Returns the event id of the text message.
+async fn send_matrix_message(matrix: &Matrix, post: &MirrorPost, event_id: Option<&str>) -> Result<String> {
+    let (body, formatted_body) = match &post.template_text {
+        Some(text) => (text.clone(), escape_html(text).replace('\n', "<br>")),
+        None => (
+            format!("{}\n\n{}\n\n{}\n{}", post.title, post.text, post.footer, post.url),
+            format!(
+                "<h3><a href=\"{}\">{}</a></h3><p>{}</p><p><i>{}</i></p>",
+                escape_html(&post.url),
+                escape_html(&post.title),
+                escape_html(&post.text).replace('\n', "<br>"),
+                escape_html(&post.footer)
+            ),
+        ),
+    };
+    let content = serde_json::json!({
+        "msgtype": "m.text",
+        "body": body,
+        "format": "org.matrix.custom.html",
+        "formatted_body": formatted_body,
+    });
+
+    let message = match event_id {
+        Some(event_id) => serde_json::json!({
+            "msgtype": "m.text",
+            "body": format!("* {}", body),
+            "m.new_content": content,
+            "m.relates_to": { "rel_type": "m.replace", "event_id": event_id },
+        }),
+        None => {
+            if let Some(image) = &post.image {
+                let bytes = read_artifact(image)?;
+                let size = bytes.len();
+                let upload_url = format!("{}/_matrix/media/v3/upload", matrix.homeserver_url);
+                let access_token = matrix.access_token.clone();
+                let uploaded = mirror_request(
+                    move |client| {
+                        Ok(client
+                            .post(&upload_url)
+                            .query(&[("filename", "illustration.png")])
+                            .bearer_auth(&access_token)
+                            .header(reqwest::header::CONTENT_TYPE, "image/png")
+                            .body(bytes))
+                    },
+                    "Matrix media upload",
+                )
+                .await?;
+                let content_uri = uploaded["content_uri"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Matrix media upload returned no content_uri: {}", uploaded))?;
+                send_matrix_event(
+                    matrix,
+                    &format!("{}-image", post.id),
+                    serde_json::json!({
+                        "msgtype": "m.image",
+                        "body": "illustration.png",
+                        "url": content_uri,
+                        "info": { "mimetype": "image/png", "size": size },
+                    }),
+                )
+                .await?;
+            }
+            content
+        }
+    };
+    send_matrix_event(matrix, &post.id, message).await
+}
+
+/// Sends an `m.room.message` event and returns its event id.
+async fn send_matrix_event(matrix: &Matrix, txn_prefix: &str, content: serde_json::Value) -> Result<String> {
+    // The transaction id only has to be unique for the access token
+    let txn_id = format!("robo-news-{}-{}", txn_prefix, Utc::now().timestamp_millis());
+    let mut url = reqwest::Url::parse(&matrix.homeserver_url).context("MATRIX_HOMESERVER_URL is not a valid URL")?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("MATRIX_HOMESERVER_URL is not a valid homeserver URL"))?
+        .pop_if_empty()
+        .extend(["_matrix", "client", "v3", "rooms", &matrix.room_id, "send", "m.room.message", &txn_id]);
+    let access_token = matrix.access_token.clone();
+    let response = mirror_request(move |client| Ok(client.put(url).bearer_auth(&access_token).json(&content)), "Matrix send").await?;
+    response["event_id"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Matrix returned no event_id: {}", response))
+}
+
+/// Broadcasts a kind-1 note with the headline, the source link and the illustration's URL to the
+/// relays. Returns the note id once at least one relay accepted it.
+async fn send_nostr_note(nostr: &Nostr, post: &MirrorPost) -> Result<String> {
+    // Nostr clients load images by URL, so the illustration is hosted on telegra.ph
+    let image_url = match &post.image {
+        Some(image) => {
+            let uploaded = match read_artifact(image) {
+                Ok(bytes) => upload_to_telegraph(bytes, "image/png").await,
+                Err(e) => Err(e),
+            };
+            match uploaded {
+                Ok(url) => Some(url),
+                Err(e) => {
+                    warn!("Failed to host the illustration of item {} for Nostr: {}", post.id, e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    let mut content = post
+        .template_text
+        .clone()
+        .unwrap_or_else(|| format!("{}\n\n{}", post.title, post.url));
+    let mut tags = vec![serde_json::json!(["r", post.url])];
+    if let Some(image_url) = &image_url {
+        content.push_str(&format!("\n\n{}", image_url));
+        tags.push(serde_json::json!(["imeta", format!("url {}", image_url), "m image/png"]));
+    }
+    let event = sign_nostr_event(&nostr.keypair, 1, tags, &content)?;
+    let event_id = event["id"].as_str().unwrap_or_default().to_string();
+
+    let relays = nostr.relays.clone();
+    let news_id = post.id.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut accepted = 0;
+        for relay in &relays {
+            match publish_to_relay(relay, &event) {
+                Ok(()) => accepted += 1,
+                Err(e) => warn!("Nostr relay {} did not take item {}: {}", relay, news_id, e),
+            }
+        }
+        if accepted == 0 {
+            return Err(anyhow!("no relay accepted the note"));
+        }
+        Ok(())
+    })
+    .await
+    .context("Nostr task failed")??;
+    Ok(event_id)
+}
+
+/// Builds a signed NIP-01 event: the id is the SHA-256 of the compact JSON array
+/// `[0, pubkey, created_at, kind, tags, content]`, signed with a BIP-340 Schnorr signature.
+fn sign_nostr_event(keypair: &secp256k1::Keypair, kind: u32, tags: Vec<serde_json::Value>, content: &str) -> Result<serde_json::Value> {
+    let secp = secp256k1::Secp256k1::new();
+    let pubkey = hex::encode(keypair.x_only_public_key().0.serialize());
+    let created_at = Utc::now().timestamp();
+    let serialized = serde_json::to_string(&serde_json::json!([0, pubkey, created_at, kind, tags, content]))?;
+    let id: [u8; 32] = Sha256::digest(serialized.as_bytes()).into();
+    let signature = secp.sign_schnorr_no_aux_rand(&secp256k1::Message::from_digest(id), keypair);
+    Ok(serde_json::json!({
+        "id": hex::encode(id),
+        "pubkey": pubkey,
+        "created_at": created_at,
+        "kind": kind,
+        "tags": tags,
+        "content": content,
+        "sig": hex::encode(signature.as_ref()),
+    }))
+}
+
+/// Sends the event to one relay and waits for its `["OK", <id>, <accepted>, <message>]` answer.
+fn publish_to_relay(relay: &str, event: &serde_json::Value) -> Result<()> {
+    let (mut socket, _) = tungstenite::connect(relay).context("Failed to connect")?;
+    let timeout = Some(std::time::Duration::from_secs(NOSTR_RELAY_TIMEOUT_SECS));
+    match socket.get_mut() {
+        tungstenite::stream::MaybeTlsStream::Plain(stream) => stream.set_read_timeout(timeout)?,
+        tungstenite::stream::MaybeTlsStream::NativeTls(stream) => stream.get_mut().set_read_timeout(timeout)?,
+        _ => {}
+    }
+
+    socket.send(tungstenite::Message::text(serde_json::json!(["EVENT", event]).to_string()))?;
+    let id = event["id"].as_str().unwrap_or_default();
+    loop {
+        // Relays may send notices or other subscriptions' events first
+        let message = socket.read().context("No answer from the relay")?;
+        let Ok(reply) = serde_json::from_str::<serde_json::Value>(message.to_text().unwrap_or_default()) else {
+            continue;
+        };
+        if reply[0] == "OK" && reply[1] == id {
+            let _ = socket.close(None);
+            return match reply[2].as_bool() {
+                Some(true) => Ok(()),
+                _ => Err(anyhow!("rejected: {}", reply[3])),
+            };
+        }
+    }
+}
+
+fn truncate_chars(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(limit - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Appends the published item to the `publish_chain` table.
+///
+/// The content hash covers the published HTML and the illustration; the record hash covers the
+/// previous record hash, so editing or deleting any earlier record breaks every later one
+/// (see `ctl verify-chain`).
+fn append_to_publish_chain(conn: &Connection, item: &NewsItem, stage: &PipelineStage, status: &Status) -> Result<()> {
+    let html = format!("{}_{}.html", stage.output_artifact, artifact_suffix(item));
+    let image = format!("illustrator_{}.png", item.id);
+
+    let mut content = Sha256::new();
+    content.update(read_artifact(&html).context("Failed to read published file")?);
+    if let Some(bytes) = load_artifact(&image)? {
+        content.update(bytes);
+        for album_image in album_images(item)? {
+            content.update(read_artifact(&album_image).context("Failed to read image")?);
+        }
+    }
+    let content_hash = hex::encode(content.finalize());
+
+    let prev_hash: String = conn
+        .query_row("SELECT record_hash FROM publish_chain ORDER BY seq DESC LIMIT 1", [], |row| row.get(0))
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(GENESIS_HASH.to_string()),
+            e => Err(e),
+        })?;
+
+    let lang = item.lang.as_deref().unwrap_or("");
+    let published_at = Local::now().to_rfc3339();
+    let record_hash = publish_record_hash(&prev_hash, &item.id, lang, status.as_str(), &published_at, &content_hash);
+
+    conn.execute(
+        "INSERT INTO publish_chain (news_id, lang, status, published_at, content_hash, prev_hash, record_hash) \
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![item.id, lang, status, published_at, content_hash, prev_hash, record_hash],
+    )?;
+    Ok(())
+}
+
+/// Counts a failed publishing attempt and moves the item to `publisher_retry`, due after the
+/// backoff delay or the FLOOD_WAIT Telegram asked for, whichever is longer; after the last attempt
+/// it becomes a `publish_error`.
+fn fail_publish(conn: &Connection, item: &NewsItem, policy: &RetryPolicy, error_msg: &str) -> Result<()> {
+    with_immediate_transaction(conn, |conn| {
+        let lang = item.lang.as_deref();
+        let attempts = record_failure(conn, &item.id, lang, error_msg)?;
+        let delay = policy.delay_before(attempts).max(flood_wait_secs(error_msg).unwrap_or(0));
+        let next_attempt_at = Utc::now().timestamp() + delay;
+        match lang {
+            Some(lang) => conn.execute(
+                "UPDATE news_translations SET next_attempt_at = ? WHERE news_id = ? AND lang = ?",
+                params![next_attempt_at, item.id, lang],
+            )?,
+            None => conn.execute(
+                "UPDATE news SET next_attempt_at = ? WHERE id = ?",
+                params![next_attempt_at, item.id],
+            )?,
+        };
+
+        if attempts >= policy.max_attempts {
+            update_status(conn, &item.id, lang, &item.status, &Status::PublishError)?;
+            error!("Item {} failed {} times, giving up: {}", item.id, attempts, error_msg);
+        } else {
+            update_status(conn, &item.id, lang, &item.status, &Status::PublisherRetry)?;
+            warn!(
+                "Item {} failed (attempt {}/{}), retrying in {} seconds: {}",
+                item.id, attempts, policy.max_attempts, delay, error_msg
+            );
+        }
+        Ok(())
+    })
+}
+
+/// Seconds from a `FLOOD_WAIT_<n>` (or `FLOOD_PREMIUM_WAIT_<n>`) error of the Telegram API.
+fn flood_wait_secs(error: &str) -> Option<i64> {
+    let (_, rest) = error.split_once("WAIT_")?;
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+// Note: Bot API specific retry-after parsing was removed when migrating to user API.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_html_escapes_markup_characters() {
+        assert_eq!(escape_html(r#"a < b & "c" > d"#), "a &lt; b &amp; &quot;c&quot; &gt; d");
+        // An entity in the text is text too
+        assert_eq!(escape_html("&lt;"), "&amp;lt;");
+        assert_eq!(escape_html("plain text, 'quoted'"), "plain text, 'quoted'");
+    }
+
+    #[test]
+    fn telegram_text_length_counts_the_visible_text_in_utf16() {
+        assert_eq!(telegram_text_length("<b>Hi</b> &amp; <a href=\"https://example.com\">bye</a>"), 8);
+        assert_eq!(telegram_text_length("\n  <i>x</i>\n\n"), 1);
+        // Characters outside the Basic Multilingual Plane take two code units
+        assert_eq!(telegram_text_length("😀 ok"), 5);
+        assert_eq!(telegram_text_length("Ćao"), 3);
+    }
+
+    #[test]
+    fn telegram_text_length_of_escaped_text_is_the_text_length() {
+        let text = r#"1 < 2 && "3" > 0"#;
+        assert_eq!(telegram_text_length(&escape_html(text)), text.encode_utf16().count());
+    }
+
+    #[test]
+    fn transform_html_escapes_text_and_link_targets() {
+        let html = r#"<html><body><p>Prices &lt; 5 &amp; "rising" &gt; 3</p><p><a href="https://example.com/?a=1&amp;b=&quot;2&quot;">Source</a></p></body></html>"#;
+        assert_eq!(
+            transform_html(html).unwrap(),
+            "Prices &lt; 5 &amp; &quot;rising&quot; &gt; 3\n\n<a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\">Source</a>\n\n"
+        );
+    }
+
+    #[test]
+    fn transform_html_keeps_only_the_tags_telegram_supports() {
+        let html = r#"<html><head><title>Page</title></head><body><h2>Q&amp;A</h2><p>Read <strong>this</strong> <a href="https://example.com">here</a> <span class="x">now</span><br>and <i>more</i></p><script>alert("<b>")</script><div>Footer<img src="a.png"></div></body></html>"#;
+        assert_eq!(
+            transform_html(html).unwrap(),
+            "<b>Q&amp;A</b>\n\nRead <b>this</b> <a href=\"https://example.com\">here</a> now\nand more\n\nFooter\n\n"
+        );
+    }
+
+    #[test]
+    fn transform_html_accepts_fragments() {
+        // The parser puts a fragment into a body of its own
+        assert_eq!(transform_html("<p>text</p>").unwrap(), "text\n\n");
+    }
+}
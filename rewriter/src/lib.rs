@@ -0,0 +1,2209 @@
+use anyhow::{Context, Result, anyhow};
+use rusqlite::{params, Connection, Row};
+use robo_news_core::{add_usage, block_on, cancellable, check_daily_budget, claim_item, clear_provider_error, effective_chunk_chars, extract_html_response, gemini_reasoning_effort_from_reasoning, heartbeat, item_span, listen_for_shutdown, load_artifact, load_pipeline_stage, looks_like_html, open_db, parse_chat_body, parse_provider_error, perplexity_reasoning_effort_from_reasoning, process_in_chunks, read_artifact_string, read_attempt_policy_from_env, read_concurrency_from_env, read_daily_budget_from_env, read_glossary_from_env, read_interval_from_env, read_price_from_env, read_reasoning_config_from_env, read_sampling_config_from_env, record_failed_attempt, record_provider_error, record_usage, redact_secrets, release_claim, remove_artifact, reset_attempts, run_async, run_workers, send_dead_letter_alerts, shutdown_requested, take_usage, track_usage, unix_now, update_status, wait_for_wakeup, wakeup_seq, write_artifact, ApiError, AttemptPolicy, Cancelled, Concurrency, ConcurrencyTuner, DailyBudget, GeminiChatRequest, Glossary, GlossaryTerm, Message, OpenRouterChatRequest, PerplexityChatRequest, PipelineStage, ReasoningConfig, SamplingConfig, Status, UsageModel, WORKER_CONNECTIONS};
+use tracing::{debug, error, info, warn, Instrument};
+use reqwest::{Client, RequestBuilder, Response};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
+use std::sync::{Arc, Mutex, OnceLock};
+
+const DATA_DIR: &str = "data";
+const DEFAULT_REWRITE_INTERVAL_SECS: u64 = 60;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 120;
+const ANTHROPIC_API_VERSION: &str = "2023-06-01";
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 16000;
+// Rate-limited (429) requests are retried in place this many times before the item is left for the next cycle
+const DEFAULT_RATE_LIMIT_RETRIES: u32 = 5;
+const RATE_LIMIT_BASE_DELAY_SECS: u64 = 5;
+const DEFAULT_RATE_LIMIT_MAX_DELAY_SECS: u64 = 300;
+const DEFAULT_CACHE_DAYS: u64 = 30;
+const AI_LOG_DIR: &str = "data/ai_log";
+const DEFAULT_FACT_CHECK_PROMPT: &str = "You check a news rewrite against its source. The user message contains the SOURCE and the REWRITE. \
+List every name, number, date, quote or claim in the REWRITE that the SOURCE does not support. \
+Answer with an HTML document only: <html><body><p>OK</p></body></html> when everything is supported, \
+otherwise <html><body><ul><li>one unsupported claim per item</li></ul></body></html>.";
+const DEFAULT_CATEGORIES: &str = "politics,tech,sport,economy";
+const DEFAULT_CATEGORY_PROMPT: &str = "You assign a news article to exactly one of these categories: {{categories}}. \
+Answer with the category name only, in lowercase, without any other text.";
+
+// Set by the worker that hit a 429, so parallel workers hold their requests until the limit resets
+static RATE_LIMITED_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+// Set at startup with AI_PROVIDER_REWRITER_ADAPTIVE_CONCURRENCY, fed by every provider call
+static CONCURRENCY_TUNER: OnceLock<ConcurrencyTuner> = OnceLock::new();
+
+tokio::task_local! {
+    /// Requests and raw responses of the item being processed in this task (AI_PROVIDER_REWRITER_LOG_REQUESTS).
+    static AI_EXCHANGES: RefCell<Vec<serde_json::Value>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AiProviderType {
+    OpenRouter,
+    Perplexity,
+    Gemini,
+    OpenAi,
+    Anthropic,
+    /// Any OpenAI-compatible server (Ollama, vLLM, LM Studio) at AI_PROVIDER_REWRITER_BASE_URL.
+    OpenAiCompatible,
+}
+
+impl AiProviderType {
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "openrouter" => Ok(Self::OpenRouter),
+            "perplexity" => Ok(Self::Perplexity),
+            "gemini" => Ok(Self::Gemini),
+            "openai" => Ok(Self::OpenAi),
+            "anthropic" | "claude" => Ok(Self::Anthropic),
+            "openaicompatible" | "openai_compatible" | "ollama" | "local" => Ok(Self::OpenAiCompatible),
+            other => Err(anyhow!(
+                "AI_PROVIDER_REWRITER_TYPE must be either 'OpenRouter', 'Perplexity', 'Gemini', 'OpenAI', 'Anthropic', or 'OpenAICompatible' (got '{}')",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AiProviderConfig {
+    provider_type: AiProviderType,
+    api_key: String,
+    api_url: Option<String>,
+    model: String,
+    prompt: String,
+    reasoning: Option<ReasoningConfig>,
+    /// Articles longer than this many characters are rewritten chunk by chunk (0 = off).
+    chunk_chars: usize,
+    /// Model context size in tokens; longer inputs are chunked before the call (0 = off).
+    context_tokens: usize,
+    /// Temperature, top_p and output token limit (AI_PROVIDER_REWRITER_TEMPERATURE, _TOP_P, _MAX_TOKENS).
+    sampling: SamplingConfig,
+    /// USD per million prompt/completion tokens, used for the cost estimate in `ai_usage`.
+    input_price: f64,
+    output_price: f64,
+    /// Base URL of an OpenAI-compatible server, e.g. `http://localhost:11434/v1`.
+    base_url: Option<String>,
+    /// How often a 429 response is retried after waiting (AI_PROVIDER_REWRITER_RATE_LIMIT_RETRIES).
+    rate_limit_retries: u32,
+    /// Upper bound for a single wait, in seconds (AI_PROVIDER_REWRITER_RATE_LIMIT_MAX_DELAY_SECS).
+    rate_limit_max_delay_secs: u64,
+    /// Items rewritten in parallel, optionally tuned (AI_PROVIDER_REWRITER_CONCURRENCY and co.).
+    concurrency: Concurrency,
+    /// Save every request and raw response per item to `data/ai_log/` (AI_PROVIDER_REWRITER_LOG_REQUESTS).
+    log_requests: bool,
+    /// How long successful rewrites are reused for identical input (AI_PROVIDER_REWRITER_CACHE_DAYS, 0 = off).
+    cache_days: u64,
+    /// Built once at startup with AI_PROVIDER_REWRITER_TIMEOUT_SECS and shared by all requests.
+    client: Client,
+    /// Second model that checks rewrites against their source (AI_PROVIDER_REWRITER_FACT_CHECK_MODEL).
+    fact_check: Option<FactCheckConfig>,
+    /// Prompts for items of a given source (AI_PROVIDER_REWRITER_SOURCE_PROMPTS), as (source, prompt).
+    source_prompts: Vec<(String, String)>,
+    /// Prompt for a second headline/lead variant of every rewrite (AI_PROVIDER_REWRITER_VARIANT_PROMPT).
+    variant_prompt: Option<String>,
+    /// Prompt for a 2-3 sentence summary of every rewrite (AI_PROVIDER_REWRITER_SUMMARY_PROMPT).
+    summary_prompt: Option<String>,
+    /// Assigns a category to every rewrite (AI_PROVIDER_REWRITER_CATEGORY_MODEL, CATEGORY_KEYWORDS_FILE).
+    classifier: Option<ClassifierConfig>,
+}
+
+impl AiProviderConfig {
+    /// Provider, model and prices for the cost estimate in `ai_usage`.
+    fn usage_model(&self) -> UsageModel<'_> {
+        UsageModel {
+            provider: format!("{:?}", self.provider_type),
+            model: &self.model,
+            input_price: self.input_price,
+            output_price: self.output_price,
+        }
+    }
+}
+
+/// Same provider and key as the rewriter, usually with a cheaper model.
+#[derive(Debug, Clone)]
+struct FactCheckConfig {
+    model: String,
+    prompt: String,
+    input_price: f64,
+    output_price: f64,
+}
+
+/// Category classification of rewrites, stored in `news.category` for the publisher. The model
+/// (same provider and key as the rewriter) is asked first; the keyword list is used without a
+/// model or when its answer isn't one of the categories.
+#[derive(Debug, Clone)]
+struct ClassifierConfig {
+    categories: Vec<String>,
+    /// `(category, keywords)` from CATEGORY_KEYWORDS_FILE, keywords in lowercase.
+    keywords: Vec<(String, Vec<String>)>,
+    model: Option<String>,
+    prompt: String,
+    input_price: f64,
+    output_price: f64,
+}
+
+struct NewsItem {
+    id: String,
+    // Keep these fields even though they're not directly used in our code
+    // because they are part of the database schema and are returned by the query
+    #[allow(dead_code)]
+    title: String,
+    #[allow(dead_code)]
+    url: String,
+    #[allow(dead_code)]
+    date: String,
+    status: Status,
+    /// Extra target language of a `news_translations` row; `None` for the main `news` row.
+    lang: Option<String>,
+    /// Feed the item came from (`news.source`), used to pick a per-source prompt.
+    source: Option<String>,
+    /// Sent back by an operator, so the rewrite must not come from `rewriter_cache`.
+    skip_cache: bool,
+}
+
+/// An extra target language from TARGET_LANGUAGES (all entries after the first one).
+struct TargetLanguage {
+    code: String,
+    prompt: String,
+}
+
+#[derive(Serialize)]
+struct OpenAiChatRequest {
+    model: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    /// OpenAI's replacement for `max_tokens`; the only limit its reasoning models accept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(flatten)]
+    sampling: SamplingConfig,
+}
+
+// Anthropic Messages API docs:
+// - https://docs.anthropic.com/en/api/messages
+// Endpoint:
+//   POST https://api.anthropic.com/v1/messages
+// Auth:
+//   x-api-key: <ANTHROPIC_API_KEY>, anthropic-version: 2023-06-01
+#[derive(Serialize)]
+struct AnthropicMessagesRequest {
+    model: String,
+    max_tokens: u32,
+    system: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<AnthropicThinking>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct AnthropicThinking {
+    #[serde(rename = "type")]
+    kind: String,
+    budget_tokens: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicMessagesResponse {
+    #[serde(default)]
+    content: Vec<AnthropicContentBlock>,
+    stop_reason: Option<String>,
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+}
+
+/// Starts the rewriter with the provider, prompts and budget from the environment and rewrites
+/// translated items until the process ends, or for one cycle with `--once`.
+pub fn run(options: RunOptions) -> Result<()> {
+    let interval_secs = read_interval_from_env("REWRITER_INTERVAL_SECS", DEFAULT_REWRITE_INTERVAL_SECS)?;
+
+    // Check required environment variables
+    let provider_type = AiProviderType::parse(
+        &env::var("AI_PROVIDER_REWRITER_TYPE").context("AI_PROVIDER_REWRITER_TYPE environment variable not set")?,
+    )?;
+
+    let model = env::var("AI_PROVIDER_REWRITER_MODEL").context("AI_PROVIDER_REWRITER_MODEL environment variable not set")?;
+    let prompt = read_prompt_from_env("AI_PROVIDER_REWRITER_PROMPT")?
+        .context("AI_PROVIDER_REWRITER_PROMPT or AI_PROVIDER_REWRITER_PROMPT_FILE environment variable not set")?;
+    // Local OpenAI-compatible servers usually run without authentication.
+    let api_key = match env::var("AI_PROVIDER_REWRITER_API_KEY") {
+        Ok(api_key) => api_key,
+        Err(_) if provider_type == AiProviderType::OpenAiCompatible => String::new(),
+        Err(e) => return Err(e).context("AI_PROVIDER_REWRITER_API_KEY environment variable not set"),
+    };
+    let api_url = env::var("AI_PROVIDER_REWRITER_API_URL")
+        .ok()
+        .filter(|value| !value.trim().is_empty());
+    let base_url = env::var("AI_PROVIDER_REWRITER_BASE_URL")
+        .ok()
+        .map(|value| value.trim().trim_end_matches('/').to_string())
+        .filter(|value| !value.is_empty());
+    if provider_type == AiProviderType::OpenAiCompatible && base_url.is_none() && api_url.is_none() {
+        return Err(anyhow!(
+            "AI_PROVIDER_REWRITER_BASE_URL environment variable not set (required for OpenAICompatible)"
+        ));
+    }
+
+    let reasoning = read_reasoning_config_from_env("AI_PROVIDER_REWRITER");
+
+    let context_tokens = match env::var("AI_PROVIDER_REWRITER_CONTEXT_TOKENS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| anyhow!("AI_PROVIDER_REWRITER_CONTEXT_TOKENS must be a non-negative integer: {}", e))?,
+        _ => 0,
+    };
+
+    let chunk_chars = match env::var("AI_PROVIDER_REWRITER_CHUNK_CHARS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| anyhow!("AI_PROVIDER_REWRITER_CHUNK_CHARS must be a non-negative integer: {}", e))?,
+        _ => 0,
+    };
+
+    let sampling = read_sampling_config_from_env("AI_PROVIDER_REWRITER")?;
+    let input_price = read_price_from_env("AI_PROVIDER_REWRITER_PRICE_INPUT")?;
+    let output_price = read_price_from_env("AI_PROVIDER_REWRITER_PRICE_OUTPUT")?;
+
+    let concurrency = read_concurrency_from_env("AI_PROVIDER_REWRITER")?;
+
+    let log_requests = match env::var("AI_PROVIDER_REWRITER_LOG_REQUESTS") {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "" | "0" | "false" | "no" | "off" => false,
+            "1" | "true" | "yes" | "on" => true,
+            other => return Err(anyhow!("AI_PROVIDER_REWRITER_LOG_REQUESTS must be true or false (got '{}')", other)),
+        },
+        Err(_) => false,
+    };
+
+    let cache_days = match env::var("AI_PROVIDER_REWRITER_CACHE_DAYS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u64>()
+            .map_err(|e| anyhow!("AI_PROVIDER_REWRITER_CACHE_DAYS must be a non-negative integer: {}", e))?,
+        _ => DEFAULT_CACHE_DAYS,
+    };
+
+    let rate_limit_retries = match env::var("AI_PROVIDER_REWRITER_RATE_LIMIT_RETRIES") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| anyhow!("AI_PROVIDER_REWRITER_RATE_LIMIT_RETRIES must be a non-negative integer: {}", e))?,
+        _ => DEFAULT_RATE_LIMIT_RETRIES,
+    };
+
+    let rate_limit_max_delay_secs = match env::var("AI_PROVIDER_REWRITER_RATE_LIMIT_MAX_DELAY_SECS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u64>()
+            .map_err(|e| anyhow!("AI_PROVIDER_REWRITER_RATE_LIMIT_MAX_DELAY_SECS must be a positive integer: {}", e))?,
+        _ => DEFAULT_RATE_LIMIT_MAX_DELAY_SECS,
+    };
+
+    let timeout_secs = match env::var("AI_PROVIDER_REWRITER_TIMEOUT_SECS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .ok_or_else(|| anyhow!("AI_PROVIDER_REWRITER_TIMEOUT_SECS must be a positive integer, got '{}'", value.trim()))?,
+        _ => DEFAULT_REQUEST_TIMEOUT_SECS,
+    };
+    let client = Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .context("Failed to build HTTP client")?;
+
+    let fact_check = match env::var("AI_PROVIDER_REWRITER_FACT_CHECK_MODEL") {
+        Ok(model) if !model.trim().is_empty() => Some(FactCheckConfig {
+            model: model.trim().to_string(),
+            prompt: read_prompt_from_env("AI_PROVIDER_REWRITER_FACT_CHECK_PROMPT")?
+                .unwrap_or_else(|| DEFAULT_FACT_CHECK_PROMPT.to_string()),
+            input_price: read_price_from_env("AI_PROVIDER_REWRITER_FACT_CHECK_PRICE_INPUT")?,
+            output_price: read_price_from_env("AI_PROVIDER_REWRITER_FACT_CHECK_PRICE_OUTPUT")?,
+        }),
+        _ => None,
+    };
+
+    let variant_prompt = read_prompt_from_env("AI_PROVIDER_REWRITER_VARIANT_PROMPT")?;
+    let summary_prompt = read_prompt_from_env("AI_PROVIDER_REWRITER_SUMMARY_PROMPT")?;
+    let source_prompts = read_source_prompts_from_env()?;
+    let classifier = read_classifier_from_env()?;
+
+    let mut provider = AiProviderConfig {
+        provider_type,
+        api_key,
+        api_url,
+        model,
+        prompt,
+        reasoning,
+        chunk_chars,
+        context_tokens,
+        sampling,
+        input_price,
+        output_price,
+        base_url,
+        rate_limit_retries,
+        rate_limit_max_delay_secs,
+        concurrency,
+        log_requests,
+        cache_days,
+        client,
+        fact_check,
+        source_prompts,
+        variant_prompt,
+        summary_prompt,
+        classifier,
+    };
+    
+    let stage = load_pipeline_stage(
+        "rewriter",
+        PipelineStage {
+            input_status: Status::Translated,
+            output_status: Status::Rewriter,
+            input_artifact: "translator".to_string(),
+            output_artifact: "rewriter".to_string(),
+        },
+    )?;
+    
+    let mut languages = read_target_languages_from_env(&provider)?;
+    let glossary = read_glossary_from_env()?;
+    let attempt_policy = read_attempt_policy_from_env(Status::RewriterRetry, Status::RewriterError)?;
+    let budget = read_daily_budget_from_env()?;
+
+    // Initialize database and data directory
+    let conn = init_db()?;
+    init_data_dir()?;
+    if let Some(tuner) = provider.concurrency.tuner("Rewriter") {
+        let _ = CONCURRENCY_TUNER.set(tuner);
+    }
+    
+    info!("Starting rewriter...");
+    listen_for_shutdown()?;
+    
+    // Main loop - run every REWRITER_INTERVAL_SECS seconds until SIGTERM or Ctrl-C
+    while !shutdown_requested() {
+        if !options.once {
+            if let Err(e) = heartbeat(&conn, interval_secs) {
+                warn!("Failed to record heartbeat: {}", e);
+            }
+        }
+        // Read before the cycle, so items handed over during it end the next sleep early
+        let seen = wakeup_seq(&conn, &[&stage.input_status]).unwrap_or(0);
+        // Prompt files are re-read every cycle, so prompt changes don't need a restart
+        if let Err(e) = reload_prompts(&mut provider, &mut languages) {
+            warn!("Failed to reload prompts, keeping the previous ones: {}", e);
+        }
+
+        // Stop spending once today's AI budget is used up
+        if !run_async(check_daily_budget(&conn, "Rewriter", &budget, &provider.client)) {
+            if let Err(e) = run_rewriter(&conn, &provider, &stage, &languages, &glossary, &attempt_policy, &budget) {
+                if options.once {
+                    return Err(e);
+                }
+                error!("Error in run_rewriter loop: {}", e);
+            }
+        }
+        if let Err(e) = block_on(send_dead_letter_alerts(&conn)) {
+            warn!("Failed to send dead-letter alerts: {}", e);
+        }
+        if options.once {
+            return Ok(());
+        }
+        
+        info!(
+            "Sleeping for up to {} seconds",
+            interval_secs
+        );
+        wait_for_wakeup(interval_secs, seen, || wakeup_seq(&conn, &[&stage.input_status]))?;
+    }
+    info!("Stopped");
+    Ok(())
+}
+
+/// Command-line options: `--once` processes the current queue and exits (for cron- or CI-driven runs).
+pub struct RunOptions {
+    pub once: bool,
+}
+
+pub fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { once: false };
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--once" => options.once = true,
+            other => return Err(anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn init_db() -> Result<Connection> {
+    let conn = open_db()?;
+    
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rewriter_cache (
+            cache_key TEXT PRIMARY KEY,
+            content TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )
+    .context("Failed to create rewriter_cache table")?;
+    
+    Ok(conn)
+}
+
+fn init_data_dir() -> Result<()> {
+    if !Path::new(DATA_DIR).exists() {
+        fs::create_dir_all(DATA_DIR).context("Failed to create data directory")?;
+    }
+    Ok(())
+}
+
+fn run_rewriter(
+    conn: &Connection,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    languages: &[TargetLanguage],
+    glossary: &Glossary,
+    attempt_policy: &AttemptPolicy,
+    budget: &DailyBudget,
+) -> Result<()> {
+    info!("Checking for news items to rewrite");
+    
+    // Fetch news items with "translator" or "rewriter_retry" status
+    let mut news_items = fetch_items_to_rewrite(conn, &stage.input_status)?;
+    if !languages.is_empty() {
+        news_items.extend(fetch_translations_to_rewrite(conn, &stage.input_status)?);
+    }
+    
+    if news_items.is_empty() {
+        info!("No items to rewrite");
+        return Ok(());
+    }
+    
+    info!(
+        "Found {} items to rewrite",
+        news_items.len()
+    );
+    
+    let concurrency = provider.concurrency.max.min(news_items.len());
+    if concurrency <= 1 {
+        run_async(async {
+            for item in news_items {
+                if shutdown_requested() || check_daily_budget(conn, "Rewriter", budget, &provider.client).await {
+                    break;
+                }
+                let span = item_span("rewriter", &item.id);
+                track_usage(rewrite_item(conn, item, provider, stage, languages, glossary, attempt_policy))
+                    .instrument(span)
+                    .await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })?;
+    } else {
+        info!("Rewriting with up to {} workers", concurrency);
+        run_workers(news_items, concurrency, CONCURRENCY_TUNER.get(), || WORKER_CONNECTIONS.get(), async |conn, item| {
+            if check_daily_budget(conn, "Rewriter", budget, &provider.client).await {
+                return;
+            }
+            let item_id = item.id.clone();
+            let span = item_span("rewriter", &item_id);
+            let rewrite = track_usage(rewrite_item(conn, item, provider, stage, languages, glossary, attempt_policy));
+            if let Err(e) = rewrite.instrument(span).await {
+                error!("Failed to rewrite item {}: {}", item_id, e);
+            }
+        });
+    }
+    
+    info!("Rewriting cycle completed");
+    Ok(())
+}
+
+/// Rewrites one item; with AI_PROVIDER_REWRITER_LOG_REQUESTS its provider calls are saved afterwards.
+async fn rewrite_item(
+    conn: &Connection,
+    item: NewsItem,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    languages: &[TargetLanguage],
+    glossary: &Glossary,
+    attempt_policy: &AttemptPolicy,
+) -> Result<()> {
+    let suffix = match &item.lang {
+        Some(lang) => format!("{}_{}", item.id, lang),
+        None => item.id.clone(),
+    };
+    AI_EXCHANGES
+        .scope(RefCell::default(), async {
+            let result = rewrite_and_update_status(conn, item, provider, stage, languages, glossary, attempt_policy).await;
+            if provider.log_requests {
+                if let Err(e) = write_ai_log(&suffix) {
+                    warn!("Failed to write AI request log for item {}: {}", suffix, e);
+                }
+            }
+            result
+        })
+        .await
+}
+
+/// Rewrites one item and moves it to its next status.
+async fn rewrite_and_update_status(
+    conn: &Connection,
+    item: NewsItem,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    languages: &[TargetLanguage],
+    glossary: &Glossary,
+    attempt_policy: &AttemptPolicy,
+) -> Result<()> {
+    let item_id = item.id.clone(); // Clone id for logging in case of error
+    let current_status = item.status.clone(); // Clone status for logic
+    let lang = item.lang.as_deref();
+
+    // Another replica may have taken the item since it was fetched
+    if !claim_item(conn, &item_id, lang, &current_status)? {
+        return Ok(());
+    }
+
+    // Extra languages may use their own prompt, default-language items one for their source
+    let item_provider;
+    let provider = match lang {
+        Some(code) => {
+            let Some(language) = languages.iter().find(|l| l.code == code) else {
+                warn!(
+                    "Language '{}' of item {} is no longer in TARGET_LANGUAGES, skipping",
+                    code, item_id
+                );
+                release_claim(conn, &item_id, lang)?;
+                return Ok(());
+            };
+            item_provider = AiProviderConfig {
+                prompt: language.prompt.clone(),
+                ..provider.clone()
+            };
+            &item_provider
+        }
+        None => match item
+            .source
+            .as_deref()
+            .and_then(|source| provider.source_prompts.iter().find(|(name, _)| name == source))
+        {
+            Some((_, prompt)) => {
+                item_provider = AiProviderConfig {
+                    prompt: prompt.clone(),
+                    ..provider.clone()
+                };
+                &item_provider
+            }
+            None => provider,
+        },
+    };
+
+    let glossary = glossary.for_language(lang);
+
+    match process_news_item(conn, &item, provider, stage, &glossary).await {
+        Ok((finish_reason_opt, pending_cache)) => {
+            let next_status = match finish_reason_opt.as_deref() {
+                // Not a failure of the item: keep its status and attempt count for the next cycle
+                Some("rate_limited") => {
+                    warn!(
+                        "Item {} is rate limited. Keeping status {} for the next cycle.",
+                        item_id, current_status
+                    );
+                    release_claim(conn, &item_id, lang)?;
+                    return Ok(());
+                }
+                Some(reason @ ("error" | "length")) => {
+                    let error = format!("Rewriting failed with finish_reason={}", reason);
+                    let (next_status, attempts) = record_failed_attempt(conn, &item_id, lang, attempt_policy, &error)?;
+                    let message = format!(
+                        "Rewriting failed for item {} (finish_reason={:?}, attempt {}/{}). Setting status to {}.",
+                        item_id, finish_reason_opt, attempts, attempt_policy.max_attempts, next_status
+                    );
+                    if next_status == Status::RewriterError {
+                        error!("{}", message);
+                    } else {
+                        warn!("{}", message);
+                    }
+                    next_status
+                }
+                Some(_) | None => {
+                    reset_attempts(conn, &item_id, lang)?;
+                    // Without variant B the publisher simply uses the main rewrite
+                    if let Err(e) = rewrite_variant(conn, &item, provider, stage).await {
+                        warn!(
+                            "Failed to write variant B for item {}: {}. Only variant A will be published.",
+                            item_id, e
+                        );
+                    }
+                    // Without a summary the publisher posts the full rewrite
+                    if let Err(e) = write_summary(conn, &item, provider, stage).await {
+                        warn!(
+                            "Failed to write summary for item {}: {}. The full text will be posted.",
+                            item_id, e
+                        );
+                    }
+                    // Uncategorized items are published without category routing, emoji or hashtag
+                    if item.lang.is_none() {
+                        if let Err(e) = classify_item(conn, &item, provider, stage).await {
+                            warn!(
+                                "Failed to classify item {}: {}. It stays without a category.",
+                                item_id, e
+                            );
+                        }
+                    }
+                    // A failed check doesn't hold the item back; only flagged claims do
+                    match check_facts(conn, &item, provider, stage).await {
+                        Ok(Some(findings)) => {
+                            warn!(
+                                "Fact check flagged item {}: {}. Setting status to {}.",
+                                item_id,
+                                findings.replace('\n', "; "),
+                                Status::Review
+                            );
+                            Status::Review
+                        }
+                        Ok(None) => {
+                            info!(
+                                "Successfully processed news item: {}",
+                                item_id
+                            );
+                            if let Some(pending) = &pending_cache {
+                                store_cached_rewrite(conn, provider, &pending.key, &pending.content)?;
+                            }
+                            clear_skip_cache(conn, &item_id, lang)?;
+                            stage.output_status.clone()
+                        }
+                        Err(_) if shutdown_requested() => {
+                            // Shutting down: the next start rewrites and checks it again
+                            release_claim(conn, &item_id, lang)?;
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            warn!(
+                                "Fact check failed for item {}: {}. Passing it on unchecked.",
+                                item_id, e
+                            );
+                            stage.output_status.clone()
+                        }
+                    }
+                }
+            };
+            update_status(conn, &item_id, lang, &current_status, &next_status)?;
+        }
+        Err(e) if e.is::<Cancelled>() => {
+            // Shutting down: the next start rewrites it again
+            release_claim(conn, &item_id, lang)?;
+        }
+        Err(e) => {
+            let (next_status, attempts) = record_failed_attempt(conn, &item_id, lang, attempt_policy, &e.to_string())?;
+            error!(
+                "Critical error processing item {} (attempt {}/{}): {}. Setting status to {}.",
+                item_id, attempts, attempt_policy.max_attempts, e, next_status
+            );
+
+            update_status(conn, &item_id, lang, &current_status, &next_status)?;
+        }
+    }
+    Ok(())
+}
+
+fn fetch_items_to_rewrite(conn: &Connection, input_status: &Status) -> Result<Vec<NewsItem>> {
+    let mut stmt = conn.prepare("SELECT id, title, url, date, status, NULL, source, skip_cache FROM news \
+         WHERE status = ? OR (status = ? AND COALESCE(next_attempt_at, 0) <= ?) ORDER BY date ASC")?;
+    let news_iter = stmt.query_map(params![input_status, Status::RewriterRetry, unix_now()], news_item_from_row)?;
+    
+    let mut news_items = Vec::new();
+    for item in news_iter {
+        news_items.push(item?);
+    }
+    
+    Ok(news_items)
+}
+
+fn fetch_translations_to_rewrite(conn: &Connection, input_status: &Status) -> Result<Vec<NewsItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title, n.url, n.date, t.status, t.lang, n.source, t.skip_cache FROM news_translations t JOIN news n ON n.id = t.news_id \
+         WHERE t.status = ? OR (t.status = ? AND COALESCE(t.next_attempt_at, 0) <= ?) ORDER BY n.date ASC",
+    )?;
+    let news_iter = stmt.query_map(params![input_status, Status::RewriterRetry, unix_now()], news_item_from_row)?;
+
+    let mut news_items = Vec::new();
+    for item in news_iter {
+        news_items.push(item?);
+    }
+
+    Ok(news_items)
+}
+
+fn news_item_from_row(row: &Row) -> rusqlite::Result<NewsItem> {
+    Ok(NewsItem {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        url: row.get(2)?,
+        date: row.get(3)?,
+        status: row.get(4)?,
+        lang: row.get(5)?,
+        source: row.get(6)?,
+        skip_cache: row.get(7)?,
+    })
+}
+
+/// Reads AI_PROVIDER_REWRITER_SOURCE_PROMPTS: comma-separated `source=prompt_file` pairs, e.g.
+/// `press=/etc/robo-news/press.txt,blog=/etc/robo-news/blog.txt`.
+fn read_source_prompts_from_env() -> Result<Vec<(String, String)>> {
+    let Ok(raw) = env::var("AI_PROVIDER_REWRITER_SOURCE_PROMPTS") else {
+        return Ok(Vec::new());
+    };
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (source, path) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("AI_PROVIDER_REWRITER_SOURCE_PROMPTS entry '{}' must be source=prompt_file", entry))?;
+            let prompt = fs::read_to_string(path.trim())
+                .context(format!("Failed to read prompt file {} for source {}", path.trim(), source.trim()))?;
+            let prompt = prompt.trim().to_string();
+            if prompt.is_empty() {
+                return Err(anyhow!("Prompt file {} for source {} is empty", path.trim(), source.trim()));
+            }
+            Ok((source.trim().to_string(), prompt))
+        })
+        .collect()
+}
+
+/// Reads the classifier settings: AI_PROVIDER_REWRITER_CATEGORY_MODEL enables classification by the
+/// model, CATEGORY_KEYWORDS_FILE by keywords; without either items get no category.
+fn read_classifier_from_env() -> Result<Option<ClassifierConfig>> {
+    let model = env::var("AI_PROVIDER_REWRITER_CATEGORY_MODEL")
+        .ok()
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty());
+    let keywords = match env::var("CATEGORY_KEYWORDS_FILE") {
+        Ok(path) if !path.trim().is_empty() => read_category_keywords(path.trim())?,
+        _ => Vec::new(),
+    };
+    if model.is_none() && keywords.is_empty() {
+        return Ok(None);
+    }
+
+    let mut categories: Vec<String> = env::var("CATEGORIES")
+        .unwrap_or_else(|_| DEFAULT_CATEGORIES.to_string())
+        .split(',')
+        .map(|category| category.trim().to_lowercase())
+        .filter(|category| !category.is_empty())
+        .collect();
+    for (category, _) in &keywords {
+        if !categories.contains(category) {
+            categories.push(category.clone());
+        }
+    }
+    let prompt = read_prompt_from_env("AI_PROVIDER_REWRITER_CATEGORY_PROMPT")?
+        .unwrap_or_else(|| DEFAULT_CATEGORY_PROMPT.to_string())
+        .replace("{{categories}}", &categories.join(", "));
+
+    Ok(Some(ClassifierConfig {
+        categories,
+        keywords,
+        model,
+        prompt,
+        input_price: read_price_from_env("AI_PROVIDER_REWRITER_CATEGORY_PRICE_INPUT")?,
+        output_price: read_price_from_env("AI_PROVIDER_REWRITER_CATEGORY_PRICE_OUTPUT")?,
+    }))
+}
+
+/// Reads a keyword list: one `category: word, word, ...` line per category; `#` starts a comment.
+fn read_category_keywords(path: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let content = fs::read_to_string(path).context(format!("Failed to read category keywords file {}", path))?;
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (category, words) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Line '{}' in {} must be category: word, word", line, path))?;
+            let words: Vec<String> = words
+                .split(',')
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect();
+            Ok((category.trim().to_lowercase(), words))
+        })
+        .collect()
+}
+
+/// Reads the extra languages from TARGET_LANGUAGES (everything after the first entry).
+/// AI_PROVIDER_REWRITER_PROMPT_<LANG> (or its `_FILE` variant) overrides the prompt per language.
+fn read_target_languages_from_env(provider: &AiProviderConfig) -> Result<Vec<TargetLanguage>> {
+    env::var("TARGET_LANGUAGES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|code| code.trim().to_ascii_lowercase())
+        .filter(|code| !code.is_empty())
+        .skip(1)
+        .map(|code| {
+            let prompt_var = format!("AI_PROVIDER_REWRITER_PROMPT_{}", code.to_ascii_uppercase().replace('-', "_"));
+            let prompt = read_prompt_from_env(&prompt_var)?.unwrap_or_else(|| provider.prompt.clone());
+            Ok(TargetLanguage { code, prompt })
+        })
+        .collect()
+}
+
+/// A prompt from the file at `<var>_FILE`, or from `<var>` itself; multi-paragraph prompts are
+/// easier to keep in a file than in an environment variable.
+fn read_prompt_from_env(var: &str) -> Result<Option<String>> {
+    let file_var = format!("{}_FILE", var);
+    match env::var(&file_var) {
+        Ok(path) if !path.trim().is_empty() => {
+            let prompt = fs::read_to_string(path.trim())
+                .context(format!("Failed to read prompt file {} ({})", path.trim(), file_var))?;
+            let prompt = prompt.trim().to_string();
+            if prompt.is_empty() {
+                return Err(anyhow!("Prompt file {} ({}) is empty", path.trim(), file_var));
+            }
+            Ok(Some(prompt))
+        }
+        _ => Ok(env::var(var).ok()),
+    }
+}
+
+/// Re-reads the prompts at the start of a cycle; a broken file leaves the previous prompts in place.
+fn reload_prompts(provider: &mut AiProviderConfig, languages: &mut Vec<TargetLanguage>) -> Result<()> {
+    let prompt = read_prompt_from_env("AI_PROVIDER_REWRITER_PROMPT")?
+        .context("AI_PROVIDER_REWRITER_PROMPT or AI_PROVIDER_REWRITER_PROMPT_FILE environment variable not set")?;
+    let fact_check_prompt = read_prompt_from_env("AI_PROVIDER_REWRITER_FACT_CHECK_PROMPT")?
+        .unwrap_or_else(|| DEFAULT_FACT_CHECK_PROMPT.to_string());
+    let variant_prompt = read_prompt_from_env("AI_PROVIDER_REWRITER_VARIANT_PROMPT")?;
+    let summary_prompt = read_prompt_from_env("AI_PROVIDER_REWRITER_SUMMARY_PROMPT")?;
+    let source_prompts = read_source_prompts_from_env()?;
+    let previous = std::mem::replace(&mut provider.prompt, prompt);
+    let reloaded = match read_target_languages_from_env(provider) {
+        Ok(reloaded) => reloaded,
+        Err(e) => {
+            provider.prompt = previous;
+            return Err(e);
+        }
+    };
+
+    let mut changed = provider.prompt != previous
+        || reloaded.len() != languages.len()
+        || reloaded.iter().zip(languages.iter()).any(|(new, old)| new.code != old.code || new.prompt != old.prompt);
+    if provider.source_prompts != source_prompts {
+        changed = true;
+        provider.source_prompts = source_prompts;
+    }
+    if provider.variant_prompt != variant_prompt {
+        changed = true;
+        provider.variant_prompt = variant_prompt;
+    }
+    if provider.summary_prompt != summary_prompt {
+        changed = true;
+        provider.summary_prompt = summary_prompt;
+    }
+    if let Some(fact_check) = &mut provider.fact_check {
+        changed |= fact_check.prompt != fact_check_prompt;
+        fact_check.prompt = fact_check_prompt;
+    }
+    if let Some(classifier) = &mut provider.classifier {
+        let category_prompt = read_prompt_from_env("AI_PROVIDER_REWRITER_CATEGORY_PROMPT")?
+            .unwrap_or_else(|| DEFAULT_CATEGORY_PROMPT.to_string())
+            .replace("{{categories}}", &classifier.categories.join(", "));
+        changed |= classifier.prompt != category_prompt;
+        classifier.prompt = category_prompt;
+    }
+    if changed {
+        info!("Prompts changed, using the new version from this cycle on");
+    }
+    *languages = reloaded;
+    Ok(())
+}
+
+fn glossary_prompt(glossary: &[GlossaryTerm]) -> String {
+    if glossary.is_empty() {
+        return String::new();
+    }
+    let terms = glossary
+        .iter()
+        .map(|term| format!("\"{}\"", term.target))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(" Keep these terms exactly as written: {}.", terms)
+}
+
+/// Glossary terms present in the translated input that the rewrite dropped or changed, ignoring
+/// case on both sides.
+fn find_mangled_terms(input: &str, output: &str, glossary: &[GlossaryTerm]) -> Vec<String> {
+    let input = input.to_lowercase();
+    let output = output.to_lowercase();
+    glossary
+        .iter()
+        .filter(|term| input.contains(&term.target.to_lowercase()) && !output.contains(&term.target.to_lowercase()))
+        .map(|term| term.target.clone())
+        .collect()
+}
+
+async fn process_news_item(
+    conn: &Connection,
+    item: &NewsItem,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    glossary: &[GlossaryTerm],
+) -> Result<(Option<String>, Option<PendingCache>)> {
+    // Extra languages read and write `<artifact>_<id>_<lang>.*`
+    let suffix = match &item.lang {
+        Some(lang) => format!("{}_{}", item.id, lang),
+        None => item.id.clone(),
+    };
+    let input_artifact = format!("{}_{}.html", stage.input_artifact, suffix);
+    let output_artifact = format!("{}_{}.html", stage.output_artifact, suffix);
+    
+    debug!("Processing item: {} (language: {})", item.id, item.lang.as_deref().unwrap_or("default"));
+    
+    let html_content = match load_artifact(&input_artifact)? {
+        Some(bytes) => String::from_utf8(bytes)
+            .context(format!("Failed to read content from file: {}", input_artifact))?,
+        None => return Err(anyhow!("Input file not found: {}", input_artifact)),
+    };
+    
+    // Send to AI provider API and get content + finish_reason
+    let prompt = format!("{}{}", provider.prompt, glossary_prompt(glossary));
+    // Articles that don't fit the model's context are rewritten chunk by chunk
+    let chunk_chars = effective_chunk_chars(
+        "AI_PROVIDER_REWRITER_CONTEXT_TOKENS",
+        provider.chunk_chars,
+        provider.context_tokens,
+        provider.sampling.max_tokens,
+        &prompt,
+        &html_content,
+    );
+    let cache_key = rewrite_cache_key(provider, &prompt, &html_content);
+    // Sent back by an operator (`ctl requeue`, `ctl replay`, the dashboard): the cached rewrite is what they want redone
+    let cached = if item.skip_cache {
+        info!("Item {} was requeued, bypassing the rewriter cache", item.id);
+        None
+    } else {
+        cached_rewrite(conn, provider, &cache_key)?
+    };
+    take_usage();
+    let rewrite_result = if let Some(content) = cached.clone() {
+        info!("Reusing cached rewrite for item {}", item.id);
+        Ok((content, Some("stop".to_string())))
+    } else if chunk_chars > 0 && html_content.len() > chunk_chars {
+        info!(
+            "Item {} has {} characters, rewriting in chunks of up to {}",
+            item.id,
+            html_content.len(),
+            chunk_chars
+        );
+        process_in_chunks(&html_content, chunk_chars, async |html| cancellable(rewrite_content(html, provider, &prompt)).await)
+            .await
+    } else {
+        cancellable(rewrite_content(&html_content, provider, &prompt)).await
+    };
+    let error_stage = match &item.lang {
+        Some(lang) => format!("rewriter/{}", lang),
+        None => "rewriter".to_string(),
+    };
+    record_usage(conn, "rewriter", &item.id, item.lang.as_deref(), &provider.usage_model(), take_usage())?;
+    match &rewrite_result {
+        Ok(_) => clear_provider_error(conn, &item.id, &error_stage)?,
+        Err(ApiError::Cancelled(_)) => {}
+        Err(e) => record_provider_error(conn, &item.id, &error_stage, &e.failure())?,
+    }
+    
+    // Match on the actual Result, not a reference
+    match &rewrite_result {
+        Ok((ref content, _)) => {
+            // Content is now &String, so use as_bytes()
+            debug!(
+                "Writing successful content to: {}",
+                output_artifact
+            );
+            write_artifact(&output_artifact, content)
+                .context(format!(
+                    "Failed to write content to output file: {}",
+                    output_artifact
+                ))?;
+
+            // Plaintext version for consumers that need text only (TTS, search, length checks)
+            let text_artifact = format!("{}_{}.txt", stage.output_artifact, suffix);
+            debug!(
+                "Writing plaintext content to: {}",
+                text_artifact
+            );
+            write_artifact(&text_artifact, html_to_plain_text(content))
+                .context(format!("Failed to write plaintext output file: {}", text_artifact))?;
+        }
+        Err(ApiError::ApiReturnedError { status, .. }) if *status == StatusCode::TOO_MANY_REQUESTS => {
+            warn!(
+                "AI provider still rate limited for item {}. No content to save.",
+                item.id
+            );
+            return Ok((Some("rate_limited".to_string()), None));
+        }
+        Err(ApiError::ApiReturnedError { ref content, .. }) => {
+            // Use 'ref content' to borrow from the error struct
+            debug!(
+                "Writing partial content from API error to: {}",
+                output_artifact
+            );
+            write_artifact(&output_artifact, content)
+                .context(format!(
+                    "Failed to write partial content to output file: {}",
+                    output_artifact
+                ))?;
+        }
+        Err(ref e @ ApiError::RequestError(_)) => {
+            // Borrow the error to avoid moving it
+            error!(
+                "API request failed for item {}: {}. No content to save.",
+                item.id, e
+            );
+            // Convert ApiError directly to anyhow::Error
+            return Err(anyhow!(e.clone()));
+        }
+         Err(ref e @ ApiError::ParseError(_)) => {
+            // Borrow the error
+             error!(
+                "Failed to parse API response for item {}: {}. No content to save.",
+                item.id, e
+            );
+            // Convert ApiError directly to anyhow::Error
+            return Err(anyhow!(e.clone()));
+        }
+        Err(ApiError::Cancelled(cancelled)) => return Err((*cancelled).into()),
+        Err(ref e @ (ApiError::EmptyChoices | ApiError::EmptyImageData | ApiError::ConfigurationError(_))) => {
+            error!(
+                "API returned no content for item {}: {}. No content to save.",
+                item.id, e
+            );
+            // Convert ApiError directly to anyhow::Error
+            return Err(anyhow!(e.clone()));
+        }
+    }
+
+    // Return the finish_reason if successful or if API returned a controlled error
+    match rewrite_result {
+        Ok((content, finish_reason)) => {
+            let mangled = find_mangled_terms(&html_content, &content, glossary);
+            if !mangled.is_empty() && finish_reason.as_deref() != Some("length") {
+                warn!(
+                    "Protected terms missing from rewrite of item {}: {}. Forcing finish_reason='error' to trigger retry.",
+                    item.id,
+                    mangled.join(", ")
+                );
+                return Ok((Some("error".to_string()), None));
+            }
+            let pending_cache = (cached.is_none() && !matches!(finish_reason.as_deref(), Some("length") | Some("error")))
+                .then_some(PendingCache {
+                    key: cache_key,
+                    content,
+                });
+            Ok((finish_reason, pending_cache))
+        }
+        Err(ApiError::ApiReturnedError { finish_reason, .. }) => Ok((finish_reason, None)),
+        // Other errors were already returned as Err(anyhow::Error)
+        Err(e) => Err(anyhow!(e)), // Convert remaining ApiError variants - this signals critical errors to run_rewriter
+    }
+}
+
+/// A fresh rewrite, stored in `rewriter_cache` only once the fact check has passed.
+struct PendingCache {
+    key: String,
+    content: String,
+}
+
+/// Cache key of a rewrite: model, prompt and input, so any change to one of them misses the cache.
+fn rewrite_cache_key(provider: &AiProviderConfig, prompt: &str, content: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [provider.model.as_str(), prompt, content] {
+        hasher.update(hex::encode(Sha256::digest(part.as_bytes())));
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+fn cached_rewrite(conn: &Connection, provider: &AiProviderConfig, cache_key: &str) -> Result<Option<String>> {
+    if provider.cache_days == 0 {
+        return Ok(None);
+    }
+    let since = unix_now() - (provider.cache_days * 86_400) as i64;
+    match conn.query_row(
+        "SELECT content FROM rewriter_cache WHERE cache_key = ?1 AND created_at >= ?2",
+        params![cache_key, since],
+        |row| row.get(0),
+    ) {
+        Ok(content) => Ok(Some(content)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e).context("Failed to read rewriter cache"),
+    }
+}
+
+/// Stores a successful rewrite and drops entries older than AI_PROVIDER_REWRITER_CACHE_DAYS.
+fn store_cached_rewrite(conn: &Connection, provider: &AiProviderConfig, cache_key: &str, content: &str) -> Result<()> {
+    if provider.cache_days == 0 {
+        return Ok(());
+    }
+    let now = unix_now();
+    conn.execute(
+        "INSERT OR REPLACE INTO rewriter_cache (cache_key, content, created_at) VALUES (?1, ?2, ?3)",
+        params![cache_key, content, now],
+    )
+    .context("Failed to write rewriter cache")?;
+    conn.execute(
+        "DELETE FROM rewriter_cache WHERE created_at < ?1",
+        params![now - (provider.cache_days * 86_400) as i64],
+    )
+    .context("Failed to prune rewriter cache")?;
+    Ok(())
+}
+
+/// Lets later rewrites of an item an operator sent back use the cache again.
+fn clear_skip_cache(conn: &Connection, id: &str, lang: Option<&str>) -> Result<()> {
+    match lang {
+        Some(lang) => conn.execute(
+            "UPDATE news_translations SET skip_cache = 0 WHERE news_id = ?1 AND lang = ?2 AND skip_cache = 1",
+            params![id, lang],
+        )?,
+        None => conn.execute("UPDATE news SET skip_cache = 0 WHERE id = ?1 AND skip_cache = 1", params![id])?,
+    };
+    Ok(())
+}
+
+/// Writes a second headline/lead variant of the rewrite to `data/<output_artifact>_b_<id>.html`
+/// when AI_PROVIDER_REWRITER_VARIANT_PROMPT is set; the publisher picks one of the two per post.
+async fn rewrite_variant(conn: &Connection, item: &NewsItem, provider: &AiProviderConfig, stage: &PipelineStage) -> Result<()> {
+    let suffix = match &item.lang {
+        Some(lang) => format!("{}_{}", item.id, lang),
+        None => item.id.clone(),
+    };
+    let rewrite_artifact = format!("{}_{}.html", stage.output_artifact, suffix);
+    let variant_artifact = format!("{}_b_{}.html", stage.output_artifact, suffix);
+    let variant_text_artifact = format!("{}_b_{}.txt", stage.output_artifact, suffix);
+    // A variant left over from an earlier rewrite no longer matches variant A
+    remove_artifact(&variant_artifact).context(format!("Failed to remove old variant: {}", variant_artifact))?;
+    remove_artifact(&variant_text_artifact).context(format!("Failed to remove old variant: {}", variant_text_artifact))?;
+    let Some(variant_prompt) = &provider.variant_prompt else {
+        return Ok(());
+    };
+
+    let rewrite = read_artifact_string(&rewrite_artifact).context("Failed to read rewritten file")?;
+    debug!("Writing variant B of item {}", item.id);
+    take_usage();
+    let result = cancellable(rewrite_content(&rewrite, provider, variant_prompt)).await;
+    record_usage(conn, "rewriter", &item.id, item.lang.as_deref(), &provider.usage_model(), take_usage())?;
+    let (content, finish_reason) = result.map_err(|e| anyhow!(e))?;
+    if matches!(finish_reason.as_deref(), Some("length") | Some("error")) {
+        return Err(anyhow!("variant rewrite ended with finish_reason {:?}", finish_reason));
+    }
+
+    write_artifact(&variant_text_artifact, html_to_plain_text(&content))
+        .context(format!("Failed to write variant plaintext file: {}", variant_text_artifact))?;
+    write_artifact(&variant_artifact, content).context(format!("Failed to write variant file: {}", variant_artifact))?;
+    Ok(())
+}
+
+/// Writes a 2-3 sentence summary of the rewrite to `data/<output_artifact>_summary_<id>.html` when
+/// AI_PROVIDER_REWRITER_SUMMARY_PROMPT is set; the publisher posts it instead of a post that is too
+/// long for Telegram and links the full text on Telegraph.
+async fn write_summary(conn: &Connection, item: &NewsItem, provider: &AiProviderConfig, stage: &PipelineStage) -> Result<()> {
+    let suffix = match &item.lang {
+        Some(lang) => format!("{}_{}", item.id, lang),
+        None => item.id.clone(),
+    };
+    let rewrite_artifact = format!("{}_{}.html", stage.output_artifact, suffix);
+    let summary_artifact = format!("{}_summary_{}.html", stage.output_artifact, suffix);
+    // A summary left over from an earlier rewrite no longer matches the text
+    remove_artifact(&summary_artifact).context(format!("Failed to remove old summary: {}", summary_artifact))?;
+    let Some(summary_prompt) = &provider.summary_prompt else {
+        return Ok(());
+    };
+
+    let rewrite = read_artifact_string(&rewrite_artifact).context("Failed to read rewritten file")?;
+    debug!("Writing summary of item {}", item.id);
+    take_usage();
+    let result = cancellable(rewrite_content(&rewrite, provider, summary_prompt)).await;
+    record_usage(conn, "rewriter", &item.id, item.lang.as_deref(), &provider.usage_model(), take_usage())?;
+    let (content, finish_reason) = result.map_err(|e| anyhow!(e))?;
+    if matches!(finish_reason.as_deref(), Some("length") | Some("error")) {
+        return Err(anyhow!("summary ended with finish_reason {:?}", finish_reason));
+    }
+
+    write_artifact(&summary_artifact, content).context(format!("Failed to write summary file: {}", summary_artifact))?;
+    Ok(())
+}
+
+/// Stores the category of the rewrite in `news.category`: the model's answer when it names one of
+/// the categories, otherwise the category with the most keyword hits. No match clears the column.
+async fn classify_item(conn: &Connection, item: &NewsItem, provider: &AiProviderConfig, stage: &PipelineStage) -> Result<()> {
+    let Some(classifier) = &provider.classifier else {
+        return Ok(());
+    };
+    let rewrite_artifact = format!("{}_{}.html", stage.output_artifact, item.id);
+    let rewrite = read_artifact_string(&rewrite_artifact).context("Failed to read rewritten file")?;
+    let text = html_to_plain_text(&rewrite);
+
+    let mut category = None;
+    if let Some(model) = &classifier.model {
+        let checker = AiProviderConfig {
+            model: model.clone(),
+            prompt: classifier.prompt.clone(),
+            reasoning: None,
+            sampling: SamplingConfig::default(),
+            input_price: classifier.input_price,
+            output_price: classifier.output_price,
+            ..provider.clone()
+        };
+        debug!("Classifying item {} with model {}", item.id, model);
+        take_usage();
+        let result = cancellable(rewrite_content(&text, &checker, &checker.prompt)).await;
+        record_usage(conn, "rewriter", &item.id, item.lang.as_deref(), &checker.usage_model(), take_usage())?;
+        let (answer, _) = result.map_err(|e| anyhow!(e))?;
+        let answer = html_to_plain_text(&answer)
+            .trim()
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        if classifier.categories.contains(&answer) {
+            category = Some(answer);
+        } else {
+            warn!(
+                "Classifier answered '{}' for item {}, which is not one of the categories",
+                answer, item.id
+            );
+        }
+    }
+    if category.is_none() {
+        category = keyword_category(&text, &classifier.keywords);
+    }
+
+    info!(
+        "Item {} classified as {}",
+        item.id,
+        category.as_deref().unwrap_or("uncategorized")
+    );
+    conn.execute("UPDATE news SET category = ?1 WHERE id = ?2", params![category, item.id])
+        .context("Failed to store category")?;
+    Ok(())
+}
+
+/// The category whose keywords occur most often as whole words in the text; ties go to the
+/// category listed first.
+fn keyword_category(text: &str, keywords: &[(String, Vec<String>)]) -> Option<String> {
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect();
+    let mut best: Option<(&str, usize)> = None;
+    for (category, category_keywords) in keywords {
+        let hits: usize = category_keywords
+            .iter()
+            .map(|keyword| {
+                if keyword.contains(' ') {
+                    text.matches(keyword.as_str()).count()
+                } else {
+                    words.iter().filter(|word| **word == keyword).count()
+                }
+            })
+            .sum();
+        if hits > 0 && best.is_none_or(|(_, best_hits)| hits > best_hits) {
+            best = Some((category, hits));
+        }
+    }
+    best.map(|(category, _)| category.to_string())
+}
+
+/// Asks the fact-check model whether the rewrite only states what its source (the rewriter input)
+/// says. Returns the unsupported claims, which are also saved to `data/factcheck_<id>.txt`, or None
+/// when the rewrite is consistent or no fact-check model is configured.
+async fn check_facts(conn: &Connection, item: &NewsItem, provider: &AiProviderConfig, stage: &PipelineStage) -> Result<Option<String>> {
+    let Some(fact_check) = &provider.fact_check else {
+        return Ok(None);
+    };
+    let suffix = match &item.lang {
+        Some(lang) => format!("{}_{}", item.id, lang),
+        None => item.id.clone(),
+    };
+    let source = read_artifact_string(&format!("{}_{}.html", stage.input_artifact, suffix))
+        .context("Failed to read source file")?;
+    let rewrite = read_artifact_string(&format!("{}_{}.html", stage.output_artifact, suffix))
+        .context("Failed to read rewritten file")?;
+
+    let checker = AiProviderConfig {
+        model: fact_check.model.clone(),
+        prompt: fact_check.prompt.clone(),
+        reasoning: None,
+        sampling: SamplingConfig::default(),
+        input_price: fact_check.input_price,
+        output_price: fact_check.output_price,
+        ..provider.clone()
+    };
+    let message = format!(
+        "SOURCE:\n{}\nREWRITE:\n{}",
+        html_to_plain_text(&source),
+        html_to_plain_text(&rewrite)
+    );
+
+    debug!("Checking facts of item {} with model {}", item.id, checker.model);
+    take_usage();
+    let result = cancellable(rewrite_content(&message, &checker, &checker.prompt)).await;
+    record_usage(conn, "rewriter", &item.id, item.lang.as_deref(), &checker.usage_model(), take_usage())?;
+    let (verdict, _) = result.map_err(|e| anyhow!(e))?;
+
+    let findings = html_to_plain_text(&verdict).trim().to_string();
+    if findings.trim_matches(|c: char| !c.is_alphanumeric()).eq_ignore_ascii_case("ok") {
+        return Ok(None);
+    }
+    let findings_artifact = format!("factcheck_{}.txt", suffix);
+    write_artifact(&findings_artifact, format!("{}\n", findings))
+        .context(format!("Failed to write fact check findings: {}", findings_artifact))?;
+    Ok(Some(findings))
+}
+
+async fn rewrite_content(content: &str, provider: &AiProviderConfig, prompt: &str) -> Result<(String, Option<String>), ApiError> {
+    // Shared client: keeps connections and TLS sessions alive between items
+    let client = &provider.client;
+    
+    let messages = vec![
+        Message {
+            role: "system".to_string(),
+            content: prompt.to_string(),
+        },
+        Message {
+            role: "user".to_string(),
+            content: content.to_string(),
+        },
+    ];
+    
+    match provider.provider_type {
+        AiProviderType::OpenRouter => {
+            if let Some(reasoning) = &provider.reasoning {
+                debug!(
+                    "OpenRouter reasoning config applied: enabled={:?}, effort={:?}",
+                    reasoning.enabled, reasoning.effort
+                );
+            }
+
+            let request = OpenRouterChatRequest {
+                model: provider.model.clone(),
+                messages,
+                reasoning: provider.reasoning.clone(),
+                sampling: provider.sampling.clone(),
+            };
+
+            // Log before sending - ignore result
+            debug!(
+                "Sending request to OpenRouter API with model: {}",
+                provider.model
+            );
+
+            let request_builder = client
+                .post("https://openrouter.ai/api/v1/chat/completions")
+                .header("Authorization", format!("Bearer {}", provider.api_key))
+                .header("Content-Type", "application/json")
+                .json(&request);
+            let response = send_with_backoff(request_builder, provider).await?;
+
+            parse_chat_response(response).await
+        }
+        AiProviderType::Perplexity => {
+            let reasoning_effort = perplexity_reasoning_effort_from_reasoning(&provider.reasoning);
+            if let Some(ref effort) = reasoning_effort {
+                debug!(
+                    "Perplexity reasoning_effort applied: {}",
+                    effort
+                );
+            }
+
+            let request = PerplexityChatRequest {
+                model: provider.model.clone(),
+                messages,
+                reasoning_effort,
+                sampling: provider.sampling.clone(),
+            };
+
+            debug!(
+                "Sending request to Perplexity API with model: {}",
+                provider.model
+            );
+
+            let request_builder = client
+                .post("https://api.perplexity.ai/chat/completions")
+                .header("Authorization", format!("Bearer {}", provider.api_key))
+                .header("Content-Type", "application/json")
+                .json(&request);
+            let response = send_with_backoff(request_builder, provider).await?;
+
+            parse_chat_response(response).await
+        }
+        AiProviderType::Gemini => {
+            // Gemini OpenAI compatibility docs:
+            // https://ai.google.dev/gemini-api/docs/openai
+            // Endpoint:
+            //   POST https://generativelanguage.googleapis.com/v1beta/openai/chat/completions
+            // Auth:
+            //   Authorization: Bearer <GEMINI_API_KEY>
+            let api_url = provider
+                .api_url
+                .as_deref()
+                .unwrap_or("https://generativelanguage.googleapis.com/v1beta/openai/chat/completions");
+            let reasoning_effort = gemini_reasoning_effort_from_reasoning(&provider.reasoning);
+            if let Some(ref effort) = reasoning_effort {
+                debug!(
+                    "Gemini reasoning_effort applied: {}",
+                    effort
+                );
+            }
+
+            let request = GeminiChatRequest {
+                model: provider.model.clone(),
+                messages,
+                stream: provider.api_url.as_ref().map(|_| false),
+                reasoning_effort,
+                sampling: provider.sampling.clone(),
+            };
+
+            debug!(
+                "Sending request to Gemini OpenAI-compatible API with model: {}",
+                provider.model
+            );
+
+            let request_builder = client
+                .post(api_url)
+                .header("Authorization", format!("Bearer {}", provider.api_key))
+                .header("Content-Type", "application/json")
+                .json(&request);
+            let response = send_with_backoff(request_builder, provider).await?;
+
+            parse_chat_response(response).await
+        }
+        AiProviderType::OpenAi => {
+            // OpenAI Chat Completions docs:
+            // https://platform.openai.com/docs/api-reference/chat/create
+            // Endpoint:
+            //   POST https://api.openai.com/v1/chat/completions
+            // Auth:
+            //   Authorization: Bearer <OPENAI_API_KEY>
+            let api_url = provider
+                .api_url
+                .as_deref()
+                .unwrap_or("https://api.openai.com/v1/chat/completions");
+            let reasoning_effort = openai_reasoning_effort_from_reasoning(&provider.reasoning);
+            if let Some(ref effort) = reasoning_effort {
+                debug!(
+                    "OpenAI reasoning_effort applied: {}",
+                    effort
+                );
+            }
+
+            let request = OpenAiChatRequest {
+                model: provider.model.clone(),
+                messages,
+                reasoning_effort,
+                max_completion_tokens: provider.sampling.max_tokens,
+                sampling: SamplingConfig {
+                    max_tokens: None,
+                    ..provider.sampling.clone()
+                },
+            };
+
+            debug!(
+                "Sending request to OpenAI API with model: {}",
+                provider.model
+            );
+
+            let request_builder = client
+                .post(api_url)
+                .header("Authorization", format!("Bearer {}", provider.api_key))
+                .header("Content-Type", "application/json")
+                .json(&request);
+            let response = send_with_backoff(request_builder, provider).await?;
+
+            parse_chat_response(response).await
+        }
+        AiProviderType::Anthropic => {
+            let api_url = provider
+                .api_url
+                .as_deref()
+                .unwrap_or("https://api.anthropic.com/v1/messages");
+
+            // Claude takes the system prompt as a separate field.
+            let budget_tokens = anthropic_thinking_budget_from_reasoning(&provider.reasoning);
+            let mut max_tokens = provider.sampling.max_tokens.unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS);
+            let thinking = budget_tokens.map(|budget_tokens| {
+                debug!(
+                    "Anthropic thinking budget applied: {} tokens",
+                    budget_tokens
+                );
+                // The thinking budget counts towards max_tokens, so keep room for the answer.
+                max_tokens = max_tokens.saturating_add(budget_tokens);
+                AnthropicThinking {
+                    kind: "enabled".to_string(),
+                    budget_tokens,
+                }
+            });
+
+            // Extended thinking doesn't accept a custom temperature.
+            let temperature = match (&thinking, provider.sampling.temperature) {
+                (Some(_), Some(temperature)) => {
+                    warn!(
+                        "AI_PROVIDER_REWRITER_TEMPERATURE={} is ignored while Anthropic extended thinking is enabled.",
+                        temperature
+                    );
+                    None
+                }
+                (_, temperature) => temperature,
+            };
+
+            let request = AnthropicMessagesRequest {
+                model: provider.model.clone(),
+                max_tokens,
+                system: prompt.to_string(),
+                messages: messages.into_iter().filter(|message| message.role != "system").collect(),
+                thinking,
+                temperature,
+                top_p: provider.sampling.top_p,
+            };
+
+            debug!(
+                "Sending request to Anthropic API with model: {}",
+                provider.model
+            );
+
+            let request_builder = client
+                .post(api_url)
+                .header("x-api-key", provider.api_key.clone())
+                .header("anthropic-version", ANTHROPIC_API_VERSION)
+                .header("Content-Type", "application/json")
+                .json(&request);
+            let response = send_with_backoff(request_builder, provider).await?;
+
+            parse_anthropic_response(response).await
+        }
+        AiProviderType::OpenAiCompatible => {
+            // OpenAI-compatible servers expose <base_url>/chat/completions:
+            // - Ollama: http://localhost:11434/v1
+            // - vLLM: http://localhost:8000/v1
+            // - LM Studio: http://localhost:1234/v1
+            let api_url = match (&provider.api_url, &provider.base_url) {
+                (Some(api_url), _) => api_url.clone(),
+                (None, Some(base_url)) => format!("{}/chat/completions", base_url),
+                (None, None) => {
+                    return Err(ApiError::ParseError(Arc::new(anyhow!(
+                        "AI_PROVIDER_REWRITER_BASE_URL is not configured"
+                    ))))
+                }
+            };
+            let reasoning_effort = openai_reasoning_effort_from_reasoning(&provider.reasoning);
+
+            let request = OpenAiChatRequest {
+                model: provider.model.clone(),
+                messages,
+                reasoning_effort,
+                max_completion_tokens: None,
+                sampling: provider.sampling.clone(),
+            };
+
+            debug!(
+                "Sending request to OpenAI-compatible API at {} with model: {}",
+                api_url, provider.model
+            );
+
+            let mut request_builder = client
+                .post(&api_url)
+                .header("Content-Type", "application/json")
+                .json(&request);
+            if !provider.api_key.trim().is_empty() {
+                request_builder = request_builder.header("Authorization", format!("Bearer {}", provider.api_key));
+            }
+            let response = send_with_backoff(request_builder, provider).await?;
+
+            parse_chat_response(response).await
+        }
+    }
+}
+
+/// Sends the request and waits out 429 responses in place, so a rate limit doesn't use up the
+/// item's single retry. After `rate_limit_retries` waits the 429 response is returned as is.
+async fn send_with_backoff(request: RequestBuilder, provider: &AiProviderConfig) -> Result<Response, ApiError> {
+    let mut attempt: u32 = 0;
+    loop {
+        wait_for_rate_limit_pause().await;
+
+        // JSON bodies can always be cloned; anything else is sent once
+        let Some(current) = request.try_clone() else {
+            return request.send().await.map_err(|e| ApiError::RequestError(Arc::new(e)));
+        };
+        if provider.log_requests {
+            if let Some(built) = current.try_clone().and_then(|builder| builder.build().ok()) {
+                record_ai_request(&built);
+            }
+        }
+        let started = Instant::now();
+        let response = current.send().await;
+        // A failed request (mostly a timeout) tells the tuner the same as a 429: the provider has too much
+        let overloaded = response.as_ref().map_or(true, |response| response.status() == StatusCode::TOO_MANY_REQUESTS);
+        if let Some(tuner) = CONCURRENCY_TUNER.get() {
+            tuner.record(started.elapsed(), overloaded);
+        }
+        let response = response.map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+        if response.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= provider.rate_limit_retries {
+            return Ok(response);
+        }
+
+        attempt += 1;
+        let headers = response.headers().clone();
+        let body = response.text().await.unwrap_or_default();
+        record_ai_response(StatusCode::TOO_MANY_REQUESTS, &body);
+        let delay = rate_limit_delay(&headers, &body, attempt).min(provider.rate_limit_max_delay_secs);
+        warn!(
+            "AI provider rate limit hit (attempt {}/{}). Waiting {} seconds before retrying.",
+            attempt, provider.rate_limit_retries, delay
+        );
+        let until = Instant::now() + Duration::from_secs(delay);
+        let mut paused = RATE_LIMITED_UNTIL.lock().unwrap_or_else(|e| e.into_inner());
+        if paused.is_none_or(|current| current < until) {
+            *paused = Some(until);
+        }
+    }
+}
+
+/// Remembers a request for the item's AI log. Only the URL and the JSON body are kept; the API keys
+/// and other secrets are masked wherever they appear.
+fn record_ai_request(request: &reqwest::Request) {
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(|bytes| redact_secrets(&String::from_utf8_lossy(bytes)).into_owned())
+        .unwrap_or_default();
+    let entry = serde_json::json!({
+        "sent_at": unix_now(),
+        "method": request.method().as_str(),
+        "url": redact_secrets(request.url().as_str()),
+        "request": serde_json::from_str::<serde_json::Value>(&body).unwrap_or(serde_json::Value::String(body)),
+    });
+    let _ = AI_EXCHANGES.try_with(|exchanges| exchanges.borrow_mut().push(entry));
+}
+
+/// Attaches the raw response to the last request recorded for the item.
+fn record_ai_response(status: StatusCode, body: &str) {
+    let _ = AI_EXCHANGES.try_with(|exchanges| {
+        let mut exchanges = exchanges.borrow_mut();
+        if let Some(entry) = exchanges.last_mut().and_then(|entry| entry.as_object_mut()) {
+            if entry.contains_key("status") {
+                return;
+            }
+            entry.insert("status".to_string(), serde_json::Value::from(status.as_u16()));
+            entry.insert(
+                "response".to_string(),
+                serde_json::from_str(body).unwrap_or_else(|_| serde_json::Value::String(body.to_string())),
+            );
+        }
+    });
+}
+
+/// Writes the calls made for one item to `data/ai_log/rewriter_<id>.json`, replacing the previous run's.
+fn write_ai_log(suffix: &str) -> Result<()> {
+    let exchanges = AI_EXCHANGES.try_with(|exchanges| std::mem::take(&mut *exchanges.borrow_mut())).unwrap_or_default();
+    if exchanges.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(AI_LOG_DIR).context("Failed to create AI log directory")?;
+    let path = format!("{}/rewriter_{}.json", AI_LOG_DIR, suffix);
+    let log = serde_json::json!({ "item": suffix, "exchanges": exchanges });
+    fs::write(&path, serde_json::to_string_pretty(&log)?).context(format!("Failed to write AI log: {}", path))?;
+    Ok(())
+}
+
+async fn wait_for_rate_limit_pause() {
+    let until = *RATE_LIMITED_UNTIL.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(until) = until {
+        let now = Instant::now();
+        if until > now {
+            tokio::time::sleep(until - now).await;
+        }
+    }
+}
+
+/// Seconds to wait after a 429: `Retry-After`/`retry-after-ms`, then OpenRouter's
+/// `X-RateLimit-Reset` (epoch milliseconds, as a header or in `error.metadata.headers`),
+/// then exponential backoff from RATE_LIMIT_BASE_DELAY_SECS.
+fn rate_limit_delay(headers: &HeaderMap, body: &str, attempt: u32) -> u64 {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.trim().to_string());
+
+    if let Some(ms) = header("retry-after-ms").and_then(|value| value.parse::<f64>().ok()) {
+        return (ms / 1000.0).ceil().max(1.0) as u64;
+    }
+    if let Some(secs) = header("retry-after").and_then(|value| value.parse::<f64>().ok()) {
+        return secs.ceil().max(1.0) as u64;
+    }
+
+    let reset = header("x-ratelimit-reset").or_else(|| {
+        let value: serde_json::Value = serde_json::from_str(body).ok()?;
+        match &value["error"]["metadata"]["headers"]["X-RateLimit-Reset"] {
+            serde_json::Value::String(reset) => Some(reset.clone()),
+            serde_json::Value::Number(reset) => Some(reset.to_string()),
+            _ => None,
+        }
+    });
+    if let Some(reset_ms) = reset.and_then(|value| value.parse::<u64>().ok()) {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_millis() as u64)
+            .unwrap_or(0);
+        return reset_ms.saturating_sub(now_ms).div_ceil(1000).max(1);
+    }
+
+    RATE_LIMIT_BASE_DELAY_SECS.saturating_mul(1 << (attempt - 1).min(10))
+}
+
+fn anthropic_thinking_budget_from_reasoning(reasoning: &Option<ReasoningConfig>) -> Option<u32> {
+    let reasoning = reasoning.as_ref()?;
+
+    // If explicitly disabled, do not enable extended thinking.
+    if reasoning.enabled == Some(false) {
+        return None;
+    }
+
+    // Claude has no effort levels; extended thinking takes a token budget (minimum 1024).
+    // We map OpenRouter-style values to budgets:
+    // xhigh -> 32000, high -> 16000, medium -> 8000, low -> 4000, minimal -> 1024, none -> omit.
+    match reasoning.effort.as_deref() {
+        Some("xhigh") => Some(32000),
+        Some("high") => Some(16000),
+        Some("medium") => Some(8000),
+        Some("low") => Some(4000),
+        Some("minimal") => Some(1024),
+        Some("none") => None,
+        // Enabled without an effort: use a moderate budget.
+        None if reasoning.enabled == Some(true) => Some(8000),
+        None => None,
+        Some(other) => {
+            warn!(
+                "AI_PROVIDER_REWRITER_REASONING_EFFORT='{}' is not supported for Anthropic. Omitting thinking.",
+                other
+            );
+            None
+        }
+    }
+}
+
+async fn parse_anthropic_response(response: reqwest::Response) -> Result<(String, Option<String>), ApiError> {
+    let status = response.status();
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+    record_ai_response(status, &response_text);
+
+    // Error bodies have a different shape ({"type":"error","error":{...}}), so check the status first.
+    if !status.is_success() {
+        warn!(
+            "Anthropic API returned non-success status: {}. Body: {}",
+            status, response_text
+        );
+        return Err(ApiError::ApiReturnedError {
+            provider_error: parse_provider_error(&response_text),
+            status,
+            content: String::new(),
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    let response_data: AnthropicMessagesResponse = match serde_json::from_str(&response_text) {
+        Ok(data) => data,
+        Err(e) => {
+            error!(
+                "Failed to parse Anthropic response JSON. Status: {}. Body: {}",
+                status, response_text
+            );
+            return Err(ApiError::ParseError(Arc::new(e.into())));
+        }
+    };
+
+    debug!(
+        "Parsed response from Anthropic: stop_reason={:?}, blocks={}",
+        response_data.stop_reason,
+        response_data.content.len()
+    );
+
+    if let Some(usage) = &response_data.usage {
+        add_usage(usage.input_tokens, usage.output_tokens);
+    }
+
+    // Thinking blocks are skipped; only the text blocks make up the answer.
+    let text: String = response_data
+        .content
+        .iter()
+        .filter(|block| block.kind == "text")
+        .filter_map(|block| block.text.as_deref())
+        .collect();
+    if text.trim().is_empty() {
+        error!("Anthropic returned no text content.");
+        return Err(ApiError::EmptyChoices);
+    }
+
+    // Map Claude's stop_reason onto the OpenAI-style finish_reason used by the retry logic.
+    let finish_reason = match response_data.stop_reason.as_deref() {
+        Some("max_tokens") => Some("length".to_string()),
+        Some("refusal") => Some("error".to_string()),
+        Some(_) => Some("stop".to_string()),
+        None => None,
+    };
+
+    let cleaned_content = post_process_html_response(&text);
+    if matches!(finish_reason.as_deref(), Some("length") | Some("error")) {
+        warn!(
+            "Anthropic returned stop_reason {:?}.",
+            response_data.stop_reason
+        );
+        return Err(ApiError::ApiReturnedError {
+            provider_error: None,
+            status,
+            content: cleaned_content,
+            finish_reason,
+        });
+    }
+
+    if !looks_like_html(&cleaned_content) {
+        warn!("Anthropic returned content that does not look like HTML. Forcing finish_reason='error' to trigger retry.");
+        return Err(ApiError::ApiReturnedError {
+            provider_error: None,
+            status,
+            content: cleaned_content,
+            finish_reason: Some("error".to_string()),
+        });
+    }
+    Ok((cleaned_content, finish_reason))
+}
+
+fn openai_reasoning_effort_from_reasoning(reasoning: &Option<ReasoningConfig>) -> Option<String> {
+    let reasoning = reasoning.as_ref()?;
+
+    // If explicitly disabled, do not send reasoning_effort.
+    if reasoning.enabled == Some(false) {
+        return None;
+    }
+
+    let effort = reasoning.effort.as_deref()?;
+
+    // OpenAI reasoning models accept reasoning_effort: minimal | low | medium | high.
+    // We map OpenRouter-style values to OpenAI values:
+    // xhigh/high -> high, medium -> medium, low -> low, minimal -> minimal, none -> omit.
+    match effort {
+        "xhigh" | "high" => Some("high".to_string()),
+        "medium" => Some("medium".to_string()),
+        "low" => Some("low".to_string()),
+        "minimal" => Some("minimal".to_string()),
+        "none" => None,
+        other => {
+            warn!(
+                "AI_PROVIDER_REWRITER_REASONING_EFFORT='{}' is not supported for OpenAI. Omitting reasoning_effort.",
+                other
+            );
+            None
+        }
+    }
+}
+
+/// `parse_chat_body` on a response that is also kept for the item's AI log.
+async fn parse_chat_response(response: reqwest::Response) -> Result<(String, Option<String>), ApiError> {
+    let status = response.status();
+    // Read the body text regardless of status code
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+    record_ai_response(status, &response_text);
+
+    parse_chat_body(status, &response_text, post_process_html_response)
+}
+
+fn post_process_html_response(content: &str) -> String {
+    sanitize_html(&extract_html_response(content))
+}
+
+/// Reduces model HTML to what the publisher and Telegram understand: the document skeleton
+/// (html/head/body, p, h1-h6, br) and Telegram's formatting tags. List items and block containers
+/// become line breaks, scripts and embeds are dropped with their content, any other tag is unwrapped,
+/// and attributes are removed except safe link targets, spoilers and code languages.
+fn sanitize_html(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut result = String::with_capacity(html.len());
+    let mut removed: Vec<String> = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = html[pos..].find('<') {
+        let tag_start = pos + offset;
+        result.push_str(&html[pos..tag_start]);
+
+        let rest = &lower[tag_start + 1..];
+        if rest.starts_with("!--") {
+            pos = rest
+                .find("-->")
+                .map(|end| tag_start + 1 + end + 3)
+                .unwrap_or(html.len());
+            continue;
+        }
+        let is_tag = rest.starts_with('!') || rest.trim_start_matches('/').starts_with(|c: char| c.is_ascii_alphabetic());
+        let Some(tag_len) = html[tag_start..].find('>').filter(|_| is_tag) else {
+            // A bare '<' in the text (e.g. "a < b") would be read as a tag by Telegram
+            result.push_str("&lt;");
+            pos = tag_start + 1;
+            continue;
+        };
+        let tag_end = tag_start + tag_len + 1;
+        let tag = &html[tag_start + 1..tag_end - 1];
+        let tag_lower = &lower[tag_start + 1..tag_end - 1];
+        let closing = tag_lower.starts_with('/');
+        let name: String = tag_lower
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect();
+        pos = tag_end;
+
+        if tag_lower.starts_with('!') {
+            if tag_lower.starts_with("!doctype") {
+                result.push_str(&html[tag_start..tag_end]);
+            }
+            continue;
+        }
+
+        match name.as_str() {
+            "script" | "style" | "iframe" | "object" | "embed" | "svg" | "math" | "noscript" | "template"
+            | "canvas" | "video" | "audio" | "form" | "button" | "select" | "textarea" => {
+                if !closing && !tag_lower.ends_with('/') {
+                    let closing_tag = format!("</{}", name);
+                    pos = lower[tag_end..]
+                        .find(&closing_tag)
+                        .and_then(|c| lower[tag_end + c..].find('>').map(|e| tag_end + c + e + 1))
+                        .unwrap_or(html.len());
+                }
+                removed.push(name);
+            }
+            "a" if !closing => match tag_attribute(tag, "href").filter(|href| is_safe_link(href)) {
+                Some(href) => result.push_str(&format!("<a href=\"{}\">", href.replace('"', "&quot;"))),
+                None => result.push_str("<a>"),
+            },
+            "span" if !closing => match tag_attribute(tag, "class") {
+                Some("tg-spoiler") => result.push_str("<span class=\"tg-spoiler\">"),
+                _ => result.push_str("<span>"),
+            },
+            "code" if !closing => match tag_attribute(tag, "class").filter(|class| {
+                class.starts_with("language-") && class.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '+')
+            }) {
+                Some(class) => result.push_str(&format!("<code class=\"{}\">", class)),
+                None => result.push_str("<code>"),
+            },
+            // The head is not published; keep charset and friends as they are
+            "meta" => result.push_str(&html[tag_start..tag_end]),
+            "html" | "head" | "title" | "body" | "p" | "br" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "b"
+            | "strong" | "i" | "em" | "u" | "ins" | "s" | "strike" | "del" | "a" | "span" | "code" | "pre"
+            | "blockquote" | "tg-spoiler" => {
+                result.push_str(if closing { "</" } else { "<" });
+                result.push_str(&name);
+                result.push('>');
+            }
+            "li" => {
+                if !closing {
+                    result.push_str("<br>• ");
+                }
+            }
+            "div" | "section" | "article" | "header" | "footer" | "main" | "aside" | "nav" | "figure"
+            | "figcaption" | "ul" | "ol" | "dl" | "dt" | "dd" | "table" | "thead" | "tbody" | "tfoot" | "tr"
+            | "address" | "hr" => {
+                if closing || name == "hr" {
+                    result.push_str("<br>");
+                }
+            }
+            "td" | "th" => {
+                if closing {
+                    result.push(' ');
+                }
+            }
+            _ => removed.push(name),
+        }
+    }
+    result.push_str(&html[pos..]);
+
+    if !removed.is_empty() {
+        removed.sort();
+        removed.dedup();
+        debug!(
+            "Removed unsupported tags from model output: {}",
+            removed.join(", ")
+        );
+    }
+    result
+}
+
+/// Value of an attribute inside a tag (`a href="..." class=x`), without the quotes.
+fn tag_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let lower = tag.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(offset) = lower[search..].find(name) {
+        let start = search + offset;
+        search = start + name.len();
+        let after = lower[search..].trim_start();
+        if !lower[..start].ends_with(|c: char| c.is_ascii_whitespace()) || !after.starts_with('=') {
+            continue;
+        }
+        let value = tag[tag.len() - after.len() + 1..].trim_start();
+        return Some(match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+            _ => value.split(|c: char| c.is_ascii_whitespace()).next().unwrap_or(""),
+        });
+    }
+    None
+}
+
+fn is_safe_link(href: &str) -> bool {
+    let href = href.trim().to_ascii_lowercase();
+    ["http://", "https://", "tg://", "mailto:"].iter().any(|scheme| href.starts_with(scheme))
+}
+
+/// Converts rewritten HTML into plain text: block elements become paragraphs,
+/// tags are dropped and common entities are decoded.
+fn html_to_plain_text(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+
+    // Only the body is meaningful; fall back to the whole document.
+    let body_start = lower
+        .find("<body")
+        .and_then(|pos| lower[pos..].find('>').map(|end| pos + end + 1))
+        .unwrap_or(0);
+    let body_end = lower.rfind("</body>").filter(|&end| end >= body_start).unwrap_or(html.len());
+    let body = &html[body_start..body_end];
+    let body_lower = &lower[body_start..body_end];
+
+    let mut text = String::with_capacity(body.len());
+    let mut pos = 0;
+    while let Some(offset) = body[pos..].find('<') {
+        let tag_start = pos + offset;
+        text.push_str(&body[pos..tag_start]);
+
+        let Some(tag_len) = body[tag_start..].find('>') else {
+            pos = body.len();
+            break;
+        };
+        let tag_end = tag_start + tag_len + 1;
+        let tag = &body_lower[tag_start + 1..tag_end - 1];
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+
+        match name.as_str() {
+            // Skip non-visible content entirely
+            "script" | "style" | "head" if !tag.starts_with('/') => {
+                let closing = format!("</{}", name);
+                pos = body_lower[tag_end..]
+                    .find(&closing)
+                    .and_then(|c| body_lower[tag_end + c..].find('>').map(|e| tag_end + c + e + 1))
+                    .unwrap_or(body.len());
+                continue;
+            }
+            "br" => text.push('\n'),
+            "li" if !tag.starts_with('/') => text.push_str("\n- "),
+            "p" | "div" | "section" | "article" | "blockquote" | "ul" | "ol" | "table" | "tr"
+            | "figure" | "figcaption" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => text.push_str("\n\n"),
+            _ => {}
+        }
+        pos = tag_end;
+    }
+    text.push_str(&body[pos..]);
+
+    let decoded = decode_html_entities(&text);
+
+    // Collapse whitespace inside lines and keep at most one blank line between paragraphs.
+    let mut result = String::with_capacity(decoded.len());
+    let mut blank_lines = 0;
+    for line in decoded.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !result.is_empty() {
+            result.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        result.push_str(&line);
+        blank_lines = 0;
+    }
+    result.push('\n');
+    result
+}
+
+fn decode_html_entities(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        let after = &rest[amp..];
+        let entity_end = after.find(';').filter(|&end| end <= 10);
+        let decoded = entity_end.and_then(|end| {
+            let entity = &after[1..end];
+            let ch = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                "nbsp" => Some(' '),
+                "laquo" => Some('«'),
+                "raquo" => Some('»'),
+                "mdash" => Some('—'),
+                "ndash" => Some('–'),
+                "hellip" => Some('…'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            ch.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                result.push(c);
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &after[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
@@ -0,0 +1,665 @@
+use anyhow::{Context, Result};
+use robo_news_core::{
+    block_on, claim_item, fetch_items_due, heartbeat, http_timeout, item_span, listen_for_shutdown, load_pipeline_stage,
+    news_field, open_db, read_artifact_string, read_attempt_policy_from_env, read_interval_from_env,
+    record_failed_attempt, release_claim, reset_attempts, send_dead_letter_alerts, set_news_field, shutdown_requested,
+    transition, wait_for_wakeup, wakeup_seq, write_artifact, AttemptPolicy, Cancelled, NewsField, NewsItem,
+    PipelineStage, Status,
+};
+use rusqlite::Connection;
+use tracing::{error, info, warn};
+use std::env;
+use std::fs;
+use std::path::Path;
+use readability::extractor;
+use regex::Regex;
+use reqwest::Client;
+use serde::Serialize;
+use std::process::Command;
+use std::sync::LazyLock;
+use url::Url;
+
+const DATA_DIR: &str = "data";
+const DEFAULT_SCRAPE_INTERVAL_SECS: u64 = 60;
+const OCR_DEFAULT_COMMAND: &str = "tesseract";
+const OCR_DEFAULT_LANGUAGES: &str = "eng";
+const OCR_DEFAULT_MIN_TEXT_CHARS: usize = 200;
+const OCR_DEFAULT_MIN_IMAGE_BYTES: usize = 50_000;
+const OCR_DEFAULT_MAX_IMAGES: usize = 3;
+
+// Primary language subtag declared by `<html lang>` or `og:locale` (either attribute order)
+static PAGE_LANGUAGE: LazyLock<[Regex; 3]> = LazyLock::new(|| {
+    [
+        r#"(?i)<html\b[^>]*\blang\s*=\s*["']?([a-z]{2,3})(?:[-_"'\s>]|$)"#,
+        r#"(?i)<meta\b[^>]*\bproperty\s*=\s*["']og:locale["'][^>]*\bcontent\s*=\s*["']([a-z]{2,3})[-_"']"#,
+        r#"(?i)<meta\b[^>]*\bcontent\s*=\s*["']([a-z]{2,3})[-_"'][^>]*\bproperty\s*=\s*["']og:locale["']"#,
+    ]
+    .map(|pattern| Regex::new(pattern).expect("valid regex"))
+});
+static HIDDEN_ELEMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(script|style|noscript)[^>]*>.*?</(script|style|noscript)>").expect("valid regex")
+});
+static FIGCAPTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<figcaption[^>]*>(.*?)</figcaption>").expect("valid regex"));
+// WordPress and common theme caption/credit blocks
+static WP_CAPTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<(?:p|div|span)[^>]*class="[^"]*(?:wp-caption-text|wp-element-caption|image-credit|photo-credit)[^"]*"[^>]*>(.*?)</(?:p|div|span)>"#)
+        .expect("valid regex")
+});
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]+>").expect("valid regex"));
+
+/// Boilerplate phrases stripped from the extracted content before it reaches the translator.
+struct BoilerplateRules {
+    // Applied to every item
+    global: Vec<Regex>,
+    // Applied only to items of the feed, by news.source
+    feeds: Vec<FeedBoilerplate>,
+    // Paragraphs left empty by the stripping
+    empty_paragraph: Regex,
+}
+
+struct FeedBoilerplate {
+    source: String,
+    patterns: Vec<Regex>,
+}
+
+/// OCR fallback for articles published as images (announcements, posters).
+///
+/// Runs the `tesseract` CLI on the largest images of the page when readability
+/// extracts almost no text.
+#[derive(Debug, Clone)]
+struct OcrConfig {
+    command: String,
+    languages: String,
+    min_text_chars: usize,
+    min_image_bytes: usize,
+    max_images: usize,
+}
+
+/// Per-item extraction diagnostics written next to the scraped HTML as `scraper_<id>.json`.
+#[derive(Serialize)]
+struct ScrapeReport {
+    id: String,
+    url: String,
+    title: String,
+    /// Rough 0..1 estimate of how much readable text was found; halved when the text came from OCR.
+    extraction_confidence: f64,
+    source_chars: usize,
+    extracted_chars: usize,
+    output_chars: usize,
+    removed_boilerplate: Vec<String>,
+    preserved_captions: Vec<String>,
+    images: Vec<String>,
+    ocr_used: bool,
+    ocr_chars: usize,
+    /// Source language declared by the page (`<html lang>` or `og:locale`), if any.
+    lang: Option<String>,
+}
+
+/// Extracts the article text of downloaded items until the process ends, or for one cycle with `--once`.
+pub fn run(options: RunOptions) -> Result<()> {
+    let interval_secs = read_interval_from_env("SCRAPER_INTERVAL_SECS", DEFAULT_SCRAPE_INTERVAL_SECS)?;
+
+    // Initialize database and data directory
+    let conn = open_db()?;
+    init_data_dir()?;
+    let client = Client::builder().timeout(http_timeout()?).build()?;
+
+    let stage = load_pipeline_stage(
+        "scraper",
+        PipelineStage {
+            input_status: Status::Downloaded,
+            output_status: Status::Scraper,
+            input_artifact: "news".to_string(),
+            output_artifact: "scraper".to_string(),
+        },
+    )?;
+
+    let boilerplate = read_boilerplate_rules_from_env()?;
+    let ocr = read_ocr_config_from_env()?;
+    let attempt_policy = read_attempt_policy_from_env(Status::ScraperRetry, Status::ScraperError)?;
+    
+    info!("Starting scraper...");
+    listen_for_shutdown()?;
+    
+    if options.once {
+        run_scraper(&conn, &client, &stage, &boilerplate, ocr.as_ref(), &attempt_policy, options.limit)?;
+        return block_on(send_dead_letter_alerts(&conn));
+    }
+    
+    // Main loop - run every SCRAPER_INTERVAL_SECS seconds until SIGTERM or Ctrl-C
+    while !shutdown_requested() {
+        if let Err(e) = heartbeat(&conn, interval_secs) {
+            warn!("Failed to record heartbeat: {}", e);
+        }
+        // Read before the cycle, so items handed over during it end the next sleep early
+        let seen = wakeup_seq(&conn, &[&stage.input_status]).unwrap_or(0);
+        if let Err(e) = run_scraper(&conn, &client, &stage, &boilerplate, ocr.as_ref(), &attempt_policy, options.limit) {
+            error!("Error during scraping: {}", e);
+        }
+        if let Err(e) = block_on(send_dead_letter_alerts(&conn)) {
+            warn!("Failed to send dead-letter alerts: {}", e);
+        }
+        
+        info!("Sleeping for up to {} seconds", interval_secs);
+        wait_for_wakeup(interval_secs, seen, || wakeup_seq(&conn, &[&stage.input_status]))?;
+    }
+    info!("Stopped");
+    Ok(())
+}
+
+/// Command-line options for one-off runs (used by `ctl seed` and cron or CI):
+/// `--once` runs a single cycle and exits, `--limit N` handles at most N items per cycle.
+pub struct RunOptions {
+    pub once: bool,
+    pub limit: Option<usize>,
+}
+
+pub fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { once: false, limit: None };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--once" => options.once = true,
+            "--limit" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--limit requires a value"))?;
+                options.limit = Some(
+                    value
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("--limit must be a non-negative integer: {}", e))?,
+                );
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn init_data_dir() -> Result<()> {
+    if !Path::new(DATA_DIR).exists() {
+        fs::create_dir_all(DATA_DIR).context("Failed to create data directory")?;
+    }
+    Ok(())
+}
+
+fn read_boilerplate_rules_from_env() -> Result<BoilerplateRules> {
+    // SCRAPER_BOILERPLATE_PATTERNS applies to all items.
+    // FEED<N>_BOILERPLATE_PATTERNS applies to items the parser stored with the source of that feed
+    // (`feed<n>`, or FEED<N>_SOURCE if set).
+    // Both hold one regular expression per line.
+    let global = match env::var("SCRAPER_BOILERPLATE_PATTERNS") {
+        Ok(value) => parse_boilerplate_patterns("SCRAPER_BOILERPLATE_PATTERNS", &value)?,
+        Err(_) => Vec::new(),
+    };
+
+    let mut feeds = Vec::new();
+    for (key, value) in env::vars() {
+        let Some(feed) = key.strip_suffix("_BOILERPLATE_PATTERNS") else {
+            continue;
+        };
+        let is_feed_key = feed
+            .strip_prefix("FEED")
+            .map(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(false);
+        if !is_feed_key {
+            continue;
+        }
+
+        let patterns = parse_boilerplate_patterns(&key, &value)?;
+        if !patterns.is_empty() {
+            let source = env::var(format!("{}_SOURCE", feed))
+                .ok()
+                .map(|source| source.trim().to_string())
+                .filter(|source| !source.is_empty())
+                .unwrap_or_else(|| feed.to_ascii_lowercase());
+            feeds.push(FeedBoilerplate { source, patterns });
+        }
+    }
+
+    let feed_patterns: usize = feeds.iter().map(|f| f.patterns.len()).sum();
+    info!(
+        "Loaded {} global and {} per-feed boilerplate patterns",
+        global.len(),
+        feed_patterns
+    );
+
+    let empty_paragraph = Regex::new(r"(?i)<p(\s[^>]*)?>\s*</p>").expect("valid regex");
+    Ok(BoilerplateRules { global, feeds, empty_paragraph })
+}
+
+fn parse_boilerplate_patterns(key: &str, value: &str) -> Result<Vec<Regex>> {
+    value
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            Regex::new(line).with_context(|| format!("{} contains invalid regex '{}'", key, line))
+        })
+        .collect()
+}
+
+fn strip_boilerplate(content: &str, source: Option<&str>, rules: &BoilerplateRules) -> (String, Vec<String>) {
+    let feed_patterns = rules
+        .feeds
+        .iter()
+        .filter(|feed| source == Some(feed.source.as_str()))
+        .flat_map(|feed| feed.patterns.iter());
+
+    let mut result = content.to_string();
+    let mut removed = Vec::new();
+    for pattern in rules.global.iter().chain(feed_patterns) {
+        let matches: Vec<String> = pattern
+            .find_iter(&result)
+            .map(|m| m.as_str().to_string())
+            .collect();
+        if !matches.is_empty() {
+            removed.extend(matches);
+            result = pattern.replace_all(&result, "").into_owned();
+        }
+    }
+
+    if !removed.is_empty() {
+        // Drop paragraphs that became empty after stripping
+        result = rules.empty_paragraph.replace_all(&result, "").into_owned();
+    }
+
+    (result, removed)
+}
+
+fn read_ocr_config_from_env() -> Result<Option<OcrConfig>> {
+    let enabled = match env::var("SCRAPER_OCR_ENABLED") {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "y" | "on" => true,
+            "" | "0" | "false" | "no" | "n" | "off" => false,
+            other => {
+                return Err(anyhow::anyhow!(
+                    "SCRAPER_OCR_ENABLED has invalid value '{}'",
+                    other
+                ))
+            }
+        },
+        Err(_) => false,
+    };
+
+    if !enabled {
+        return Ok(None);
+    }
+
+    let config = OcrConfig {
+        command: env::var("SCRAPER_OCR_COMMAND")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| OCR_DEFAULT_COMMAND.to_string()),
+        languages: env::var("SCRAPER_OCR_LANGUAGES")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| OCR_DEFAULT_LANGUAGES.to_string()),
+        min_text_chars: read_usize_env("SCRAPER_OCR_MIN_TEXT_CHARS", OCR_DEFAULT_MIN_TEXT_CHARS)?,
+        min_image_bytes: read_usize_env("SCRAPER_OCR_MIN_IMAGE_BYTES", OCR_DEFAULT_MIN_IMAGE_BYTES)?,
+        max_images: read_usize_env("SCRAPER_OCR_MAX_IMAGES", OCR_DEFAULT_MAX_IMAGES)?,
+    };
+
+    info!(
+        "OCR fallback enabled: command='{}', languages='{}', min_text_chars={}",
+        config.command, config.languages, config.min_text_chars
+    );
+
+    Ok(Some(config))
+}
+
+fn read_usize_env(key: &str, default: usize) -> Result<usize> {
+    match env::var(key) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .with_context(|| format!("{} must be a non-negative integer", key)),
+        _ => Ok(default),
+    }
+}
+
+fn run_scraper(
+    conn: &Connection,
+    client: &Client,
+    stage: &PipelineStage,
+    boilerplate: &BoilerplateRules,
+    ocr: Option<&OcrConfig>,
+    attempt_policy: &AttemptPolicy,
+    limit: Option<usize>,
+) -> Result<()> {
+    info!("Checking for news items to scrape");
+    
+    // Fetch news items with the stage input status ("downloaded" by default) and retries that are due
+    let mut news_items = fetch_items_due(conn, &stage.input_status, &attempt_policy.retry_status)?;
+    if let Some(limit) = limit {
+        news_items.truncate(limit);
+    }
+    
+    if news_items.is_empty() {
+        info!("No items to scrape");
+        return Ok(());
+    }
+    
+    info!("Found {} items to scrape", news_items.len());
+    
+    // Process each news item
+    for item in news_items {
+        if shutdown_requested() {
+            break;
+        }
+        let _span = item_span("scraper", &item.id).entered();
+        // Another replica may have taken the item since it was fetched
+        if !claim_item(conn, &item.id, None, &item.status)? {
+            continue;
+        }
+        let source = news_field(conn, &item.id, NewsField::Source)?;
+        match process_news_item(client, &item, source.as_deref(), stage, boilerplate, ocr) {
+            Ok(lang) => {
+                set_news_field(conn, &item.id, NewsField::Lang, lang.as_deref())?;
+                // Update status to the stage output status ("scraper" by default)
+                reset_attempts(conn, &item.id, None)?;
+                transition(conn, &item.id, None, &item.status, &stage.output_status)?;
+                info!("Successfully scraped news item: {}", item.id);
+            }
+            Err(e) if e.is::<Cancelled>() => {
+                // Shutting down: the next start scrapes it again
+                release_claim(conn, &item.id, None)?;
+                break;
+            }
+            Err(e) => {
+                let (next_status, attempts) = record_failed_attempt(conn, &item.id, None, attempt_policy, &e.to_string())?;
+                let message = format!(
+                    "Failed to scrape news item {} (attempt {}/{}): {}. Setting status to {}.",
+                    item.id, attempts, attempt_policy.max_attempts, e, next_status
+                );
+                if next_status == Status::ScraperError {
+                    error!("{}", message);
+                } else {
+                    warn!("{}", message);
+                }
+                // Also gives up the claim, so the retry isn't blocked until the claim times out
+                transition(conn, &item.id, None, &item.status, &next_status)?;
+                // Continue with the next item
+            }
+        }
+    }
+    
+    info!("Scraping process completed");
+    Ok(())
+}
+
+fn process_news_item(
+    client: &Client,
+    item: &NewsItem,
+    source: Option<&str>,
+    stage: &PipelineStage,
+    boilerplate: &BoilerplateRules,
+    ocr: Option<&OcrConfig>,
+) -> Result<Option<String>> {
+    // Read the downloaded HTML
+    let html_content = read_artifact_string(&format!("{}_{}.html", stage.input_artifact, item.id))
+        .context("Failed to read HTML file")?;
+    
+    // Create a fake URL for the readability library
+    let base_url = Url::parse("http://localhost/").context("Failed to parse base URL")?;
+    
+    // Extract readable content using readability
+    // We need to create a cursor from our string to use it with readability
+    let mut content_cursor = std::io::Cursor::new(html_content.as_bytes());
+    let product = extractor::extract(&mut content_cursor, &base_url)
+        .context("Failed to extract content with readability")?;
+
+    let (mut content, removed) = strip_boilerplate(&product.content, source, boilerplate);
+    if !removed.is_empty() {
+        info!("Stripped {} boilerplate fragments from news item {}", removed.len(), item.id);
+    }
+
+    // Readability tends to drop figcaptions, which silently removes photo credits.
+    let captions = extract_image_captions(&html_content, &content);
+    if !captions.is_empty() {
+        info!("Preserving {} image captions for news item {}", captions.len(), item.id);
+        content.push_str(&image_captions_to_html(&captions));
+    }
+
+    let extracted_chars = product.text.trim().chars().count();
+    let mut ocr_used = false;
+    let mut ocr_chars = 0;
+
+    if let Some(ocr) = ocr {
+        if extracted_chars < ocr.min_text_chars {
+            info!(
+                "News item {} has only {} characters of text, trying OCR",
+                item.id, extracted_chars
+            );
+            match ocr_page_images(client, item, &html_content, ocr) {
+                Ok(ocr_text) if !ocr_text.is_empty() => {
+                    ocr_used = true;
+                    ocr_chars = ocr_text.chars().count();
+                    content.push_str(&ocr_text_to_html(&ocr_text));
+                }
+                Ok(_) => warn!("OCR found no text for news item {}", item.id),
+                Err(e) if e.is::<Cancelled>() => return Err(e),
+                Err(e) => warn!("OCR failed for news item {}: {}", item.id, e),
+            }
+        }
+    }
+    
+    // Create a simple HTML document with the extracted content
+    let result_html = format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+    <meta charset=\"UTF-8\">
+    <title>{}</title>
+</head>
+<body>
+    <h1>{}</h1>
+    {}
+</body>
+</html>",
+        product.title,
+        product.title,
+        content
+    );
+    
+    // Save the extracted content
+    write_artifact(&format!("{}_{}.html", stage.output_artifact, item.id), &result_html)
+        .context("Failed to write extracted content")?;
+
+    let page_url = Url::parse(&item.url).ok();
+    let images = extract_image_sources(&html_content)
+        .into_iter()
+        .map(|src| match page_url.as_ref().and_then(|base| base.join(&src).ok()) {
+            Some(url) => url.to_string(),
+            None => src,
+        })
+        .collect();
+
+    let useful_chars = extracted_chars.max(ocr_chars) as f64;
+    let mut extraction_confidence = useful_chars / (useful_chars + 500.0);
+    if ocr_used {
+        extraction_confidence /= 2.0;
+    }
+
+    let report = ScrapeReport {
+        id: item.id.clone(),
+        url: item.url.clone(),
+        title: product.title.clone(),
+        extraction_confidence: (extraction_confidence * 100.0).round() / 100.0,
+        source_chars: visible_text_chars(&html_content),
+        extracted_chars,
+        output_chars: normalize_caption(&content).chars().count(),
+        removed_boilerplate: removed,
+        preserved_captions: captions,
+        images,
+        ocr_used,
+        ocr_chars,
+        lang: detect_page_language(&html_content),
+    };
+    write_scrape_report(&report, &stage.output_artifact)?;
+    
+    Ok(report.lang)
+}
+
+/// Primary language subtag declared by the page: `<html lang="en-US">` or `og:locale` (`en_US`).
+fn detect_page_language(html: &str) -> Option<String> {
+    PAGE_LANGUAGE.iter().find_map(|pattern| {
+        pattern
+            .captures(html)
+            .map(|captures| captures[1].to_ascii_lowercase())
+    })
+}
+
+fn write_scrape_report(report: &ScrapeReport, output_artifact: &str) -> Result<()> {
+    let json = serde_json::to_string_pretty(report).context("Failed to serialize scrape report")?;
+    write_artifact(&format!("{}_{}.json", output_artifact, report.id), json).context("Failed to write scrape report")?;
+    Ok(())
+}
+
+fn visible_text_chars(html: &str) -> usize {
+    normalize_caption(&HIDDEN_ELEMENT.replace_all(html, " ")).chars().count()
+}
+
+fn extract_image_captions(html: &str, content: &str) -> Vec<String> {
+    let content_text = normalize_caption(content);
+    let mut captions: Vec<String> = Vec::new();
+
+    for caps in FIGCAPTION.captures_iter(html).chain(WP_CAPTION.captures_iter(html)) {
+        let caption = normalize_caption(&caps[1]);
+        if caption.is_empty() || content_text.contains(&caption) || captions.contains(&caption) {
+            continue;
+        }
+        captions.push(caption);
+    }
+
+    captions
+}
+
+fn normalize_caption(html: &str) -> String {
+    TAG.replace_all(html, " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn image_captions_to_html(captions: &[String]) -> String {
+    let items: Vec<String> = captions
+        .iter()
+        .map(|caption| format!("<p class=\"image-caption\">{}</p>", caption))
+        .collect();
+
+    format!("\n<div class=\"image-captions\">\n{}\n</div>", items.join("\n"))
+}
+
+fn ocr_page_images(client: &Client, item: &NewsItem, html: &str, ocr: &OcrConfig) -> Result<String> {
+    let page_url = Url::parse(&item.url).context("Failed to parse news item URL")?;
+    let mut texts = Vec::new();
+    let mut processed = 0;
+
+    for src in extract_image_sources(html) {
+        if processed >= ocr.max_images {
+            break;
+        }
+
+        let image_url = match page_url.join(&src) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+
+        let download = block_on(async {
+            let response = client.get(image_url.as_str()).send().await?.error_for_status()?;
+            Ok::<_, anyhow::Error>(response.bytes().await?)
+        });
+        let bytes = match download {
+            Ok(bytes) => bytes,
+            Err(e) if e.is::<Cancelled>() => return Err(e),
+            Err(e) => {
+                warn!("Failed to download image {}: {}", image_url, e);
+                continue;
+            }
+        };
+
+        // Icons, logos and tracking pixels are too small to carry an announcement
+        if bytes.len() < ocr.min_image_bytes {
+            continue;
+        }
+        processed += 1;
+
+        let image_path = format!("{}/ocr_{}_{}.img", DATA_DIR, item.id, processed);
+        fs::write(&image_path, &bytes)
+            .context(format!("Failed to write image for OCR: {}", image_path))?;
+
+        let output = Command::new(&ocr.command)
+            .arg(&image_path)
+            .arg("stdout")
+            .arg("-l")
+            .arg(&ocr.languages)
+            .output();
+        let _ = fs::remove_file(&image_path);
+
+        let output = output.context(format!("Failed to run OCR command '{}'", ocr.command))?;
+        if !output.status.success() {
+            warn!(
+                "OCR command failed for {}: {}",
+                image_url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            continue;
+        }
+
+        let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !text.is_empty() {
+            info!("OCR extracted {} characters from {}", text.chars().count(), image_url);
+            texts.push(text);
+        }
+    }
+
+    Ok(texts.join("\n\n"))
+}
+
+fn extract_image_sources(html: &str) -> Vec<String> {
+    let lower = html.to_ascii_lowercase();
+    let mut sources = Vec::new();
+    let mut pos = 0;
+
+    while let Some(start) = lower[pos..].find("<img") {
+        let tag_start = pos + start;
+        let tag_end = lower[tag_start..].find('>').map(|e| tag_start + e).unwrap_or(lower.len());
+        let tag = &html[tag_start..tag_end];
+        let tag_lower = &lower[tag_start..tag_end];
+
+        if let Some(attr) = tag_lower.find(" src=") {
+            let value = &tag[attr + 5..];
+            let src = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+                _ => value.split(char::is_whitespace).next().unwrap_or(""),
+            };
+            let src = src.trim();
+            if !src.is_empty() && !src.starts_with("data:") && !sources.iter().any(|s| s == src) {
+                sources.push(src.to_string());
+            }
+        }
+
+        pos = tag_end;
+    }
+
+    sources
+}
+
+fn ocr_text_to_html(text: &str) -> String {
+    let paragraphs: Vec<String> = text
+        .split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("<p>{}</p>", escape_html(&p)))
+        .collect();
+
+    format!("\n<div class=\"ocr-text\">\n{}\n</div>", paragraphs.join("\n"))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
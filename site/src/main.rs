@@ -0,0 +1,351 @@
+use anyhow::{Context, Result};
+use robo_news_core::{
+    artifact_exists, artifact_url, builtin_stage, init_logging, load_config_file, load_secret_files, open_db,
+    open_db_read_only, read_artifact, read_artifact_string, PipelineStage,
+};
+use rusqlite::params;
+use std::env;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+const DEFAULT_OUT_DIR: &str = "site";
+const DEFAULT_INDEX_SIZE: usize = 20;
+const DEFAULT_SITE_TITLE: &str = "Robo News";
+// Characters of text shown for each item on the front page
+const EXCERPT_LENGTH: usize = 300;
+
+const USAGE: &str = "Usage: site [--out <dir>] [--lang <code>] [--index-size <n>] [--templates <dir>]
+
+Renders the published items into a static site in <dir> (default: site):
+  index.html           the latest <n> items (default: 20)
+  archive.html         every published item, by month
+  articles/<id>.html   one page per item
+  images/<id>.png      the illustrations (with ARTIFACT_STORE=s3 the pages link presigned URLs
+                       instead, so render again before S3_URL_EXPIRES_SECS runs out)
+  style.css
+
+--lang selects one of TARGET_LANGUAGES (default: the first). --templates points to a directory
+with your own layout.html and/or style.css. The layout gets {{site_title}}, {{title}}, {{lang}},
+{{root}} (relative path to the site root) and {{content}}. SITE_TITLE sets the site's name.";
+
+const LAYOUT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="{{lang}}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{title}} — {{site_title}}</title>
+<link rel="stylesheet" href="{{root}}style.css">
+</head>
+<body>
+<header><a href="{{root}}index.html">{{site_title}}</a> · <a href="{{root}}archive.html">Archive</a></header>
+<main>
+{{content}}
+</main>
+</body>
+</html>
+"#;
+
+const STYLESHEET: &str = "body { max-width: 42rem; margin: 0 auto; padding: 1rem; font-family: sans-serif; line-height: 1.5; }
+header { margin-bottom: 2rem; font-weight: bold; }
+img { max-width: 100%; height: auto; }
+.date { color: #666; font-size: 0.9rem; }
+.body { white-space: pre-line; }
+article { margin-bottom: 2rem; }
+";
+
+struct Options {
+    out_dir: String,
+    lang: Option<String>,
+    index_size: usize,
+    templates: Option<String>,
+}
+
+struct PublishedItem {
+    id: String,
+    title: String,
+    url: String,
+    date: String,
+    /// Published HTML as sent to Telegram.
+    body: String,
+    has_image: bool,
+    /// Presigned URL of the illustration (ARTIFACT_STORE=s3), linked instead of a copy in images/.
+    image_url: Option<String>,
+}
+
+fn main() -> Result<()> {
+    load_config_file()?;
+    load_secret_files()?;
+    init_logging()?;
+    let options = parse_options()?;
+    let publisher = builtin_stage("publisher")?;
+    // Brings the schema up to date before the read-only queries
+    open_db()?;
+
+    let default_lang = env::var("TARGET_LANGUAGES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|code| code.trim().to_ascii_lowercase())
+        .find(|code| !code.is_empty());
+    let lang = options
+        .lang
+        .as_ref()
+        .map(|code| code.trim().to_ascii_lowercase())
+        .filter(|code| default_lang.as_deref() != Some(code.as_str()));
+    let site_title = env::var("SITE_TITLE")
+        .ok()
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| DEFAULT_SITE_TITLE.to_string());
+
+    let layout = read_template(&options, "layout.html", LAYOUT_TEMPLATE)?;
+    let stylesheet = read_template(&options, "style.css", STYLESHEET)?;
+    let page = |title: &str, root: &str, content: &str| {
+        layout
+            .replace("{{site_title}}", &escape_html(&site_title))
+            .replace("{{title}}", &escape_html(title))
+            .replace("{{lang}}", lang.as_deref().or(default_lang.as_deref()).unwrap_or("en"))
+            .replace("{{root}}", root)
+            .replace("{{content}}", content)
+    };
+
+    let items = fetch_published_items(&publisher, lang.as_deref())?;
+    let out = Path::new(&options.out_dir);
+    fs::create_dir_all(out.join("articles")).context(format!("Failed to create {}/articles", options.out_dir))?;
+    fs::create_dir_all(out.join("images")).context(format!("Failed to create {}/images", options.out_dir))?;
+    write(out, "style.css", &stylesheet)?;
+
+    for item in &items {
+        if item.has_image && item.image_url.is_none() {
+            let image = out.join(format!("images/{}.png", item.id));
+            fs::write(&image, read_artifact(&format!("illustrator_{}.png", item.id))?)
+                .context(format!("Failed to write {}", image.display()))?;
+        }
+        write(out, &format!("articles/{}.html", item.id), &page(&item.title, "../", &article_content(item, "../")))?;
+    }
+
+    let front_page: String = items
+        .iter()
+        .take(options.index_size)
+        .map(|item| index_entry(item, ""))
+        .collect();
+    write(out, "index.html", &page(&site_title, "", &front_page))?;
+    write(out, "archive.html", &page("Archive", "", &archive_content(&items)))?;
+
+    println!(
+        "site: [INFO] Rendered {} published items ({}) into {}",
+        items.len(),
+        lang.as_deref().or(default_lang.as_deref()).unwrap_or("default language"),
+        options.out_dir
+    );
+    Ok(())
+}
+
+fn parse_options() -> Result<Options> {
+    let mut options = Options {
+        out_dir: DEFAULT_OUT_DIR.to_string(),
+        lang: None,
+        index_size: DEFAULT_INDEX_SIZE,
+        templates: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| anyhow::anyhow!("{} requires a value", name));
+        match arg.as_str() {
+            "--out" => options.out_dir = value("--out")?,
+            "--lang" => options.lang = Some(value("--lang")?),
+            "--index-size" => {
+                options.index_size = value("--index-size")?
+                    .parse()
+                    .context("--index-size must be a non-negative integer")?
+            }
+            "--templates" => options.templates = Some(value("--templates")?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            other => return Err(anyhow::anyhow!("Unknown argument: {}\n\n{}", other, USAGE)),
+        }
+    }
+
+    Ok(options)
+}
+
+/// The file from --templates if it has one, the built-in template otherwise.
+fn read_template(options: &Options, name: &str, default: &str) -> Result<String> {
+    let Some(dir) = &options.templates else {
+        return Ok(default.to_string());
+    };
+    let path = Path::new(dir).join(name);
+    if !path.exists() {
+        return Ok(default.to_string());
+    }
+    fs::read_to_string(&path).context(format!("Failed to read template {}", path.display()))
+}
+
+/// Published items of one language, newest first.
+fn fetch_published_items(publisher: &PipelineStage, lang: Option<&str>) -> Result<Vec<PublishedItem>> {
+    // The generator only reads; the pipeline services own the database
+    let conn = open_db_read_only()?;
+
+    let rows: Vec<(String, String, String, String)> = match lang {
+        None => {
+            let mut stmt = conn.prepare(
+                "SELECT id, title, url, date FROM news \
+                 WHERE status IN (?1, 'published_plain', 'updated') ORDER BY date DESC",
+            )?;
+            let rows = stmt
+                .query_map(params![publisher.output_status], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        }
+        Some(lang) => {
+            let mut stmt = conn.prepare(
+                "SELECT n.id, n.title, n.url, n.date FROM news_translations t JOIN news n ON n.id = t.news_id \
+                 WHERE t.lang = ?1 AND t.status IN (?2, 'published_plain', 'updated') ORDER BY n.date DESC",
+            )?;
+            let rows = stmt
+                .query_map(params![lang, publisher.output_status], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        }
+    };
+
+    let mut items = Vec::new();
+    for (id, title, url, date) in rows {
+        // The id becomes a file name
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            warn!("Skipping item with an id that is not a safe file name: {}", id);
+            continue;
+        }
+
+        // Extra languages use `<artifact>_<id>_<lang>`, the default language `<artifact>_<id>`
+        let suffix = match lang {
+            Some(lang) => format!("{}_{}", id, lang),
+            None => id.clone(),
+        };
+        let body = match read_artifact_string(&format!("{}_{}.html", publisher.output_artifact, suffix)) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Skipping item {}: {:#}", id, e);
+                continue;
+            }
+        };
+        let image = format!("illustrator_{}.png", id);
+        let has_image = artifact_exists(&image)?;
+        let image_url = if has_image { artifact_url(&image)? } else { None };
+        items.push(PublishedItem {
+            id,
+            title,
+            url,
+            date,
+            body,
+            has_image,
+            image_url,
+        });
+    }
+
+    Ok(items)
+}
+
+fn article_content(item: &PublishedItem, root: &str) -> String {
+    let image = image_tag(item, root);
+    format!(
+        "<article>\n<h1>{}</h1>\n<p class=\"date\">{}</p>\n{}<div class=\"body\">{}</div>\n<p><a href=\"{}\">Source</a></p>\n</article>\n",
+        escape_html(&item.title),
+        escape_html(display_date(&item.date)),
+        image,
+        item.body.trim(),
+        escape_html(&item.url)
+    )
+}
+
+fn index_entry(item: &PublishedItem, root: &str) -> String {
+    let image = image_tag(item, root);
+    format!(
+        "<article>\n<h2><a href=\"{}articles/{}.html\">{}</a></h2>\n<p class=\"date\">{}</p>\n{}<p>{}</p>\n</article>\n",
+        root,
+        item.id,
+        escape_html(&item.title),
+        escape_html(display_date(&item.date)),
+        image,
+        excerpt(&item.body)
+    )
+}
+
+fn image_tag(item: &PublishedItem, root: &str) -> String {
+    if !item.has_image {
+        return String::new();
+    }
+    let src = match &item.image_url {
+        Some(url) => escape_html(url),
+        None => format!("{}images/{}.png", root, item.id),
+    };
+    format!("<img src=\"{}\" alt=\"\">\n", src)
+}
+
+/// Every item as a link, grouped under a heading per month (`YYYY-MM`).
+fn archive_content(items: &[PublishedItem]) -> String {
+    let mut content = String::from("<h1>Archive</h1>\n");
+    let mut month = None;
+    for item in items {
+        let item_month = item.date.get(..7).unwrap_or(&item.date);
+        if month != Some(item_month) {
+            if month.is_some() {
+                content.push_str("</ul>\n");
+            }
+            content.push_str(&format!("<h2>{}</h2>\n<ul>\n", escape_html(item_month)));
+            month = Some(item_month);
+        }
+        content.push_str(&format!(
+            "<li><span class=\"date\">{}</span> <a href=\"articles/{}.html\">{}</a></li>\n",
+            escape_html(display_date(&item.date)),
+            item.id,
+            escape_html(&item.title)
+        ));
+    }
+    if month.is_some() {
+        content.push_str("</ul>\n");
+    }
+    content
+}
+
+/// Start of the item's text without its markup, cut at a word boundary.
+fn excerpt(body: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in body.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(if c == '\n' { ' ' } else { c }),
+            _ => {}
+        }
+    }
+    let text = text.trim();
+    if text.chars().count() <= EXCERPT_LENGTH {
+        return text.to_string();
+    }
+    // The body is already escaped; cutting at a space keeps entities such as &amp; whole
+    let cut: String = text.chars().take(EXCERPT_LENGTH).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(head, _)| head);
+    format!("{}…", cut.trim_end())
+}
+
+/// `YYYY-MM-DD` of RFC 3339 and `YYYY-MM-DD HH:MM:SS` dates, anything else as it is.
+fn display_date(date: &str) -> &str {
+    date.get(..10).unwrap_or(date)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write(out: &Path, relative: &str, content: &str) -> Result<()> {
+    let path = out.join(relative);
+    fs::write(&path, content).context(format!("Failed to write {}", path.display()))
+}
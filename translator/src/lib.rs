@@ -0,0 +1,1174 @@
+use anyhow::{Context, Result, anyhow};
+use rusqlite::{params, Connection, Row};
+use robo_news_core::{block_on, cancellable, check_daily_budget, claim_item, clear_provider_error, effective_chunk_chars, extract_html_response, gemini_reasoning_effort_from_reasoning, heartbeat, item_span, listen_for_shutdown, load_artifact, load_pipeline_stage, looks_like_html, open_db, parse_chat_response, parse_provider_error, perplexity_reasoning_effort_from_reasoning, process_in_chunks, read_artifact, read_concurrency_from_env, read_daily_budget_from_env, read_glossary_from_env, read_interval_from_env, read_price_from_env, read_reasoning_config_from_env, read_sampling_config_from_env, record_failure, record_provider_error, record_usage, release_claim, reset_attempts, run_async, run_workers, send_dead_letter_alerts, shutdown_requested, take_usage, track_usage, update_status, wait_for_wakeup, wakeup_seq, write_artifact, ApiError, Cancelled, Concurrency, ConcurrencyTuner, DailyBudget, GeminiChatRequest, Glossary, GlossaryTerm, Message, OpenRouterChatRequest, PerplexityChatRequest, PipelineStage, ReasoningConfig, SamplingConfig, Status, UsageModel, WORKER_CONNECTIONS};
+use tracing::{debug, error, info, warn, Instrument};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+const DATA_DIR: &str = "data";
+const DEFAULT_TRANSLATE_INTERVAL_SECS: u64 = 60;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 120;
+const GOOGLE_TRANSLATE_DEFAULT_LOCATION: &str = "global";
+
+// Set at startup with AI_PROVIDER_TRANSLATOR_ADAPTIVE_CONCURRENCY, fed by every provider call
+static CONCURRENCY_TUNER: OnceLock<ConcurrencyTuner> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AiProviderType {
+    OpenRouter,
+    Perplexity,
+    Gemini,
+    GoogleTranslate,
+}
+
+impl AiProviderType {
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "openrouter" => Ok(Self::OpenRouter),
+            "perplexity" => Ok(Self::Perplexity),
+            "gemini" => Ok(Self::Gemini),
+            "google" | "googletranslate" | "google_translate" => Ok(Self::GoogleTranslate),
+            other => Err(anyhow!(
+                "AI_PROVIDER_TRANSLATOR_TYPE must be either 'OpenRouter', 'Perplexity', 'Gemini', or 'Google' (got '{}')",
+                other
+            )),
+        }
+    }
+}
+
+/// Google Cloud Translation (v3) settings, used either as the main provider
+/// (AI_PROVIDER_TRANSLATOR_TYPE=Google) or as a fallback when the LLM provider is unreachable
+/// (AI_PROVIDER_TRANSLATOR_FALLBACK_TYPE=Google).
+#[derive(Debug, Clone)]
+struct GoogleTranslateConfig {
+    /// OAuth access token (e.g. from `gcloud auth print-access-token` or a service account).
+    access_token: String,
+    project_id: String,
+    location: String,
+    source_language: Option<String>,
+    target_language: String,
+    /// Optional model, e.g. `general/nmt` or `general/translation-llm`.
+    model: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct AiProviderConfig {
+    provider_type: AiProviderType,
+    api_key: String,
+    api_url: Option<String>,
+    model: String,
+    prompt: String,
+    prompt_cut: String,
+    reasoning: Option<ReasoningConfig>,
+    google_translate: Option<GoogleTranslateConfig>,
+    fallback_to_google: bool,
+    /// Articles longer than this many characters are translated chunk by chunk (0 = off).
+    chunk_chars: usize,
+    /// Model context size in tokens; longer inputs are chunked before the call (0 = off).
+    context_tokens: usize,
+    /// Temperature, top_p and output token limit (AI_PROVIDER_TRANSLATOR_TEMPERATURE, _TOP_P, _MAX_TOKENS).
+    sampling: SamplingConfig,
+    /// USD per million prompt/completion tokens, used for the cost estimate in `ai_usage`.
+    input_price: f64,
+    output_price: f64,
+    /// Items translated in parallel, optionally tuned (AI_PROVIDER_TRANSLATOR_CONCURRENCY and co.).
+    concurrency: Concurrency,
+    /// Built once at startup with AI_PROVIDER_TRANSLATOR_TIMEOUT_SECS and shared by all requests.
+    client: Client,
+}
+
+impl AiProviderConfig {
+    /// Provider, model and prices for the cost estimate in `ai_usage`.
+    fn usage_model(&self) -> UsageModel<'_> {
+        UsageModel {
+            provider: format!("{:?}", self.provider_type),
+            model: &self.model,
+            input_price: self.input_price,
+            output_price: self.output_price,
+        }
+    }
+}
+
+struct NewsItem {
+    id: String,
+    // Keep these fields even though they're not directly used in our code
+    // because they are part of the database schema and are returned by the query
+    #[allow(dead_code)]
+    title: String,
+    #[allow(dead_code)]
+    url: String,
+    #[allow(dead_code)]
+    date: String,
+    status: Status,
+    /// Extra target language of a `news_translations` row; `None` for the main `news` row.
+    lang: Option<String>,
+    /// Source language detected by the scraper, if any.
+    source_lang: Option<String>,
+}
+
+/// An extra target language from TARGET_LANGUAGES (all entries after the first one).
+struct TargetLanguage {
+    code: String,
+    prompt: String,
+}
+
+// Google Cloud Translation v3 docs:
+// - https://cloud.google.com/translate/docs/reference/rest/v3/projects.locations/translateText
+// Endpoint:
+//   POST https://translation.googleapis.com/v3/projects/{project}/locations/{location}:translateText
+// Auth:
+//   Authorization: Bearer <OAuth access token>
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleTranslateRequest {
+    contents: Vec<String>,
+    mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_language_code: Option<String>,
+    target_language_code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GoogleTranslateResponse {
+    #[serde(default)]
+    translations: Vec<GoogleTranslation>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GoogleTranslation {
+    translated_text: String,
+    #[allow(dead_code)]
+    detected_language_code: Option<String>,
+}
+
+/// Starts the translator: checks its provider settings, then translates the scraped items every
+/// TRANSLATOR_INTERVAL_SECS (or as soon as new ones arrive), or once with `--once`.
+pub fn run(options: RunOptions) -> Result<()> {
+    let interval_secs = read_interval_from_env("TRANSLATOR_INTERVAL_SECS", DEFAULT_TRANSLATE_INTERVAL_SECS)?;
+
+    // Check required environment variables
+    let provider_type = AiProviderType::parse(
+        &env::var("AI_PROVIDER_TRANSLATOR_TYPE").context("AI_PROVIDER_TRANSLATOR_TYPE environment variable not set")?,
+    )?;
+
+    // Google Translate is prompt-free, so model and prompt are optional for it.
+    let is_google = provider_type == AiProviderType::GoogleTranslate;
+    let model = match env::var("AI_PROVIDER_TRANSLATOR_MODEL") {
+        Ok(model) => model,
+        Err(_) if is_google => String::new(),
+        Err(e) => return Err(e).context("AI_PROVIDER_TRANSLATOR_MODEL environment variable not set"),
+    };
+    let prompt = match env::var("AI_PROVIDER_TRANSLATOR_PROMPT") {
+        Ok(prompt) => prompt,
+        Err(_) if is_google => String::new(),
+        Err(e) => return Err(e).context("AI_PROVIDER_TRANSLATOR_PROMPT environment variable not set"),
+    };
+    let api_key = env::var("AI_PROVIDER_TRANSLATOR_API_KEY").context("AI_PROVIDER_TRANSLATOR_API_KEY environment variable not set")?;
+    let api_url = env::var("AI_PROVIDER_TRANSLATOR_API_URL")
+        .ok()
+        .filter(|value| !value.trim().is_empty());
+
+    // Optional environment variable (can be empty)
+    let prompt_cut = env::var("AI_PROVIDER_TRANSLATOR_PROMPT_CUT").unwrap_or_else(|_| {
+        warn!("AI_PROVIDER_TRANSLATOR_PROMPT_CUT environment variable not set. Length reduction attempts might fail.");
+        String::new()
+    });
+
+    let reasoning = read_reasoning_config_from_env("AI_PROVIDER_TRANSLATOR");
+
+    let sampling = read_sampling_config_from_env("AI_PROVIDER_TRANSLATOR")?;
+    let input_price = read_price_from_env("AI_PROVIDER_TRANSLATOR_PRICE_INPUT")?;
+    let output_price = read_price_from_env("AI_PROVIDER_TRANSLATOR_PRICE_OUTPUT")?;
+    let concurrency = read_concurrency_from_env("AI_PROVIDER_TRANSLATOR")?;
+
+    let context_tokens = match env::var("AI_PROVIDER_TRANSLATOR_CONTEXT_TOKENS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| anyhow!("AI_PROVIDER_TRANSLATOR_CONTEXT_TOKENS must be a non-negative integer: {}", e))?,
+        _ => 0,
+    };
+
+    let chunk_chars = match env::var("AI_PROVIDER_TRANSLATOR_CHUNK_CHARS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| anyhow!("AI_PROVIDER_TRANSLATOR_CHUNK_CHARS must be a non-negative integer: {}", e))?,
+        _ => 0,
+    };
+
+    let fallback_to_google = match env::var("AI_PROVIDER_TRANSLATOR_FALLBACK_TYPE") {
+        Ok(value) if !value.trim().is_empty() && value.trim() != "-" => {
+            if AiProviderType::parse(&value)? != AiProviderType::GoogleTranslate {
+                return Err(anyhow!(
+                    "AI_PROVIDER_TRANSLATOR_FALLBACK_TYPE supports only 'Google' (got '{}')",
+                    value.trim()
+                ));
+            }
+            true
+        }
+        _ => false,
+    };
+
+    let timeout_secs = read_interval_from_env("AI_PROVIDER_TRANSLATOR_TIMEOUT_SECS", DEFAULT_REQUEST_TIMEOUT_SECS)?;
+    let client = Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .context("Failed to build HTTP client")?;
+
+    let google_translate = if is_google || fallback_to_google {
+        Some(read_google_translate_config_from_env(is_google, &api_key, &model)?)
+    } else {
+        None
+    };
+
+    let provider = AiProviderConfig {
+        provider_type,
+        api_key,
+        api_url,
+        model,
+        prompt,
+        prompt_cut,
+        reasoning,
+        google_translate,
+        fallback_to_google,
+        chunk_chars,
+        context_tokens,
+        sampling,
+        input_price,
+        output_price,
+        concurrency,
+        client,
+    };
+    
+    let stage = load_pipeline_stage(
+        "translator",
+        PipelineStage {
+            input_status: Status::Scraper,
+            output_status: Status::Translated,
+            input_artifact: "scraper".to_string(),
+            output_artifact: "translator".to_string(),
+        },
+    )?;
+    
+    let languages = read_target_languages_from_env(&provider)?;
+    let glossary = read_glossary_from_env()?;
+    let budget = read_daily_budget_from_env()?;
+
+    // Language of the default translation; items already written in it are passed through as is
+    let target_language = env::var("TARGET_LANGUAGES")
+        .ok()
+        .and_then(|raw| raw.split(',').next().map(|code| code.trim().to_ascii_lowercase()))
+        .filter(|code| !code.is_empty())
+        .or_else(|| {
+            env::var("AI_PROVIDER_TRANSLATOR_TARGET_LANGUAGE")
+                .ok()
+                .map(|code| code.trim().to_ascii_lowercase())
+                .filter(|code| !code.is_empty())
+        });
+
+    // Initialize database and data directory
+    let conn = init_db()?;
+    init_data_dir()?;
+    if let Some(tuner) = provider.concurrency.tuner("Translator") {
+        let _ = CONCURRENCY_TUNER.set(tuner);
+    }
+    
+    info!("Starting translator...");
+    listen_for_shutdown()?;
+    
+    // Main loop - run every TRANSLATOR_INTERVAL_SECS seconds until SIGTERM or Ctrl-C
+    while !shutdown_requested() {
+        if !options.once {
+            if let Err(e) = heartbeat(&conn, interval_secs) {
+                warn!("Failed to record heartbeat: {}", e);
+            }
+        }
+        // Read before the cycle, so items handed over during it end the next sleep early
+        let seen = wakeup_seq(&conn, &[&stage.input_status]).unwrap_or(0);
+        // Stop spending once today's AI budget is used up
+        if !run_async(check_daily_budget(&conn, "Translator", &budget, &provider.client)) {
+            if let Err(e) = run_translator(&conn, &provider, &stage, &languages, target_language.as_deref(), &glossary, &budget) {
+                if options.once {
+                    return Err(e);
+                }
+                error!("Error in run_translator loop: {}", e);
+            }
+        }
+        if let Err(e) = block_on(send_dead_letter_alerts(&conn)) {
+            warn!("Failed to send dead-letter alerts: {}", e);
+        }
+        if options.once {
+            return Ok(());
+        }
+        
+        info!(
+            "Sleeping for up to {} seconds",
+            interval_secs
+        );
+        wait_for_wakeup(interval_secs, seen, || wakeup_seq(&conn, &[&stage.input_status]))?;
+    }
+    info!("Stopped");
+    Ok(())
+}
+
+/// Command-line options: `--once` processes the current queue and exits (for cron- or CI-driven runs).
+pub struct RunOptions {
+    pub once: bool,
+}
+
+pub fn parse_run_options() -> Result<RunOptions> {
+    let mut options = RunOptions { once: false };
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--once" => options.once = true,
+            other => return Err(anyhow!("Unknown argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn init_db() -> Result<Connection> {
+    let conn = open_db()?;
+    
+    Ok(conn)
+}
+
+fn init_data_dir() -> Result<()> {
+    if !Path::new(DATA_DIR).exists() {
+        fs::create_dir_all(DATA_DIR).context("Failed to create data directory")?;
+    }
+    Ok(())
+}
+
+fn run_translator(
+    conn: &Connection,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    languages: &[TargetLanguage],
+    target_language: Option<&str>,
+    glossary: &Glossary,
+    budget: &DailyBudget,
+) -> Result<()> {
+    info!("Checking for news items to translate");
+
+    // Fan out: every item reaching the translator gets one row per extra target language
+    for language in languages {
+        conn.execute(
+            "INSERT OR IGNORE INTO news_translations (news_id, lang, status) SELECT id, ?, status FROM news WHERE status = ?",
+            params![language.code, stage.input_status],
+        )?;
+    }
+    
+    // Fetch news items with the stage input status ("scraper" by default), "translator_retry", or "translator_length"
+    let mut news_items = fetch_items_to_translate(conn, &stage.input_status)?;
+    if !languages.is_empty() {
+        news_items.extend(fetch_translations_to_translate(conn, &stage.input_status)?);
+    }
+    
+    if news_items.is_empty() {
+        info!("No items to translate");
+        return Ok(());
+    }
+    
+    info!(
+        "Found {} items to translate",
+        news_items.len()
+    );
+    
+    let concurrency = provider.concurrency.max.min(news_items.len());
+    if concurrency <= 1 {
+        run_async(async {
+            for item in news_items {
+                if shutdown_requested() || check_daily_budget(conn, "Translator", budget, &provider.client).await {
+                    break;
+                }
+                let span = item_span("translator", &item.id);
+                track_usage(translate_item(conn, item, provider, stage, languages, target_language, glossary))
+                    .instrument(span)
+                    .await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })?;
+    } else {
+        info!("Translating with up to {} workers", concurrency);
+        run_workers(news_items, concurrency, CONCURRENCY_TUNER.get(), || WORKER_CONNECTIONS.get(), async |conn, item| {
+            if check_daily_budget(conn, "Translator", budget, &provider.client).await {
+                return;
+            }
+            let item_id = item.id.clone();
+            let span = item_span("translator", &item_id);
+            let translation = track_usage(translate_item(conn, item, provider, stage, languages, target_language, glossary));
+            if let Err(e) = translation.instrument(span).await {
+                error!("Failed to translate item {}: {}", item_id, e);
+            }
+        });
+    }
+    
+    info!("Translation cycle completed");
+    Ok(())
+}
+
+/// Translates one item, or passes it through when it is already in the target language.
+async fn translate_item(
+    conn: &Connection,
+    item: NewsItem,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    languages: &[TargetLanguage],
+    target_language: Option<&str>,
+    glossary: &Glossary,
+) -> Result<()> {
+    let item_id = item.id.clone(); // Clone id for logging in case of error
+    let current_status = item.status.clone(); // Clone status for logic
+    let lang = item.lang.as_deref();
+    // Another replica may have taken the item since it was fetched
+    if !claim_item(conn, &item_id, lang, &current_status)? {
+        return Ok(());
+    }
+
+    // Nothing to translate when the article is already in the target language
+    let item_target = lang.or(target_language);
+    if item_target.is_some() && item.source_lang.as_deref() == item_target {
+        match pass_through_untranslated(&item, stage) {
+            Ok(()) => {
+                info!(
+                    "Item {} is already in '{}', skipping translation",
+                    item_id,
+                    item_target.unwrap_or_default()
+                );
+                reset_attempts(conn, &item_id, lang)?;
+                update_status(conn, &item_id, lang, &current_status, &stage.output_status)?;
+            }
+            Err(e) => {
+                error!("Failed to pass through item {}: {}", item_id, e);
+                record_failure(conn, &item_id, lang, &e.to_string())?;
+            }
+        }
+        return Ok(());
+    }
+
+    // Extra languages use their own prompt (and target language for Google Translate)
+    let language_provider;
+    let provider = match lang {
+        Some(code) => {
+            let Some(language) = languages.iter().find(|l| l.code == code) else {
+                warn!(
+                    "Language '{}' of item {} is no longer in TARGET_LANGUAGES, skipping",
+                    code, item_id
+                );
+                release_claim(conn, &item_id, lang)?;
+                return Ok(());
+            };
+            language_provider = provider_for_language(provider, language);
+            &language_provider
+        }
+        None => provider,
+    };
+
+    let glossary = glossary.for_language(lang);
+
+    // Pass current_status and prompt_cut to process_news_item
+    match process_news_item(conn, &item, provider, stage, &current_status, &glossary).await {
+        Ok(finish_reason_opt) => {
+            // Decide the next status based on the finish_reason, current status, and attempt type
+            let next_status = match &current_status {
+                // Handling first attempt (stage input status or translator_retry)
+                status if *status == stage.input_status || *status == Status::TranslatorRetry => {
+                    match finish_reason_opt.as_deref() {
+                        Some("length") => {
+                            warn!(
+                                "Translation result too long for item {}. Setting status to translator_length.",
+                                item_id
+                            );
+                            Status::TranslatorLength // Set to length status for retry with cut prompt
+                        }
+                        Some("error") => {
+                            if current_status == Status::TranslatorRetry {
+                                error!(
+                                    "Translation failed again for item {}. Setting status to translator_error.",
+                                    item_id
+                                );
+                                Status::TranslatorError // Failed again, set to error
+                            } else {
+                                warn!(
+                                    "Translation API returned error for item {}. Setting status to translator_retry.",
+                                    item_id
+                                );
+                                Status::TranslatorRetry // First failure, set to retry
+                            }
+                        }
+                        Some(_) | None => {
+                            // Consider success if finish_reason is not "error" or "length", or is None
+                            info!(
+                                "Successfully processed news item: {}",
+                                item_id
+                            );
+                            stage.output_status.clone() // Success
+                        }
+                    }
+                }
+                // Handling second attempt (translator_length)
+                Status::TranslatorLength => {
+                    match finish_reason_opt.as_deref() {
+                         Some("length") | Some("error") => {
+                            error!(
+                                "Translation failed on second attempt (status: {}) for item {}. Setting status to translator_error.",
+                                 finish_reason_opt.as_deref().unwrap_or("unknown"), item_id
+                            );
+                            Status::TranslatorError // Failed on second attempt (length or error), set to final error
+                        }
+                        Some(_) | None => {
+                            info!(
+                                "Successfully processed news item on second attempt: {}",
+                                item_id
+                            );
+                            stage.output_status.clone() // Success on second attempt
+                        }
+                    }
+                }
+                // Should not happen based on fetch query, but handle defensively
+                _ => {
+                     error!(
+                        "Unexpected current status '{}' for item {}. Setting to translator_error.",
+                        current_status, item_id
+                     );
+                     Status::TranslatorError
+                }
+            };
+            // Keep why the item failed next to it; a translated item starts the next stage afresh
+            if next_status == stage.output_status {
+                reset_attempts(conn, &item_id, lang)?;
+            } else {
+                let error = format!(
+                    "Translation failed in status '{}' with finish_reason={}",
+                    current_status,
+                    finish_reason_opt.as_deref().unwrap_or("none")
+                );
+                record_failure(conn, &item_id, lang, &error)?;
+            }
+            update_status(conn, &item_id, lang, &current_status, &next_status)?;
+        }
+        Err(e) if e.is::<Cancelled>() => {
+            // Shutting down: the next start translates it again
+            release_claim(conn, &item_id, lang)?;
+        }
+        Err(e) => {
+            record_failure(conn, &item_id, lang, &e.to_string())?;
+             // Decide the next status based on the error and current status
+            let next_status = if current_status == Status::TranslatorLength {
+                 error!(
+                    "Critical error processing item {} on second attempt: {}. Setting status to translator_error.",
+                    item_id, e
+                 );
+                Status::TranslatorError // Critical error on second attempt -> final error
+            } else {
+                 error!(
+                    "Critical error processing item {}: {}. Status remains '{}' for potential retry.",
+                    item_id, e, current_status
+                 );
+                // On critical errors during first attempt (scraper/translator_retry),
+                // keep the current status to allow retry mechanisms or error logging on next cycle.
+                // Don't update status here.
+                return Ok(()); // Skip update_status call for this item on critical error during first attempt
+            };
+             // Update status only if it was translator_length initially or if we decided to set translator_error
+             if current_status == Status::TranslatorLength {
+                update_status(conn, &item_id, lang, &current_status, &next_status)?;
+             }
+             // If it was scraper/translator_retry and a critical error occurred, status remains unchanged
+        }
+    }
+    Ok(())
+}
+
+fn fetch_items_to_translate(conn: &Connection, input_status: &Status) -> Result<Vec<NewsItem>> {
+    let mut stmt = conn.prepare("SELECT id, title, url, date, status, NULL, lang FROM news WHERE status IN (?, ?, ?) ORDER BY date ASC")?;
+    let news_iter = stmt.query_map(
+        params![input_status, Status::TranslatorRetry, Status::TranslatorLength],
+        news_item_from_row,
+    )?;
+    
+    let mut news_items = Vec::new();
+    for item in news_iter {
+        news_items.push(item?);
+    }
+    
+    Ok(news_items)
+}
+
+fn fetch_translations_to_translate(conn: &Connection, input_status: &Status) -> Result<Vec<NewsItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT n.id, n.title, n.url, n.date, t.status, t.lang, n.lang FROM news_translations t JOIN news n ON n.id = t.news_id \
+         WHERE t.status IN (?, ?, ?) ORDER BY n.date ASC",
+    )?;
+    let news_iter = stmt.query_map(
+        params![input_status, Status::TranslatorRetry, Status::TranslatorLength],
+        news_item_from_row,
+    )?;
+
+    let mut news_items = Vec::new();
+    for item in news_iter {
+        news_items.push(item?);
+    }
+
+    Ok(news_items)
+}
+
+fn news_item_from_row(row: &Row) -> rusqlite::Result<NewsItem> {
+    Ok(NewsItem {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        url: row.get(2)?,
+        date: row.get(3)?,
+        status: row.get(4)?,
+        lang: row.get(5)?,
+        source_lang: row.get::<_, Option<String>>(6)?.map(|code| code.to_ascii_lowercase()),
+    })
+}
+
+/// Copies the scraped article to the translator artifact unchanged.
+fn pass_through_untranslated(item: &NewsItem, stage: &PipelineStage) -> Result<()> {
+    let input_artifact = format!("{}_{}.html", stage.input_artifact, item.id);
+    let output_artifact = match &item.lang {
+        Some(lang) => format!("{}_{}_{}.html", stage.output_artifact, item.id, lang),
+        None => format!("{}_{}.html", stage.output_artifact, item.id),
+    };
+    write_artifact(&output_artifact, read_artifact(&input_artifact)?)
+        .context(format!("Failed to copy {} to {}", input_artifact, output_artifact))?;
+    Ok(())
+}
+
+/// Reads TARGET_LANGUAGES (comma-separated, e.g. `ru,de,es`). The first language is the one
+/// configured by AI_PROVIDER_TRANSLATOR_PROMPT and keeps the plain `translator_<id>.html` artifact;
+/// every further language needs AI_PROVIDER_TRANSLATOR_PROMPT_<LANG> (not for Google Translate)
+/// and produces `translator_<id>_<lang>.html`.
+fn read_target_languages_from_env(provider: &AiProviderConfig) -> Result<Vec<TargetLanguage>> {
+    let raw = env::var("TARGET_LANGUAGES").unwrap_or_default();
+    let mut languages = Vec::new();
+
+    for code in raw
+        .split(',')
+        .map(|code| code.trim().to_ascii_lowercase())
+        .filter(|code| !code.is_empty())
+        .skip(1)
+    {
+        let prompt_var = format!("AI_PROVIDER_TRANSLATOR_PROMPT_{}", code.to_ascii_uppercase().replace('-', "_"));
+        let prompt = match env::var(&prompt_var) {
+            Ok(prompt) => prompt,
+            Err(_) if provider.provider_type == AiProviderType::GoogleTranslate => String::new(),
+            Err(_) => return Err(anyhow!("{} environment variable not set", prompt_var)),
+        };
+        languages.push(TargetLanguage { code, prompt });
+    }
+
+    Ok(languages)
+}
+
+fn glossary_prompt(glossary: &[GlossaryTerm]) -> String {
+    if glossary.is_empty() {
+        return String::new();
+    }
+    let terms = glossary
+        .iter()
+        .map(|term| {
+            if term.is_kept() {
+                format!("\"{}\" (keep as is)", term.source)
+            } else {
+                format!("\"{}\" -> \"{}\"", term.source, term.target)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    format!(" Use these terms exactly as given: {}.", terms)
+}
+
+/// Glossary terms whose source appears in the input but whose expected form is missing from the output.
+/// Both sides ignore case, since a term may start a sentence in one language and not in the other.
+fn find_mangled_terms(input: &str, output: &str, glossary: &[GlossaryTerm]) -> Vec<String> {
+    let input = input.to_lowercase();
+    let output = output.to_lowercase();
+    glossary
+        .iter()
+        .filter(|term| input.contains(&term.source.to_lowercase()) && !output.contains(&term.target.to_lowercase()))
+        .map(|term| term.target.clone())
+        .collect()
+}
+
+fn provider_for_language(provider: &AiProviderConfig, language: &TargetLanguage) -> AiProviderConfig {
+    let mut language_provider = provider.clone();
+    language_provider.prompt = language.prompt.clone();
+    if let Some(google) = language_provider.google_translate.as_mut() {
+        google.target_language = language.code.clone();
+    }
+    language_provider
+}
+
+async fn process_news_item(
+    conn: &Connection,
+    item: &NewsItem,
+    provider: &AiProviderConfig,
+    stage: &PipelineStage,
+    current_status: &Status,
+    glossary: &[GlossaryTerm],
+) -> Result<Option<String>> {
+    let input_artifact = format!("{}_{}.html", stage.input_artifact, item.id);
+    let output_artifact = match &item.lang {
+        Some(lang) => format!("{}_{}_{}.html", stage.output_artifact, item.id, lang),
+        None => format!("{}_{}.html", stage.output_artifact, item.id),
+    };
+    
+    debug!("Processing item: {} (language: {})", item.id, item.lang.as_deref().unwrap_or("default"));
+    
+    let html_content = match load_artifact(&input_artifact)? {
+        Some(bytes) => String::from_utf8(bytes)
+            .context(format!("Failed to read content from file: {}", input_artifact))?,
+        None => return Err(anyhow!("Input file not found: {}", input_artifact)),
+    };
+    
+    // Construct the final prompt based on the current status
+    let mut final_prompt = if *current_status == Status::TranslatorLength {
+        // Combine the original prompt with the cut prompt for the retry
+        format!("{} {}", provider.prompt, provider.prompt_cut)
+    } else {
+        // Use the original prompt for the first attempt
+        provider.prompt.to_string()
+    };
+    final_prompt.push_str(&glossary_prompt(glossary));
+    // Google Translate never sees the glossary, so its output isn't held to it
+    let used_google = Cell::new(provider.provider_type == AiProviderType::GoogleTranslate);
+
+    let translate = async |html: &str| {
+        // Send to OpenRouter API and get content + finish_reason using the final prompt
+        let mut result = cancellable(translate_content(html, provider, &final_prompt)).await;
+
+        // Fall back to Google Translate when the LLM provider is unreachable or overloaded.
+        if provider.fallback_to_google && is_provider_unavailable(&result) {
+            if let Some(google) = &provider.google_translate {
+                warn!(
+                    "AI provider unavailable for item {}. Falling back to Google Translate.",
+                    item.id
+                );
+                result = cancellable(translate_with_google(html, google, &provider.client)).await;
+                used_google.set(true);
+            }
+        }
+        result
+    };
+
+    // Articles that don't fit the model's context are translated chunk by chunk
+    let chunk_chars = effective_chunk_chars(
+        "AI_PROVIDER_TRANSLATOR_CONTEXT_TOKENS",
+        provider.chunk_chars,
+        provider.context_tokens,
+        provider.sampling.max_tokens,
+        &final_prompt,
+        &html_content,
+    );
+    take_usage();
+    let translation_result = if chunk_chars > 0 && html_content.len() > chunk_chars {
+        info!(
+            "Item {} has {} characters, translating in chunks of up to {}",
+            item.id,
+            html_content.len(),
+            chunk_chars
+        );
+        process_in_chunks(&html_content, chunk_chars, translate).await
+    } else {
+        translate(&html_content).await
+    };
+    let error_stage = match &item.lang {
+        Some(lang) => format!("translator/{}", lang),
+        None => "translator".to_string(),
+    };
+    record_usage(conn, "translator", &item.id, item.lang.as_deref(), &provider.usage_model(), take_usage())?;
+    match &translation_result {
+        Ok(_) => clear_provider_error(conn, &item.id, &error_stage)?,
+        Err(ApiError::Cancelled(_)) => {}
+        Err(e) => record_provider_error(conn, &item.id, &error_stage, &e.failure())?,
+    }
+    
+    // Match on the actual Result, not a reference
+    match &translation_result {
+        Ok((ref content, _)) => {
+            // Content is now &String, so use as_bytes()
+            debug!(
+                "Writing successful content to: {}",
+                output_artifact
+            );
+            write_artifact(&output_artifact, content)
+                .context(format!(
+                    "Failed to write content to output file: {}",
+                    output_artifact
+                ))?;
+        }
+        Err(ApiError::ApiReturnedError { ref content, .. }) => {
+            // Use 'ref content' to borrow from the error struct
+            debug!(
+                "Writing partial content from API error to: {}",
+                output_artifact
+            );
+            write_artifact(&output_artifact, content)
+                .context(format!(
+                    "Failed to write partial content to output file: {}",
+                    output_artifact
+                ))?;
+        }
+        Err(ref e @ ApiError::RequestError(_)) => {
+            // Borrow the error to avoid moving it
+            error!(
+                "API request failed for item {}: {}. No content to save.",
+                item.id, e
+            );
+            // Convert ApiError directly to anyhow::Error
+            return Err(anyhow!(e.clone()));
+        }
+         Err(ref e @ ApiError::ParseError(_)) => {
+            // Borrow the error
+             error!(
+                "Failed to parse API response for item {}: {}. No content to save.",
+                item.id, e
+            );
+            // Convert ApiError directly to anyhow::Error
+            return Err(anyhow!(e.clone()));
+        }
+        Err(ApiError::Cancelled(cancelled)) => return Err((*cancelled).into()),
+        Err(ref e @ (ApiError::EmptyChoices | ApiError::EmptyImageData | ApiError::ConfigurationError(_))) => {
+            error!(
+                "API returned no content for item {}: {}. No content to save.",
+                item.id, e
+            );
+            // Convert ApiError directly to anyhow::Error
+            return Err(anyhow!(e.clone()));
+        }
+    }
+
+    // Return the finish_reason if successful or if API returned a controlled error
+    match translation_result {
+        Ok((content, finish_reason)) => {
+            let mangled = if used_google.get() {
+                Vec::new()
+            } else {
+                find_mangled_terms(&html_content, &content, glossary)
+            };
+            if !mangled.is_empty() && finish_reason.as_deref() != Some("length") {
+                warn!(
+                    "Protected terms missing from translation of item {}: {}. Forcing finish_reason='error' to trigger retry.",
+                    item.id,
+                    mangled.join(", ")
+                );
+                return Ok(Some("error".to_string()));
+            }
+            Ok(finish_reason)
+        }
+        Err(ApiError::ApiReturnedError { finish_reason, .. }) => Ok(finish_reason),
+        // Other errors were already returned as Err(anyhow::Error)
+        Err(e) => Err(anyhow!(e)), // Convert remaining ApiError variants - this signals critical errors to run_translator
+    }
+}
+
+async fn translate_content(content: &str, provider: &AiProviderConfig, prompt: &str) -> Result<(String, Option<String>), ApiError> {
+    let client = &provider.client;
+
+    let messages = vec![
+        Message {
+            role: "system".to_string(),
+            content: prompt.to_string(),
+        },
+        Message {
+            role: "user".to_string(),
+            content: content.to_string(),
+        },
+    ];
+    
+    match provider.provider_type {
+        AiProviderType::GoogleTranslate => {
+            let google = provider
+                .google_translate
+                .as_ref()
+                .ok_or_else(|| ApiError::ParseError(Arc::new(anyhow!("Google Translate configuration is missing"))))?;
+            translate_with_google(content, google, client).await
+        }
+        AiProviderType::OpenRouter => {
+            if let Some(reasoning) = &provider.reasoning {
+                debug!(
+                    "OpenRouter reasoning config applied: enabled={:?}, effort={:?}",
+                    reasoning.enabled, reasoning.effort
+                );
+            }
+
+            let request = OpenRouterChatRequest {
+                model: provider.model.clone(),
+                messages,
+                reasoning: provider.reasoning.clone(),
+                sampling: provider.sampling.clone(),
+            };
+
+            // Log before sending - ignore result
+            debug!(
+                "Sending request to OpenRouter API with model: {}",
+                provider.model
+            );
+
+            let response = send_request(
+                client
+                    .post("https://openrouter.ai/api/v1/chat/completions")
+                    .header("Authorization", format!("Bearer {}", provider.api_key))
+                    .header("Content-Type", "application/json")
+                    .json(&request),
+            )
+            .await?;
+
+            parse_chat_response(response, extract_html_response).await
+        }
+        AiProviderType::Perplexity => {
+            let reasoning_effort = perplexity_reasoning_effort_from_reasoning(&provider.reasoning);
+            if let Some(ref effort) = reasoning_effort {
+                debug!(
+                    "Perplexity reasoning_effort applied: {}",
+                    effort
+                );
+            }
+
+            let request = PerplexityChatRequest {
+                model: provider.model.clone(),
+                messages,
+                reasoning_effort,
+                sampling: provider.sampling.clone(),
+            };
+
+            debug!(
+                "Sending request to Perplexity API with model: {}",
+                provider.model
+            );
+
+            let response = send_request(
+                client
+                    .post("https://api.perplexity.ai/chat/completions")
+                    .header("Authorization", format!("Bearer {}", provider.api_key))
+                    .header("Content-Type", "application/json")
+                    .json(&request),
+            )
+            .await?;
+
+            parse_chat_response(response, extract_html_response).await
+        }
+        AiProviderType::Gemini => {
+            // Gemini OpenAI compatibility docs:
+            // https://ai.google.dev/gemini-api/docs/openai
+            // Endpoint:
+            //   POST https://generativelanguage.googleapis.com/v1beta/openai/chat/completions
+            // Auth:
+            //   Authorization: Bearer <GEMINI_API_KEY>
+            let api_url = provider
+                .api_url
+                .as_deref()
+                .unwrap_or("https://generativelanguage.googleapis.com/v1beta/openai/chat/completions");
+            let reasoning_effort = gemini_reasoning_effort_from_reasoning(&provider.reasoning);
+            if let Some(ref effort) = reasoning_effort {
+                debug!(
+                    "Gemini reasoning_effort applied: {}",
+                    effort
+                );
+            }
+
+            let request = GeminiChatRequest {
+                model: provider.model.clone(),
+                messages,
+                stream: provider.api_url.as_ref().map(|_| false),
+                reasoning_effort,
+                sampling: provider.sampling.clone(),
+            };
+
+            debug!(
+                "Sending request to Gemini OpenAI-compatible API with model: {}",
+                provider.model
+            );
+
+            let response = send_request(
+                client
+                    .post(api_url)
+                    .header("Authorization", format!("Bearer {}", provider.api_key))
+                    .header("Content-Type", "application/json")
+                    .json(&request),
+            )
+            .await?;
+
+            parse_chat_response(response, extract_html_response).await
+        }
+    }
+}
+
+/// Sends a provider request and tells the concurrency tuner how long it took.
+async fn send_request(request: RequestBuilder) -> Result<Response, ApiError> {
+    let started = Instant::now();
+    let response = request.send().await;
+    if let Some(tuner) = CONCURRENCY_TUNER.get() {
+        // A failed request (mostly a timeout) tells the tuner the same as a 429: the provider has too much
+        let overloaded = response.as_ref().map_or(true, |response| response.status() == StatusCode::TOO_MANY_REQUESTS);
+        tuner.record(started.elapsed(), overloaded);
+    }
+    response.map_err(|e| ApiError::RequestError(Arc::new(e)))
+}
+
+async fn translate_with_google(
+    content: &str,
+    google: &GoogleTranslateConfig,
+    client: &Client,
+) -> Result<(String, Option<String>), ApiError> {
+    let url = format!(
+        "https://translation.googleapis.com/v3/projects/{}/locations/{}:translateText",
+        google.project_id, google.location
+    );
+    let model = google.model.as_ref().map(|model| {
+        format!(
+            "projects/{}/locations/{}/models/{}",
+            google.project_id, google.location, model
+        )
+    });
+
+    let request = GoogleTranslateRequest {
+        contents: vec![content.to_string()],
+        mime_type: "text/html".to_string(),
+        source_language_code: google.source_language.clone(),
+        target_language_code: google.target_language.clone(),
+        model,
+    };
+
+    debug!(
+        "Sending request to Google Translate API: target='{}', html_len={}",
+        google.target_language,
+        content.len()
+    );
+
+    let response = send_request(
+        client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", google.access_token))
+            .header("x-goog-user-project", google.project_id.clone())
+            .header("Content-Type", "application/json")
+            .json(&request),
+    )
+    .await?;
+
+    let status = response.status();
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| ApiError::RequestError(Arc::new(e)))?;
+
+    if !status.is_success() {
+        warn!(
+            "Google Translate returned non-success status: {}. Body: {}",
+            status, response_text
+        );
+        return Err(ApiError::ApiReturnedError {
+            provider_error: parse_provider_error(&response_text),
+            status,
+            content: String::new(),
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    let response_data: GoogleTranslateResponse = serde_json::from_str(&response_text).map_err(|e| {
+        error!(
+            "Failed to parse Google Translate response JSON. Status: {}. Body: {}",
+            status, response_text
+        );
+        ApiError::ParseError(Arc::new(e.into()))
+    })?;
+
+    let translated = response_data
+        .translations
+        .into_iter()
+        .next()
+        .ok_or(ApiError::EmptyChoices)?
+        .translated_text;
+
+    if !looks_like_html(&translated) {
+        warn!("Google Translate returned content that does not look like HTML. Forcing finish_reason='error' to trigger retry.");
+        return Err(ApiError::ApiReturnedError {
+            provider_error: None,
+            status,
+            content: translated,
+            finish_reason: Some("error".to_string()),
+        });
+    }
+
+    Ok((translated, None))
+}
+
+fn is_provider_unavailable(result: &Result<(String, Option<String>), ApiError>) -> bool {
+    match result {
+        Err(ApiError::RequestError(_)) => true,
+        Err(ApiError::ApiReturnedError { status, .. }) => {
+            status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        }
+        _ => false,
+    }
+}
+
+fn read_google_translate_config_from_env(is_primary: bool, api_key: &str, model: &str) -> Result<GoogleTranslateConfig> {
+    let access_token = match env::var("AI_PROVIDER_TRANSLATOR_GOOGLE_ACCESS_TOKEN") {
+        Ok(token) if !token.trim().is_empty() => token.trim().to_string(),
+        _ if is_primary => api_key.trim().to_string(),
+        _ => {
+            return Err(anyhow!(
+                "AI_PROVIDER_TRANSLATOR_GOOGLE_ACCESS_TOKEN environment variable not set (required for the Google fallback)"
+            ))
+        }
+    };
+
+    let project_id = env::var("AI_PROVIDER_TRANSLATOR_GOOGLE_PROJECT_ID")
+        .context("AI_PROVIDER_TRANSLATOR_GOOGLE_PROJECT_ID environment variable not set")?
+        .trim()
+        .to_string();
+    let target_language = env::var("AI_PROVIDER_TRANSLATOR_TARGET_LANGUAGE")
+        .context("AI_PROVIDER_TRANSLATOR_TARGET_LANGUAGE environment variable not set")?
+        .trim()
+        .to_string();
+    if project_id.is_empty() || target_language.is_empty() {
+        return Err(anyhow!(
+            "AI_PROVIDER_TRANSLATOR_GOOGLE_PROJECT_ID and AI_PROVIDER_TRANSLATOR_TARGET_LANGUAGE must not be empty"
+        ));
+    }
+
+    let location = env::var("AI_PROVIDER_TRANSLATOR_GOOGLE_LOCATION")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| GOOGLE_TRANSLATE_DEFAULT_LOCATION.to_string());
+    let source_language = env::var("AI_PROVIDER_TRANSLATOR_SOURCE_LANGUAGE")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+
+    // AI_PROVIDER_TRANSLATOR_MODEL belongs to the LLM when Google is only the fallback.
+    let model = if is_primary {
+        Some(model.trim().to_string()).filter(|m| !m.is_empty() && m != "-")
+    } else {
+        env::var("AI_PROVIDER_TRANSLATOR_GOOGLE_MODEL")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+
+    Ok(GoogleTranslateConfig {
+        access_token,
+        project_id,
+        location,
+        source_language,
+        target_language,
+        model,
+    })
+}